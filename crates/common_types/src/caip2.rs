@@ -0,0 +1,198 @@
+use std::str::FromStr;
+
+use diesel::backend::Backend;
+use diesel::deserialize::{FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::Pg;
+use diesel::serialize::ToSql;
+use diesel::sql_types;
+use serde::{Deserialize, Serialize};
+
+/// A [`serde`] and [`diesel`]-compatible type definition for CAIP-2 chain IDs,
+/// e.g. `eip155:1` for Ethereum mainnet.
+///
+/// See <https://github.com/ChainAgnostic/CAIPs/blob/main/CAIPs/caip-2.md>.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+    derive_more::Display,
+)]
+#[diesel(sql_type = sql_types::Text)]
+#[serde(try_from = "String", into = "String")]
+pub struct Caip2ChainId(String);
+
+/// The error returned when parsing a malformed [`Caip2ChainId`].
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+#[display(
+    fmt = "invalid CAIP-2 chain ID `{}`, expected `namespace:reference` (e.g. `eip155:1`)",
+    _0
+)]
+pub struct InvalidCaip2ChainId(String);
+
+impl std::error::Error for InvalidCaip2ChainId {}
+
+impl Caip2ChainId {
+    /// A regular expression that matches valid CAIP-2 chain IDs.
+    pub const PATTERN: &'static str = "^[-a-z0-9]{3,8}:[-_a-zA-Z0-9]{1,32}$";
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The blockchain ecosystem, e.g. `eip155` for EVM chains.
+    pub fn namespace(&self) -> &str {
+        self.0.split_once(':').unwrap().0
+    }
+
+    /// The chain within the namespace, e.g. the chain ID of EVM chains.
+    pub fn reference(&self) -> &str {
+        self.0.split_once(':').unwrap().1
+    }
+}
+
+impl FromStr for Caip2ChainId {
+    type Err = InvalidCaip2ChainId;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = s.split_once(':').map_or(false, |(namespace, reference)| {
+            (3..=8).contains(&namespace.len())
+                && namespace
+                    .chars()
+                    .all(|c| c == '-' || c.is_ascii_lowercase() || c.is_ascii_digit())
+                && (1..=32).contains(&reference.len())
+                && reference
+                    .chars()
+                    .all(|c| c == '-' || c == '_' || c.is_ascii_alphanumeric())
+        });
+
+        if valid {
+            Ok(Self(s.to_string()))
+        } else {
+            Err(InvalidCaip2ChainId(s.to_string()))
+        }
+    }
+}
+
+impl TryFrom<String> for Caip2ChainId {
+    type Error = InvalidCaip2ChainId;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Caip2ChainId> for String {
+    fn from(chain_id: Caip2ChainId) -> Self {
+        chain_id.0
+    }
+}
+
+impl schemars::JsonSchema for Caip2ChainId {
+    fn schema_name() -> String {
+        "Caip2ChainId".to_owned()
+    }
+
+    fn json_schema(_gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        schemars::schema::SchemaObject {
+            instance_type: Some(schemars::schema::InstanceType::String.into()),
+            string: Some(Box::new(schemars::schema::StringValidation {
+                pattern: Some(Self::PATTERN.to_owned()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+impl ToSql<sql_types::Text, Pg> for Caip2ChainId {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        ToSql::<sql_types::Text, Pg>::to_sql(&self.0, &mut out.reborrow())
+    }
+}
+
+impl FromSql<sql_types::Text, Pg> for Caip2ChainId {
+    fn from_sql(bytes: <Pg as Backend>::RawValue<'_>) -> diesel::deserialize::Result<Self> {
+        let s = String::from_sql(bytes)?;
+        Ok(Caip2ChainId::from_str(&s)?)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl ToSql<sql_types::Text, diesel::sqlite::Sqlite> for Caip2ChainId {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, diesel::sqlite::Sqlite>,
+    ) -> diesel::serialize::Result {
+        ToSql::<sql_types::Text, diesel::sqlite::Sqlite>::to_sql(self.0.as_str(), out)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl FromSql<sql_types::Text, diesel::sqlite::Sqlite> for Caip2ChainId {
+    fn from_sql(
+        bytes: <diesel::sqlite::Sqlite as Backend>::RawValue<'_>,
+    ) -> diesel::deserialize::Result<Self> {
+        let s = <String as FromSql<sql_types::Text, diesel::sqlite::Sqlite>>::from_sql(bytes)?;
+        Ok(Caip2ChainId::from_str(&s)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_chain_ids() {
+        for s in [
+            "eip155:1",
+            "eip155:42161",
+            "bip122:000000000019d6689c085ae165831e93",
+            "cosmos:cosmoshub-3",
+            "cosmos:Binance-Chain-Tigris",
+            "starknet:SN_MAIN",
+        ] {
+            let chain_id = Caip2ChainId::from_str(s).unwrap();
+            assert_eq!(chain_id.to_string(), s);
+        }
+
+        let chain_id = Caip2ChainId::from_str("eip155:1").unwrap();
+        assert_eq!(chain_id.namespace(), "eip155");
+        assert_eq!(chain_id.reference(), "1");
+    }
+
+    #[test]
+    fn invalid_chain_ids() {
+        for s in [
+            "",
+            "eip155",
+            "eip155:",
+            ":1",
+            "ei:1",
+            "EIP155:1",
+            "eip155:1:2",
+            "eip155 :1",
+            "averylongnamespace:1",
+            "eip155:000000000019d6689c085ae165831e93ab",
+        ] {
+            assert!(Caip2ChainId::from_str(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn serde_roundtrip() {
+        let chain_id: Caip2ChainId = serde_json::from_str("\"eip155:1\"").unwrap();
+        assert_eq!(serde_json::to_string(&chain_id).unwrap(), "\"eip155:1\"");
+        assert!(serde_json::from_str::<Caip2ChainId>("\"mainnet\"").is_err());
+    }
+}
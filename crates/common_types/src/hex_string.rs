@@ -8,13 +8,13 @@ use diesel::expression::AsExpression;
 use diesel::pg::Pg;
 use diesel::serialize::ToSql;
 use diesel::sql_types;
-use hex::FromHex;
 use quickcheck::Arbitrary;
 use serde::{Deserialize, Serialize};
 
 /// A [`serde`], [`diesel`], and [`async_graphql`]-compatible wrapper around a
-/// hex-encoded byte sequence (of arbitrary length) with `0x` prefix. Parsing
-/// and deserializing from hex strings without the `0x` prefix is also allowed.
+/// hex-encoded byte sequence with `0x` prefix. Parsing and deserializing from
+/// hex strings without the `0x` prefix is also allowed, and fixed-length byte
+/// arrays only accept hex strings of the exact length.
 ///
 /// You should generally try to avoid using this type directly, and instead
 /// alias it to something more descriptive for its intended use case, possibly
@@ -48,10 +48,80 @@ impl<T: ToOwned> HexString<T> {
     }
 }
 
+impl HexString<Vec<u8>> {
+    /// Parses a block hash that must decode to exactly 32 bytes. Unlike
+    /// [`FromStr`], which accepts any length, truncated or over-long values are
+    /// rejected. See [`HexString::parse_block_hash`] for the accepted encodings
+    /// and more detailed errors.
+    pub fn try_from_exact_32(s: &str) -> Result<Self, &'static str> {
+        Self::parse_block_hash(s).map_err(|_| "expected a block hash of exactly 32 bytes")
+    }
+
+    /// Parses a block hash, which must decode to exactly 32 bytes. Depending
+    /// on the chain, `graph-node` reports block hashes as hex strings (with
+    /// or without the `0x` prefix), base58 strings (e.g. NEAR and Solana) or
+    /// padded base64 strings. They're all normalized to the same bytes, so
+    /// that a block has a single hash no matter how it was encoded.
+    pub fn parse_block_hash(s: &str) -> Result<Self, String> {
+        use cid::multibase::Base;
+
+        let (encoding, bytes) = match s.strip_prefix("0x") {
+            Some(hex) => ("hex", hex::decode(hex).map_err(|e| e.to_string())),
+            None if s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                ("hex", hex::decode(s).map_err(|e| e.to_string()))
+            }
+            None if s.ends_with('=') || s.contains(['+', '/']) => (
+                "base64",
+                Base::Base64Pad.decode(s).map_err(|e| e.to_string()),
+            ),
+            None => match Base::Base58Btc.decode(s) {
+                Ok(bytes) => ("base58", Ok(bytes)),
+                Err(_) => {
+                    return Err(
+                        "unrecognized block hash encoding, expected hex, base58 or base64"
+                            .to_string(),
+                    )
+                }
+            },
+        };
+        let bytes = bytes.map_err(|e| format!("invalid {} block hash: {}", encoding, e))?;
+        if bytes.len() != 32 {
+            return Err(format!(
+                "expected a {} block hash of 32 bytes, got {}",
+                encoding,
+                bytes.len()
+            ));
+        }
+
+        Ok(HexString(bytes))
+    }
+}
+
+/// Byte sequences that a [`HexString`] can wrap.
+pub trait HexBytes: Sized {
+    /// Fails with a descriptive error if `bytes` doesn't have the right length.
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, String>;
+}
+
+impl HexBytes for Vec<u8> {
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, String> {
+        Ok(bytes)
+    }
+}
+
+impl<const N: usize> HexBytes for [u8; N] {
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, String> {
+        let len = bytes.len();
+        bytes
+            .try_into()
+            .map_err(|_| format!("expected {} bytes, got {}", N, len))
+    }
+}
+
 #[async_graphql::Scalar]
 impl<T> async_graphql::ScalarType for HexString<T>
 where
-    T: AsRef<[u8]> + FromHex + Send + Sync,
+    T: AsRef<[u8]> + HexBytes + Send + Sync,
 {
     fn parse(value: async_graphql::Value) -> async_graphql::InputValueResult<Self> {
         Ok(Deserialize::deserialize(value.into_json()?)?)
@@ -77,19 +147,18 @@ impl<T: AsRef<[u8]>> Serialize for HexString<T> {
     }
 }
 
-impl<T: FromHex> FromStr for HexString<T> {
-    type Err = &'static str;
+impl<T: HexBytes> FromStr for HexString<T> {
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         // The `0x` prefix is optional.
         let stripped = s.strip_prefix("0x").unwrap_or(s);
-        FromHex::from_hex(stripped)
-            .map(Self)
-            .map_err(|_| "invalid hex string")
+        let bytes = hex::decode(stripped).map_err(|e| format!("invalid hex string: {}", e))?;
+        T::from_bytes(bytes).map(Self)
     }
 }
 
-impl<'a, T: FromHex> Deserialize<'a> for HexString<T> {
+impl<'a, T: HexBytes> Deserialize<'a> for HexString<T> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'a>,
@@ -133,9 +202,45 @@ where
     }
 }
 
-impl<T: Arbitrary> Arbitrary for HexString<T> {
+#[cfg(feature = "sqlite")]
+impl<T> ToSql<sql_types::Binary, diesel::sqlite::Sqlite> for HexString<T>
+where
+    T: AsRef<[u8]> + Debug,
+{
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, diesel::sqlite::Sqlite>,
+    ) -> diesel::serialize::Result {
+        ToSql::<sql_types::Binary, diesel::sqlite::Sqlite>::to_sql(self.0.as_ref(), out)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl<T> FromSql<sql_types::Binary, diesel::sqlite::Sqlite> for HexString<T>
+where
+    T: TryFrom<Vec<u8>>,
+    T::Error: Debug,
+{
+    fn from_sql(
+        bytes: <diesel::sqlite::Sqlite as Backend>::RawValue<'_>,
+    ) -> diesel::deserialize::Result<Self> {
+        T::try_from(FromSql::<sql_types::Binary, diesel::sqlite::Sqlite>::from_sql(bytes)?)
+            .map(HexString)
+            .map_err(|e| anyhow::anyhow!("{:?}", e).into())
+    }
+}
+
+impl Arbitrary for HexString<Vec<u8>> {
     fn arbitrary(g: &mut quickcheck::Gen) -> Self {
-        Self(T::arbitrary(g))
+        Self(Vec::arbitrary(g))
+    }
+}
+
+// `quickcheck` doesn't implement `Arbitrary` for arrays, which PoIs and
+// addresses are made of.
+impl<const N: usize> Arbitrary for HexString<[u8; N]> {
+    fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+        Self(std::array::from_fn(|_| u8::arbitrary(g)))
     }
 }
 
@@ -174,6 +279,51 @@ mod tests {
         assert_eq!(hex_string.to_string(), "0xdeadbeef");
     }
 
+    #[test]
+    fn block_hashes_are_exactly_32_bytes() {
+        let hash = format!("0x{}", "ab".repeat(32));
+        let hex_string = HexString::parse_block_hash(&hash).unwrap();
+        assert_eq!(hex_string.to_string(), hash);
+
+        assert!(HexString::parse_block_hash(&hash[..64]).is_err());
+        assert!(HexString::parse_block_hash(&format!("{}ab", hash)).is_err());
+        assert!(HexString::parse_block_hash("0x").is_err());
+        assert_eq!(HexString::try_from_exact_32(&hash), Ok(hex_string));
+        assert!(HexString::try_from_exact_32(&hash[..64]).is_err());
+    }
+
+    #[quickcheck]
+    fn block_hash_encodings_roundtrip(hash: HexString<[u8; 32]>) -> bool {
+        use cid::multibase::Base;
+
+        let encodings = [
+            hash.to_string(),
+            hex::encode(hash.0),
+            Base::Base58Btc.encode(hash.0),
+            Base::Base64Pad.encode(hash.0),
+        ];
+        encodings.iter().all(|encoded| {
+            HexString::parse_block_hash(encoded).as_ref() == Ok(&HexString(hash.0.to_vec()))
+        })
+    }
+
+    #[test]
+    fn block_hash_encoding_errors() {
+        let parse = |s: &str| HexString::parse_block_hash(s).unwrap_err();
+
+        assert_eq!(
+            parse(&cid::multibase::Base::Base58Btc.encode([1; 31])),
+            "expected a base58 block hash of 32 bytes, got 31"
+        );
+        assert!(parse("0xzz").starts_with("invalid hex block hash"));
+        assert!(parse("not/base64=").starts_with("invalid base64 block hash"));
+        // `0`, `O`, `I` and `l` aren't part of the base58 alphabet.
+        assert_eq!(
+            parse("0OIl"),
+            "unrecognized block hash encoding, expected hex, base58 or base64"
+        );
+    }
+
     #[quickcheck]
     fn from_str_roundtrip(hex_string: HexString<Vec<u8>>) -> bool {
         let string = hex_string.to_string();
@@ -181,4 +331,59 @@ mod tests {
 
         hex_string == hex_string2
     }
+
+    /// PoIs, unlike [`HexString<Vec<u8>>`], have a fixed length.
+    fn poi_bytes(mut bytes: Vec<u8>) -> HexString<[u8; 32]> {
+        bytes.resize(32, 0);
+        HexString(bytes.try_into().unwrap())
+    }
+
+    #[quickcheck]
+    fn fixed_length_from_str_roundtrip(bytes: Vec<u8>) -> bool {
+        let hex_string = poi_bytes(bytes);
+        let prefixed: HexString<[u8; 32]> = hex_string.to_string().parse().unwrap();
+        let unprefixed: HexString<[u8; 32]> = hex::encode(hex_string.0).parse().unwrap();
+
+        hex_string == prefixed && hex_string == unprefixed
+    }
+
+    #[quickcheck]
+    fn fixed_length_async_graphql_roundtrip(bytes: Vec<u8>) -> bool {
+        let hex_string = poi_bytes(bytes);
+        let unprefixed = async_graphql::Value::String(hex::encode(hex_string.0));
+        let hex_string2: HexString<[u8; 32]> =
+            async_graphql::ScalarType::parse(hex_string.to_value()).unwrap();
+        let hex_string3: HexString<[u8; 32]> =
+            async_graphql::ScalarType::parse(unprefixed).unwrap();
+
+        hex_string == hex_string2 && hex_string == hex_string3
+    }
+
+    #[quickcheck]
+    fn fixed_length_serde_roundtrip(hex_string: HexString<[u8; 32]>) -> bool {
+        let json = serde_json::to_string(&hex_string).unwrap();
+        let hex_string2: HexString<[u8; 32]> = serde_json::from_str(&json).unwrap();
+
+        hex_string == hex_string2
+    }
+
+    #[test]
+    fn fixed_length_errors() {
+        let parse = |s: &str| s.parse::<HexString<[u8; 32]>>();
+
+        assert_eq!(
+            parse(&"ab".repeat(31)).unwrap_err(),
+            "expected 32 bytes, got 31"
+        );
+        assert_eq!(
+            parse(&format!("0x{}", "ab".repeat(33))).unwrap_err(),
+            "expected 32 bytes, got 33"
+        );
+        assert!(parse("0xabc")
+            .unwrap_err()
+            .starts_with("invalid hex string"));
+        assert!(parse(&"zz".repeat(32))
+            .unwrap_err()
+            .starts_with("invalid hex string"));
+    }
 }
@@ -43,6 +43,10 @@ pub struct IndexersQuery {
     pub address: Option<IndexerAddress>,
     /// Upper limit on the number of shown results.
     pub limit: Option<u16>,
+    /// Number of results to skip, for pagination.
+    pub offset: Option<u32>,
+    /// Whether to include indexers that haven't been seen for a while.
+    pub include_inactive: bool,
 }
 
 /// A block range, specified by optional start and end block numbers.
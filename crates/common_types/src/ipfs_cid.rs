@@ -11,6 +11,10 @@ use serde::{Deserialize, Serialize};
 
 /// A [`serde`], [`diesel`], and [`async_graphql`]-compatible type definition
 /// for IPFS CIDs and subgraph deployment IDs.
+///
+/// CIDs are canonicalized when parsed: CIDv1s that have a CIDv0 equivalent
+/// (e.g. `bafy...` deployment IDs) are converted to it (`Qm...`), so that both
+/// forms of the same CID are equal and displayed the same way.
 #[derive(
     Debug,
     Clone,
@@ -22,11 +26,33 @@ use serde::{Deserialize, Serialize};
     AsExpression,
     FromSqlRow,
     derive_more::Display,
-    derive_more::FromStr,
 )]
 #[diesel(sql_type = sql_types::Text)]
+#[serde(from = "cid::Cid")]
 pub struct IpfsCid(cid::Cid);
 
+impl From<cid::Cid> for IpfsCid {
+    fn from(cid: cid::Cid) -> Self {
+        // Only DAG-PB CIDs with SHA2-256 hashes can be expressed as CIDv0.
+        const DAG_PB: u64 = 0x70;
+
+        match cid.version() {
+            cid::Version::V1 if cid.codec() == DAG_PB => {
+                Self(cid::Cid::new_v0(*cid.hash()).unwrap_or(cid))
+            }
+            _ => Self(cid),
+        }
+    }
+}
+
+impl FromStr for IpfsCid {
+    type Err = cid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        cid::Cid::from_str(s).map(Self::from)
+    }
+}
+
 #[async_graphql::Scalar]
 impl async_graphql::ScalarType for IpfsCid {
     fn parse(value: async_graphql::Value) -> async_graphql::InputValueResult<Self> {
@@ -34,8 +60,7 @@ impl async_graphql::ScalarType for IpfsCid {
             return Err(async_graphql::InputValueError::expected_type(value));
         };
 
-        let cid = cid::Cid::from_str(&string)?;
-        Ok(IpfsCid(cid))
+        Ok(IpfsCid::from_str(&string)?)
     }
 
     fn to_value(&self) -> async_graphql::Value {
@@ -55,13 +80,34 @@ impl ToSql<sql_types::Text, Pg> for IpfsCid {
 impl FromSql<sql_types::Text, Pg> for IpfsCid {
     fn from_sql(bytes: <Pg as Backend>::RawValue<'_>) -> diesel::deserialize::Result<Self> {
         let s = String::from_sql(bytes)?;
-        Ok(IpfsCid(cid::Cid::from_str(&s)?))
+        Ok(IpfsCid::from_str(&s)?)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl ToSql<sql_types::Text, diesel::sqlite::Sqlite> for IpfsCid {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, diesel::sqlite::Sqlite>,
+    ) -> diesel::serialize::Result {
+        out.set_value(self.to_string());
+        Ok(diesel::serialize::IsNull::No)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl FromSql<sql_types::Text, diesel::sqlite::Sqlite> for IpfsCid {
+    fn from_sql(
+        bytes: <diesel::sqlite::Sqlite as Backend>::RawValue<'_>,
+    ) -> diesel::deserialize::Result<Self> {
+        let s = <String as FromSql<sql_types::Text, diesel::sqlite::Sqlite>>::from_sql(bytes)?;
+        Ok(IpfsCid::from_str(&s)?)
     }
 }
 
 impl Arbitrary for IpfsCid {
     fn arbitrary(g: &mut quickcheck::Gen) -> Self {
-        Self(cid::Cid::arbitrary(g))
+        Self::from(cid::Cid::arbitrary(g))
     }
 }
 
@@ -103,4 +149,26 @@ mod tests {
 
         assert_eq!(ipfs_id.to_string(), deployment_id);
     }
+
+    #[test]
+    fn cid_v1_equals_cid_v0() {
+        let v0 = "QmNY7gDNXHECV8SXoEY7hbfg4BX1aDMxTBDiFuG4huaSGA";
+        let v1 = "bafybeiac57qysyjtvsycky2uhna4nuqyks3ilgkyilkyblk6hdiwljvhtm";
+
+        let from_v0 = IpfsCid::from_str(v0).unwrap();
+        let from_v1 = IpfsCid::from_str(v1).unwrap();
+        assert_eq!(from_v0, from_v1);
+        assert_eq!(from_v1.to_string(), v0);
+
+        let async_graphql_value = async_graphql::Value::String(v1.to_string());
+        let from_graphql: IpfsCid = ScalarType::parse(async_graphql_value).unwrap();
+        assert_eq!(from_graphql, from_v0);
+    }
+
+    #[quickcheck]
+    fn canonicalization_is_idempotent(ipfs_cid: IpfsCid) -> bool {
+        let ipfs_cid2 = IpfsCid::from(ipfs_cid.0.into_v1().unwrap_or(ipfs_cid.0));
+
+        ipfs_cid == ipfs_cid2
+    }
 }
@@ -3,11 +3,13 @@
 //! A few of these are shared with database models as well. Should we keep them
 //! separate? It would be cleaner, but at the cost of some code duplication.
 
+mod caip2;
 mod hex_string;
 pub mod inputs;
 mod ipfs_cid;
 
 use async_graphql::*;
+pub use caip2::{Caip2ChainId, InvalidCaip2ChainId};
 use chrono::NaiveDateTime;
 pub use divergence_investigation::*;
 pub use hex_string::HexString;
@@ -44,6 +46,45 @@ mod divergence_investigation {
         Complete,
     }
 
+    /// Where a divergence investigation report stands in the triage done by
+    /// the people operating Graphix, e.g. after telling whether a divergence
+    /// is a known `graph-node` bug or an indexer's fault.
+    #[derive(Debug, Default, Copy, Clone, Enum, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum DivergenceTriageStatus {
+        /// Nobody has looked into the report yet. All reports start as new.
+        #[default]
+        New,
+        /// Someone is looking into the report.
+        Acknowledged,
+        /// The cause of the divergence is known and has been dealt with.
+        Resolved,
+        /// There was no actual divergence, e.g. because of a misbehaving
+        /// indexer endpoint.
+        FalsePositive,
+    }
+
+    impl DivergenceTriageStatus {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Self::New => "new",
+                Self::Acknowledged => "acknowledged",
+                Self::Resolved => "resolved",
+                Self::FalsePositive => "false_positive",
+            }
+        }
+
+        /// The inverse of [`DivergenceTriageStatus::as_str`]. Unknown values
+        /// are treated as new.
+        pub fn from_stored(s: &str) -> Self {
+            match s {
+                "acknowledged" => Self::Acknowledged,
+                "resolved" => Self::Resolved,
+                "false_positive" => Self::FalsePositive,
+                _ => Self::New,
+            }
+        }
+    }
+
     /// A divergence investigation report contains all information that pertains to a divergence
     /// investigation, including the results of its bisection run(s).
     #[derive(Debug, Serialize, SimpleObject, Deserialize)]
@@ -58,11 +99,51 @@ mod divergence_investigation {
         /// divergence investigation. If the investigation is still in progress,
         /// this list may be incomplete.
         pub bisection_runs: Vec<BisectionRunReport>,
+        /// For investigations of a whole subgraph deployment, what was found
+        /// out about each pair of groups of indexers that disagree with each
+        /// other. Empty for investigations of a list of PoIs.
+        #[serde(default)]
+        pub pairwise_findings: Vec<PairwiseDivergenceFinding>,
+        /// Set if an investigation of a whole subgraph deployment found that
+        /// all indexers agree on it, in which case nothing was bisected.
+        #[serde(default)]
+        pub no_divergence_found: bool,
         /// If the divergence investigation failed altogether, this field
         /// contains the error message. Please note that specific bisection runs
         /// may also fail, in which case the error message will be in the
         /// `error` field of the corresponding `BisectionRunReport`.
         pub error: Option<String>,
+        /// Where the report stands in triage. Not part of the stored report,
+        /// see `updateDivergenceStatus`.
+        #[serde(skip)]
+        pub triage_status: DivergenceTriageStatus,
+        /// The note left with the latest triage status change, if any.
+        #[serde(skip)]
+        pub triage_note: Option<String>,
+    }
+
+    /// When Graphix investigates a whole subgraph deployment, it groups
+    /// indexers by their latest PoI and bisects the first indexer of each
+    /// group against the first indexer of every other group. This is what it
+    /// found out about one such pair of groups.
+    #[derive(Debug, Clone, Serialize, SimpleObject, Deserialize)]
+    pub struct PairwiseDivergenceFinding {
+        /// The PoI of the first group of indexers.
+        pub poi1: PoiBytes,
+        /// The PoI of the second group of indexers.
+        pub poi2: PoiBytes,
+        /// The indexers that reported the first PoI. The first one of them
+        /// was bisected.
+        pub indexers1: Vec<IndexerAddress>,
+        /// The indexers that reported the second PoI. The first one of them
+        /// was bisected.
+        pub indexers2: Vec<IndexerAddress>,
+        /// The UUID of the bisection run between the two groups, which can be
+        /// found among the report's `bisectionRuns`.
+        pub bisection_run_uuid: Uuid,
+        /// The first block at which the PoIs of the two groups differ, unless
+        /// the bisection run failed.
+        pub first_diverging_block: Option<i64>,
     }
 
     #[derive(Debug, Clone, Serialize, SimpleObject, Deserialize)]
@@ -103,6 +184,12 @@ mod divergence_investigation {
         pub poi1: PoiBytes,
         /// The second PoI that was used to start the bisection run.
         pub poi2: PoiBytes,
+        /// The name of the indexer that served the first PoI, if known.
+        #[serde(default)]
+        pub indexer1_display_name: Option<String>,
+        /// The name of the indexer that served the second PoI, if known.
+        #[serde(default)]
+        pub indexer2_display_name: Option<String>,
         /// The lower and upper block bounds inside which the bisection run
         /// occurred.
         pub divergence_block_bounds: DivergenceBlockBounds,
@@ -149,6 +236,8 @@ pub struct GraphNodeCollectedVersion {
     pub commit: Option<String>,
     pub error_response: Option<String>,
     pub collected_at: NaiveDateTime,
+    /// `None` if graph-node doesn't list the features it supports.
+    pub supported_features: Option<Vec<String>>,
 }
 
 #[derive(SimpleObject)]
@@ -0,0 +1,206 @@
+//! Backfills of historical PoIs for a subgraph deployment, requested through
+//! the GraphQL API and handled one at a time in the background.
+
+use std::time::Duration;
+
+use futures::{stream, StreamExt};
+use graphix_indexer_client::{IndexingStatus, ProofOfIndexing, SubgraphDeployment};
+use graphix_lib::config::PoiBackfillConfig;
+use graphix_lib::indexing_loop::{backfill_poi_request_count, backfill_poi_requests};
+use graphix_lib::metrics;
+use graphix_store::models::PoiBackfillJob;
+use graphix_store::{PoiLiveness, Store};
+use tokio::sync::watch;
+use tracing::{debug, error, info};
+
+/// How many PoI requests are sent between two progress updates.
+const PROGRESS_UPDATE_INTERVAL: usize = 100;
+
+pub async fn handle_poi_backfill_jobs(
+    store: &Store,
+    mut indexing_statuses: watch::Receiver<Vec<IndexingStatus>>,
+    config: &PoiBackfillConfig,
+) -> anyhow::Result<()> {
+    // Without indexing statuses, we wouldn't know which indexers to query.
+    indexing_statuses.changed().await?;
+
+    let requeued_jobs = store.requeue_in_progress_poi_backfill_jobs().await?;
+    if requeued_jobs > 0 {
+        info!(requeued_jobs, "Requeued interrupted PoI backfill jobs");
+    }
+
+    loop {
+        debug!("Checking for new PoI backfill jobs");
+
+        let Some(job) = store.claim_next_poi_backfill_job().await? else {
+            tokio::time::sleep(Duration::from_secs(3)).await;
+            continue;
+        };
+        info!(
+            uuid = %job.uuid,
+            deployment = %job.deployment,
+            from_block = job.from_block,
+            to_block = job.to_block,
+            block_interval = job.block_interval,
+            "Starting PoI backfill job"
+        );
+
+        // Indexing statuses tell us which indexers can provide PoIs for which
+        // blocks. They're taken as of the start of the job.
+        let indexing_statuses = indexing_statuses.borrow().clone();
+        let result = run_poi_backfill_job(store, &job, &indexing_statuses, config).await;
+
+        if let Err(err) = &result {
+            error!(uuid = %job.uuid, error = %err, "PoI backfill job failed");
+        } else {
+            info!(uuid = %job.uuid, "PoI backfill job completed");
+        }
+        store
+            .finish_poi_backfill_job(&job.uuid, result.err().map(|err| err.to_string()))
+            .await?;
+    }
+}
+
+async fn run_poi_backfill_job(
+    store: &Store,
+    job: &PoiBackfillJob,
+    indexing_statuses: &[IndexingStatus],
+    config: &PoiBackfillConfig,
+) -> anyhow::Result<()> {
+    let deployment = SubgraphDeployment::new(job.deployment.clone());
+    let from_block = job.from_block.try_into()?;
+    let to_block = job.to_block.try_into()?;
+    let block_interval = job.block_interval.try_into()?;
+
+    // Checked before planning the requests, which would otherwise take up
+    // memory in proportion to the size of the backfill.
+    let requests_total = backfill_poi_request_count(
+        indexing_statuses,
+        &deployment,
+        from_block,
+        to_block,
+        block_interval,
+    );
+    anyhow::ensure!(
+        requests_total <= config.max_requests_per_job as u64,
+        "the backfill needs {} PoI requests, more than the limit of {}",
+        requests_total,
+        config.max_requests_per_job
+    );
+    let requests = backfill_poi_requests(
+        indexing_statuses,
+        &deployment,
+        from_block,
+        to_block,
+        block_interval,
+    );
+    let requests_total = requests.len();
+
+    let mut requests_sent = 0;
+    let mut pois_written = 0;
+    store
+        .update_poi_backfill_progress(&job.uuid, requests_total, 0, 0)
+        .await?;
+
+    for requests in requests.chunks(PROGRESS_UPDATE_INTERVAL) {
+        let pois: Vec<ProofOfIndexing> = stream::iter(requests.iter().cloned())
+            .map(|(indexer, request)| indexer.proofs_of_indexing(vec![request]))
+            .buffer_unordered(config.max_concurrent_requests.max(1))
+            .concat()
+            .await;
+
+        requests_sent += requests.len();
+        let pois_count = pois.len();
+        store.write_pois(pois, PoiLiveness::NotLive).await?;
+        pois_written += pois_count;
+        metrics()
+            .pois_written
+            .with_label_values(&["not_live"])
+            .inc_by(pois_count as u64);
+        store
+            .update_poi_backfill_progress(&job.uuid, requests_total, requests_sent, pois_written)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use graphix_common_types::inputs::PoisQuery;
+    use graphix_indexer_client::IndexerClient;
+    use graphix_lib::test_utils::gen::{gen_blocks, gen_deployments};
+    use graphix_lib::test_utils::mocks::{
+        DeploymentDetails, MockIndexerBuilder, PartialProofOfIndexing,
+    };
+    use graphix_lib::test_utils::store::EmptyStoreForTesting;
+    use graphix_store::models::NewNetwork;
+    use testcontainers::clients::Cli;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn backfills_span_several_blocks() {
+        let docker = Cli::default();
+        let store = EmptyStoreForTesting::new(&docker).await.unwrap();
+        store
+            .create_network(&NewNetwork {
+                name: "mainnet".to_string(),
+                caip2: None,
+            })
+            .await
+            .unwrap();
+
+        let deployment = gen_deployments().remove(0);
+        let blocks = gen_blocks();
+        let indexer = |name: &str, earliest_block: usize| {
+            MockIndexerBuilder::new(name)
+                .deployment(DeploymentDetails {
+                    deployment: deployment.clone(),
+                    network: "mainnet".into(),
+                    latest_block: blocks[9].clone(),
+                    canonical_pois: blocks
+                        .iter()
+                        .map(|block| PartialProofOfIndexing {
+                            block: block.clone(),
+                            proof_of_indexing: [1; 32].into(),
+                        })
+                        .collect(),
+                    earliest_block_num: blocks[earliest_block].number,
+                })
+                .client()
+        };
+        // `pruned` can't provide PoIs below block 4.
+        let indexers = [indexer("full", 0), indexer("pruned", 4)];
+        store.write_indexers(&indexers).await.unwrap();
+        let mut indexing_statuses = vec![];
+        for indexer in &indexers {
+            indexing_statuses.extend(indexer.clone().indexing_statuses().await.unwrap());
+        }
+
+        let job = store
+            .request_poi_backfill(&deployment.parse().unwrap(), 0, 8, 2, 1000)
+            .await
+            .unwrap();
+        run_poi_backfill_job(
+            &store,
+            &job,
+            &indexing_statuses,
+            &PoiBackfillConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let rows = store
+            .poi_export_batch(&PoisQuery::default(), None, None, 100)
+            .await
+            .unwrap();
+        let mut block_numbers: Vec<_> = rows.iter().map(|row| row.block_number).collect();
+        block_numbers.sort();
+        assert_eq!(block_numbers, vec![0, 2, 4, 4, 6, 6, 8, 8]);
+
+        let job = store.poi_backfill_job(&job.uuid).await.unwrap().unwrap();
+        assert_eq!(job.requests_total, Some(8));
+        assert_eq!(job.pois_written, 8);
+    }
+}
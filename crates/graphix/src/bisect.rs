@@ -1,22 +1,25 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::anyhow;
+use graphix_common_types::inputs::IndexersQuery;
 use graphix_common_types::{
     BisectionReport, BisectionRunReport, DivergenceBlockBounds, DivergenceInvestigationReport,
-    DivergenceInvestigationStatus, DivergingBlock as DivergentBlock, HexString, PartialBlock,
-    PoiBytes,
+    DivergenceInvestigationStatus, DivergingBlock as DivergentBlock, HexString, IpfsCid,
+    PairwiseDivergenceFinding, PartialBlock, PoiBytes,
 };
 use graphix_indexer_client::{
-    IndexerClient, IndexerId, PoiRequest, ProofOfIndexing, SubgraphDeployment,
+    BlockPointer, IndexerClient, IndexerId, PoiRangeRequest, ProofOfIndexing, SubgraphDeployment,
 };
 use graphix_lib::graphql_api::api_types::{self, Indexer};
 use graphix_lib::graphql_api::ApiSchemaContext;
-use graphix_store::models::DivergenceInvestigationRequest;
+use graphix_store::models::{DivergenceInvestigationRequest, Poi};
 use graphix_store::Store;
 use thiserror::Error;
 use tokio::sync::watch;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, info_span, Instrument};
 use uuid::Uuid;
 
 use crate::utils::unordered_pairs_combinations;
@@ -78,7 +81,7 @@ impl PoiBisectingContext {
         &self.poi1_data.deployment
     }
 
-    pub async fn start(mut self) -> (BisectionRunReport, u64) {
+    pub async fn start(mut self, store: &Store) -> (BisectionRunReport, u64) {
         let deployment: api_types::SubgraphDeployment = self.deployment().clone();
 
         let indexer1 = self.poi1_data.indexer_client.clone();
@@ -90,67 +93,224 @@ impl PoiBisectingContext {
             "Starting Poi bisecting"
         );
 
-        // The range of block numbers that we're investigating is bounded
-        // inclusively both below and above. The bisection algorithm will
-        // continue searching until only a single block number is left in the
-        // range.
-        let mut bounds = 0..=self.poi1_data.block.number();
+        let bisection = bisect_pois(
+            indexer1,
+            indexer2,
+            &SubgraphDeployment(deployment.cid().to_string()),
+            0..=self.poi1_data.block.number(),
+            Some(store),
+        )
+        .await;
 
-        loop {
-            let block_number = (bounds.start() + bounds.end()) / 2;
+        self.report.bisects = bisection.bisects;
+        if let Some(err) = bisection.error {
+            self.report.error = Some(err.to_string());
+        }
+        self.report.divergence_block_bounds.lower_bound.number = bisection.last_agreeing_block as _;
+        self.report.divergence_block_bounds.upper_bound.number =
+            bisection.first_diverging_block as _;
 
-            debug!(
-                bisection_id = %self.bisection_id,
-                deployment = ?deployment.cid(),
-                lower_bound = ?bounds.start(),
-                upper_bound = ?bounds.end(),
-                block_number,
-                "Bisecting Pois"
-            );
+        (self.report, bisection.first_diverging_block)
+    }
+}
 
-            let poi1 = indexer1
-                .clone()
-                .proof_of_indexing(PoiRequest {
-                    deployment: SubgraphDeployment(deployment.cid().to_string()),
-                    block_number,
-                })
-                .await;
-            let poi2 = indexer2
-                .clone()
-                .proof_of_indexing(PoiRequest {
-                    deployment: SubgraphDeployment(deployment.cid().to_string()),
-                    block_number,
-                })
-                .await;
+/// The outcome of [`bisect_pois`].
+pub struct Bisection {
+    pub bisects: Vec<BisectionReport>,
+    pub last_agreeing_block: u64,
+    pub first_diverging_block: u64,
+    /// Why the search stopped before the bounds were adjacent, if it did.
+    pub error: Option<DivergenceInvestigationError>,
+}
 
-            let bisect = BisectionReport {
-                block: PartialBlock {
-                    number: block_number as _,
-                    hash: None,
-                },
-                indexer1_response: format!("{:?}", poi1),
-                indexer2_response: format!("{:?}", poi2),
-            };
-            self.report.bisects.push(bisect);
-
-            if poi1.ok() == poi2.ok() {
-                bounds = block_number..=*bounds.end();
-                self.report.divergence_block_bounds.lower_bound.number = block_number as _;
-            } else {
-                bounds = *bounds.start()..=block_number;
-                self.report.divergence_block_bounds.upper_bound.number = block_number as _;
-            }
+/// Searches for the first block at which two indexers' PoIs for `deployment`
+/// diverge. The PoIs are assumed to agree at the start of `bounds` and to
+/// differ at its end. The range of block numbers that we're investigating is
+/// bounded inclusively both below and above, and the search continues until
+/// the bounds are adjacent, or until the PoIs at a block can't be compared
+/// because a query failed. PoIs that are already in `store` are used instead
+/// of querying the indexers again.
+pub async fn bisect_pois(
+    indexer1: Arc<dyn IndexerClient>,
+    indexer2: Arc<dyn IndexerClient>,
+    deployment: &SubgraphDeployment,
+    bounds: RangeInclusive<u64>,
+    store: Option<&Store>,
+) -> Bisection {
+    let mut bisects = vec![];
+    let mut error = None;
+    let (mut lower_bound, mut upper_bound) = bounds.into_inner();
+    // PoIs that were fetched before the steps that need them.
+    let mut probes = HashMap::new();
+
+    while let Some(block_number) = next_probe(lower_bound, upper_bound) {
+        debug!(
+            deployment = %deployment.as_str(),
+            lower_bound,
+            upper_bound,
+            block_number,
+            "Bisecting Pois"
+        );
 
-            if bounds.start() == bounds.end() {
+        if !probes.contains_key(&block_number) {
+            // Whichever way this step goes, the next one probes one of these
+            // blocks, so both are speculatively fetched along with this one.
+            // That way, only every other step has to wait for the indexers.
+            let block_numbers: Vec<u64> = [block_number]
+                .into_iter()
+                .chain(next_probe(lower_bound, block_number))
+                .chain(next_probe(block_number, upper_bound))
+                .collect();
+            probes
+                .extend(probe_pois(store, &indexer1, &indexer2, deployment, &block_numbers).await);
+        }
+        let (poi1, poi2) = probes
+            .remove(&block_number)
+            .expect("the PoIs of every step are fetched before it");
+
+        bisects.push(BisectionReport {
+            block: PartialBlock {
+                number: block_number as _,
+                hash: None,
+            },
+            indexer1_response: format!("{:?}", poi1),
+            indexer2_response: format!("{:?}", poi2),
+        });
+
+        // Only compare the PoI bytes; the PoIs themselves can never be equal,
+        // as they come from different indexers. A failed query says nothing
+        // about whether the indexers agree, so the search ends there.
+        let (poi1, poi2) = match (poi1, poi2) {
+            (Ok(poi1), Ok(poi2)) => (poi1.proof_of_indexing, poi2.proof_of_indexing),
+            (Err(err), _) | (_, Err(err)) => {
+                error = Some(DivergenceInvestigationError::CouldNotCompare {
+                    block_number,
+                    reason: format!("{:#}", err),
+                });
                 break;
             }
+        };
+        if poi1 == poi2 {
+            lower_bound = block_number;
+        } else {
+            upper_bound = block_number;
         }
+    }
+
+    Bisection {
+        bisects,
+        last_agreeing_block: lower_bound,
+        first_diverging_block: upper_bound,
+        error,
+    }
+}
+
+/// The block that bisecting between `lower_bound` and `upper_bound` probes,
+/// unless they're adjacent already.
+fn next_probe(lower_bound: u64, upper_bound: u64) -> Option<u64> {
+    if upper_bound.saturating_sub(lower_bound) > 1 {
+        Some((lower_bound + upper_bound) / 2)
+    } else {
+        None
+    }
+}
+
+type PoiProbe = (
+    anyhow::Result<ProofOfIndexing>,
+    anyhow::Result<ProofOfIndexing>,
+);
+
+/// Fetches the PoIs of both indexers at all of `block_numbers`. All of them
+/// are queried at once, so that this takes as long as the slowest query
+/// rather than all of them.
+async fn probe_pois(
+    store: Option<&Store>,
+    indexer1: &Arc<dyn IndexerClient>,
+    indexer2: &Arc<dyn IndexerClient>,
+    deployment: &SubgraphDeployment,
+    block_numbers: &[u64],
+) -> Vec<(u64, PoiProbe)> {
+    let fetch = |indexer: &Arc<dyn IndexerClient>| {
+        let indexer = indexer.clone();
+        async move {
+            let started = Instant::now();
+            let pois = futures::future::join_all(block_numbers.iter().map(|&block_number| {
+                proof_of_indexing(store, indexer.clone(), deployment, block_number)
+            }))
+            .await;
+            (pois, started.elapsed())
+        }
+    };
 
-        let diverging_block = *bounds.start();
-        (self.report, diverging_block)
+    let started = Instant::now();
+    let ((pois1, duration1), (pois2, duration2)) = futures::join!(fetch(indexer1), fetch(indexer2));
+    debug!(
+        deployment = %deployment.as_str(),
+        ?block_numbers,
+        indexer1 = %indexer1.address_string(),
+        indexer1_duration_ms = duration1.as_millis() as u64,
+        indexer2 = %indexer2.address_string(),
+        indexer2_duration_ms = duration2.as_millis() as u64,
+        step_duration_ms = started.elapsed().as_millis() as u64,
+        "Fetched Pois of bisection step"
+    );
+
+    block_numbers
+        .iter()
+        .copied()
+        .zip(pois1.into_iter().zip(pois2))
+        .collect()
+}
+
+/// Returns the PoI of `indexer` for `deployment` at `block_number`, from
+/// `store` if Graphix already collected it, or else from the indexer.
+async fn proof_of_indexing(
+    store: Option<&Store>,
+    indexer: Arc<dyn IndexerClient>,
+    deployment: &SubgraphDeployment,
+    block_number: u64,
+) -> anyhow::Result<ProofOfIndexing> {
+    if let (Some(store), Ok(cid)) = (store, deployment.as_str().parse::<IpfsCid>()) {
+        match store.poi(&indexer.address(), &cid, block_number).await {
+            Ok(Some((poi, _live))) => {
+                return Ok(ProofOfIndexing {
+                    indexer,
+                    deployment: deployment.clone(),
+                    block: BlockPointer {
+                        number: block_number,
+                        hash: None,
+                    },
+                    proof_of_indexing: poi.poi,
+                })
+            }
+            Ok(None) => {}
+            Err(err) => debug!(
+                error = %err,
+                block_number,
+                "Failed to look up stored PoI, querying the indexer"
+            ),
+        }
     }
+
+    // Probes go through the range API like any other batch of blocks, so that
+    // they benefit from the same caching and batching.
+    let request = PoiRangeRequest {
+        deployment: deployment.clone(),
+        from_block: block_number,
+        to_block: block_number,
+        step: 1,
+    };
+    indexer
+        .proofs_of_indexing_range(request)
+        .await?
+        .pop()
+        .ok_or_else(|| anyhow!("no proof of indexing returned for block {block_number}"))
 }
 
+/// The number of bisections is quadratic to the number of Pois, so it's
+/// important not to allow too many in a single request.
+const MAX_NUMBER_OF_POIS_PER_REQUEST: u32 = 4;
+
 #[derive(Debug, Error)]
 pub enum DivergenceInvestigationError {
     #[error("Too many POIs in a single request, the max. is {max}")]
@@ -175,6 +335,16 @@ pub enum DivergenceInvestigationError {
         poi1_block: i64,
         poi2_block: i64,
     },
+    #[error("The two Pois were produced for different forks, they cannot be compared: both are for block {block_number}, but {poi1} is for block hash {poi1_block_hash}, and {poi2} for {poi2_block_hash}")]
+    BlockHashMismatch {
+        poi1: String,
+        poi2: String,
+        block_number: i64,
+        poi1_block_hash: String,
+        poi2_block_hash: String,
+    },
+    #[error("Could not compare the Pois at block {block_number}: {reason}")]
+    CouldNotCompare { block_number: u64, reason: String },
     #[error(transparent)]
     Database(anyhow::Error),
 }
@@ -204,6 +374,12 @@ pub async fn handle_divergence_investigation_requests(
 
         let req_contents =
             serde_json::from_value(req_contents_blob).expect("invalid request blob; this is a bug");
+        // Each investigation is a trace of its own.
+        let span = info_span!(
+            parent: None,
+            "divergence_investigation",
+            request_uuid = %req_uuid
+        );
         let report = handle_divergence_investigation_request(
             store,
             &req_uuid,
@@ -211,6 +387,7 @@ pub async fn handle_divergence_investigation_requests(
             indexers.clone(),
             ctx,
         )
+        .instrument(span)
         .await;
 
         let serialized_report = serde_json::to_value(&report).unwrap();
@@ -244,7 +421,7 @@ impl PoiWithRelatedData {
         indexers: &[Arc<dyn IndexerClient>],
         ctx: &ApiSchemaContext,
     ) -> anyhow::Result<Option<Self>> {
-        let Some(poi_model) = store.poi(poi_bytes).await? else {
+        let Some(poi_model) = store.poi_by_hash(poi_bytes).await? else {
             return Ok(None);
         };
 
@@ -296,6 +473,8 @@ async fn handle_divergence_investigation_request_pair(
         uuid: Uuid::new_v4(),
         poi1: *poi1_s,
         poi2: *poi2_s,
+        indexer1_display_name: None,
+        indexer2_display_name: None,
         divergence_block_bounds: DivergenceBlockBounds {
             lower_bound: PartialBlock {
                 number: 1,
@@ -329,6 +508,9 @@ async fn handle_divergence_investigation_request_pair(
 
     debug!(?req_uuid, poi1 = %poi1_s, poi2 = %poi2_s, "Fetched Pois");
 
+    report.indexer1_display_name = poi1_data.indexer.name().map(str::to_string);
+    report.indexer2_display_name = poi2_data.indexer.name().map(str::to_string);
+
     report.divergence_block_bounds.upper_bound.number = poi1_data.block.number_i64();
 
     // Two PoIs need to relate to the same subgraph deployment to be comparable.
@@ -357,6 +539,24 @@ async fn handle_divergence_investigation_request_pair(
         );
     }
 
+    // PoIs at blocks with the same number but different hashes come from
+    // different forks, there's no divergence to bisect.
+    if poi1_data.block.number() == poi2_data.block.number()
+        && poi1_data.block.hash() != poi2_data.block.hash()
+    {
+        report.error = Some(
+            DivergenceInvestigationError::BlockHashMismatch {
+                poi1: poi1_s.to_string(),
+                poi2: poi2_s.to_string(),
+                block_number: poi1_data.block.number_i64(),
+                poi1_block_hash: poi1_data.block.hash().to_string(),
+                poi2_block_hash: poi2_data.block.hash().to_string(),
+            }
+            .to_string(),
+        );
+        return report;
+    }
+
     debug!(?req_uuid, poi1 = %poi1_s, poi2 = %poi2_s, "Fetching indexers");
 
     debug!(?req_uuid, poi1 = %poi1_s, poi2 = %poi2_s, "Fetched indexers");
@@ -370,7 +570,7 @@ async fn handle_divergence_investigation_request_pair(
 
     let context = PoiBisectingContext::new(report, bisection_uuid, poi1_data, poi2_data)
         .expect("bisect context creation failed");
-    let (report, _block_num) = context.start().await;
+    let (report, _block_num) = context.start(store).await;
 
     report
 }
@@ -386,12 +586,20 @@ async fn handle_divergence_investigation_request(
         uuid: req_uuid.clone(),
         status: DivergenceInvestigationStatus::Complete,
         bisection_runs: vec![],
+        pairwise_findings: vec![],
+        no_divergence_found: false,
         error: None,
+        triage_status: Default::default(),
+        triage_note: None,
     };
 
-    // The number of bisections is quadratic to the number of Pois, so it's
-    // important not to allow too many in a single request.
-    const MAX_NUMBER_OF_POIS_PER_REQUEST: u32 = 4;
+    if let Some(deployment) = &req_contents.deployment {
+        let indexers = indexers.borrow().clone();
+        return handle_deployment_divergence_investigation(
+            store, &indexers, req_uuid, deployment, ctx, report,
+        )
+        .await;
+    }
 
     if req_contents.pois.len() > MAX_NUMBER_OF_POIS_PER_REQUEST as usize {
         report.error = Some(
@@ -414,16 +622,331 @@ async fn handle_divergence_investigation_request(
         .await;
         debug!(?req_uuid, poi1 = %poi1_s, poi2 = %poi2_s, "Finished bisection run");
         report.bisection_runs.push(bisection_run_report);
-        let report_json = serde_json::to_value(&report).unwrap();
-        if let Err(err) = store
-            .create_or_update_divergence_investigation_report(req_uuid, report_json)
-            .await
-        {
-            error!(?req_uuid, error = %err, "Failed to upsert divergence investigation report to the database");
-        }
+        write_partial_report(store, req_uuid, &report).await;
     }
 
     info!(?req_uuid, "Finished bisecting Pois");
 
     report
 }
+
+/// Investigates all indexers of `deployment`: their latest live PoIs are
+/// grouped with [`poi_groups`], and the first indexer of each group is
+/// bisected against the first indexer of every other group. Only the
+/// [`MAX_NUMBER_OF_POIS_PER_REQUEST`] largest groups are investigated.
+async fn handle_deployment_divergence_investigation(
+    store: &Store,
+    indexers: &[Arc<dyn IndexerClient>],
+    req_uuid: &Uuid,
+    deployment: &IpfsCid,
+    ctx: &ApiSchemaContext,
+    mut report: DivergenceInvestigationReport,
+) -> DivergenceInvestigationReport {
+    let deployments = [deployment.clone()];
+    let (pois, indexer_addresses) = match futures::try_join!(
+        store.live_pois(None, Some(&deployments), None, None, false),
+        store.indexers(IndexersQuery {
+            include_inactive: true,
+            ..Default::default()
+        }),
+    ) {
+        Ok((pois, indexer_models)) => (
+            pois,
+            indexer_models
+                .into_iter()
+                .map(|indexer| (indexer.id, indexer.address))
+                .collect::<HashMap<_, _>>(),
+        ),
+        Err(err) => {
+            report.error = Some(DivergenceInvestigationError::Database(err).to_string());
+            return report;
+        }
+    };
+
+    let mut groups = poi_groups(&pois);
+    if groups.len() < 2 {
+        info!(?req_uuid, %deployment, "No divergence found, all indexers agree");
+        report.no_divergence_found = true;
+        return report;
+    }
+    if groups.len() > MAX_NUMBER_OF_POIS_PER_REQUEST as usize {
+        info!(
+            ?req_uuid,
+            %deployment,
+            groups = groups.len(),
+            "Too many groups of indexers disagree, only investigating the largest ones"
+        );
+        groups.truncate(MAX_NUMBER_OF_POIS_PER_REQUEST as usize);
+    }
+
+    let addresses = |group: &[&Poi]| {
+        group
+            .iter()
+            .filter_map(|poi| indexer_addresses.get(&poi.indexer_id).copied())
+            .collect::<Vec<_>>()
+    };
+    for (i, group1) in groups.iter().enumerate() {
+        for group2 in &groups[i + 1..] {
+            let (poi1, poi2) = (group1[0].poi, group2[0].poi);
+            let bisection_run_report = handle_divergence_investigation_request_pair(
+                store, indexers, req_uuid, &poi1, &poi2, ctx,
+            )
+            .await;
+            debug!(?req_uuid, %poi1, %poi2, "Finished bisection run");
+
+            report.pairwise_findings.push(PairwiseDivergenceFinding {
+                poi1,
+                poi2,
+                indexers1: addresses(group1),
+                indexers2: addresses(group2),
+                bisection_run_uuid: bisection_run_report.uuid,
+                first_diverging_block: bisection_run_report.error.is_none().then_some(
+                    bisection_run_report
+                        .divergence_block_bounds
+                        .upper_bound
+                        .number,
+                ),
+            });
+            report.bisection_runs.push(bisection_run_report);
+            write_partial_report(store, req_uuid, &report).await;
+        }
+    }
+
+    info!(?req_uuid, %deployment, "Finished bisecting Pois");
+
+    report
+}
+
+/// Groups `pois` by value, largest groups first, and orders each group by
+/// indexer. Only PoIs at the same block are comparable, so only those at the
+/// block that most of `pois` are at are kept. PoIs at pinned blocks are
+/// ignored.
+fn poi_groups(pois: &[Poi]) -> Vec<Vec<&Poi>> {
+    let mut pois_by_block: BTreeMap<_, Vec<&Poi>> = BTreeMap::new();
+    for poi in pois.iter().filter(|poi| !poi.pinned) {
+        pois_by_block.entry(poi.block_id).or_default().push(poi);
+    }
+    // Ties go to the most recently stored block.
+    let Some(pois) = pois_by_block.into_values().max_by_key(|pois| pois.len()) else {
+        return vec![];
+    };
+
+    let mut groups: BTreeMap<PoiBytes, Vec<&Poi>> = BTreeMap::new();
+    for poi in pois {
+        groups.entry(poi.poi).or_default().push(poi);
+    }
+    let mut groups: Vec<_> = groups.into_values().collect();
+    for group in &mut groups {
+        group.sort_by_key(|poi| poi.indexer_id);
+    }
+    groups.sort_by_key(|group| std::cmp::Reverse(group.len()));
+    groups
+}
+
+/// Stores `report` while the investigation is still ongoing, so that
+/// partial results can be shown.
+async fn write_partial_report(
+    store: &Store,
+    req_uuid: &Uuid,
+    report: &DivergenceInvestigationReport,
+) {
+    let report_json = serde_json::to_value(report).unwrap();
+    if let Err(err) = store
+        .create_or_update_divergence_investigation_report(req_uuid, report_json)
+        .await
+    {
+        error!(?req_uuid, error = %err, "Failed to upsert divergence investigation report to the database");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graphix_lib::test_utils::fixture::{TestFixture, TestFixtureBuilder};
+    use graphix_lib::test_utils::mocks::MockIndexer;
+    use graphix_lib::test_utils::test_seed;
+
+    use super::*;
+
+    /// Bisects every pair of indexers on every deployment of `fixture` and
+    /// checks the results against the expected divergences.
+    async fn assert_bisections(fixture: &TestFixture) {
+        let latest_block = fixture.blocks.last().unwrap().number;
+
+        for (deployment_index, deployment) in fixture.deployments.iter().enumerate() {
+            for (i, j) in unordered_pairs_combinations(0..fixture.indexers.len()) {
+                let Some(diverge_at) = fixture.first_diverging_block(i, j, deployment_index) else {
+                    continue;
+                };
+                let bisection = bisect_pois(
+                    fixture.indexers[i].clone(),
+                    fixture.indexers[j].clone(),
+                    deployment,
+                    0..=latest_block,
+                    None,
+                )
+                .await;
+
+                assert_eq!(
+                    bisection.first_diverging_block, diverge_at,
+                    "seed {}, indexers {} and {}, deployment {}",
+                    fixture.seed, i, j, deployment_index
+                );
+                assert_eq!(bisection.last_agreeing_block, diverge_at - 1);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn bisection_finds_first_diverging_block() {
+        for diverge_at in 1..10 {
+            let fixture = TestFixtureBuilder::new(0).diverge(1, 0, diverge_at).build();
+            assert_bisections(&fixture).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn bisection_with_random_divergences() {
+        for i in 0..20 {
+            let fixture = TestFixtureBuilder::new(test_seed(i))
+                .indexers(4)
+                .deployments(2)
+                .blocks(100)
+                .random_divergences(3)
+                .build();
+            assert_bisections(&fixture).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn bisection_with_several_diverging_indexers() {
+        let fixture = TestFixtureBuilder::new(42)
+            .indexers(3)
+            .blocks(50)
+            .diverge(0, 0, 30)
+            .diverge(2, 0, 12)
+            .build();
+        assert_eq!(fixture.first_diverging_block(0, 1, 0), Some(30));
+        assert_eq!(fixture.first_diverging_block(0, 2, 0), Some(12));
+        assert_bisections(&fixture).await;
+    }
+
+    #[tokio::test]
+    async fn bisection_stops_at_failed_poi_queries() {
+        let fixture = TestFixtureBuilder::new(0)
+            .blocks(64)
+            .diverge(1, 0, 40)
+            .build();
+        let indexer = |index: usize, fail: bool| {
+            let mut indexer = MockIndexer::clone(&fixture.indexers[index]);
+            if fail {
+                indexer.faults.failing_poi_deployments = fixture.deployments.clone();
+            }
+            Arc::new(indexer) as Arc<dyn IndexerClient>
+        };
+
+        // Failed queries are neither agreement nor disagreement, whichever
+        // indexers they're sent to.
+        for (fail1, fail2) in [(true, false), (false, true), (true, true)] {
+            let bisection = bisect_pois(
+                indexer(0, fail1),
+                indexer(1, fail2),
+                &fixture.deployments[0],
+                0..=63,
+                None,
+            )
+            .await;
+
+            let error = bisection.error.expect("the bisection should fail");
+            assert!(
+                matches!(
+                    error,
+                    DivergenceInvestigationError::CouldNotCompare {
+                        block_number: 31,
+                        ..
+                    }
+                ),
+                "{error}"
+            );
+            assert_eq!(bisection.bisects.len(), 1);
+            assert_eq!(bisection.last_agreeing_block, 0);
+            assert_eq!(bisection.first_diverging_block, 63);
+        }
+    }
+
+    #[tokio::test]
+    async fn bisection_waits_for_the_indexers_every_other_step() {
+        let latency = Duration::from_millis(100);
+        let fixture = TestFixtureBuilder::new(0)
+            .blocks(64)
+            .diverge(1, 0, 40)
+            .build();
+        let slow = |indexer: &MockIndexer| {
+            let mut indexer = indexer.clone();
+            indexer.faults.latency = Some((latency, latency));
+            Arc::new(indexer) as Arc<dyn IndexerClient>
+        };
+
+        let started = Instant::now();
+        let bisection = bisect_pois(
+            slow(&fixture.indexers[0]),
+            slow(&fixture.indexers[1]),
+            &fixture.deployments[0],
+            0..=63,
+            None,
+        )
+        .await;
+        let elapsed = started.elapsed();
+
+        assert_eq!(bisection.first_diverging_block, 40);
+        // Every step would take at least the latency if it had to wait for
+        // its own PoIs, and twice that if the indexers were queried one after
+        // the other. With the next probes prefetched, it's half of it.
+        let steps = bisection.bisects.len() as u32;
+        assert!(steps > 1);
+        assert!(
+            elapsed < latency * steps * 3 / 4,
+            "{steps} steps took {elapsed:?}"
+        );
+    }
+
+    #[test]
+    fn pois_are_grouped_by_value_at_the_most_common_block() {
+        let poi = |indexer_id: i32, block_id: i64, poi: u8, pinned: bool| Poi {
+            id: 0,
+            poi: HexString([poi; 32]),
+            sg_deployment_id: 1,
+            indexer_id,
+            block_id,
+            created_at: chrono::Utc::now().naive_utc(),
+            superseded_at: None,
+            pinned,
+            source_url: None,
+        };
+        let pois = vec![
+            poi(5, 2, 1, false),
+            poi(4, 2, 2, false),
+            poi(3, 2, 1, false),
+            poi(2, 2, 3, false),
+            // Not at the block most indexers are at.
+            poi(1, 1, 4, false),
+            poi(6, 3, 5, true),
+            poi(7, 3, 5, true),
+            poi(8, 3, 5, true),
+            poi(9, 3, 5, true),
+            poi(10, 3, 5, true),
+        ];
+
+        let groups = poi_groups(&pois)
+            .into_iter()
+            .map(|group| group.iter().map(|poi| poi.indexer_id).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(groups[0], vec![3, 5]);
+        assert_eq!(groups.len(), 3);
+        assert!(groups.contains(&vec![4]));
+        assert!(groups.contains(&vec![2]));
+
+        // All indexers agree.
+        assert_eq!(poi_groups(&pois[..1]).len(), 1);
+        assert!(poi_groups(&[]).is_empty());
+    }
+}
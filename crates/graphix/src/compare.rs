@@ -0,0 +1,250 @@
+//! The `graphix compare` subcommand, which checks whether two indexers agree
+//! on a subgraph deployment's PoIs. It talks to the indexers directly and
+//! never touches the database.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use anyhow::Context;
+use clap::Args;
+use graphix_common_types::{IndexerAddress, IpfsCid, PoiBytes};
+use graphix_indexer_client::{
+    IndexerClient, IndexerId, IndexingStatus, PoiRequest, RealIndexer, SubgraphDeployment,
+};
+use graphix_lib::config::{config_to_indexers, http_client, Config, HttpClientOptions};
+use graphix_lib::metrics;
+
+use crate::bisect::bisect_pois;
+
+#[derive(Args, Debug)]
+pub struct CompareOptions {
+    /// The first indexer, either as the URL of its index-node status endpoint
+    /// or as an address that can be resolved via the configuration file.
+    indexer1: String,
+    /// The second indexer, in the same format as the first one.
+    indexer2: String,
+    /// IPFS CID of the subgraph deployment to compare.
+    #[clap(long)]
+    deployment: IpfsCid,
+    /// The block at which to compare PoIs. Defaults to the most recent block
+    /// that both indexers have indexed.
+    #[clap(long)]
+    block: Option<u64>,
+    /// Prints the report as JSON, for scripting.
+    #[clap(long)]
+    json: bool,
+}
+
+pub async fn compare_indexers(
+    config: Option<&Config>,
+    options: CompareOptions,
+) -> anyhow::Result<()> {
+    let indexer1 = resolve_indexer(&options.indexer1, config).await?;
+    let indexer2 = resolve_indexer(&options.indexer2, config).await?;
+    let deployment = SubgraphDeployment(options.deployment.to_string());
+
+    let status1 = deployment_status(indexer1.clone(), &deployment).await?;
+    let status2 = deployment_status(indexer2.clone(), &deployment).await?;
+
+    // PoIs can only be compared for blocks that are available on both indexers.
+    let earliest_block = status1.earliest_block_num.max(status2.earliest_block_num);
+    let latest_block = status1.latest_block.number.min(status2.latest_block.number);
+    let block = options.block.unwrap_or(latest_block);
+    anyhow::ensure!(
+        (earliest_block..=latest_block).contains(&block),
+        "block #{} is not available on both indexers, choose one between #{} and #{}",
+        block,
+        earliest_block,
+        latest_block
+    );
+
+    let poi1 = poi(indexer1.clone(), &deployment, block).await?;
+    let poi2 = poi(indexer2.clone(), &deployment, block).await?;
+
+    let first_diverging_block = if poi1 == poi2 {
+        None
+    } else if pois_agree(&indexer1, &indexer2, &deployment, earliest_block).await? {
+        let bisection = bisect_pois(
+            indexer1.clone(),
+            indexer2.clone(),
+            &deployment,
+            earliest_block..=block,
+            None,
+        )
+        .await;
+        if let Some(err) = bisection.error {
+            anyhow::bail!("{}", err);
+        }
+        Some(bisection.first_diverging_block)
+    } else {
+        // Nothing to bisect, the indexers disagree as far back as we can see.
+        Some(earliest_block)
+    };
+
+    if options.json {
+        let report = serde_json::json!({
+            "deployment": deployment.as_str(),
+            "block": block,
+            "indexer1": { "id": indexer_id(&indexer1), "poi": poi1.to_string() },
+            "indexer2": { "id": indexer_id(&indexer2), "poi": poi2.to_string() },
+            "agree": first_diverging_block.is_none(),
+            "earliestComparableBlock": earliest_block,
+            "firstDivergingBlock": first_diverging_block,
+        });
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("Deployment: {}", deployment.as_str());
+        println!("Block:      #{}", block);
+        println!("Indexer 1:  {} (PoI {})", indexer_id(&indexer1), poi1);
+        println!("Indexer 2:  {} (PoI {})", indexer_id(&indexer2), poi2);
+        match first_diverging_block {
+            None => println!("Result:     the indexers agree"),
+            Some(diverging_block) if diverging_block == earliest_block => println!(
+                "Result:     the indexers disagree since at least block #{}, the earliest block available on both",
+                diverging_block
+            ),
+            Some(diverging_block) => println!(
+                "Result:     the indexers disagree since block #{}",
+                diverging_block
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolves an indexer from either the URL of its status endpoint or its
+/// address, as found in the configuration file.
+async fn resolve_indexer(
+    indexer: &str,
+    config: Option<&Config>,
+) -> anyhow::Result<Arc<dyn IndexerClient>> {
+    if let Ok(address) = indexer.parse::<IndexerAddress>() {
+        let config = config.with_context(|| {
+            format!("resolving indexer {} by address requires --config", indexer)
+        })?;
+        return config_to_indexers(config.clone(), metrics())
+            .await?
+            .indexers
+            .into_iter()
+            .find(|indexer| indexer.address() == address)
+            .with_context(|| format!("indexer {} not found in the configuration", address));
+    }
+
+    anyhow::ensure!(
+        indexer.starts_with("http://") || indexer.starts_with("https://"),
+        "{} is neither an indexer address nor a URL",
+        indexer
+    );
+
+    // Indexers given by URL have no known address, so we make up one that is
+    // unique to the URL.
+    let mut hasher = DefaultHasher::new();
+    indexer.hash(&mut hasher);
+    let mut address = [0u8; 20];
+    address[12..].copy_from_slice(&hasher.finish().to_be_bytes());
+
+    let http_client_options = config
+        .map(HttpClientOptions::from_config)
+        .unwrap_or_default();
+    Ok(Arc::new(
+        RealIndexer::new(
+            Some(indexer.to_string()),
+            address.into(),
+            indexer.to_string(),
+            metrics().public_proofs_of_indexing_requests.clone(),
+        )
+        .with_client(http_client(&http_client_options)?),
+    ))
+}
+
+async fn deployment_status(
+    indexer: Arc<dyn IndexerClient>,
+    deployment: &SubgraphDeployment,
+) -> anyhow::Result<IndexingStatus> {
+    let id = indexer_id(&indexer);
+    indexer
+        .indexing_statuses()
+        .await
+        .with_context(|| format!("failed to query indexing statuses of {}", id))?
+        .into_iter()
+        .find(|status| &status.deployment == deployment)
+        .with_context(|| format!("{} is not indexing {}", id, deployment.as_str()))
+}
+
+async fn poi(
+    indexer: Arc<dyn IndexerClient>,
+    deployment: &SubgraphDeployment,
+    block_number: u64,
+) -> anyhow::Result<PoiBytes> {
+    let id = indexer_id(&indexer);
+    let poi = indexer
+        .proof_of_indexing(PoiRequest {
+            deployment: deployment.clone(),
+            block_number,
+        })
+        .await
+        .with_context(|| format!("failed to query PoI of {} at block #{}", id, block_number))?;
+
+    Ok(poi.proof_of_indexing)
+}
+
+/// Whether both indexers have the same PoI at `block_number`. Fails if either
+/// PoI can't be queried, which says nothing about whether they agree.
+async fn pois_agree(
+    indexer1: &Arc<dyn IndexerClient>,
+    indexer2: &Arc<dyn IndexerClient>,
+    deployment: &SubgraphDeployment,
+    block_number: u64,
+) -> anyhow::Result<bool> {
+    let compare = async {
+        let poi1 = poi(indexer1.clone(), deployment, block_number).await?;
+        let poi2 = poi(indexer2.clone(), deployment, block_number).await?;
+        anyhow::Ok(poi1 == poi2)
+    };
+
+    compare
+        .await
+        .with_context(|| format!("could not compare PoIs at block #{}", block_number))
+}
+
+fn indexer_id(indexer: &Arc<dyn IndexerClient>) -> String {
+    match indexer.name() {
+        Some(name) => name.into_owned(),
+        None => indexer.address_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graphix_lib::test_utils::fixture::TestFixtureBuilder;
+    use graphix_lib::test_utils::mocks::MockIndexer;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn failed_poi_queries_are_not_agreement() {
+        let fixture = TestFixtureBuilder::new(0).build();
+        let deployment = &fixture.deployments[0];
+        let indexer = |index: usize, fail: bool| {
+            let mut indexer = MockIndexer::clone(&fixture.indexers[index]);
+            if fail {
+                indexer.faults.failing_poi_deployments = vec![deployment.clone()];
+            }
+            Arc::new(indexer) as Arc<dyn IndexerClient>
+        };
+
+        assert!(
+            pois_agree(&indexer(0, false), &indexer(1, false), deployment, 5)
+                .await
+                .unwrap()
+        );
+        for (fail1, fail2) in [(true, false), (false, true), (true, true)] {
+            let err = pois_agree(&indexer(0, fail1), &indexer(1, fail2), deployment, 5)
+                .await
+                .unwrap_err();
+            assert_eq!(err.to_string(), "could not compare PoIs at block #5");
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! The `graphix db` subcommands, for database maintenance.
+
+use std::time::Duration;
+
+use clap::{Args, Subcommand, ValueEnum};
+use graphix_store::Store;
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    /// Prints the size of every table and any pending migrations.
+    Status,
+    /// Deletes old PoIs. PoIs that divergence investigations refer to are
+    /// never deleted. There's no indexing status history to prune, as only
+    /// the latest status of each indexer and deployment is stored.
+    Prune(PruneOptions),
+    /// Runs pending migrations and exits.
+    Migrate,
+}
+
+#[derive(Args, Debug)]
+pub struct PruneOptions {
+    /// Only PoIs collected longer ago than this are deleted, e.g. `30d`.
+    /// Supported units are `s`, `m`, `h`, `d` and `w`.
+    #[clap(long, value_parser = parse_duration)]
+    older_than: Duration,
+    /// Whether live PoIs may be deleted too.
+    #[clap(long, value_enum, default_value_t = PruneLiveness::Live)]
+    liveness: PruneLiveness,
+    /// Prints how many PoIs would be deleted, without deleting them.
+    #[clap(long)]
+    dry_run: bool,
+    /// How many PoIs to delete at a time.
+    #[clap(long, default_value_t = 1000)]
+    batch_size: u16,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PruneLiveness {
+    /// Keeps live PoIs, i.e. the most recent PoI of each indexer for each
+    /// subgraph deployment, no matter how old.
+    Live,
+    /// Deletes old PoIs regardless of whether they're live.
+    All,
+}
+
+pub async fn run(database_url: &str, command: DbCommand) -> anyhow::Result<()> {
+    match command {
+        DbCommand::Status => {
+            // Migrations would change the very thing we're reporting on.
+            let store = Store::connect(database_url)?;
+            print_status(&store).await
+        }
+        DbCommand::Prune(options) => {
+            let store = Store::new(database_url).await?;
+            prune(&store, options).await
+        }
+        DbCommand::Migrate => {
+            Store::new(database_url).await?;
+            println!("Migrations are up to date");
+            Ok(())
+        }
+    }
+}
+
+async fn print_status(store: &Store) -> anyhow::Result<()> {
+    let tables = store.table_stats().await?;
+
+    println!("{:<45} {:>12} {:>12}", "Table", "Rows", "Size");
+    for table in tables {
+        println!(
+            "{:<45} {:>12} {:>12}",
+            table.name,
+            table.row_count,
+            format_bytes(table.total_bytes)
+        );
+    }
+
+    let pending_migrations = store.pending_migrations().await?;
+    println!();
+    if pending_migrations.is_empty() {
+        println!("No pending migrations");
+    } else {
+        println!("Pending migrations:");
+        for migration in pending_migrations {
+            println!("  {}", migration);
+        }
+    }
+
+    Ok(())
+}
+
+async fn prune(store: &Store, options: PruneOptions) -> anyhow::Result<()> {
+    anyhow::ensure!(options.batch_size > 0, "batch size must be positive");
+
+    let keep_live = options.liveness == PruneLiveness::Live;
+    let count = store
+        .prune_pois(
+            options.older_than,
+            keep_live,
+            options.batch_size,
+            options.dry_run,
+        )
+        .await?;
+
+    if options.dry_run {
+        println!("Would prune {} PoIs", count);
+    } else {
+        println!("Pruned {} PoIs", count);
+    }
+
+    Ok(())
+}
+
+fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    let unit_index = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| anyhow::anyhow!("missing unit in duration `{}`", s))?;
+    let (amount, unit) = s.split_at(unit_index);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid duration `{}`", s))?;
+
+    let unit_in_seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => anyhow::bail!("invalid unit `{}` in duration `{}`", unit, s),
+    };
+
+    amount
+        .checked_mul(unit_in_seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| anyhow::anyhow!("duration `{}` is too long", s))
+}
+
+fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(
+            parse_duration("30d").unwrap(),
+            Duration::from_secs(30 * 86400)
+        );
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! The `graphix export` subcommand and the `/export/pois.ndjson` route,
+//! which dump PoIs from the database.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::body::{Body, Bytes};
+use axum::extract::Query;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use clap::{Args, ValueEnum};
+use graphix_common_types::{inputs, IpfsCid};
+use graphix_store::models::PoiExportRow;
+use graphix_store::Store;
+use serde::Deserialize;
+use tracing::error;
+
+const CSV_HEADER: &str = "timestamp,indexer_address,deployment_cid,block_number,block_hash,poi";
+
+/// How many PoIs the `/export/pois.ndjson` route fetches from the database
+/// at a time, and sends as a single chunk.
+const NDJSON_BATCH_SIZE: u16 = 1000;
+
+#[derive(Args, Debug)]
+pub struct ExportOptions {
+    /// Only export PoIs for this subgraph deployment (IPFS CID). Can be
+    /// specified multiple times.
+    #[clap(long)]
+    deployment: Vec<IpfsCid>,
+    /// Only export PoIs for subgraph deployments that index this network.
+    #[clap(long)]
+    network: Option<String>,
+    /// Only export PoIs for blocks at or above this block number.
+    #[clap(long)]
+    from_block: Option<u64>,
+    /// Only export PoIs for blocks at or below this block number.
+    #[clap(long)]
+    to_block: Option<u64>,
+    #[clap(long, value_enum, default_value_t = ExportFormat::Csv)]
+    format: ExportFormat,
+    /// The file to write to. Defaults to stdout.
+    #[clap(long)]
+    output: Option<PathBuf>,
+    /// How many PoIs to fetch from the database at a time.
+    #[clap(long, default_value_t = 1000)]
+    batch_size: u16,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+/// Streams all PoIs matching `options` from the database, in batches, and
+/// writes them out in the requested format.
+pub async fn export_pois(store: &Store, options: ExportOptions) -> anyhow::Result<()> {
+    anyhow::ensure!(options.batch_size > 0, "batch size must be positive");
+
+    let filter = inputs::PoisQuery {
+        network: options.network,
+        deployments: options.deployment,
+        block_range: (options.from_block.is_some() || options.to_block.is_some()).then_some(
+            inputs::BlockRange {
+                start: options.from_block,
+                end: options.to_block,
+            },
+        ),
+        limit: None,
+    };
+
+    let output: Box<dyn Write> = match &options.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut output = BufWriter::new(output);
+
+    if options.format == ExportFormat::Csv {
+        writeln!(output, "{}", CSV_HEADER)?;
+    }
+
+    let mut row_count: u64 = 0;
+    let mut last_id = None;
+    loop {
+        let rows = store
+            .poi_export_batch(&filter, None, last_id, options.batch_size)
+            .await?;
+        let Some(last_row) = rows.last() else {
+            break;
+        };
+        last_id = Some(last_row.id);
+
+        for row in &rows {
+            write_row(&mut output, options.format, row)?;
+        }
+        row_count += rows.len() as u64;
+    }
+    output.flush()?;
+
+    // Printed to stderr, so that it doesn't end up in the exported data.
+    eprintln!("Exported {} PoIs", row_count);
+
+    Ok(())
+}
+
+fn write_row(output: &mut impl Write, format: ExportFormat, row: &PoiExportRow) -> io::Result<()> {
+    match format {
+        // None of the fields may contain commas, quotes or newlines, so no
+        // escaping is necessary.
+        ExportFormat::Csv => writeln!(
+            output,
+            "{},{},{},{},{},{}",
+            row.timestamp.format("%Y-%m-%dT%H:%M:%S%.f"),
+            row.indexer_address,
+            row.deployment_cid,
+            row.block_number,
+            row.block_hash,
+            row.poi
+        ),
+        ExportFormat::Jsonl => {
+            serde_json::to_writer(&mut *output, row)?;
+            writeln!(output)
+        }
+    }
+}
+
+/// The query parameters of the `/export/pois.ndjson` route.
+#[derive(Deserialize, Debug)]
+pub struct NdjsonExportQuery {
+    /// Only export PoIs for this subgraph deployment (IPFS CID).
+    deployment: Option<String>,
+    /// Only export PoIs collected at or after this time, in RFC 3339 format.
+    since: Option<DateTime<Utc>>,
+}
+
+/// Streams all PoIs matching `query` from the database as newline-delimited
+/// JSON, in batches, so that memory use doesn't depend on the number of
+/// PoIs. The response has no known length, so it's sent with chunked
+/// transfer encoding.
+pub async fn export_pois_ndjson(store: Store, Query(query): Query<NdjsonExportQuery>) -> Response {
+    let deployments = match query.deployment.map(|cid| cid.parse::<IpfsCid>()) {
+        Some(Ok(cid)) => vec![cid],
+        Some(Err(err)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("invalid deployment: {err}"),
+            )
+                .into_response()
+        }
+        None => vec![],
+    };
+    let filter = Arc::new(inputs::PoisQuery {
+        deployments,
+        ..Default::default()
+    });
+    let created_since = query.since.map(|since| since.naive_utc());
+
+    // `None` once all PoIs have been sent, otherwise the ID of the last PoI
+    // that was sent, if any.
+    let batches = futures::stream::try_unfold(Some(None), move |last_id| {
+        let store = store.clone();
+        let filter = filter.clone();
+        async move {
+            let Some(last_id) = last_id else {
+                return Ok(None);
+            };
+            let rows = store
+                .poi_export_batch(&filter, created_since, last_id, NDJSON_BATCH_SIZE)
+                .await
+                .map_err(|err| {
+                    error!(error = %err, "Failed to export PoIs");
+                    err
+                })?;
+            let Some(last_row) = rows.last() else {
+                return Ok(None);
+            };
+            let next = (rows.len() == usize::from(NDJSON_BATCH_SIZE)).then_some(Some(last_row.id));
+
+            let mut chunk = vec![];
+            for row in &rows {
+                write_row(&mut chunk, ExportFormat::Jsonl, row)?;
+            }
+            Ok::<_, anyhow::Error>(Some((Bytes::from(chunk), next)))
+        }
+    });
+
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(batches),
+    )
+        .into_response()
+}
@@ -1,59 +1,198 @@
 #![allow(clippy::type_complexity)]
 
+mod backfill;
 mod bisect;
+mod compare;
+mod db;
+mod export;
+mod main_loop;
+mod manifests;
+mod poll_trigger;
+mod rate_limiting;
+mod request_logging;
+mod rest_api;
+mod tls;
 mod utils;
 
 use std::collections::HashSet;
-use std::net::Ipv4Addr;
+use std::future::Future;
+use std::net::{Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use anyhow::Context;
 use async_graphql::http::GraphiQLSource;
-use async_graphql_axum::GraphQL;
 use axum::response::IntoResponse;
 use axum::Router;
-use clap::Parser;
-use graphix_indexer_client::{IndexerClient, IndexerId};
-use graphix_lib::config::Config;
+use clap::{Parser, Subcommand};
+use graphix_indexer_client::{IndexerClient, IndexingStatus, PoiCache, SubgraphDeployment};
+use graphix_lib::config::{Config, ConsensusWeighting, LogFormat};
 use graphix_lib::graphql_api::{self, ApiSchemaContext};
-use graphix_lib::indexing_loop::{query_indexing_statuses, query_proofs_of_indexing};
-use graphix_lib::{config, metrics, PrometheusExporter, GRAPHIX_VERSION};
-use graphix_store::{models, PoiLiveness, Store};
+use graphix_lib::indexing_loop::{query_graph_node_versions, IndexerConnectivity};
+use graphix_lib::manifest::IpfsGateway;
+use graphix_lib::{
+    config, metrics, PrometheusExporter, GRAPHIX_GIT_SHA, GRAPHIX_LONG_VERSION, GRAPHIX_VERSION,
+};
+use graphix_store::{models, PoiStore, Store};
 use prometheus_exporter::prometheus;
 use tokio::net::TcpListener;
 use tokio::sync::watch;
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+use tower_http::decompression::RequestDecompressionLayer;
 use tracing::*;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
 
+use crate::backfill::handle_poi_backfill_jobs;
 use crate::bisect::handle_divergence_investigation_requests;
+use crate::compare::{compare_indexers, CompareOptions};
+use crate::db::DbCommand;
+use crate::export::{export_pois, ExportOptions};
+use crate::main_loop::MainLoop;
+use crate::manifests::fetch_deployment_manifests_forever;
+use crate::poll_trigger::PollTrigger;
+
+/// The log filter if neither `--log-level`, `RUST_LOG` nor `logLevel` are set.
+const DEFAULT_LOG_FILTER: &str = "info";
 
 #[derive(Parser, Debug)]
+#[clap(version = GRAPHIX_LONG_VERSION)]
 struct CliOptions {
+    /// The path to the configuration file. Only `compare` can run without
+    /// one.
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// The format of log output, `plain` or `json`. Overrides the
+    /// configuration file.
+    #[clap(long)]
+    log_format: Option<LogFormat>,
+    /// The log level (`trace`, `debug`, `info`, `warn` or `error`), or any
+    /// filter in `RUST_LOG` syntax (e.g. `graphix=debug,info`). Overrides
+    /// `RUST_LOG` and the configuration file. Defaults to `info`.
     #[clap(long)]
-    config: PathBuf,
+    log_level: Option<String>,
+    /// Runs the Graphix service if omitted.
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Exports PoIs from the database as CSV or JSON Lines.
+    Export(ExportOptions),
+    /// Compares two indexers' PoIs for a subgraph deployment and, if they
+    /// disagree, finds the first diverging block.
+    Compare(CompareOptions),
+    /// Database maintenance.
+    #[clap(subcommand)]
+    Db(DbCommand),
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    init_tracing();
-
-    info!("Parse options");
     let cli_options = CliOptions::parse();
 
-    info!("Loading configuration file");
-    let config = Config::read(&cli_options.config)?;
+    // The configuration file is read before tracing is initialized, because
+    // it may contain logging options.
+    let config = match &cli_options.config {
+        Some(path) => Some(Config::read(path)?),
+        None => None,
+    };
+
+    let export_traces = init_tracing(&cli_options, config.as_ref())?;
+    if config.is_some() {
+        info!("Loaded configuration file");
+    }
+
+    if !export_traces {
+        return run(cli_options, config).await;
+    }
+
+    // Without this, spans that haven't been exported yet would be lost on
+    // shutdown.
+    let result = tokio::select! {
+        result = run(cli_options, config) => result,
+        result = shutdown_signal() => {
+            info!("Shutting down");
+            result
+        }
+    };
+    tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider).await?;
+    result
+}
+
+async fn run(cli_options: CliOptions, config: Option<Config>) -> anyhow::Result<()> {
+    let export_options = match cli_options.command {
+        // Comparisons don't need a database connection, so they happen
+        // before anything else.
+        Some(Command::Compare(options)) => {
+            return compare_indexers(config.as_ref(), options).await;
+        }
+        Some(Command::Db(command)) => {
+            let config = config.context("missing --config")?;
+            return db::run(&config.database_url, command).await;
+        }
+        Some(Command::Export(options)) => Some(options),
+        None => None,
+    };
+    let config = config.context("missing --config")?;
 
     info!("Initialize store and running migrations");
-    let store = Store::new(&config.database_url).await?;
+    let store = Store::new_with_options(
+        &config.database_url,
+        config.database.store_options(metrics()),
+    )
+    .await?;
     info!("Store initialization successful");
 
-    if config.graphql.port != 0 {
+    if let Some(options) = export_options {
+        return export_pois(&store, options).await;
+    }
+
+    let (tx_indexing_statuses, rx_indexing_statuses) = watch::channel(vec![]);
+    let (tx_indexer_connectivity, rx_indexer_connectivity) = watch::channel(vec![]);
+    // Set once the main loop has written data, see `/ready`.
+    let (tx_initialized, rx_initialized) = watch::channel(false);
+    let poll_trigger = Arc::new(PollTrigger::default());
+
+    if !config.graphql.is_enabled() {
+        info!("GraphQL API server is disabled");
+    } else if let Some(tls_config) = config.graphql.tls.clone() {
+        // Broken certificates fail startup, rather than the server task.
+        let tls = tls::load(&tls_config).await?;
+        let address = SocketAddr::from((Ipv4Addr::UNSPECIFIED, config.graphql.port));
+        info!(%address, "GraphQL API server listening over HTTPS");
+
         let config = config.clone();
+        let indexer_connectivity = rx_indexer_connectivity.clone();
+        let initialized = rx_initialized.clone();
+        let poll_trigger = poll_trigger.clone();
+        tokio::spawn(tls::reload_forever(tls_config, tls.clone()));
         tokio::spawn(async move {
             // Listen to requests forever.
+            let router =
+                axum_server(config, indexer_connectivity, initialized, poll_trigger).await?;
+            tls::serve(router, address, tls).await
+        });
+    } else {
+        let config = config.clone();
+        let indexer_connectivity = rx_indexer_connectivity.clone();
+        let initialized = rx_initialized.clone();
+        let poll_trigger = poll_trigger.clone();
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, config.graphql.port)).await?;
+        info!(address = %listener.local_addr()?, "GraphQL API server listening");
+
+        tokio::spawn(async move {
+            // Listen to requests forever.
+            let router =
+                axum_server(config, indexer_connectivity, initialized, poll_trigger).await?;
             axum::serve(
-                TcpListener::bind((Ipv4Addr::UNSPECIFIED, config.graphql.port)).await?,
-                axum_server(config).await?,
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
             )
             .await?;
 
@@ -61,15 +200,31 @@ async fn main() -> anyhow::Result<()> {
         });
     }
 
-    let sleep_duration = Duration::from_secs(config.polling_period_in_seconds);
+    warn_about_query_spread(&config);
 
     // Prometheus metrics.
     let registry = prometheus::default_registry().clone();
-    let _exporter = PrometheusExporter::start(config.prometheus_port, registry.clone()).unwrap();
+    let _exporter = match config.prometheus_tls.clone() {
+        Some(tls_config) => {
+            let tls = tls::load(&tls_config).await?;
+            let address = SocketAddr::from((Ipv4Addr::UNSPECIFIED, config.prometheus_port));
+            info!(%address, "Prometheus metrics server listening over HTTPS");
+
+            tokio::spawn(tls::reload_forever(tls_config, tls.clone()));
+            tokio::spawn(tls::serve(
+                tls::metrics_router(registry.clone()),
+                address,
+                tls,
+            ));
+            None
+        }
+        None => Some(PrometheusExporter::start(config.prometheus_port, registry.clone()).unwrap()),
+    };
 
     info!("Initializing bisect request handler");
     let store_clone = store.clone();
     let (tx_indexers, rx_indexers) = watch::channel(vec![]);
+    let (tx_tracked_deployments, rx_tracked_deployments) = watch::channel(None);
     let ctx = ApiSchemaContext::new(store_clone.clone(), config.clone());
 
     {
@@ -84,94 +239,755 @@ async fn main() -> anyhow::Result<()> {
         store_clone.create_networks_if_missing(&networks).await?;
     }
 
-    tokio::spawn(async move {
-        handle_divergence_investigation_requests(&store_clone, rx_indexers, &ctx)
-            .await
-            .unwrap()
-    });
+    // The first indexer refresh happens before any task starts, so that they
+    // all see the full indexer set from the get-go.
+    let poi_cache = config.poi_cache.build(metrics());
+    refresh_indexers(&config, &store, poi_cache.as_ref(), &tx_indexers).await?;
+    refresh_tracked_deployments(&config, &store, &tx_tracked_deployments).await;
 
-    loop {
-        info!("New main loop iteration");
-        info!("Initialize inputs (indexers, indexing statuses etc.)");
+    {
+        let rx_indexers = rx_indexers.clone();
+        tokio::spawn(async move {
+            handle_divergence_investigation_requests(&store_clone, rx_indexers, &ctx)
+                .await
+                .unwrap()
+        });
+    }
+
+    info!("Initializing PoI backfill handler");
+    {
+        let store = store.clone();
+        let config = config.poi_backfill.clone();
+        tokio::spawn(async move {
+            handle_poi_backfill_jobs(&store, rx_indexing_statuses, &config)
+                .await
+                .unwrap()
+        });
+    }
 
-        let mut indexers = config::config_to_indexers(config.clone(), metrics()).await?;
-        // Different data sources, especially network subgraphs, result in
-        // duplicate indexers.
-        indexers = deduplicate_indexers(&indexers);
+    if let Some(ipfs_gateway) = config.ipfs_gateway.clone() {
+        info!("Initializing deployment manifest fetcher");
+        tokio::spawn(fetch_deployment_manifests_forever(
+            store.clone(),
+            IpfsGateway::new(ipfs_gateway),
+        ));
+    }
 
-        store.write_indexers(&indexers).await?;
+    // The polling tasks pick up configuration changes on `SIGHUP`.
+    let (tx_config, rx_config) = watch::channel(config);
+    {
+        let path = cli_options.config.context("missing --config")?;
+        tokio::spawn(async move { reload_config_on_sighup(path, tx_config).await.unwrap() });
+    }
 
-        tx_indexers.send(indexers.clone())?;
+    tokio::spawn(refresh_indexers_forever(
+        rx_config.clone(),
+        store.clone(),
+        poi_cache,
+        tx_indexers,
+        tx_tracked_deployments,
+    ));
+    tokio::spawn(poll_graph_node_versions_forever(
+        rx_config.clone(),
+        store.clone(),
+        rx_indexers.clone(),
+    ));
+
+    let poi_store: Arc<dyn PoiStore> = Arc::new(store.clone());
+    poll_pois_forever(
+        rx_config,
+        store,
+        poi_store,
+        rx_indexers,
+        rx_tracked_deployments,
+        tx_indexing_statuses,
+        tx_indexer_connectivity,
+        tx_initialized,
+        &poll_trigger,
+    )
+    .await;
+    Ok(())
+}
 
-        let graph_node_versions =
-            graphix_lib::indexing_loop::query_graph_node_versions(&indexers, metrics()).await;
-        store.write_graph_node_versions(graph_node_versions).await?;
+fn warn_about_query_spread(config: &Config) {
+    if config.query_spread_seconds > config.polling_period_in_seconds {
+        warn!(
+            query_spread_seconds = config.query_spread_seconds,
+            polling_period_in_seconds = config.polling_period_in_seconds,
+            "The query spread is longer than the polling period, main loop iterations will take longer than expected"
+        );
+    }
+}
 
-        let indexing_statuses = query_indexing_statuses(&indexers, metrics()).await;
+/// Re-reads the configuration file whenever Graphix receives `SIGHUP` and
+/// publishes the changes that can be applied without a restart (see
+/// [`Config::reload`]). Invalid configuration files are logged and ignored.
+async fn reload_config_on_sighup(
+    path: PathBuf,
+    tx_config: watch::Sender<Config>,
+) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sighup = signal(SignalKind::hangup()).context("failed to listen for SIGHUP")?;
+    while sighup.recv().await.is_some() {
+        info!(path = %path.display(), "Received SIGHUP, reloading configuration file");
+        let new_config = match Config::read(&path) {
+            Ok(config) => config,
+            Err(err) => {
+                error!(
+                    error = %err,
+                    "Invalid configuration file, keeping the current configuration"
+                );
+                continue;
+            }
+        };
+
+        let config = tx_config.borrow().reload(new_config);
+        warn_about_query_spread(&config);
+        tx_config.send_replace(config);
+        info!("Reloaded configuration");
+    }
 
-        info!("Monitor proofs of indexing");
-        let pois = query_proofs_of_indexing(indexing_statuses, config.block_choice_policy).await;
+    Ok(())
+}
 
-        info!(pois = pois.len(), "Finished tracking Pois");
+/// Sleeps for `period`, or until the configuration changes or `trigger` is
+/// triggered.
+async fn sleep_or_reload(
+    task: &str,
+    period: Duration,
+    rx_config: &mut watch::Receiver<Config>,
+    trigger: Option<&PollTrigger>,
+) {
+    info!(
+        task,
+        sleep_seconds = period.as_secs(),
+        "Sleeping for a while before next polling task iteration"
+    );
+    let triggered = async {
+        match trigger {
+            Some(trigger) => trigger.triggered().await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = tokio::time::sleep(period) => {}
+        // Errors mean that configuration changes are no longer possible.
+        Ok(()) = rx_config.changed() => {
+            info!(task, "Configuration changed, starting next polling task iteration early");
+        }
+        _ = triggered => {
+            info!(task, "Poll triggered, starting next polling task iteration early");
+        }
+    }
+}
 
-        let write_err = store.write_pois(pois, PoiLiveness::Live).await.err();
-        if let Some(err) = write_err {
-            error!(error = %err, "Failed to write POIs to database");
+/// Runs `iteration` with the current configuration every `period`, logging
+/// and exporting the duration and completion time of each iteration under the
+/// `task` label. Configuration changes start the next iteration right away, and
+/// so does `trigger` unless an iteration is running.
+async fn run_periodically<F, Fut>(
+    task: &str,
+    mut rx_config: watch::Receiver<Config>,
+    period: fn(&Config) -> Duration,
+    trigger: Option<&PollTrigger>,
+    mut iteration: F,
+) where
+    F: FnMut(Config) -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    loop {
+        info!(task, "New polling task iteration");
+        let config = rx_config.borrow_and_update().clone();
+        let period = period(&config);
+        let iteration_start = Instant::now();
+        // Each iteration is a trace of its own.
+        let span = info_span!(parent: None, "loop_iteration", task);
+        let iteration = iteration(config).instrument(span);
+        let result = match trigger {
+            Some(trigger) => trigger.run(iteration).await,
+            None => iteration.await,
+        };
+
+        let iteration_duration = iteration_start.elapsed();
+        metrics()
+            .loop_iteration_duration_seconds
+            .with_label_values(&[task])
+            .observe(iteration_duration.as_secs_f64());
+        match result {
+            Ok(()) => {
+                let completion_timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                metrics()
+                    .last_loop_completion_timestamp_seconds
+                    .with_label_values(&[task])
+                    .set(completion_timestamp.as_secs_f64());
+                info!(
+                    task,
+                    duration_secs = iteration_duration.as_secs_f64(),
+                    completion_timestamp = completion_timestamp.as_secs(),
+                    "Polling task iteration completed successfully"
+                );
+            }
+            Err(err) => error!(task, error = %err, "Polling task iteration failed"),
         }
 
-        info!(
-            sleep_seconds = sleep_duration.as_secs(),
-            "Sleeping for a while before next main loop iteration"
-        );
-        tokio::time::sleep(sleep_duration).await;
+        sleep_or_reload(task, period, &mut rx_config, trigger).await;
     }
 }
 
-fn init_tracing() {
-    tracing_subscriber::fmt::init();
+/// Re-creates the indexers from the configured sources (network subgraphs
+/// included), writes them and their configured metadata to the database and
+/// publishes them to the other tasks. Stored indexers that the sources stopped
+/// listing a while ago are flagged as inactive. The active allocations listed
+/// by the network subgraphs are written as well.
+async fn refresh_indexers(
+    config: &Config,
+    store: &Store,
+    poi_cache: Option<&Arc<PoiCache>>,
+    tx_indexers: &watch::Sender<Vec<Arc<dyn IndexerClient>>>,
+) -> anyhow::Result<()> {
+    let discovered = config::config_to_indexers(config.clone(), metrics()).await?;
+    metrics()
+        .observe_store_write(
+            "write_indexer_discovery_counts",
+            store.write_indexer_discovery_counts(discovered.counts),
+        )
+        .await?;
+    // Different data sources, especially network subgraphs, result in
+    // duplicate indexers.
+    let mut indexers = config::deduplicate_indexers(&discovered.indexers, metrics());
+    if let Some(poi_cache) = poi_cache {
+        indexers = config::with_poi_cache(indexers, poi_cache);
+    }
+
+    metrics()
+        .observe_store_write("write_indexers", store.write_indexers(&indexers))
+        .await?;
+    if let Some(inactive_after_days) = config.indexer_inactive_after_days {
+        let deactivated = metrics()
+            .observe_store_write(
+                "deactivate_indexers",
+                store.deactivate_indexers(inactive_after_days),
+            )
+            .await?;
+        if deactivated > 0 {
+            info!(
+                deactivated_indexers = deactivated,
+                inactive_after_days = inactive_after_days,
+                "Flagged indexers that weren't seen for a while as inactive"
+            );
+        }
+    }
+    let indexer_metadata = config
+        .indexer_metadata
+        .iter()
+        .map(|metadata| {
+            let changeset = models::IndexerMetadataChangeset {
+                region: metadata.region.clone(),
+                labels: metadata.labels.clone(),
+            };
+            (metadata.address, changeset)
+        })
+        .collect();
+    metrics()
+        .observe_store_write(
+            "write_indexer_metadata",
+            store.write_indexer_metadata(indexer_metadata),
+        )
+        .await?;
+    if config.consensus_weighting == ConsensusWeighting::Stake {
+        match config::discover_indexer_stakes(config, metrics()).await {
+            Ok(stakes) => {
+                metrics()
+                    .observe_store_write("write_indexer_stakes", store.write_indexer_stakes(stakes))
+                    .await?
+            }
+            Err(err) => {
+                warn!(error = %err, "Failed to query indexer stakes, keeping the previous ones")
+            }
+        }
+    }
+    for (network_subgraph, allocations) in config::discover_allocations(config, metrics()).await? {
+        let closed = metrics()
+            .observe_store_write(
+                "write_active_allocations",
+                store.write_active_allocations(&network_subgraph, allocations),
+            )
+            .await?;
+        if closed > 0 {
+            info!(
+                network_subgraph = %network_subgraph,
+                closed_allocations = closed,
+                "Marked allocations that are no longer active as closed"
+            );
+        }
+    }
+
+    tx_indexers.send_replace(indexers);
+    Ok(())
 }
 
-fn deduplicate_indexers(indexers: &[Arc<dyn IndexerClient>]) -> Vec<Arc<dyn IndexerClient>> {
-    info!(len = indexers.len(), "Deduplicating indexers");
-    let mut seen = HashSet::new();
-    let mut deduplicated = vec![];
-    for indexer in indexers {
-        if !seen.contains(&indexer.address()) {
-            deduplicated.push(indexer.clone());
-            seen.insert(indexer.address());
+/// Discovers the subgraph deployments to track from the network subgraphs
+/// (see [`config::discover_deployments`]), writes them to the database and
+/// publishes them to the main loop. If discovery fails, the previously
+/// discovered deployments remain tracked.
+async fn refresh_tracked_deployments(
+    config: &Config,
+    store: &Store,
+    tx_tracked_deployments: &watch::Sender<Option<HashSet<SubgraphDeployment>>>,
+) {
+    let discovered = match config::discover_deployments(config, metrics()).await {
+        Ok(discovered) => discovered,
+        Err(err) => {
+            warn!(error = %err, "Failed to discover deployments, keeping the previous ones");
+            return;
+        }
+    };
+    let tracked_deployments = discovered.as_ref().map(|discovered| {
+        discovered
+            .iter()
+            .map(|deployment| SubgraphDeployment(deployment.ipfs_cid.clone()))
+            .collect()
+    });
+
+    match metrics()
+        .observe_store_write(
+            "replace_tracked_deployments",
+            store.replace_tracked_deployments(discovered.unwrap_or_default()),
+        )
+        .await
+    {
+        Ok(changes) => {
+            for deployment in changes.added {
+                info!(deployment = %deployment, "Started tracking deployment");
+            }
+            for deployment in changes.removed {
+                info!(deployment = %deployment, "Stopped tracking deployment");
+            }
         }
+        Err(err) => error!(error = %err, "Failed to write tracked deployments"),
     }
-    info!(
-        len = deduplicated.len(),
-        delta = indexers.len() - deduplicated.len(),
-        "Successfully deduplicated indexers"
-    );
-    deduplicated
+
+    tx_tracked_deployments.send_replace(tracked_deployments);
+}
+
+async fn refresh_indexers_forever(
+    mut rx_config: watch::Receiver<Config>,
+    store: Store,
+    // Shared across refreshes, as indexers are re-created every time.
+    poi_cache: Option<Arc<PoiCache>>,
+    tx_indexers: watch::Sender<Vec<Arc<dyn IndexerClient>>>,
+    tx_tracked_deployments: watch::Sender<Option<HashSet<SubgraphDeployment>>>,
+) {
+    let period = |config: &Config| Duration::from_secs(config.indexer_refresh_period_in_seconds);
+    let (store, poi_cache, tx_indexers) = (&store, poi_cache.as_ref(), &tx_indexers);
+    let tx_tracked_deployments = &tx_tracked_deployments;
+
+    // The initial refresh has already happened.
+    let initial_period = period(&rx_config.borrow());
+    sleep_or_reload("indexers", initial_period, &mut rx_config, None).await;
+    run_periodically(
+        "indexers",
+        rx_config,
+        period,
+        None,
+        move |config| async move {
+            refresh_indexers(&config, store, poi_cache, tx_indexers).await?;
+            refresh_tracked_deployments(&config, store, tx_tracked_deployments).await;
+            Ok(())
+        },
+    )
+    .await
+}
+
+async fn poll_graph_node_versions_forever(
+    rx_config: watch::Receiver<Config>,
+    store: Store,
+    rx_indexers: watch::Receiver<Vec<Arc<dyn IndexerClient>>>,
+) {
+    let period =
+        |config: &Config| Duration::from_secs(config.graph_node_version_polling_period_in_seconds);
+    let (store, rx_indexers) = (&store, &rx_indexers);
+
+    run_periodically(
+        "graph_node_versions",
+        rx_config,
+        period,
+        None,
+        move |config| async move {
+            let query_spread = Duration::from_secs(config.query_spread_seconds);
+            let indexers = rx_indexers.borrow().clone();
+            let graph_node_versions =
+                query_graph_node_versions(&indexers, metrics(), query_spread).await;
+            metrics()
+                .observe_store_write(
+                    "write_graph_node_versions",
+                    store.write_graph_node_versions(graph_node_versions),
+                )
+                .await
+        },
+    )
+    .await
 }
 
-async fn axum_server(config: Config) -> anyhow::Result<Router<()>> {
+#[allow(clippy::too_many_arguments)]
+async fn poll_pois_forever(
+    rx_config: watch::Receiver<Config>,
+    store: Store,
+    poi_store: Arc<dyn PoiStore>,
+    rx_indexers: watch::Receiver<Vec<Arc<dyn IndexerClient>>>,
+    rx_tracked_deployments: watch::Receiver<Option<HashSet<SubgraphDeployment>>>,
+    tx_indexing_statuses: watch::Sender<Vec<IndexingStatus>>,
+    tx_indexer_connectivity: watch::Sender<Vec<IndexerConnectivity>>,
+    tx_initialized: watch::Sender<bool>,
+    poll_trigger: &PollTrigger,
+) {
+    let period = |config: &Config| Duration::from_secs(config.polling_period_in_seconds);
+    let main_loop = MainLoop::new(tx_indexing_statuses, tx_indexer_connectivity);
+    let (main_loop, store, rx_indexers) = (&main_loop, &store, &rx_indexers);
+    let poi_store = poi_store.as_ref();
+    let (rx_tracked_deployments, tx_initialized) = (&rx_tracked_deployments, &tx_initialized);
+
+    let trigger = Some(poll_trigger);
+    run_periodically(
+        "pois",
+        rx_config,
+        period,
+        trigger,
+        move |config| async move {
+            let indexers = rx_indexers.borrow().clone();
+            let tracked_deployments = rx_tracked_deployments.borrow().clone();
+            main_loop
+                .run_iteration(
+                    &config,
+                    &indexers,
+                    tracked_deployments.as_ref(),
+                    store,
+                    poi_store,
+                )
+                .await?;
+
+            // Failed iterations may have written nothing, so only successful
+            // ones count.
+            if !tx_initialized.send_replace(true) {
+                info!("First main loop iteration completed, ready to serve data");
+            }
+            Ok(())
+        },
+    )
+    .await
+}
+
+/// Sets up logging, and trace export if `observability.otlpEndpoint` is
+/// configured. The log format and filter are taken from the CLI options, then
+/// `RUST_LOG` (filter only), then the configuration file. Invalid filters are
+/// reported as errors rather than ignored. Returns whether traces are
+/// exported.
+fn init_tracing(cli_options: &CliOptions, config: Option<&Config>) -> anyhow::Result<bool> {
+    let format = cli_options
+        .log_format
+        .or_else(|| config.and_then(|config| config.log_format))
+        .unwrap_or_default();
+
+    let env_filter = std::env::var(EnvFilter::DEFAULT_ENV).ok();
+    let filter = log_filter(
+        cli_options.log_level.as_deref(),
+        env_filter.as_deref(),
+        config.and_then(|config| config.log_level.as_deref()),
+    )?;
+
+    // Logs go to stderr, so that they don't get mixed up with data exported
+    // to stdout.
+    let fmt_layer = tracing_subscriber::fmt::layer().with_writer(std::io::stderr);
+    let fmt_layer = match format {
+        LogFormat::Plain => fmt_layer.boxed(),
+        LogFormat::Json => fmt_layer
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_target(true)
+            .boxed(),
+    };
+
+    let otlp_endpoint = config.and_then(|config| config.observability.otlp_endpoint.as_deref());
+    let otel_layer = match otlp_endpoint {
+        Some(endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(opentelemetry_sdk::trace::config().with_resource(
+                    opentelemetry_sdk::Resource::new([opentelemetry::KeyValue::new(
+                        "service.name",
+                        "graphix",
+                    )]),
+                ))
+                .install_batch(opentelemetry_sdk::runtime::Tokio)
+                .with_context(|| format!("failed to set up trace export to `{}`", endpoint))?;
+            // Spans of the HTTP and gRPC libraries would include those of the
+            // export itself.
+            let targets = Targets::new().with_target("graphix", Level::DEBUG);
+            Some(
+                tracing_opentelemetry::layer()
+                    .with_tracer(tracer)
+                    .with_filter(targets),
+            )
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(fmt_layer.with_filter(filter))
+        .with(otel_layer)
+        .try_init()
+        .context("failed to initialize tracing")?;
+
+    Ok(otlp_endpoint.is_some())
+}
+
+/// Resolves on `SIGINT` or `SIGTERM`.
+async fn shutdown_signal() -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = signal(SignalKind::terminate()).context("failed to listen for SIGTERM")?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result.context("failed to listen for SIGINT"),
+        _ = sigterm.recv() => Ok(()),
+    }
+}
+
+/// The first of the given log filters that is set, in order of precedence,
+/// or `info` if none are.
+fn log_filter(
+    cli_options_level: Option<&str>,
+    env_filter: Option<&str>,
+    config_level: Option<&str>,
+) -> anyhow::Result<EnvFilter> {
+    let directives = cli_options_level
+        .or(env_filter)
+        .or(config_level)
+        .unwrap_or(DEFAULT_LOG_FILTER);
+
+    EnvFilter::try_new(directives).with_context(|| format!("invalid log filter `{}`", directives))
+}
+
+async fn axum_server(
+    config: Config,
+    indexer_connectivity: watch::Receiver<Vec<IndexerConnectivity>>,
+    initialized: watch::Receiver<bool>,
+    poll_trigger: Arc<PollTrigger>,
+) -> anyhow::Result<Router<()>> {
+    use axum::extract::Query;
+    use axum::http::HeaderMap;
     use axum::routing::get;
 
-    let store = Store::new(config.database_url.as_str()).await?;
-    let api_schema_ctx = graphql_api::ApiSchemaContext::new(store.clone(), config.clone());
+    let store = Store::new_with_options(
+        &config.database_url,
+        config.database.store_options(metrics()),
+    )
+    .await?;
+    let api_schema_ctx = graphql_api::ApiSchemaContext::new(store.clone(), config.clone())
+        .with_indexer_connectivity(indexer_connectivity)
+        .with_initialized(initialized.clone());
     let api_schema = graphql_api::api_schema(api_schema_ctx);
 
-    Ok(axum::Router::new()
+    let admin_api_keys = Arc::new(config.graphql.admin_api_keys.clone());
+    let mut graphql_route =
+        get(graphiql_route).post(move |request_id, headers: HeaderMap, request| {
+            let admin_api_key = utils::admin_api_key(&admin_api_keys, &headers);
+            request_logging::graphql(api_schema.clone(), request_id, admin_api_key, request)
+        });
+    if let Some(rate_limit) = &config.graphql.rate_limit {
+        let limiter = Arc::new(rate_limiting::ClientRateLimiter::new(rate_limit.clone()));
+        graphql_route = graphql_route.layer(axum::middleware::from_fn(move |request, next| {
+            rate_limiting::limit_requests(limiter.clone(), request, next)
+        }));
+    }
+
+    let mut router = axum::Router::new()
         .route(
             "/",
             get(|| async {
                 format!(
-                    "Welcome to Graphix v{}. Go to /graphql to use the playground.",
-                    GRAPHIX_VERSION
+                    "Welcome to Graphix v{} (commit {}). Go to /graphql to use the playground.",
+                    GRAPHIX_VERSION, GRAPHIX_GIT_SHA
                 )
             }),
         )
-        .route(
-            "/graphql",
-            get(graphiql_route).post_service(GraphQL::new(api_schema)),
-        ))
+        .route("/graphql", graphql_route)
+        .route("/ready", get(move || ready(initialized.clone())))
+        .merge(rest_api::router(store.clone()));
+    if let Some(token) = config.graphql.poll_token.clone() {
+        router = router.merge(poll_trigger::router(poll_trigger, token));
+    }
+    if config.graphql.poi_export_enabled {
+        router = router.route(
+            "/export/pois.ndjson",
+            get(move |query: Query<export::NdjsonExportQuery>| {
+                export::export_pois_ndjson(store.clone(), query)
+            }),
+        );
+    }
+
+    // Responses are compressed according to the `Accept-Encoding` request
+    // header, and requests are decompressed according to `Content-Encoding`.
+    let compress_when = SizeAbove::new(config.graphql.compression_min_size_in_bytes)
+        .and(NotForContentType::GRPC)
+        .and(NotForContentType::IMAGES)
+        .and(NotForContentType::SSE);
+    router = router
+        .layer(RequestDecompressionLayer::new())
+        .layer(CompressionLayer::new().compress_when(compress_when));
+
+    // Applies to all routes, so it's added last.
+    let slow_request_threshold =
+        Duration::from_secs_f64(config.graphql.slow_request_threshold_in_seconds);
+    Ok(
+        router.layer(axum::middleware::from_fn(move |request, next| {
+            request_logging::log_requests(slow_request_threshold, request, next)
+        })),
+    )
 }
 
 async fn graphiql_route() -> impl IntoResponse {
     axum::response::Html(GraphiQLSource::build().endpoint("/graphql").finish())
 }
+
+/// `GET /ready`, which fails until the main loop has completed an iteration,
+/// so that load balancers and dashboards don't mistake the empty database of
+/// a fresh instance for indexers that all agree.
+async fn ready(initialized: watch::Receiver<bool>) -> impl IntoResponse {
+    use axum::http::StatusCode;
+
+    if *initialized.borrow() {
+        (StatusCode::OK, "ready")
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "waiting for the first main loop iteration",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use graphix_indexer_client::ProofOfIndexing;
+    use graphix_lib::test_utils::gen::gen_blocks;
+    use graphix_lib::test_utils::mocks::MockIndexerBuilder;
+    use graphix_lib::test_utils::store::EmptyStoreForTesting;
+    use graphix_store::models::NewNetwork;
+    use graphix_store::PoiLiveness;
+    use testcontainers::clients::Cli;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn large_responses_are_compressed() {
+        let docker = Cli::default();
+        let store = EmptyStoreForTesting::new(&docker).await.unwrap();
+        store
+            .create_network(&NewNetwork {
+                name: "mainnet".to_string(),
+                caip2: None,
+            })
+            .await
+            .unwrap();
+
+        let indexers: Vec<Arc<dyn IndexerClient>> = (0..10)
+            .map(|i| MockIndexerBuilder::new(format!("indexer-{i}")).client())
+            .collect();
+        store.write_indexers(&indexers).await.unwrap();
+        let mut pois = vec![];
+        for (i, indexer) in indexers.iter().enumerate() {
+            for block in gen_blocks() {
+                pois.push(ProofOfIndexing {
+                    indexer: indexer.clone(),
+                    deployment: SubgraphDeployment(
+                        "QmNY7gDNXHECV8SXoEY7hbfg4BX1aDMxTBDiFuG4huaSGA".to_string(),
+                    ),
+                    block,
+                    proof_of_indexing: [i as u8; 32].into(),
+                });
+            }
+        }
+        store.write_pois(pois, PoiLiveness::Live).await.unwrap();
+
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "graphql": {},
+            "databaseUrl": store.database_url(),
+            "sources": [],
+        }))
+        .unwrap();
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let url = format!("http://{}/graphql", listener.local_addr().unwrap());
+        let router = axum_server(
+            config,
+            watch::channel(vec![]).1,
+            watch::channel(true).1,
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let query = serde_json::json!({
+            "query": "{ proofsOfIndexing { hash block { number hash } indexer { address } } }"
+        });
+        let client = reqwest::Client::new();
+        let response = client
+            .post(&url)
+            .header("accept-encoding", "gzip")
+            .json(&query)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.headers()["content-encoding"], "gzip");
+
+        // Small responses and clients that don't ask for it aren't affected.
+        let response = client
+            .post(&url)
+            .header("accept-encoding", "gzip")
+            .json(&serde_json::json!({ "query": "{ __typename }" }))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.headers().get("content-encoding").is_none());
+        let response = client.post(&url).json(&query).send().await.unwrap();
+        assert!(response.headers().get("content-encoding").is_none());
+    }
+
+    #[tokio::test]
+    async fn ready_after_first_iteration() {
+        let (tx_initialized, rx_initialized) = watch::channel(false);
+        let status = || async { ready(rx_initialized.clone()).await.into_response().status() };
+
+        assert_eq!(status().await, 503);
+        tx_initialized.send_replace(true);
+        assert_eq!(status().await, 200);
+    }
+
+    #[test]
+    fn log_filter_precedence() {
+        let filter = |cli, env, config| log_filter(cli, env, config).unwrap().to_string();
+
+        assert_eq!(filter(None, None, None), "info");
+        assert_eq!(filter(None, None, Some("warn")), "warn");
+        assert_eq!(
+            filter(None, Some("graphix=trace"), Some("warn")),
+            "graphix=trace"
+        );
+        assert_eq!(
+            filter(Some("debug"), Some("graphix=trace"), Some("warn")),
+            "debug"
+        );
+        assert!(log_filter(Some("graphix=nope"), None, None).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+//! Fetches the manifests of subgraph deployments from IPFS in the background,
+//! one at a time. Failed fetches are retried with exponential backoff (see
+//! [`Store::write_sg_deployment_manifest_failure`]).
+
+use std::time::Duration;
+
+use graphix_lib::manifest::IpfsGateway;
+use graphix_store::Store;
+use tracing::{debug, error, info, warn};
+
+/// How many deployments are picked up from the database at once.
+const BATCH_SIZE: u16 = 100;
+/// Spaces out requests to the IPFS gateway.
+const DELAY_BETWEEN_FETCHES: Duration = Duration::from_secs(1);
+/// How long to wait before checking for new deployments again, once all
+/// manifests that are due have been fetched.
+const IDLE_DELAY: Duration = Duration::from_secs(60);
+
+pub async fn fetch_deployment_manifests_forever(store: Store, gateway: IpfsGateway) {
+    loop {
+        let deployments = match store.sg_deployments_without_manifest(BATCH_SIZE).await {
+            Ok(deployments) => deployments,
+            Err(err) => {
+                error!(error = %err, "Failed to load deployments without manifest");
+                tokio::time::sleep(IDLE_DELAY).await;
+                continue;
+            }
+        };
+        if deployments.is_empty() {
+            debug!("No deployment manifests to fetch");
+            tokio::time::sleep(IDLE_DELAY).await;
+            continue;
+        }
+
+        for (sg_deployment_id, cid) in deployments {
+            let result = match gateway.deployment_manifest(&cid).await {
+                Ok(manifest) => {
+                    info!(deployment = %cid, "Fetched deployment manifest");
+                    store
+                        .write_sg_deployment_manifest(&manifest.into_model(sg_deployment_id))
+                        .await
+                }
+                Err(err) => {
+                    warn!(deployment = %cid, error = %err, "Failed to fetch deployment manifest");
+                    store
+                        .write_sg_deployment_manifest_failure(sg_deployment_id, &format!("{err:#}"))
+                        .await
+                }
+            };
+            if let Err(err) = result {
+                error!(deployment = %cid, error = %err, "Failed to store deployment manifest");
+            }
+
+            tokio::time::sleep(DELAY_BETWEEN_FETCHES).await;
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! `POST /poll`, which lets operators start a main loop iteration right away
+//! rather than waiting for the polling period to elapse, e.g. while
+//! investigating an issue.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::Serialize;
+use subtle::ConstantTimeEq;
+use tokio::sync::Notify;
+use tracing::info;
+
+use crate::utils::bearer_token;
+
+/// Wakes up the main loop between iterations. Triggers while an iteration is
+/// running are coalesced into it, and so are repeated triggers before the
+/// main loop wakes up.
+#[derive(Debug, Default)]
+pub struct PollTrigger {
+    notify: Notify,
+    running: AtomicBool,
+}
+
+/// The outcome of [`PollTrigger::trigger`].
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PollOutcome {
+    /// An iteration starts as soon as the main loop wakes up.
+    Started,
+    /// An iteration is running, so no other one was started.
+    AlreadyRunning,
+}
+
+impl PollTrigger {
+    pub fn trigger(&self) -> PollOutcome {
+        if self.running.load(Ordering::SeqCst) {
+            return PollOutcome::AlreadyRunning;
+        }
+
+        // `Notify` keeps at most one permit, so that repeated triggers
+        // before the main loop wakes up result in a single iteration.
+        self.notify.notify_one();
+        PollOutcome::Started
+    }
+
+    /// Resolves when a poll is triggered.
+    pub async fn triggered(&self) {
+        self.notify.notified().await
+    }
+
+    /// Runs a main loop iteration, during which triggers are ignored.
+    pub async fn run<T>(&self, iteration: impl Future<Output = T>) -> T {
+        self.running.store(true, Ordering::SeqCst);
+        let output = iteration.await;
+        self.running.store(false, Ordering::SeqCst);
+        output
+    }
+}
+
+/// Serves `POST /poll` to requests with an `Authorization: Bearer <token>`
+/// header.
+pub fn router(trigger: Arc<PollTrigger>, token: String) -> Router {
+    Router::new()
+        .route("/poll", post(poll))
+        .with_state((trigger, Arc::new(token)))
+}
+
+#[derive(Serialize, Debug)]
+struct PollResponse {
+    outcome: PollOutcome,
+}
+
+async fn poll(
+    State((trigger, token)): State<(Arc<PollTrigger>, Arc<String>)>,
+    headers: HeaderMap,
+) -> Response {
+    if !is_authorized(&headers, &token) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({ "error": "missing or invalid bearer token" })),
+        )
+            .into_response();
+    }
+
+    let outcome = trigger.trigger();
+    info!(?outcome, "Poll triggered through the API");
+    Json(PollResponse { outcome }).into_response()
+}
+
+/// Compares the bearer token in constant time, so that response times don't
+/// leak how much of the token was guessed right.
+fn is_authorized(headers: &HeaderMap, token: &str) -> bool {
+    bearer_token(headers).map_or(false, |bearer| {
+        bearer.as_bytes().ct_eq(token.as_bytes()).into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::{header, HeaderValue};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn triggers_coalesce() {
+        let trigger = PollTrigger::default();
+        assert_eq!(trigger.trigger(), PollOutcome::Started);
+        assert_eq!(trigger.trigger(), PollOutcome::Started);
+        // Both triggers wake up the main loop only once.
+        tokio::time::timeout(Duration::from_secs(1), trigger.triggered())
+            .await
+            .unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), trigger.triggered())
+                .await
+                .is_err()
+        );
+
+        let outcome = trigger.run(async { trigger.trigger() }).await;
+        assert_eq!(outcome, PollOutcome::AlreadyRunning);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), trigger.triggered())
+                .await
+                .is_err()
+        );
+        assert_eq!(trigger.trigger(), PollOutcome::Started);
+    }
+
+    #[test]
+    fn only_the_bearer_token_is_authorized() {
+        let headers = |value: &'static str| {
+            HeaderMap::from_iter([(header::AUTHORIZATION, HeaderValue::from_static(value))])
+        };
+        assert!(is_authorized(&headers("Bearer secret"), "secret"));
+        assert!(!is_authorized(&headers("Bearer other"), "secret"));
+        assert!(!is_authorized(&headers("secret"), "secret"));
+        assert!(!is_authorized(&HeaderMap::new(), "secret"));
+    }
+}
@@ -0,0 +1,198 @@
+//! Per-client rate limiting of the GraphQL API, so that a single misbehaving
+//! client can't overload the database.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, Request};
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use graphix_lib::config::GraphQlRateLimitConfig;
+use graphix_lib::metrics;
+use tracing::*;
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+    last_seen: u64,
+}
+
+#[derive(Debug, Default)]
+struct ClientRateLimiterInner {
+    buckets: HashMap<IpAddr, TokenBucket>,
+    /// Clients ordered by the logical time at which they were last seen,
+    /// least recently seen first.
+    recency: BTreeMap<u64, IpAddr>,
+    clock: u64,
+}
+
+/// A token bucket per client IP address. Clients may send `burst` requests at
+/// once, and `requestsPerMinute` on average. Requests beyond the rate are
+/// rejected. Only the most recently seen clients are kept track of, and
+/// forgetting a client gives it a full bucket again.
+#[derive(Debug)]
+pub struct ClientRateLimiter {
+    config: GraphQlRateLimitConfig,
+    inner: Mutex<ClientRateLimiterInner>,
+}
+
+impl ClientRateLimiter {
+    pub fn new(config: GraphQlRateLimitConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(ClientRateLimiterInner::default()),
+        }
+    }
+
+    /// Takes a token from the bucket of `client`, or returns how long until
+    /// one is available if there's none.
+    fn check(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        if self
+            .config
+            .exempt_ranges
+            .iter()
+            .any(|range| range.contains(client))
+        {
+            return Ok(());
+        }
+
+        let rate = f64::from(self.config.requests_per_minute) / 60.0;
+        let capacity = f64::from(self.config.burst);
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        inner.clock += 1;
+        let last_seen = inner.clock;
+        if !inner.buckets.contains_key(&client)
+            && inner.buckets.len() >= self.config.max_tracked_clients
+        {
+            if let Some((_, evicted)) = inner.recency.pop_first() {
+                inner.buckets.remove(&evicted);
+            }
+        }
+        let bucket = inner.buckets.entry(client).or_insert(TokenBucket {
+            tokens: capacity,
+            refilled_at: now,
+            last_seen,
+        });
+        inner.recency.remove(&bucket.last_seen);
+        inner.recency.insert(last_seen, client);
+        bucket.last_seen = last_seen;
+
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * rate).min(capacity);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+/// Middleware that rejects requests beyond the rate limit of their client
+/// with `429 Too Many Requests` and a `Retry-After` header. Requests whose
+/// client address is unknown aren't limited.
+pub async fn limit_requests(
+    limiter: Arc<ClientRateLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.ip());
+
+    if let Some(client) = client {
+        if let Err(retry_after) = limiter.check(client, Instant::now()) {
+            metrics()
+                .graphql_rejected_requests
+                .with_label_values(&["rate_limit"])
+                .inc();
+            debug!(%client, "Rate limited GraphQL request");
+
+            let retry_after_seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after_seconds.to_string())],
+                "Too many requests, slow down",
+            )
+                .into_response();
+        }
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(config: serde_json::Value) -> ClientRateLimiter {
+        ClientRateLimiter::new(serde_json::from_value(config).unwrap())
+    }
+
+    #[test]
+    fn requests_beyond_the_burst_are_rejected_until_refilled() {
+        let limiter = limiter(serde_json::json!({ "requestsPerMinute": 60, "burst": 3 }));
+        let client = "192.0.2.1".parse().unwrap();
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check(client, start).is_ok());
+        }
+        assert_eq!(limiter.check(client, start), Err(Duration::from_secs(1)));
+        // Other clients have buckets of their own.
+        assert!(limiter.check("192.0.2.2".parse().unwrap(), start).is_ok());
+
+        // One request per second.
+        let now = start + Duration::from_millis(1500);
+        assert!(limiter.check(client, now).is_ok());
+        assert!(limiter.check(client, now).is_err());
+    }
+
+    #[test]
+    fn exempt_clients_are_never_limited() {
+        let limiter = limiter(serde_json::json!({
+            "requestsPerMinute": 1,
+            "burst": 1,
+            "exemptRanges": ["10.0.0.0/8"],
+        }));
+        let now = Instant::now();
+        for _ in 0..10 {
+            assert!(limiter.check("10.0.0.1".parse().unwrap(), now).is_ok());
+        }
+        assert!(limiter.check("192.0.2.1".parse().unwrap(), now).is_ok());
+        assert!(limiter.check("192.0.2.1".parse().unwrap(), now).is_err());
+    }
+
+    #[test]
+    fn least_recently_seen_clients_are_forgotten() {
+        let limiter = limiter(serde_json::json!({
+            "requestsPerMinute": 1,
+            "burst": 1,
+            "maxTrackedClients": 2,
+        }));
+        let (a, b, c) = (
+            "192.0.2.1".parse().unwrap(),
+            "192.0.2.2".parse().unwrap(),
+            "192.0.2.3".parse().unwrap(),
+        );
+        let now = Instant::now();
+
+        assert!(limiter.check(a, now).is_ok());
+        assert!(limiter.check(b, now).is_ok());
+        assert!(limiter.check(a, now).is_err());
+        // `b` is forgotten to make room for `c`.
+        assert!(limiter.check(c, now).is_ok());
+        assert_eq!(limiter.inner.lock().unwrap().buckets.len(), 2);
+        assert!(limiter.check(a, now).is_err());
+        assert!(limiter.check(b, now).is_ok());
+    }
+}
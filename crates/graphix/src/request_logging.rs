@@ -0,0 +1,130 @@
+//! Access logs for the API server. Every request gets an ID, which is logged,
+//! returned in the `x-request-id` response header and attached to the errors
+//! of GraphQL responses, so that problems reported by API users can be traced
+//! back to the logs. Query variables are never logged, as they may be
+//! sensitive.
+
+use std::time::{Duration, Instant};
+
+use async_graphql::{BatchRequest, BatchResponse};
+use async_graphql_axum::{GraphQLBatchRequest, GraphQLResponse};
+use axum::extract::{Extension, Request};
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use graphix_lib::graphql_api::{AdminApiKey, ApiSchema};
+use tracing::*;
+use uuid::Uuid;
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Identifies a request in logs and in the errors of GraphQL responses. Taken
+/// from the `x-request-id` request header if present, e.g. when a proxy
+/// already assigned one, or generated otherwise.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+/// The names of the GraphQL operations that a request executed, passed from
+/// [`graphql`] to [`log_requests`] through the response extensions.
+#[derive(Clone, Debug)]
+struct OperationNames(Vec<String>);
+
+/// Middleware that assigns request IDs and logs every request once it's been
+/// handled, as a warning if it took longer than `slow_request_threshold`.
+pub async fn log_requests(
+    slow_request_threshold: Duration,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    request
+        .extensions_mut()
+        .insert(RequestId(request_id.clone()));
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+
+    let start = Instant::now();
+    let mut response = next.run(request).await;
+    let duration = start.elapsed();
+
+    let operation_names = response
+        .extensions()
+        .get::<OperationNames>()
+        .map(|names| names.0.join(","));
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    if duration > slow_request_threshold {
+        warn!(
+            request_id,
+            %method,
+            path,
+            operation = operation_names,
+            status = response.status().as_u16(),
+            duration_ms = duration.as_millis() as u64,
+            "Slow request"
+        );
+    } else {
+        info!(
+            request_id,
+            %method,
+            path,
+            operation = operation_names,
+            status = response.status().as_u16(),
+            duration_ms = duration.as_millis() as u64,
+            "Request"
+        );
+    }
+
+    response
+}
+
+/// Executes GraphQL requests, making the [`RequestId`] and the
+/// [`AdminApiKey`], if any, available to resolvers and adding the request ID
+/// to the extensions of all errors as `requestId`.
+pub async fn graphql(
+    schema: ApiSchema,
+    Extension(request_id): Extension<RequestId>,
+    admin_api_key: Option<AdminApiKey>,
+    request: GraphQLBatchRequest,
+) -> Response {
+    let mut request = request.into_inner().data(request_id.clone());
+    if let Some(admin_api_key) = admin_api_key {
+        request = request.data(admin_api_key);
+    }
+    let operation_names = match &request {
+        BatchRequest::Single(request) => request.operation_name.iter().cloned().collect(),
+        BatchRequest::Batch(requests) => requests
+            .iter()
+            .filter_map(|request| request.operation_name.clone())
+            .collect(),
+    };
+
+    let mut response = schema.execute_batch(request).await;
+    let responses = match &mut response {
+        BatchResponse::Single(response) => std::slice::from_mut(response),
+        BatchResponse::Batch(responses) => responses.as_mut_slice(),
+    };
+    for error in responses
+        .iter_mut()
+        .flat_map(|response| &mut response.errors)
+    {
+        error
+            .extensions
+            .get_or_insert_with(Default::default)
+            .set("requestId", request_id.0.clone());
+    }
+
+    let mut response = GraphQLResponse::from(response).into_response();
+    response
+        .extensions_mut()
+        .insert(OperationNames(operation_names));
+    response
+}
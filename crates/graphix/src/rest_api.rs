@@ -0,0 +1,290 @@
+//! The `/api/v1` routes, a small REST layer over the same store queries as
+//! the GraphQL API, for clients that can't easily speak GraphQL, e.g. shell
+//! scripts or Grafana's JSON data source.
+//!
+//! Lists are paginated with the `limit` and `cursor` query parameters: each
+//! page comes with a `next_cursor`, to pass as `cursor` to fetch the next
+//! page, which is null on the last page.
+
+use std::collections::HashSet;
+
+use async_graphql::dataloader::Loader;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use graphix_common_types::{inputs, IndexerAddress, IpfsCid};
+use graphix_store::models::{self, PoiExportRow};
+use graphix_store::{Pagination, PoiFilter, PoiLiveness, Store, StoreLoader};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+/// The number of items per page if `limit` isn't given.
+const DEFAULT_LIMIT: u16 = 100;
+/// The same maximum as that of the `limit` arguments of the GraphQL API.
+const MAX_LIMIT: u16 = 250;
+
+pub fn router(store: Store) -> Router {
+    Router::new()
+        .route("/api/v1/deployments/:cid/pois", get(deployment_pois))
+        .route("/api/v1/indexers", get(indexers))
+        .route("/api/v1/divergences", get(divergences))
+        .with_state(store)
+}
+
+/// A page of results.
+#[derive(Serialize, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Null if there are no more results.
+    pub next_cursor: Option<String>,
+}
+
+/// Failed requests are answered with `{"error": "<message>"}`.
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::Internal(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            Self::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            Self::NotFound(message) => (StatusCode::NOT_FOUND, message),
+            Self::Internal(err) => {
+                error!(error = %err, "REST API request failed");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal error".to_string(),
+                )
+            }
+        };
+
+        (status, Json(serde_json::json!({ "error": message }))).into_response()
+    }
+}
+
+/// The `limit` and `cursor` query parameters that all lists take. Extracted
+/// separately from the other query parameters of a route, because
+/// `#[serde(flatten)]` breaks the parsing of numbers in query strings.
+#[derive(Deserialize, Debug)]
+pub struct PageQuery {
+    limit: Option<u16>,
+    /// Opaque, as returned in the `next_cursor` of the previous page.
+    cursor: Option<String>,
+}
+
+impl PageQuery {
+    fn pagination(&self) -> Result<Pagination, ApiError> {
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT);
+        if limit == 0 || limit > MAX_LIMIT {
+            return Err(ApiError::BadRequest(format!(
+                "`limit` must be between 1 and {MAX_LIMIT}"
+            )));
+        }
+        // Cursors are offsets for now, but clients mustn't rely on that.
+        let offset = match &self.cursor {
+            Some(cursor) => cursor
+                .parse()
+                .map_err(|_| ApiError::BadRequest("invalid `cursor`".to_string()))?,
+            None => 0,
+        };
+
+        Ok(Pagination { offset, limit })
+    }
+}
+
+impl<T> Page<T> {
+    fn new(items: Vec<T>, pagination: Pagination) -> Self {
+        // A full page might be followed by an empty one, which is fine.
+        let next_cursor = (items.len() == usize::from(pagination.limit))
+            .then(|| (pagination.offset + u32::from(pagination.limit)).to_string());
+        Self { items, next_cursor }
+    }
+}
+
+/// The query parameters of `/api/v1/deployments/:cid/pois`.
+#[derive(Deserialize, Debug)]
+pub struct PoisQuery {
+    /// Only PoIs at this block number.
+    block: Option<u64>,
+    /// Only PoIs of the indexer with this address.
+    indexer: Option<String>,
+    /// Only live PoIs if true, and only PoIs that aren't live anymore if
+    /// false.
+    live: Option<bool>,
+}
+
+/// The PoIs of a subgraph deployment, with the same fields as the lines of
+/// `/export/pois.ndjson`, highest block first, like the `proofsOfIndexing`
+/// GraphQL query.
+async fn deployment_pois(
+    State(store): State<Store>,
+    Path(cid): Path<String>,
+    Query(query): Query<PoisQuery>,
+    Query(page): Query<PageQuery>,
+) -> Result<Json<Page<PoiExportRow>>, ApiError> {
+    let cid: IpfsCid = cid
+        .parse()
+        .map_err(|err| ApiError::BadRequest(format!("invalid deployment: {err}")))?;
+    let indexer_address = query
+        .indexer
+        .map(|address| address.parse::<IndexerAddress>())
+        .transpose()
+        .map_err(|err| ApiError::BadRequest(format!("invalid indexer: {err}")))?;
+    let pagination = page.pagination()?;
+
+    let deployments = store
+        .sg_deployments(inputs::SgDeploymentsQuery {
+            ipfs_cid: Some(cid.clone()),
+            ..Default::default()
+        })
+        .await?;
+    if deployments.is_empty() {
+        return Err(ApiError::NotFound(format!("unknown deployment `{cid}`")));
+    }
+
+    let filter = PoiFilter {
+        deployments: vec![cid.clone()],
+        indexer_address,
+        block_range: query.block.map(|block| inputs::BlockRange {
+            start: Some(block),
+            end: Some(block),
+        }),
+        liveness: query.live.map(|live| match live {
+            true => PoiLiveness::Live,
+            false => PoiLiveness::NotLive,
+        }),
+        ..Default::default()
+    };
+    let pois = store.search_pois(&filter, pagination).await?;
+
+    let indexer_ids: Vec<_> = unique(pois.iter().map(|poi| poi.indexer_id));
+    let block_ids: Vec<_> = unique(pois.iter().map(|poi| poi.block_id));
+    let indexers = Loader::<models::IntId>::load(
+        &StoreLoader::<models::Indexer>::new(store.clone()),
+        &indexer_ids,
+    )
+    .await
+    .map_err(anyhow::Error::msg)?;
+    let blocks = Loader::<models::BigIntId>::load(
+        &StoreLoader::<models::Block>::new(store.clone()),
+        &block_ids,
+    )
+    .await
+    .map_err(anyhow::Error::msg)?;
+
+    let mut rows = vec![];
+    for poi in pois {
+        let (Some(indexer), Some(block)) =
+            (indexers.get(&poi.indexer_id), blocks.get(&poi.block_id))
+        else {
+            return Err(anyhow::anyhow!("inconsistent pois table, no indexer or block").into());
+        };
+        rows.push(PoiExportRow {
+            id: poi.id,
+            timestamp: poi.created_at,
+            indexer_address: indexer.address,
+            deployment_cid: cid.to_string(),
+            block_number: block.number,
+            block_hash: block.hash.clone(),
+            poi: poi.poi,
+        });
+    }
+
+    Ok(Json(Page::new(rows, pagination)))
+}
+
+/// The query parameters of `/api/v1/indexers`.
+#[derive(Deserialize, Debug)]
+pub struct IndexersQuery {
+    /// Whether to include indexers that haven't been seen for a while.
+    #[serde(default)]
+    include_inactive: bool,
+}
+
+/// All indexers, like the `indexers` GraphQL query.
+async fn indexers(
+    State(store): State<Store>,
+    Query(query): Query<IndexersQuery>,
+    Query(page): Query<PageQuery>,
+) -> Result<Json<Page<models::Indexer>>, ApiError> {
+    let pagination = page.pagination()?;
+    let indexers = store
+        .indexers(inputs::IndexersQuery {
+            address: None,
+            limit: Some(pagination.limit),
+            offset: Some(pagination.offset),
+            include_inactive: query.include_inactive,
+        })
+        .await?;
+
+    Ok(Json(Page::new(indexers, pagination)))
+}
+
+/// The subgraph deployments and blocks that indexers currently disagree on,
+/// longest-standing first, like the `staleDivergences` GraphQL query.
+async fn divergences(
+    State(store): State<Store>,
+    Query(page): Query<PageQuery>,
+) -> Result<Json<Page<models::StaleDivergence>>, ApiError> {
+    let pagination = page.pagination()?;
+    let divergences = store.stale_divergences(pagination).await?;
+
+    Ok(Json(Page::new(divergences, pagination)))
+}
+
+/// The distinct values of `values`, in no particular order.
+fn unique<T: Eq + std::hash::Hash>(values: impl Iterator<Item = T>) -> Vec<T> {
+    values.collect::<HashSet<_>>().into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_query(limit: Option<u16>, cursor: Option<&str>) -> PageQuery {
+        PageQuery {
+            limit,
+            cursor: cursor.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn cursors_point_to_the_next_page() {
+        let pagination = page_query(None, None).pagination().unwrap();
+        assert_eq!(
+            pagination,
+            Pagination {
+                offset: 0,
+                limit: DEFAULT_LIMIT
+            }
+        );
+
+        let pagination = page_query(Some(2), Some("4")).pagination().unwrap();
+        let page = Page::new(vec![1, 2], pagination);
+        assert_eq!(page.next_cursor.as_deref(), Some("6"));
+        let page = Page::new(vec![1], pagination);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn invalid_pages_are_rejected() {
+        for query in [
+            page_query(Some(0), None),
+            page_query(Some(MAX_LIMIT + 1), None),
+            page_query(None, Some("foo")),
+        ] {
+            assert!(matches!(query.pagination(), Err(ApiError::BadRequest(_))));
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Serving the GraphQL API and Prometheus metrics over HTTPS.
+
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use axum::http::StatusCode;
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use graphix_lib::config::TlsServerConfig;
+use prometheus_exporter::prometheus;
+use tracing::*;
+
+/// How often to check whether the certificate or key files changed.
+const FILE_CHANGE_POLLING_PERIOD: Duration = Duration::from_secs(60);
+
+/// Loads the certificate and key, failing if either is invalid or if they
+/// don't match.
+pub async fn load(config: &TlsServerConfig) -> anyhow::Result<RustlsConfig> {
+    RustlsConfig::from_pem_file(&config.cert_path, &config.key_path)
+        .await
+        .with_context(|| {
+            format!(
+                "invalid TLS certificate {} or key {}",
+                config.cert_path.display(),
+                config.key_path.display()
+            )
+        })
+}
+
+/// Serves `router` over HTTPS at `address`, until the server fails.
+pub async fn serve(router: Router, address: SocketAddr, tls: RustlsConfig) -> anyhow::Result<()> {
+    axum_server::bind_rustls(address, tls)
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+        .await?;
+
+    Ok(())
+}
+
+/// Reloads the certificate and key whenever the files change or Graphix
+/// receives `SIGHUP`, so that renewed certificates are picked up without a
+/// restart. Certificates that fail to load are logged and the current one is
+/// kept.
+pub async fn reload_forever(config: TlsServerConfig, tls: RustlsConfig) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sighup = signal(SignalKind::hangup()).context("failed to listen for SIGHUP")?;
+    let mut modified_at = files_modified_at(&config);
+    loop {
+        tokio::select! {
+            _ = sighup.recv() => {}
+            _ = tokio::time::sleep(FILE_CHANGE_POLLING_PERIOD) => {
+                if files_modified_at(&config) == modified_at {
+                    continue;
+                }
+            }
+        }
+        modified_at = files_modified_at(&config);
+
+        match tls
+            .reload_from_pem_file(&config.cert_path, &config.key_path)
+            .await
+        {
+            Ok(()) => info!(
+                cert_path = %config.cert_path.display(),
+                "Reloaded TLS certificate"
+            ),
+            Err(err) => error!(
+                cert_path = %config.cert_path.display(),
+                error = %err,
+                "Invalid TLS certificate or key, keeping the current one"
+            ),
+        }
+    }
+}
+
+fn files_modified_at(config: &TlsServerConfig) -> Option<(SystemTime, SystemTime)> {
+    let modified_at = |path| std::fs::metadata(path).and_then(|metadata| metadata.modified());
+    Some((
+        modified_at(&config.cert_path).ok()?,
+        modified_at(&config.key_path).ok()?,
+    ))
+}
+
+/// Serves the metrics of `registry` at `/metrics`, same as
+/// [`graphix_lib::PrometheusExporter`] does over plain HTTP.
+pub fn metrics_router(registry: prometheus::Registry) -> Router {
+    use axum::routing::get;
+
+    Router::new().route(
+        "/metrics",
+        get(move || async move {
+            let mut metrics = String::new();
+            prometheus::TextEncoder::new()
+                .encode_utf8(&registry.gather(), &mut metrics)
+                .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+            Ok::<_, (StatusCode, String)>(metrics)
+        }),
+    )
+}
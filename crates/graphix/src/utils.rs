@@ -3,6 +3,11 @@
 use std::collections::HashSet;
 use std::hash::Hash;
 
+use axum::http::{header, HeaderMap};
+use graphix_lib::config::AdminApiKeyConfig;
+use graphix_lib::graphql_api::AdminApiKey;
+use subtle::ConstantTimeEq;
+
 /// Creates all combinations of elements in the iterator, without duplicates.
 /// Elements are never paired with themselves.
 pub fn unordered_pairs_combinations<T>(iter: impl Iterator<Item = T> + Clone) -> HashSet<(T, T)>
@@ -18,6 +23,32 @@ where
     pairs
 }
 
+/// The token of an `Authorization: Bearer <token>` header, if any.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// The admin API key that a request was made with, if it sent any of `keys`
+/// as its bearer token. Keys are compared in constant time, and all of them are
+/// checked, so that response times don't leak anything about them.
+pub fn admin_api_key(keys: &[AdminApiKeyConfig], headers: &HeaderMap) -> Option<AdminApiKey> {
+    let token = bearer_token(headers)?;
+    keys.iter()
+        .fold(None, |found, key| {
+            if bool::from(key.key.as_bytes().ct_eq(token.as_bytes())) {
+                found.or(Some(key))
+            } else {
+                found
+            }
+        })
+        .map(|key| AdminApiKey {
+            label: key.label.clone(),
+        })
+}
+
 #[cfg(test)]
 mod unit_tests {
     use super::*;
@@ -36,4 +67,24 @@ mod unit_tests {
         test_unordered_pairs_combinations(vec![1, 2], vec![(1, 2)]);
         test_unordered_pairs_combinations(vec![1, 2, 3], vec![(1, 2), (2, 3), (1, 3)]);
     }
+
+    #[test]
+    fn admin_api_keys_are_matched_by_bearer_token() {
+        use axum::http::HeaderValue;
+
+        let keys = vec![AdminApiKeyConfig {
+            label: "alice".to_string(),
+            key: "secret".to_string(),
+        }];
+        let headers = |value: &'static str| {
+            HeaderMap::from_iter([(header::AUTHORIZATION, HeaderValue::from_static(value))])
+        };
+        assert_eq!(
+            admin_api_key(&keys, &headers("Bearer secret")).map(|key| key.label),
+            Some("alice".to_string())
+        );
+        assert!(admin_api_key(&keys, &headers("Bearer other")).is_none());
+        assert!(admin_api_key(&keys, &headers("secret")).is_none());
+        assert!(admin_api_key(&keys, &HeaderMap::new()).is_none());
+    }
 }
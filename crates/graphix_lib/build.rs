@@ -0,0 +1,56 @@
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// Reported for anything that can't be determined, e.g. the commit of a
+/// build from a source tarball.
+const UNKNOWN: &str = "unknown";
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-env-changed=GRAPHIX_GIT_SHA");
+
+    // Docker builds don't have the `.git` directory, so the commit can be
+    // passed in instead.
+    let git_sha = env::var("GRAPHIX_GIT_SHA")
+        .ok()
+        .filter(|sha| !sha.is_empty())
+        .or_else(git_sha)
+        .unwrap_or_else(|| UNKNOWN.to_string());
+    let rustc_version = env::var("RUSTC")
+        .ok()
+        .and_then(|rustc| command_output(&rustc, &["--version"]))
+        .unwrap_or_else(|| UNKNOWN.to_string());
+    let build_timestamp = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+
+    println!("cargo:rustc-env=GRAPHIX_GIT_SHA={git_sha}");
+    println!("cargo:rustc-env=GRAPHIX_RUSTC_VERSION={rustc_version}");
+    println!("cargo:rustc-env=GRAPHIX_BUILD_TIMESTAMP={build_timestamp}");
+}
+
+/// Returns the commit that is checked out, if any, and makes sure that the
+/// build script runs again when another one is.
+fn git_sha() -> Option<String> {
+    let git_dir = command_output("git", &["rev-parse", "--absolute-git-dir"])?;
+    let git_dir = Path::new(&git_dir);
+    println!("cargo:rerun-if-changed={}", git_dir.join("HEAD").display());
+    if let Some(head_ref) = command_output("git", &["symbolic-ref", "-q", "HEAD"]) {
+        let ref_path = git_dir.join(head_ref);
+        if ref_path.exists() {
+            println!("cargo:rerun-if-changed={}", ref_path.display());
+        }
+    }
+
+    command_output("git", &["rev-parse", "HEAD"])
+}
+
+/// The trimmed stdout of a successful command.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.trim().to_string()).filter(|stdout| !stdout.is_empty())
+}
@@ -1,18 +1,157 @@
+use std::collections::HashMap;
+
 use graphix_indexer_client::IndexingStatus;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
-#[serde(rename_all = "camelCase")]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", from = "BlockChoicePolicyConfig")]
 pub enum BlockChoicePolicy {
     // Use the earliest block that all indexers have in common
     Earliest,
     // Use the block that maximizes the total number of blocks synced across all indexers
-    #[default]
+    #[serde(rename_all = "camelCase")]
+    MaxSyncedBlocks {
+        tie_break: TieBreak,
+    },
+    // Use the same fixed block for all deployments, so that PoIs are directly
+    // comparable, skipping indexers that haven't reached it or have pruned it
+    ReferenceBlock(ReferenceBlock),
+}
+
+impl Default for BlockChoicePolicy {
+    fn default() -> Self {
+        Self::MaxSyncedBlocks {
+            tie_break: TieBreak::default(),
+        }
+    }
+}
+
+/// `blockChoicePolicy` as written in the configuration file: either the name
+/// of a policy, or an object with the name as its only key and the options of
+/// the policy as its value, e.g. `{ maxSyncedBlocks: { tieBreak: lowest } }`.
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum BlockChoicePolicyConfig {
+    Name(BlockChoicePolicyName),
+    WithOptions(BlockChoicePolicyWithOptions),
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+enum BlockChoicePolicyName {
+    Earliest,
     MaxSyncedBlocks,
 }
 
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+enum BlockChoicePolicyWithOptions {
+    #[serde(rename_all = "camelCase")]
+    MaxSyncedBlocks {
+        #[serde(default)]
+        tie_break: TieBreak,
+    },
+    ReferenceBlock(ReferenceBlock),
+}
+
+impl From<BlockChoicePolicyConfig> for BlockChoicePolicy {
+    fn from(config: BlockChoicePolicyConfig) -> Self {
+        match config {
+            BlockChoicePolicyConfig::Name(BlockChoicePolicyName::Earliest) => Self::Earliest,
+            BlockChoicePolicyConfig::Name(BlockChoicePolicyName::MaxSyncedBlocks) => {
+                Self::default()
+            }
+            BlockChoicePolicyConfig::WithOptions(
+                BlockChoicePolicyWithOptions::MaxSyncedBlocks { tie_break },
+            ) => Self::MaxSyncedBlocks { tie_break },
+            BlockChoicePolicyConfig::WithOptions(BlockChoicePolicyWithOptions::ReferenceBlock(
+                reference_block,
+            )) => Self::ReferenceBlock(reference_block),
+        }
+    }
+}
+
+/// The schema is that of the configuration file, not of the policy itself.
+impl JsonSchema for BlockChoicePolicy {
+    fn schema_name() -> String {
+        "BlockChoicePolicy".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        BlockChoicePolicyConfig::json_schema(gen)
+    }
+}
+
+/// Which of several blocks that a [`BlockChoicePolicy`] deems equally good to
+/// choose, so that the choice doesn't depend on the order of the indexing
+/// statuses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum TieBreak {
+    /// The highest block.
+    #[default]
+    Highest,
+    /// The lowest block.
+    Lowest,
+    /// The highest block that an indexer reported the hash of, e.g. so that
+    /// PoIs can be checked for block hash mismatches. Falls back to the
+    /// highest block if there are no hashes.
+    MostRecentHash,
+}
+
+impl TieBreak {
+    fn choose(&self, candidates: &[&IndexingStatus]) -> Option<u64> {
+        let highest = candidates
+            .iter()
+            .map(|status| status.latest_block.number)
+            .max();
+        match self {
+            TieBreak::Highest => highest,
+            TieBreak::Lowest => candidates
+                .iter()
+                .map(|status| status.latest_block.number)
+                .min(),
+            TieBreak::MostRecentHash => candidates
+                .iter()
+                .filter(|status| status.latest_block.hash.is_some())
+                .map(|status| status.latest_block.number)
+                .max()
+                .or(highest),
+        }
+    }
+}
+
+/// A fixed block number, either for all networks or for each network
+/// separately. Deployments on networks without a reference block are skipped.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ReferenceBlock {
+    Fixed(u64),
+    PerNetwork(HashMap<String, u64>),
+}
+
+impl ReferenceBlock {
+    pub fn block_number(&self, network: &str) -> Option<u64> {
+        match self {
+            ReferenceBlock::Fixed(block_number) => Some(*block_number),
+            ReferenceBlock::PerNetwork(block_numbers) => block_numbers.get(network).copied(),
+        }
+    }
+}
+
 impl BlockChoicePolicy {
+    /// The name of the policy as in the configuration file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlockChoicePolicy::Earliest => "earliest",
+            BlockChoicePolicy::MaxSyncedBlocks { .. } => "maxSyncedBlocks",
+            BlockChoicePolicy::ReferenceBlock(_) => "referenceBlock",
+        }
+    }
+
     pub fn choose_block<'a>(
         &self,
         statuses: impl Iterator<Item = &'a IndexingStatus>,
@@ -22,7 +161,7 @@ impl BlockChoicePolicy {
                 .map(|status| &status.latest_block.number)
                 .min()
                 .copied(),
-            BlockChoicePolicy::MaxSyncedBlocks => {
+            BlockChoicePolicy::MaxSyncedBlocks { tie_break } => {
                 // Assuming that all statuses have the same `deployment` and `earliest_block_num`,
                 // this will return the block number that maximizes the total number of blocks
                 // synced across all indexers.
@@ -31,7 +170,7 @@ impl BlockChoicePolicy {
                 indexers_ascending.sort_by_key(|status| status.latest_block.number);
 
                 let mut max_utility = 0;
-                let mut best_block: Option<u64> = None;
+                let mut best_statuses: Vec<&IndexingStatus> = vec![];
 
                 for (i, status) in indexers_ascending.iter().enumerate() {
                     let remaining_statuses = indexers_ascending.len() - i;
@@ -41,17 +180,88 @@ impl BlockChoicePolicy {
                         continue;
                     }
 
-                    let utility =
-                        remaining_statuses as u64 * (block_number - status.earliest_block_num);
+                    // Saturates rather than overflowing on absurdly high block numbers.
+                    let utility = (remaining_statuses as u64)
+                        .saturating_mul(block_number - status.earliest_block_num);
 
                     if utility > max_utility {
                         max_utility = utility;
-                        best_block = Some(block_number);
+                        best_statuses = vec![status];
+                    } else if utility == max_utility && utility > 0 {
+                        best_statuses.push(status);
                     }
                 }
 
-                best_block
+                tie_break.choose(&best_statuses)
+            }
+            BlockChoicePolicy::ReferenceBlock(reference_block) => {
+                let statuses: Vec<&IndexingStatus> = statuses.collect();
+                let block_number = reference_block.block_number(&statuses.first()?.network)?;
+
+                // Only worth querying if at least one indexer can serve it.
+                statuses
+                    .iter()
+                    .any(|status| {
+                        (status.earliest_block_num..=status.latest_block.number)
+                            .contains(&block_number)
+                    })
+                    .then_some(block_number)
             }
         }
     }
 }
+
+/// Blocks to query PoIs at in addition to the one chosen by the
+/// [`BlockChoicePolicy`], e.g. the epoch start blocks that indexers close
+/// allocations against.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PinnedBlocksConfig {
+    /// Pinned blocks per deployment ID. These take precedence over the pinned
+    /// blocks of the deployment's network.
+    #[serde(default)]
+    pub deployments: HashMap<String, PinnedBlocks>,
+    /// Pinned blocks per network name.
+    #[serde(default)]
+    pub networks: HashMap<String, PinnedBlocks>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum PinnedBlocks {
+    /// A fixed list of block numbers.
+    Blocks(Vec<u64>),
+    /// Every block that's divisible by `every`. Only the most recent one that
+    /// an indexer has reached is queried, so older ones are only collected if
+    /// Graphix was running at the time.
+    Every { every: u64 },
+}
+
+impl PinnedBlocksConfig {
+    /// The pinned blocks that the indexer with `status` can provide PoIs for,
+    /// i.e. that it has reached but not pruned yet.
+    pub fn blocks(&self, status: &IndexingStatus) -> Vec<u64> {
+        let available = status.earliest_block_num..=status.latest_block.number;
+        let pinned_blocks = self
+            .deployments
+            .get(status.deployment.as_str())
+            .or_else(|| self.networks.get(&status.network));
+
+        match pinned_blocks {
+            None => vec![],
+            Some(PinnedBlocks::Blocks(block_numbers)) => block_numbers
+                .iter()
+                .copied()
+                .filter(|block_number| available.contains(block_number))
+                .collect(),
+            Some(PinnedBlocks::Every { every }) => status
+                .latest_block
+                .number
+                .checked_div(*every)
+                .map(|epoch| epoch * every)
+                .filter(|block_number| available.contains(block_number))
+                .into_iter()
+                .collect(),
+        }
+    }
+}
@@ -3,28 +3,514 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fs::File;
-use std::path::Path;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::Context;
-use graphix_common_types::IndexerAddress;
-use graphix_indexer_client::{IndexerClient, IndexerId, IndexerInterceptor, RealIndexer};
-use graphix_network_sg_client::NetworkSubgraphClient;
+use anyhow::{ensure, Context};
+use graphix_common_types::{Caip2ChainId, IndexerAddress};
+use graphix_indexer_client::{
+    CachingIndexer, HostRateLimiter, IndexerClient, IndexerId, IndexerInterceptor,
+    IndexerNameSource, IndexerSource, PoiCache, RealIndexer, SubgraphDeployment,
+    DEFAULT_MAX_RESPONSE_BYTES,
+};
+use graphix_network_sg_client::{DeploymentAllocations, NetworkSubgraphClient};
+use graphix_store::models::{NewAllocation, NewIndexerDiscoveryCount, NewTrackedDeployment};
+use graphix_store::StoreOptions;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 use url::Url;
 
-use crate::block_choice::BlockChoicePolicy;
-use crate::PrometheusMetrics;
+use crate::block_choice::{BlockChoicePolicy, PinnedBlocks, PinnedBlocksConfig};
+use crate::{PrometheusMetrics, GRAPHIX_VERSION};
 
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct GraphQlConfig {
-    /// The port on which the GraphQL API server should listen. Set it to 0 to
-    /// disable the API server entirely.
+    /// API keys that may use admin-only mutations, e.g.
+    /// `updateDivergenceStatus`, when sent in an `Authorization: Bearer
+    /// <key>` header.
+    #[serde(default)]
+    pub admin_api_keys: Vec<AdminApiKeyConfig>,
+    /// Whether the GraphQL API server should run at all. If omitted, the
+    /// server is enabled unless `port` is 0, for backwards compatibility.
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    /// The port on which the GraphQL API server should listen. If the server
+    /// is explicitly enabled, 0 binds an ephemeral port.
     #[serde(default = "Config::default_graphql_api_port")]
     pub port: u16,
+    /// Serves the GraphQL API over HTTPS with this certificate instead of
+    /// plain HTTP.
+    #[serde(default)]
+    pub tls: Option<TlsServerConfig>,
+    /// Whether to serve all stored PoIs as newline-delimited JSON at
+    /// `/export/pois.ndjson`. Off by default, as exports of large databases
+    /// are heavy.
+    #[serde(default)]
+    pub poi_export_enabled: bool,
+    /// Requests that take longer than this are logged as warnings, rather
+    /// than at the info level.
+    #[serde(default = "GraphQlConfig::default_slow_request_threshold_in_seconds")]
+    pub slow_request_threshold_in_seconds: f64,
+    /// Responses smaller than this aren't compressed, even if the client
+    /// accepts compressed responses, as it's not worth the overhead.
+    #[serde(default = "GraphQlConfig::default_compression_min_size_in_bytes")]
+    pub compression_min_size_in_bytes: u16,
+    /// Limits the requests to `/graphql` per client IP address. Unlimited if
+    /// omitted.
+    #[serde(default)]
+    pub rate_limit: Option<GraphQlRateLimitConfig>,
+    /// Enables `POST /poll`, which starts a main loop iteration right away,
+    /// for requests with this token in an `Authorization: Bearer <token>`
+    /// header. Disabled if omitted.
+    #[serde(default)]
+    pub poll_token: Option<String>,
+}
+
+impl GraphQlConfig {
+    /// Returns whether the GraphQL API server should be started.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(self.port != 0)
+    }
+
+    fn default_slow_request_threshold_in_seconds() -> f64 {
+        5.0
+    }
+
+    fn default_compression_min_size_in_bytes() -> u16 {
+        1024
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminApiKeyConfig {
+    /// Identifies whoever uses the key, e.g. in the history of triage status
+    /// changes of divergence investigation reports.
+    pub label: String,
+    pub key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphQlRateLimitConfig {
+    /// How many requests each client may send per minute, on average.
+    pub requests_per_minute: u32,
+    /// How many requests each client may send at once, after not sending any
+    /// for a while.
+    #[serde(default = "GraphQlRateLimitConfig::default_burst")]
+    pub burst: u32,
+    /// Clients in these ranges, e.g. `10.0.0.0/8`, aren't limited.
+    #[serde(default)]
+    pub exempt_ranges: Vec<IpRange>,
+    /// How many clients to keep track of. Beyond that, the least recently
+    /// seen clients are forgotten, so that they can't exhaust memory.
+    #[serde(default = "GraphQlRateLimitConfig::default_max_tracked_clients")]
+    pub max_tracked_clients: usize,
+}
+
+impl GraphQlRateLimitConfig {
+    fn default_burst() -> u32 {
+        10
+    }
+
+    fn default_max_tracked_clients() -> usize {
+        10_000
+    }
+}
+
+/// A range of IP addresses in CIDR notation, e.g. `10.0.0.0/8` or
+/// `fd00::/8`. A single address stands for just that address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct IpRange {
+    address: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    pub fn contains(&self, address: IpAddr) -> bool {
+        // Dual-stack sockets report IPv4 clients as IPv4-mapped IPv6
+        // addresses.
+        let address = match address {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(address, IpAddr::V4),
+            IpAddr::V4(_) => address,
+        };
+        let (range_bits, address_bits, width) = match (self.address, address) {
+            (IpAddr::V4(range), IpAddr::V4(address)) => (
+                u128::from(u32::from(range)),
+                u128::from(u32::from(address)),
+                32,
+            ),
+            (IpAddr::V6(range), IpAddr::V6(address)) => {
+                (u128::from(range), u128::from(address), 128)
+            }
+            _ => return false,
+        };
+        let shift = width - u32::from(self.prefix_len);
+        range_bits.checked_shr(shift).unwrap_or(0) == address_bits.checked_shr(shift).unwrap_or(0)
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid IP range `{s}`, expected e.g. `10.0.0.0/8`");
+        let (address, prefix_len) = match s.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (s, None),
+        };
+        let address: IpAddr = address.parse().map_err(|_| invalid())?;
+        let max_prefix_len = if address.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_prefix_len)
+                .ok_or_else(invalid)?,
+            None => max_prefix_len,
+        };
+
+        Ok(Self {
+            address,
+            prefix_len,
+        })
+    }
+}
+
+impl TryFrom<String> for IpRange {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<IpRange> for String {
+    fn from(range: IpRange) -> Self {
+        format!("{}/{}", range.address, range.prefix_len)
+    }
+}
+
+impl JsonSchema for IpRange {
+    fn schema_name() -> String {
+        "IpRange".to_owned()
+    }
+
+    fn json_schema(_gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        schemars::schema::SchemaObject {
+            instance_type: Some(schemars::schema::InstanceType::String.into()),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PoiCacheConfig {
+    /// Whether PoI responses should be cached in memory, so that the same
+    /// PoIs aren't requested from indexers over and over again (e.g. during
+    /// bisection).
+    #[serde(default)]
+    pub enabled: bool,
+    /// The maximum number of PoIs to keep in the cache. Least recently used
+    /// PoIs are evicted first.
+    #[serde(default = "PoiCacheConfig::default_capacity")]
+    pub capacity: usize,
+    /// How long a cached PoI remains valid.
+    #[serde(default = "PoiCacheConfig::default_ttl_in_seconds")]
+    pub ttl_in_seconds: u64,
+}
+
+impl PoiCacheConfig {
+    /// Creates the PoI cache described by this configuration, if enabled.
+    pub fn build(&self, metrics: &PrometheusMetrics) -> Option<Arc<PoiCache>> {
+        self.enabled.then(|| {
+            Arc::new(PoiCache::new(
+                self.capacity,
+                Duration::from_secs(self.ttl_in_seconds),
+                metrics.poi_cache_hits.clone(),
+                metrics.poi_cache_misses.clone(),
+            ))
+        })
+    }
+
+    fn default_capacity() -> usize {
+        10_000
+    }
+
+    fn default_ttl_in_seconds() -> u64 {
+        60
+    }
+}
+
+impl Default for PoiCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: Self::default_capacity(),
+            ttl_in_seconds: Self::default_ttl_in_seconds(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseConfig {
+    /// The maximum number of open database connections.
+    #[serde(default = "DatabaseConfig::default_max_connections")]
+    pub max_connections: usize,
+    /// How many connections to open at startup rather than on demand.
+    #[serde(default)]
+    pub min_idle: usize,
+    /// How long to wait for a database connection before failing.
+    #[serde(default = "DatabaseConfig::default_connection_timeout_seconds")]
+    pub connection_timeout_seconds: u64,
+    /// Statements that run longer than this are cancelled. Statements may
+    /// run for as long as they take if omitted.
+    #[serde(default)]
+    pub statement_timeout_seconds: Option<u64>,
+    /// How many times to retry connecting to the database at startup before
+    /// giving up, e.g. while it's still starting up.
+    #[serde(default = "DatabaseConfig::default_connect_retries")]
+    pub connect_retries: u32,
+}
+
+impl DatabaseConfig {
+    /// The options of the store described by this configuration.
+    pub fn store_options(&self, metrics: &PrometheusMetrics) -> StoreOptions {
+        StoreOptions {
+            max_connections: self.max_connections,
+            min_idle: self.min_idle,
+            connection_timeout: Duration::from_secs(self.connection_timeout_seconds),
+            statement_timeout: self.statement_timeout_seconds.map(Duration::from_secs),
+            connect_retries: self.connect_retries,
+            pool_wait_seconds: Some(metrics.store_pool_wait_seconds.clone()),
+        }
+    }
+
+    fn default_max_connections() -> usize {
+        16
+    }
+
+    fn default_connection_timeout_seconds() -> u64 {
+        30
+    }
+
+    fn default_connect_retries() -> u32 {
+        5
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: Self::default_max_connections(),
+            min_idle: 0,
+            connection_timeout_seconds: Self::default_connection_timeout_seconds(),
+            statement_timeout_seconds: None,
+            connect_retries: Self::default_connect_retries(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PoiBackfillConfig {
+    /// How many PoI requests a backfill may have in flight at once, across all
+    /// indexers.
+    #[serde(default = "PoiBackfillConfig::default_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+    /// Backfills that need more PoI requests than this fail without sending
+    /// any, and requests for backfills of more blocks than this are rejected.
+    #[serde(default = "PoiBackfillConfig::default_max_requests_per_job")]
+    pub max_requests_per_job: usize,
+}
+
+impl PoiBackfillConfig {
+    fn default_max_concurrent_requests() -> usize {
+        4
+    }
+
+    fn default_max_requests_per_job() -> usize {
+        10_000
+    }
+}
+
+impl Default for PoiBackfillConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_requests: Self::default_max_concurrent_requests(),
+            max_requests_per_job: Self::default_max_requests_per_job(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitBreakerConfig {
+    /// Whether indexers that keep failing should be skipped for a while,
+    /// rather than queried on every main loop iteration.
+    #[serde(default)]
+    pub enabled: bool,
+    /// After how many main loop iterations in a row with a failed
+    /// `indexingStatuses` query an indexer is skipped.
+    #[serde(default = "CircuitBreakerConfig::default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// How long an indexer is skipped at first. Every time it still fails
+    /// afterwards, the period doubles.
+    #[serde(default = "CircuitBreakerConfig::default_initial_backoff_seconds")]
+    pub initial_backoff_seconds: u64,
+    /// The longest period an indexer is skipped for.
+    #[serde(default = "CircuitBreakerConfig::default_max_backoff_seconds")]
+    pub max_backoff_seconds: u64,
+}
+
+impl CircuitBreakerConfig {
+    fn default_failure_threshold() -> u32 {
+        5
+    }
+
+    fn default_initial_backoff_seconds() -> u64 {
+        60
+    }
+
+    fn default_max_backoff_seconds() -> u64 {
+        3600
+    }
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            failure_threshold: Self::default_failure_threshold(),
+            initial_backoff_seconds: Self::default_initial_backoff_seconds(),
+            max_backoff_seconds: Self::default_max_backoff_seconds(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ObservabilityConfig {
+    /// The OTLP/gRPC endpoint to export traces to, e.g.
+    /// `http://localhost:4317`. Traces aren't exported if this is omitted.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsClientCertificateConfig {
+    /// The path to the PEM-encoded client certificate, optionally followed by
+    /// intermediate certificates.
+    pub certificate_path: PathBuf,
+    /// The path to the PEM-encoded private key of the client certificate, in
+    /// PKCS #8 format.
+    pub key_path: PathBuf,
+}
+
+impl TlsClientCertificateConfig {
+    /// Reads the client certificate and key.
+    pub fn load(&self) -> anyhow::Result<reqwest::Identity> {
+        let certificate = std::fs::read(&self.certificate_path).with_context(|| {
+            format!(
+                "failed to read TLS client certificate {}",
+                self.certificate_path.display()
+            )
+        })?;
+        let key = std::fs::read(&self.key_path).with_context(|| {
+            format!("failed to read TLS client key {}", self.key_path.display())
+        })?;
+
+        reqwest::Identity::from_pkcs8_pem(&certificate, &key).with_context(|| {
+            format!(
+                "invalid TLS client certificate {} or key {}",
+                self.certificate_path.display(),
+                self.key_path.display()
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsServerConfig {
+    /// The path to the PEM-encoded server certificate, optionally followed by
+    /// intermediate certificates. Reloaded when it changes.
+    pub cert_path: PathBuf,
+    /// The path to the PEM-encoded private key of the server certificate.
+    /// Reloaded when it changes.
+    pub key_path: PathBuf,
+}
+
+impl TlsServerConfig {
+    /// Checks that the certificate and key can be read. Whether they're valid
+    /// and match is only known once a server loads them.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        std::fs::metadata(&self.cert_path).with_context(|| {
+            format!(
+                "failed to read TLS certificate {}",
+                self.cert_path.display()
+            )
+        })?;
+        std::fs::metadata(&self.key_path)
+            .with_context(|| format!("failed to read TLS key {}", self.key_path.display()))?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum LogFormat {
+    /// Human-readable, one line per event.
+    #[default]
+    Plain,
+    /// One JSON object per event, including span fields, for log aggregators.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "json" => Ok(Self::Json),
+            _ => Err("invalid log format, expected `plain` or `json`"),
+        }
+    }
+}
+
+/// How PoIs are weighted when looking for a majority among indexers.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    async_graphql::Enum,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum ConsensusWeighting {
+    /// Every indexer has the same weight.
+    #[default]
+    Count,
+    /// Indexers are weighted by the GRT they have staked, according to the
+    /// network subgraphs. Falls back to `count` for subgraph deployments
+    /// with indexers of unknown stake.
+    Stake,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
@@ -48,11 +534,21 @@ pub struct ChainSpeedConfig {
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ChainConfig {
-    pub caip2: Option<String>,
+    pub caip2: Option<Caip2ChainId>,
     #[serde(flatten, default)]
     pub speed: Option<ChainSpeedConfig>,
     #[serde(default)]
     pub block_explorer_url_template_for_block: Option<BlockExplorerUrlTemplateForBlock>,
+    /// An indexer whose PoIs are trusted unconditionally. Other indexers are
+    /// graded against it, rather than against the majority, for the
+    /// deployments it serves.
+    #[serde(default)]
+    pub trusted_indexer: Option<IndexerAddress>,
+    /// How many blocks below the block chosen by `blockChoicePolicy` to
+    /// query PoIs at, so that they're compared at a depth that's unlikely to
+    /// be reorged.
+    #[serde(default)]
+    pub finality_offset: u64,
 }
 
 /// A [`serde`]-compatible representation of Graphix's YAML configuration file.
@@ -63,9 +559,16 @@ pub struct Config {
     pub graphql: GraphQlConfig,
     /// The URL of the PostgreSQL database to use.
     pub database_url: String,
+    /// Database connection pool options.
+    #[serde(default)]
+    pub database: DatabaseConfig,
     /// The port on which the Prometheus exporter should listen.
     #[serde(default = "Config::default_prometheus_port")]
     pub prometheus_port: u16,
+    /// Serves Prometheus metrics over HTTPS with this certificate instead of
+    /// plain HTTP.
+    #[serde(default)]
+    pub prometheus_tls: Option<TlsServerConfig>,
     /// Chain-specific configuration.
     #[serde(default)]
     pub chains: HashMap<String, ChainConfig>,
@@ -73,16 +576,333 @@ pub struct Config {
     // Indexing options
     // ----------------
     pub sources: Vec<ConfigSource>,
+    /// Metadata of individual indexers, no matter which source they come
+    /// from, that Graphix passes through to the API.
+    #[serde(default)]
+    pub indexer_metadata: Vec<IndexerMetadataConfig>,
     #[serde(default)]
     pub block_choice_policy: BlockChoicePolicy,
+    /// Blocks to query PoIs at in addition to the one chosen by
+    /// `blockChoicePolicy`. PoIs at pinned blocks are stored separately from
+    /// the others and never become live.
+    #[serde(default)]
+    pub pinned_blocks: PinnedBlocksConfig,
     #[serde(default = "Config::default_polling_period_in_seconds")]
     pub polling_period_in_seconds: u64,
+    /// How often to query the `graph-node` versions of indexers.
+    #[serde(default = "Config::default_graph_node_version_polling_period_in_seconds")]
+    pub graph_node_version_polling_period_in_seconds: u64,
+    /// How often to re-create the set of indexers from the configured
+    /// sources, e.g. network subgraphs.
+    #[serde(default = "Config::default_indexer_refresh_period_in_seconds")]
+    pub indexer_refresh_period_in_seconds: u64,
+    /// Indexers that no source listed for this many days are flagged as
+    /// inactive after an indexer refresh, and hidden from the `indexers`
+    /// GraphQL query by default until they reappear. Their data is kept.
+    /// Indexers are never flagged if omitted.
+    #[serde(default)]
+    pub indexer_inactive_after_days: Option<u32>,
+    /// Spreads the queries to each indexer over this many seconds, with random
+    /// jitter, rather than sending them all at once. Applies separately to
+    /// each kind of query in a main loop iteration. 0 disables spreading.
+    #[serde(default)]
+    pub query_spread_seconds: u64,
+    /// Per-deployment overrides of `pollingPeriodInSeconds` for PoI queries,
+    /// keyed by deployment ID. Deployments are only checked once per main
+    /// loop iteration, so the actual period is rounded up to the next
+    /// iteration.
+    #[serde(default)]
+    pub deployment_polling_periods_in_seconds: HashMap<String, u64>,
+    /// Restricts the indexing statuses that are queried from indexers to
+    /// these subgraph deployments, and with them the PoIs. All deployments are
+    /// queried if empty.
+    #[serde(default)]
+    pub deployment_allowlist: Vec<String>,
+    /// Queries PoIs for at most this many subgraph deployments of each indexer
+    /// per main loop iteration, preferring the deployments that the most
+    /// indexers serve and then those with the most allocated tokens.
+    /// Deployments in `deploymentAllowlist` are always queried. No limit if
+    /// omitted.
+    #[serde(default)]
+    pub max_deployments_per_indexer: Option<usize>,
+    /// The weight given to the most recent observation when updating indexer
+    /// reliability scores, which are exponential moving averages of query
+    /// success rates. Must be in (0, 1]; higher values make scores react
+    /// faster.
+    #[serde(default = "Config::default_reliability_decay_factor")]
+    pub reliability_decay_factor: f64,
+    /// After how many main loop iterations in a row without its latest block
+    /// advancing an indexer is considered stalled on a subgraph deployment.
+    #[serde(default = "Config::default_stalled_indexer_iterations")]
+    pub stalled_indexer_iterations: u32,
+    /// How PoIs are weighted when looking for a majority among indexers.
+    #[serde(default)]
+    pub consensus_weighting: ConsensusWeighting,
+    /// More than this fraction of indexers must report the most common PoI
+    /// of a subgraph deployment at a block for it to be the consensus in
+    /// `consensusPoi` queries. Must be in [0.5, 1).
+    #[serde(default = "Config::default_consensus_quorum")]
+    pub consensus_quorum: f64,
+    /// The subgraph deployments to export the `graphix_blocks_behind` metric
+    /// for. If omitted, it's exported for the statuses that trail the most,
+    /// up to a fixed number of series.
+    #[serde(default)]
+    pub blocks_behind_metric_deployments: Option<Vec<String>>,
+    /// In-memory caching of PoI responses.
+    #[serde(default)]
+    pub poi_cache: PoiCacheConfig,
+    /// Limits for backfills of historical PoIs.
+    #[serde(default)]
+    pub poi_backfill: PoiBackfillConfig,
+    /// Skipping indexers that keep failing.
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// Divergence investigations that completed and started at most this
+    /// many seconds ago are reused for identical requests, i.e. for the same
+    /// indexers and subgraph deployments. Only pending and running ones are
+    /// reused if omitted.
+    #[serde(default)]
+    pub divergence_investigation_cooldown_in_seconds: Option<u64>,
+    /// The IPFS gateway to fetch the manifests of subgraph deployments from,
+    /// e.g. `https://ipfs.network.thegraph.com`. Manifests aren't fetched if
+    /// omitted.
+    #[serde(default)]
+    pub ipfs_gateway: Option<Url>,
+    /// The `User-Agent` header of requests to indexers and network
+    /// subgraphs. Defaults to `graphix/<version>`.
+    #[serde(default)]
+    pub http_user_agent: Option<String>,
+    /// A client certificate to present to indexers and network subgraphs
+    /// that require mutual TLS.
+    #[serde(default)]
+    pub tls_client_certificate: Option<TlsClientCertificateConfig>,
+    /// Sends requests to indexers and network subgraphs through this HTTP(S)
+    /// proxy, e.g. `http://proxy.internal:3128`, unless an indexer overrides
+    /// it. Proxies from the `HTTP_PROXY` and `HTTPS_PROXY` environment
+    /// variables are used if omitted.
+    #[serde(default)]
+    pub http_proxy: Option<Url>,
+    /// Paths to PEM-encoded root certificates, one per file, that indexers
+    /// and network subgraphs are trusted with in addition to the system's
+    /// root certificates, e.g. for indexers with self-signed certificates.
+    #[serde(default)]
+    pub extra_ca_certs: Vec<PathBuf>,
+    /// The maximum number of requests per second to each indexer host,
+    /// across all indexers and deployments on it. Requests beyond the limit
+    /// wait for their turn. Unlimited if omitted.
+    #[serde(default)]
+    pub max_requests_per_second_per_host: Option<f64>,
+    /// Queries to indexers whose responses are larger than this fail, so that
+    /// misbehaving indexers can't exhaust memory.
+    #[serde(default = "Config::default_max_indexer_response_bytes")]
+    pub max_indexer_response_bytes: usize,
+
+    // Logging options
+    // ---------------
+    /// The format of log output. Overridden by `--log-format`.
+    #[serde(default)]
+    pub log_format: Option<LogFormat>,
+    /// The log filter, in `RUST_LOG` syntax. Overridden by `--log-level` and
+    /// `RUST_LOG`.
+    #[serde(default)]
+    pub log_level: Option<String>,
+    /// Export of traces of loop iterations and divergence investigations.
+    #[serde(default)]
+    pub observability: ObservabilityConfig,
 }
 
 impl Config {
     pub fn read(path: &Path) -> anyhow::Result<Self> {
         let file = File::open(path)?;
-        serde_yaml::from_reader(file).context("invalid config file")
+        let config: Self = serde_yaml::from_reader(file).context("invalid config file")?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the constraints that deserialization alone doesn't enforce.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.polling_period_in_seconds > 0,
+            "pollingPeriodInSeconds must be greater than 0"
+        );
+        ensure!(
+            self.indexer_refresh_period_in_seconds > 0,
+            "indexerRefreshPeriodInSeconds must be greater than 0"
+        );
+        ensure!(
+            self.graph_node_version_polling_period_in_seconds > 0,
+            "graphNodeVersionPollingPeriodInSeconds must be greater than 0"
+        );
+        let pinned_blocks = &self.pinned_blocks;
+        for pinned in pinned_blocks
+            .deployments
+            .values()
+            .chain(pinned_blocks.networks.values())
+        {
+            ensure!(
+                !matches!(pinned, PinnedBlocks::Every { every: 0 }),
+                "pinned blocks can't be every 0th block"
+            );
+        }
+        ensure!(
+            self.graphql.slow_request_threshold_in_seconds >= 0.0
+                && self.graphql.slow_request_threshold_in_seconds.is_finite(),
+            "graphql.slowRequestThresholdInSeconds must be a non-negative number"
+        );
+        if let Some(rate_limit) = &self.graphql.rate_limit {
+            ensure!(
+                rate_limit.requests_per_minute > 0
+                    && rate_limit.burst > 0
+                    && rate_limit.max_tracked_clients > 0,
+                "graphql.rateLimit.requestsPerMinute, burst and maxTrackedClients must be greater than 0"
+            );
+        }
+        ensure!(
+            self.database.max_connections > 0,
+            "database.maxConnections must be greater than 0"
+        );
+        ensure!(
+            self.database.min_idle <= self.database.max_connections,
+            "database.minIdle must not be greater than database.maxConnections"
+        );
+        ensure!(
+            self.database.connection_timeout_seconds > 0,
+            "database.connectionTimeoutSeconds must be greater than 0"
+        );
+        ensure!(
+            self.stalled_indexer_iterations > 0,
+            "stalledIndexerIterations must be greater than 0"
+        );
+        ensure!(
+            self.indexer_inactive_after_days != Some(0),
+            "indexerInactiveAfterDays must be greater than 0"
+        );
+        ensure!(
+            self.max_indexer_response_bytes > 0,
+            "maxIndexerResponseBytes must be greater than 0"
+        );
+        ensure!(
+            self.circuit_breaker.failure_threshold > 0,
+            "circuitBreaker.failureThreshold must be greater than 0"
+        );
+        ensure!(
+            self.circuit_breaker.initial_backoff_seconds > 0
+                && self.circuit_breaker.initial_backoff_seconds
+                    <= self.circuit_breaker.max_backoff_seconds,
+            "circuitBreaker.initialBackoffSeconds must be greater than 0 and at most circuitBreaker.maxBackoffSeconds"
+        );
+        ensure!(
+            (0.5..1.0).contains(&self.consensus_quorum),
+            "consensusQuorum must be in [0.5, 1)"
+        );
+        ensure!(
+            self.reliability_decay_factor > 0.0 && self.reliability_decay_factor <= 1.0,
+            "reliabilityDecayFactor must be in (0, 1]"
+        );
+        ensure!(
+            self.max_requests_per_second_per_host
+                .map_or(true, |max_requests| max_requests > 0.0),
+            "maxRequestsPerSecondPerHost must be greater than 0"
+        );
+        // Certificates are loaded again for every HTTP client, but broken
+        // ones should be caught right away.
+        if let Some(client_certificate) = &self.tls_client_certificate {
+            client_certificate.load()?;
+        }
+        for path in &self.extra_ca_certs {
+            load_ca_cert(path)?;
+        }
+        // Warned about here, rather than whenever indexers are refreshed.
+        for indexer in self.indexers() {
+            if indexer.accept_invalid_certs {
+                warn!(
+                    indexer_address = %indexer.address_string(),
+                    endpoint = %indexer.index_node_endpoint,
+                    "TLS certificate verification is DISABLED for this indexer, \
+                     so its responses could come from anyone (acceptInvalidCerts)"
+                );
+            }
+        }
+        for tls in [&self.graphql.tls, &self.prometheus_tls]
+            .into_iter()
+            .flatten()
+        {
+            tls.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Returns `new` with the options that only take effect on restart (the
+    /// database, servers, caches, logging and tracing) kept as they are in `self`.
+    /// Ignored changes are logged as warnings.
+    pub fn reload(&self, new: Config) -> Config {
+        let mut ignored = vec![];
+        if new.database_url != self.database_url {
+            ignored.push("databaseUrl");
+        }
+        if new.database != self.database {
+            ignored.push("database");
+        }
+        if new.graphql != self.graphql {
+            ignored.push("graphql");
+        }
+        if new.prometheus_port != self.prometheus_port {
+            ignored.push("prometheusPort");
+        }
+        if new.prometheus_tls != self.prometheus_tls {
+            ignored.push("prometheusTls");
+        }
+        if new.poi_cache != self.poi_cache {
+            ignored.push("poiCache");
+        }
+        if new.poi_backfill != self.poi_backfill {
+            ignored.push("poiBackfill");
+        }
+        if new.divergence_investigation_cooldown_in_seconds
+            != self.divergence_investigation_cooldown_in_seconds
+        {
+            ignored.push("divergenceInvestigationCooldownInSeconds");
+        }
+        if new.consensus_weighting != self.consensus_weighting {
+            ignored.push("consensusWeighting");
+        }
+        if new.consensus_quorum != self.consensus_quorum {
+            ignored.push("consensusQuorum");
+        }
+        if new.ipfs_gateway != self.ipfs_gateway {
+            ignored.push("ipfsGateway");
+        }
+        if new.log_format != self.log_format || new.log_level != self.log_level {
+            ignored.push("logFormat/logLevel");
+        }
+        if new.observability != self.observability {
+            ignored.push("observability");
+        }
+        if !ignored.is_empty() {
+            warn!(
+                options = ignored.join(", "),
+                "Ignored configuration changes that require a restart"
+            );
+        }
+
+        Config {
+            database_url: self.database_url.clone(),
+            database: self.database.clone(),
+            graphql: self.graphql.clone(),
+            prometheus_port: self.prometheus_port,
+            prometheus_tls: self.prometheus_tls.clone(),
+            poi_cache: self.poi_cache.clone(),
+            poi_backfill: self.poi_backfill.clone(),
+            divergence_investigation_cooldown_in_seconds: self
+                .divergence_investigation_cooldown_in_seconds,
+            consensus_weighting: self.consensus_weighting,
+            consensus_quorum: self.consensus_quorum,
+            ipfs_gateway: self.ipfs_gateway.clone(),
+            log_format: self.log_format,
+            log_level: self.log_level.clone(),
+            observability: self.observability.clone(),
+            ..new
+        }
     }
 
     pub fn indexers(&self) -> Vec<IndexerConfig> {
@@ -129,10 +949,42 @@ impl Config {
             .collect()
     }
 
+    /// The subgraph deployments of `deploymentAllowlist`, with canonical IDs.
+    pub fn deployment_allowlist(&self) -> Vec<SubgraphDeployment> {
+        self.deployment_allowlist
+            .iter()
+            .map(|id| SubgraphDeployment::new(id.as_str()))
+            .collect()
+    }
+
+    fn default_max_indexer_response_bytes() -> usize {
+        DEFAULT_MAX_RESPONSE_BYTES
+    }
+
     fn default_polling_period_in_seconds() -> u64 {
         120
     }
 
+    fn default_graph_node_version_polling_period_in_seconds() -> u64 {
+        3600
+    }
+
+    fn default_indexer_refresh_period_in_seconds() -> u64 {
+        600
+    }
+
+    fn default_reliability_decay_factor() -> f64 {
+        0.1
+    }
+
+    fn default_stalled_indexer_iterations() -> u32 {
+        10
+    }
+
+    fn default_consensus_quorum() -> f64 {
+        0.5
+    }
+
     fn default_prometheus_port() -> u16 {
         9184
     }
@@ -148,6 +1000,14 @@ pub struct IndexerConfig {
     pub name: Option<String>,
     pub address: IndexerAddress,
     pub index_node_endpoint: Url,
+    /// Overrides the global `httpProxy` for this indexer.
+    #[serde(default)]
+    pub http_proxy: Option<Url>,
+    /// Skips the verification of the indexer's TLS certificate. This is
+    /// insecure and only meant for e.g. testnet indexers with self-signed
+    /// certificates that can't be added to `extraCaCerts`.
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
 }
 
 impl IndexerId for IndexerConfig {
@@ -167,6 +1027,21 @@ impl IndexerId for IndexerConfig {
 #[serde(rename_all = "camelCase")]
 pub struct IndexerByAddressConfig {
     pub address: IndexerAddress,
+    /// Overrides the name found in the network subgraph.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Opaque metadata of an indexer, e.g. for grouping indexers by region in
+/// dashboards. Fields that are omitted keep the values they had before.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexerMetadataConfig {
+    pub address: IndexerAddress,
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub labels: Option<Vec<String>>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
@@ -179,6 +1054,37 @@ pub struct NetworkSubgraphConfig {
     pub query: NetworkSubgraphQuery,
     pub stake_threshold: f64,
     pub limit: Option<u32>,
+    /// Only track the subgraph deployments that indexers have active
+    /// allocations on, as found in this network subgraph. Refreshed along
+    /// with the indexers.
+    #[serde(default)]
+    pub deployment_discovery: Option<DeploymentDiscoveryConfig>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeploymentDiscoveryConfig {
+    /// Ignore deployments with fewer GRT allocated to them in total.
+    #[serde(default)]
+    pub min_allocated_tokens: Option<f64>,
+    /// Ignore deployments that index other networks, or whose network is
+    /// unknown.
+    #[serde(default)]
+    pub networks: Option<Vec<String>>,
+}
+
+impl DeploymentDiscoveryConfig {
+    fn matches(&self, deployment: &DeploymentAllocations) -> bool {
+        let enough_tokens = self
+            .min_allocated_tokens
+            .map_or(true, |min| deployment.allocated_tokens >= min);
+        let right_network = match (&self.networks, &deployment.network) {
+            (None, _) => true,
+            (Some(networks), Some(network)) => networks.contains(network),
+            (Some(_), None) => false,
+        };
+        enough_tokens && right_network
+    }
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
@@ -206,21 +1112,126 @@ pub enum ConfigSource {
     NetworkSubgraph(NetworkSubgraphConfig),
 }
 
+/// The options of the HTTP clients for requests to indexers and network
+/// subgraphs, see [`http_client`].
+#[derive(Debug, Clone, Default)]
+pub struct HttpClientOptions<'a> {
+    pub user_agent: Option<&'a str>,
+    pub client_certificate: Option<&'a TlsClientCertificateConfig>,
+    pub proxy: Option<&'a Url>,
+    pub extra_ca_certs: &'a [PathBuf],
+    pub accept_invalid_certs: bool,
+}
+
+impl<'a> HttpClientOptions<'a> {
+    /// The options that all indexers and network subgraphs share.
+    pub fn from_config(config: &'a Config) -> Self {
+        Self {
+            user_agent: config.http_user_agent.as_deref(),
+            client_certificate: config.tls_client_certificate.as_ref(),
+            proxy: config.http_proxy.as_ref(),
+            extra_ca_certs: &config.extra_ca_certs,
+            accept_invalid_certs: false,
+        }
+    }
+
+    /// The options for `indexer`, with its overrides applied. `None` if it
+    /// has none, in which case the shared client can be used.
+    pub fn for_indexer(&self, indexer: &'a IndexerConfig) -> Option<Self> {
+        if indexer.http_proxy.is_none() && !indexer.accept_invalid_certs {
+            return None;
+        }
+
+        Some(Self {
+            proxy: indexer.http_proxy.as_ref().or(self.proxy),
+            accept_invalid_certs: indexer.accept_invalid_certs,
+            ..self.clone()
+        })
+    }
+}
+
+/// Builds the HTTP client for requests to indexers and network subgraphs,
+/// identifying Graphix with the user agent or `graphix/<version>` by default,
+/// presenting the client certificate to servers that ask for one, and
+/// trusting the extra root certificates.
+pub fn http_client(options: &HttpClientOptions) -> anyhow::Result<reqwest::Client> {
+    let user_agent = match options.user_agent {
+        Some(user_agent) => user_agent.to_string(),
+        None => format!("graphix/{}", GRAPHIX_VERSION),
+    };
+
+    let mut builder = reqwest::Client::builder().user_agent(user_agent);
+    if let Some(client_certificate) = options.client_certificate {
+        builder = builder.identity(client_certificate.load()?);
+    }
+    if let Some(proxy) = options.proxy {
+        let proxy = reqwest::Proxy::all(proxy.as_str())
+            .with_context(|| format!("invalid HTTP proxy {}", proxy))?;
+        builder = builder.proxy(proxy);
+    }
+    for path in options.extra_ca_certs {
+        builder = builder.add_root_certificate(load_ca_cert(path)?);
+    }
+    if options.accept_invalid_certs {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    builder.build().context("failed to build HTTP client")
+}
+
+/// Reads a PEM-encoded root certificate.
+fn load_ca_cert(path: &Path) -> anyhow::Result<reqwest::Certificate> {
+    let pem = std::fs::read(path)
+        .with_context(|| format!("failed to read CA certificate {}", path.display()))?;
+    reqwest::Certificate::from_pem(&pem)
+        .with_context(|| format!("invalid CA certificate {}", path.display()))
+}
+
+/// The indexers that [`config_to_indexers`] found, before deduplication.
+pub struct DiscoveredIndexers {
+    pub indexers: Vec<Arc<dyn IndexerClient>>,
+    /// How many indexers each source listed: `config` for the ones in the
+    /// configuration, the endpoint of each network subgraph, and
+    /// `interceptor`. Network subgraphs that failed are listed with 0.
+    pub counts: Vec<NewIndexerDiscoveryCount>,
+}
+
 pub async fn config_to_indexers(
     config: Config,
     metrics: &PrometheusMetrics,
-) -> anyhow::Result<Vec<Arc<dyn IndexerClient>>> {
+) -> anyhow::Result<DiscoveredIndexers> {
     let mut indexers: Vec<Arc<dyn IndexerClient>> = vec![];
+    let mut discovery_counts = vec![];
+    let max_response_bytes = config.max_indexer_response_bytes;
+    let http_client_options = HttpClientOptions::from_config(&config);
+    let http_client = http_client(&http_client_options)?;
+    let rate_limiter = config.max_requests_per_second_per_host.map(|max_requests| {
+        Arc::new(HostRateLimiter::new(
+            max_requests,
+            metrics.rate_limiter_wait_seconds.clone(),
+        ))
+    });
 
     // First, configure all the real, static indexers.
     for config in config.indexers() {
         info!(indexer_address = %config.address_string(), "Configuring indexer");
-        indexers.push(Arc::new(RealIndexer::new(
+        let mut indexer = RealIndexer::new(
             config.name().map(|s| s.into_owned()),
             config.address(),
             config.index_node_endpoint.to_string(),
             metrics.public_proofs_of_indexing_requests.clone(),
-        )));
+        )
+        .with_client(match http_client_options.for_indexer(&config) {
+            Some(options) => self::http_client(&options)?,
+            None => http_client.clone(),
+        })
+        .with_max_response_bytes(max_response_bytes)
+        .with_responses_too_large(metrics.indexer_responses_too_large.clone())
+        .with_name_source(IndexerNameSource::Config)
+        .with_source(IndexerSource::Config);
+        if let Some(rate_limiter) = &rate_limiter {
+            indexer = indexer.with_rate_limiter(rate_limiter.clone());
+        }
+        indexers.push(Arc::new(indexer));
     }
 
     // Then, configure the network subgraphs, if required, resulting in "dynamic"
@@ -230,7 +1241,14 @@ pub async fn config_to_indexers(
         let network_subgraph = NetworkSubgraphClient::new(
             config.endpoint.as_str().parse()?,
             metrics.public_proofs_of_indexing_requests.clone(),
-        );
+        )
+        .with_client(http_client.clone())
+        .with_max_indexer_response_bytes(max_response_bytes)
+        .with_indexer_responses_too_large(metrics.indexer_responses_too_large.clone());
+        let network_subgraph = match &rate_limiter {
+            Some(rate_limiter) => network_subgraph.with_rate_limiter(rate_limiter.clone()),
+            None => network_subgraph,
+        };
         let network_subgraph_indexers_res = match config.query {
             NetworkSubgraphQuery::ByAllocations => {
                 network_subgraph.indexers_by_allocations(config.limit).await
@@ -239,11 +1257,20 @@ pub async fn config_to_indexers(
                 network_subgraph.indexers_by_staked_tokens().await
             }
         };
+        // Failures count as no indexers, so that they show up in the counts.
+        let mut count = 0;
         if let Ok(mut network_subgraph_indexers) = network_subgraph_indexers_res {
             if let Some(limit) = config.limit {
                 network_subgraph_indexers.truncate(limit as usize);
             }
 
+            count = network_subgraph_indexers.len();
+            if count == 0 {
+                warn!(
+                    endpoint = %config.endpoint,
+                    "Network subgraph returned no indexers"
+                );
+            }
             indexers.extend(network_subgraph_indexers);
         } else {
             warn!(
@@ -252,6 +1279,7 @@ pub async fn config_to_indexers(
                 "Failed to configure network subgraph"
             );
         }
+        discovery_counts.push((config.endpoint.clone(), count));
     }
 
     info!(
@@ -273,16 +1301,36 @@ pub async fn config_to_indexers(
                 .endpoint
                 .parse()?,
             metrics.public_proofs_of_indexing_requests.clone(),
-        );
-        let indexer = network_subgraph
+        )
+        .with_client(http_client.clone())
+        .with_max_indexer_response_bytes(max_response_bytes)
+        .with_indexer_responses_too_large(metrics.indexer_responses_too_large.clone());
+        let network_subgraph = match &rate_limiter {
+            Some(rate_limiter) => network_subgraph.with_rate_limiter(rate_limiter.clone()),
+            None => network_subgraph,
+        };
+        let mut indexer = network_subgraph
             .indexer_by_address(&indexer_config.address)
-            .await?;
-        indexers.push(indexer);
+            .await?
+            .with_source(IndexerSource::Config);
+        if let Some(name) = indexer_config.name {
+            indexer = indexer.with_name(name, IndexerNameSource::Config);
+        }
+        indexers.push(Arc::new(indexer));
+    }
+
+    let configured = config.indexers().len() + config.indexers_by_address().len();
+    if configured > 0 {
+        discovery_counts.insert(0, ("config".to_string(), configured));
     }
 
     // Finally, configure all the interceptors, referring to the real, static
     // indexers by ID.
-    for config in config.interceptors() {
+    let interceptors = config.interceptors();
+    if !interceptors.is_empty() {
+        discovery_counts.push(("interceptor".to_string(), interceptors.len()));
+    }
+    for config in interceptors {
         info!(interceptor_id = %config.name, "Configuring interceptor");
         let target = indexers
             .iter()
@@ -294,5 +1342,597 @@ pub async fn config_to_indexers(
         )));
     }
 
-    Ok(indexers)
+    metrics.discovered_indexers.reset();
+    for (source, count) in &discovery_counts {
+        info!(source = %source, indexers = count, "Discovered indexers");
+        metrics
+            .discovered_indexers
+            .with_label_values(&[source])
+            .set(*count as i64);
+    }
+
+    Ok(DiscoveredIndexers {
+        indexers,
+        counts: discovery_counts
+            .into_iter()
+            .map(|(source, count)| NewIndexerDiscoveryCount {
+                source,
+                n_indexers: count as i32,
+            })
+            .collect(),
+    })
+}
+
+/// Discovers the subgraph deployments to track from the network subgraphs
+/// with deployment discovery enabled. Returns `None` if there are none, i.e.
+/// if all deployments should be tracked. Fails if any of the network
+/// subgraphs can't be queried, rather than tracking fewer deployments.
+pub async fn discover_deployments(
+    config: &Config,
+    metrics: &PrometheusMetrics,
+) -> anyhow::Result<Option<Vec<NewTrackedDeployment>>> {
+    let discovering_network_subgraphs: Vec<_> = config
+        .network_subgraphs()
+        .into_iter()
+        .filter_map(|config| {
+            let discovery = config.deployment_discovery.clone()?;
+            Some((config, discovery))
+        })
+        .collect();
+    if discovering_network_subgraphs.is_empty() {
+        return Ok(None);
+    }
+
+    let http_client = http_client(&HttpClientOptions::from_config(config))?;
+    let mut tracked_deployments = vec![];
+    for (config, discovery) in discovering_network_subgraphs {
+        let network_subgraph = NetworkSubgraphClient::new(
+            config.endpoint.as_str().parse()?,
+            metrics.public_proofs_of_indexing_requests.clone(),
+        )
+        .with_client(http_client.clone());
+        let deployments = network_subgraph
+            .deployments_by_active_allocations()
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to discover deployments from network subgraph `{}`",
+                    config.endpoint
+                )
+            })?;
+
+        tracked_deployments.extend(
+            deployments
+                .into_iter()
+                .filter(|deployment| discovery.matches(deployment))
+                .map(|deployment| NewTrackedDeployment {
+                    ipfs_cid: deployment.ipfs_hash,
+                    network_subgraph: config.endpoint.clone(),
+                    network: deployment.network,
+                    active_allocations: deployment.active_allocations as i32,
+                    allocated_tokens: deployment.allocated_tokens,
+                }),
+        );
+    }
+
+    Ok(Some(tracked_deployments))
+}
+
+/// Queries how much GRT each indexer has staked from all network subgraphs,
+/// summing the stakes of indexers that appear in several of them. Fails if
+/// any of the network subgraphs can't be queried, rather than returning
+/// partial stakes.
+pub async fn discover_indexer_stakes(
+    config: &Config,
+    metrics: &PrometheusMetrics,
+) -> anyhow::Result<Vec<(IndexerAddress, f64)>> {
+    let http_client = http_client(&HttpClientOptions::from_config(config))?;
+    let mut stakes = HashMap::<IndexerAddress, f64>::new();
+    for config in config.network_subgraphs() {
+        let network_subgraph = NetworkSubgraphClient::new(
+            config.endpoint.as_str().parse()?,
+            metrics.public_proofs_of_indexing_requests.clone(),
+        )
+        .with_client(http_client.clone());
+        let network_stakes = network_subgraph.indexer_stakes().await.with_context(|| {
+            format!(
+                "failed to query indexer stakes from network subgraph `{}`",
+                config.endpoint
+            )
+        })?;
+
+        for (address, staked_tokens) in network_stakes {
+            *stakes.entry(address).or_default() += staked_tokens;
+        }
+    }
+
+    Ok(stakes.into_iter().collect())
+}
+
+/// Queries the active allocations from all network subgraphs, along with the
+/// endpoint of the network subgraph that lists them. Network subgraphs that
+/// can't be queried are left out, so that their allocations aren't mistaken
+/// for closed ones.
+pub async fn discover_allocations(
+    config: &Config,
+    metrics: &PrometheusMetrics,
+) -> anyhow::Result<Vec<(String, Vec<NewAllocation>)>> {
+    let http_client = http_client(&HttpClientOptions::from_config(config))?;
+    let mut allocations = vec![];
+    for config in config.network_subgraphs() {
+        let network_subgraph = NetworkSubgraphClient::new(
+            config.endpoint.as_str().parse()?,
+            metrics.public_proofs_of_indexing_requests.clone(),
+        )
+        .with_client(http_client.clone());
+        let active_allocations = match network_subgraph.active_allocations().await {
+            Ok(active_allocations) => active_allocations,
+            Err(err) => {
+                warn!(
+                    network_subgraph = %config.endpoint,
+                    error = %err,
+                    "Failed to query active allocations, keeping the previous ones"
+                );
+                continue;
+            }
+        };
+
+        let new_allocations = active_allocations
+            .into_iter()
+            .map(|allocation| NewAllocation {
+                id: allocation.id,
+                network_subgraph: config.endpoint.clone(),
+                indexer_address: allocation.indexer,
+                ipfs_cid: allocation.ipfs_hash,
+                allocated_tokens: allocation.allocated_tokens,
+                created_at_epoch: allocation.created_at_epoch as i32,
+            })
+            .collect();
+        allocations.push((config.endpoint.clone(), new_allocations));
+    }
+
+    Ok(allocations)
+}
+
+/// Keeps a single indexer per address, as the same indexer can come from
+/// several sources, e.g. from both the configuration and a network subgraph
+/// with different URLs. The one with the most metadata wins: configured
+/// indexers win over ones from network subgraphs (see [`IndexerSource`]),
+/// then the one whose name has the highest precedence (see
+/// [`IndexerNameSource`]), or else the first one.
+pub fn deduplicate_indexers(
+    indexers: &[Arc<dyn IndexerClient>],
+    metrics: &PrometheusMetrics,
+) -> Vec<Arc<dyn IndexerClient>> {
+    info!(len = indexers.len(), "Deduplicating indexers");
+    let precedence = |indexer: &Arc<dyn IndexerClient>| (indexer.source(), indexer.name_source());
+    let source = |indexer: &Arc<dyn IndexerClient>| {
+        indexer.source().map_or("unknown", |source| source.as_str())
+    };
+
+    let mut seen = HashMap::new();
+    let mut deduplicated: Vec<Arc<dyn IndexerClient>> = vec![];
+    for indexer in indexers {
+        let Some(&i) = seen.get(&indexer.address()) else {
+            seen.insert(indexer.address(), deduplicated.len());
+            deduplicated.push(indexer.clone());
+            continue;
+        };
+
+        let (winner, loser) = if precedence(indexer) > precedence(&deduplicated[i]) {
+            (
+                indexer.clone(),
+                std::mem::replace(&mut deduplicated[i], indexer.clone()),
+            )
+        } else {
+            (deduplicated[i].clone(), indexer.clone())
+        };
+        info!(
+            indexer_address = %indexer.address(),
+            kept_source = source(&winner),
+            kept_endpoint = winner.endpoint(),
+            dropped_source = source(&loser),
+            dropped_endpoint = loser.endpoint(),
+            "Merged duplicate indexers"
+        );
+        metrics
+            .indexers_deduplicated
+            .with_label_values(&[source(&winner)])
+            .inc();
+    }
+    info!(
+        len = deduplicated.len(),
+        delta = indexers.len() - deduplicated.len(),
+        "Successfully deduplicated indexers"
+    );
+    deduplicated
+}
+
+/// Routes all PoI requests to `indexers` through `cache`.
+pub fn with_poi_cache(
+    indexers: Vec<Arc<dyn IndexerClient>>,
+    cache: &Arc<PoiCache>,
+) -> Vec<Arc<dyn IndexerClient>> {
+    indexers
+        .into_iter()
+        .map(|indexer| Arc::new(CachingIndexer::new(indexer, cache.clone())) as _)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graphql_config(yaml: &str) -> GraphQlConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn config(yaml: &str) -> Config {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn reload_keeps_restart_only_options() {
+        let old = config("graphql: {}\ndatabaseUrl: postgres://old\nsources: []");
+        let new = config(
+            "graphql: {}\ndatabaseUrl: postgres://new\nsources: []\npollingPeriodInSeconds: 30\n\
+             divergenceInvestigationCooldownInSeconds: 60\n\
+             consensusWeighting: stake\n\
+             database:\n  maxConnections: 4\n\
+             observability:\n  otlpEndpoint: http://localhost:4317",
+        );
+        let reloaded = old.reload(new);
+        assert_eq!(reloaded.database_url, "postgres://old");
+        assert_eq!(reloaded.polling_period_in_seconds, 30);
+        assert_eq!(reloaded.observability, ObservabilityConfig::default());
+        assert_eq!(reloaded.divergence_investigation_cooldown_in_seconds, None);
+        assert_eq!(reloaded.database, DatabaseConfig::default());
+        assert_eq!(reloaded.consensus_weighting, ConsensusWeighting::Count);
+    }
+
+    #[test]
+    fn invalid_configs() {
+        let valid = "graphql: {}\ndatabaseUrl: postgres://\nsources: []";
+        assert!(config(valid).validate().is_ok());
+        assert!(config(&format!("{valid}\npollingPeriodInSeconds: 0"))
+            .validate()
+            .is_err());
+        assert!(config(
+            "graphql:\n  slowRequestThresholdInSeconds: -1\ndatabaseUrl: postgres://\nsources: []"
+        )
+        .validate()
+        .is_err());
+        assert!(config(&format!(
+            "{valid}\ndatabase:\n  maxConnections: 2\n  minIdle: 3"
+        ))
+        .validate()
+        .is_err());
+        assert!(config(&format!("{valid}\nstalledIndexerIterations: 0"))
+            .validate()
+            .is_err());
+        assert!(config(&format!("{valid}\nreliabilityDecayFactor: 1.5"))
+            .validate()
+            .is_err());
+        assert!(config(&format!("{valid}\nconsensusQuorum: 1.0"))
+            .validate()
+            .is_err());
+        assert!(config(&format!("{valid}\nindexerInactiveAfterDays: 0"))
+            .validate()
+            .is_err());
+        assert!(config(&format!(
+            "{valid}\ncircuitBreaker:\n  initialBackoffSeconds: 600\n  maxBackoffSeconds: 60"
+        ))
+        .validate()
+        .is_err());
+        assert!(config(&format!("{valid}\nmaxRequestsPerSecondPerHost: 0"))
+            .validate()
+            .is_err());
+        assert!(
+            config(&format!("{valid}\nmaxRequestsPerSecondPerHost: 2.5"))
+                .validate()
+                .is_ok()
+        );
+        assert!(config(&format!(
+            "{valid}\npinnedBlocks:\n  networks:\n    mainnet: {{ every: 0 }}"
+        ))
+        .validate()
+        .is_err());
+        assert!(config(&format!(
+            "{valid}\npinnedBlocks:\n  networks:\n    mainnet: {{ every: 6646 }}\n  \
+             deployments:\n    QmA: [100, 200]"
+        ))
+        .validate()
+        .is_ok());
+    }
+
+    #[test]
+    fn graphql_rate_limit() {
+        let rate_limit = graphql_config(
+            "rateLimit:\n  requestsPerMinute: 60\n  exemptRanges: [10.0.0.0/8, '::1', 'fd00::/8']",
+        )
+        .rate_limit
+        .unwrap();
+        assert_eq!(rate_limit.burst, 10);
+        let exempt = |address: &str| {
+            let address = address.parse().unwrap();
+            rate_limit
+                .exempt_ranges
+                .iter()
+                .any(|range| range.contains(address))
+        };
+        assert!(exempt("10.1.2.3"));
+        assert!(exempt("::ffff:10.1.2.3"));
+        assert!(exempt("::1"));
+        assert!(exempt("fd12::1"));
+        assert!(!exempt("11.0.0.1"));
+        assert!(!exempt("::2"));
+        assert!(
+            graphql_config("rateLimit:\n  requestsPerMinute: 60\n  exemptRanges: [0.0.0.0/0]")
+                .rate_limit
+                .unwrap()
+                .exempt_ranges[0]
+                .contains("192.168.1.1".parse().unwrap())
+        );
+
+        for invalid in ["10.0.0.0/33", "10.0.0/8", "fd00::/129", "localhost"] {
+            assert!(
+                serde_yaml::from_str::<IpRange>(invalid).is_err(),
+                "{invalid}"
+            );
+        }
+
+        let valid = "databaseUrl: postgres://\nsources: []";
+        assert!(config(&format!(
+            "{valid}\ngraphql:\n  rateLimit:\n    requestsPerMinute: 0"
+        ))
+        .validate()
+        .is_err());
+        assert!(config(&format!(
+            "{valid}\ngraphql:\n  rateLimit:\n    requestsPerMinute: 60\n    burst: 0"
+        ))
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn missing_tls_client_certificate() {
+        let config = config(
+            "graphql: {}\ndatabaseUrl: postgres://\nsources: []\n\
+             tlsClientCertificate:\n  certificatePath: /nonexistent/cert.pem\n  keyPath: /nonexistent/key.pem",
+        );
+        let err = config.validate().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("failed to read TLS client certificate /nonexistent/cert.pem"));
+    }
+
+    #[test]
+    fn block_choice_policy_options() {
+        use crate::block_choice::{ReferenceBlock, TieBreak};
+
+        let policy = |yaml: &str| {
+            config(&format!(
+                "graphql: {{}}\ndatabaseUrl: postgres://\nsources: []\nblockChoicePolicy: {yaml}"
+            ))
+            .block_choice_policy
+        };
+        let tie_break = |yaml: &str| match policy(yaml) {
+            BlockChoicePolicy::MaxSyncedBlocks { tie_break } => Some(tie_break),
+            _ => None,
+        };
+
+        assert!(matches!(policy("earliest"), BlockChoicePolicy::Earliest));
+        assert_eq!(tie_break("maxSyncedBlocks"), Some(TieBreak::Highest));
+        assert_eq!(
+            tie_break("{ maxSyncedBlocks: {} }"),
+            Some(TieBreak::Highest)
+        );
+        assert_eq!(
+            tie_break("{ maxSyncedBlocks: { tieBreak: lowest } }"),
+            Some(TieBreak::Lowest)
+        );
+        assert_eq!(
+            tie_break("{ maxSyncedBlocks: { tieBreak: mostRecentHash } }"),
+            Some(TieBreak::MostRecentHash)
+        );
+        assert!(matches!(
+            policy("{ referenceBlock: 100 }"),
+            BlockChoicePolicy::ReferenceBlock(ReferenceBlock::Fixed(100))
+        ));
+    }
+
+    #[test]
+    fn missing_extra_ca_cert() {
+        let err = config(
+            "graphql: {}\ndatabaseUrl: postgres://\nsources: []\nextraCaCerts: [/nonexistent/ca.pem]",
+        )
+        .validate()
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("failed to read CA certificate /nonexistent/ca.pem"));
+    }
+
+    /// Stands in for an HTTP proxy, answering every request with `name` and
+    /// the request line it got.
+    async fn mock_proxy(name: &'static str) -> Url {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut head = vec![];
+                let mut buf = [0; 1024];
+                while !head.ends_with(b"\r\n\r\n") {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    head.extend_from_slice(&buf[..n]);
+                }
+
+                let head = String::from_utf8_lossy(&head);
+                let body = format!("{} {}", name, head.lines().next().unwrap_or_default());
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        url.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn indexers_can_override_the_http_proxy() {
+        let global_proxy = mock_proxy("global").await;
+        let indexer_proxy = mock_proxy("indexer").await;
+        let config = config(&format!(
+            "graphql: {{}}\ndatabaseUrl: postgres://\nsources: []\nhttpProxy: {global_proxy}"
+        ));
+        let options = HttpClientOptions::from_config(&config);
+        let indexer: IndexerConfig = serde_yaml::from_str(&format!(
+            "address: '0x{}'\nindexNodeEndpoint: http://indexer.invalid/status\nhttpProxy: {}",
+            "01".repeat(20),
+            indexer_proxy
+        ))
+        .unwrap();
+
+        let get = |options: HttpClientOptions| async move {
+            http_client(&options)
+                .unwrap()
+                .get("http://indexer.invalid/status")
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap()
+        };
+        assert_eq!(
+            get(options.clone()).await,
+            "global GET http://indexer.invalid/status HTTP/1.1"
+        );
+        assert_eq!(
+            get(options.for_indexer(&indexer).unwrap()).await,
+            "indexer GET http://indexer.invalid/status HTTP/1.1"
+        );
+
+        // Indexers without overrides share the global client.
+        let without_overrides = IndexerConfig {
+            http_proxy: None,
+            ..indexer.clone()
+        };
+        assert!(options.for_indexer(&without_overrides).is_none());
+        let insecure = IndexerConfig {
+            accept_invalid_certs: true,
+            ..without_overrides
+        };
+        let insecure_options = options.for_indexer(&insecure).unwrap();
+        assert_eq!(insecure_options.proxy, Some(&global_proxy));
+        assert!(insecure_options.accept_invalid_certs);
+    }
+
+    #[test]
+    fn missing_tls_server_certificate() {
+        let err = config(
+            "graphql:\n  tls:\n    certPath: /nonexistent/cert.pem\n    keyPath: /nonexistent/key.pem\n\
+             databaseUrl: postgres://\nsources: []",
+        )
+        .validate()
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("failed to read TLS certificate /nonexistent/cert.pem"));
+
+        let err = config(
+            "graphql: {}\ndatabaseUrl: postgres://\nsources: []\n\
+             prometheusTls:\n  certPath: /nonexistent/cert.pem\n  keyPath: /nonexistent/key.pem",
+        )
+        .validate()
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("failed to read TLS certificate /nonexistent/cert.pem"));
+    }
+
+    #[test]
+    fn graphql_server_enabled() {
+        assert!(graphql_config("{}").is_enabled());
+        assert!(graphql_config("port: 3030").is_enabled());
+        assert!(graphql_config("enabled: true\nport: 0").is_enabled());
+        // Backwards compatibility with the old port-based switch.
+        assert!(!graphql_config("port: 0").is_enabled());
+        assert!(!graphql_config("enabled: false\nport: 3030").is_enabled());
+    }
+
+    #[test]
+    fn malformed_caip2_chain_ids() {
+        let chains = |caip2: &str| {
+            serde_yaml::from_str::<Config>(&format!(
+                "graphql: {{}}\ndatabaseUrl: postgres://\nsources: []\nchains:\n  mainnet:\n    caip2: {caip2}"
+            ))
+        };
+
+        let config = chains("eip155:1").unwrap();
+        assert_eq!(
+            config.chains["mainnet"].caip2.as_ref().unwrap().reference(),
+            "1"
+        );
+        let err = chains("eip155").unwrap_err();
+        assert!(err.to_string().contains("invalid CAIP-2 chain ID `eip155`"));
+    }
+
+    #[test]
+    fn duplicate_indexers_prefer_the_configuration() {
+        let metrics = crate::metrics();
+        let address = IndexerAddress::from([1; 20]);
+        let indexer = |name: &str, endpoint: &str| {
+            RealIndexer::new(
+                Some(name.to_string()),
+                address,
+                endpoint.to_string(),
+                metrics.public_proofs_of_indexing_requests.clone(),
+            )
+        };
+        let from_network_subgraph = indexer("Display name", "http://a.example.com")
+            .with_name_source(IndexerNameSource::NetworkSubgraph)
+            .with_source(IndexerSource::NetworkSubgraph);
+        let from_config = indexer("", "http://b.example.com").with_source(IndexerSource::Config);
+        let other = RealIndexer::new(
+            None,
+            IndexerAddress::from([2; 20]),
+            "http://c.example.com".to_string(),
+            metrics.public_proofs_of_indexing_requests.clone(),
+        );
+
+        let merged = metrics
+            .indexers_deduplicated
+            .with_label_values(&["config"])
+            .get();
+        let indexers = deduplicate_indexers(
+            &[
+                Arc::new(from_network_subgraph),
+                Arc::new(other),
+                Arc::new(from_config),
+            ],
+            metrics,
+        );
+        let endpoints = indexers
+            .iter()
+            .map(|indexer| indexer.endpoint().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(endpoints, ["http://b.example.com", "http://c.example.com"]);
+        assert_eq!(
+            metrics
+                .indexers_deduplicated
+                .with_label_values(&["config"])
+                .get(),
+            merged + 1
+        );
+    }
 }
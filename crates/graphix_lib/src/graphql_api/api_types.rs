@@ -1,10 +1,16 @@
+use std::collections::BTreeMap;
+
 use async_graphql::{ComplexObject, Context, Object, SimpleObject};
-use common::{IndexerAddress, IpfsCid};
+use common::{Caip2ChainId, IndexerAddress, IpfsCid};
 use graphix_common_types as common;
+use graphix_indexer_client::{IndexerNameSource, IndexerSource};
 use graphix_store::models::{self, IntId};
+use graphix_store::{Pagination, PoiFilter};
 use num_traits::cast::ToPrimitive;
 
 use super::{ctx_data, ApiSchemaContext};
+use crate::config::ConsensusWeighting;
+use crate::indexing_loop::CircuitBreakerState;
 
 #[derive(Clone, derive_more::From)]
 pub struct SubgraphDeployment {
@@ -20,6 +26,10 @@ impl SubgraphDeployment {
         self.model.name.as_deref()
     }
 
+    pub fn staked_tokens(&self) -> Option<f64> {
+        self.model.staked_tokens
+    }
+
     pub async fn network(&self, ctx: &ApiSchemaContext) -> Result<Network, String> {
         let loader = &ctx.loader_network;
 
@@ -51,6 +61,30 @@ impl SubgraphDeployment {
     async fn graphql_network(&self, ctx: &Context<'_>) -> Result<Network, String> {
         self.network(ctx_data(ctx)).await
     }
+
+    /// Metadata from the deployment's manifest, if it has been fetched from
+    /// IPFS already. Requires `ipfsGateway` to be configured.
+    async fn manifest(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Option<models::SgDeploymentManifest>, String> {
+        let loader = &ctx_data(ctx).loader_sg_deployment_manifest;
+
+        loader.load_one(self.model.id).await.map_err(Into::into)
+    }
+
+    /// The active allocations on the deployment, the largest first, according
+    /// to the network subgraphs. Empty if no indexer is allocated on it.
+    async fn allocated_indexers(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<models::Allocation>, String> {
+        ctx_data(ctx)
+            .store
+            .allocations(None, Some(&self.model.cid), false)
+            .await
+            .map_err(|err| err.to_string())
+    }
 }
 
 /// A network where subgraph deployments are indexed.
@@ -65,7 +99,7 @@ impl Network {
     }
 
     pub fn caip2(&self) -> Option<&str> {
-        self.model.caip2.as_deref()
+        self.model.caip2.as_ref().map(Caip2ChainId::as_str)
     }
 }
 
@@ -121,10 +155,62 @@ impl Indexer {
         self.model.address.to_string()
     }
 
+    /// The human-readable name of the indexer. Names from the configuration
+    /// take precedence over display names from the network subgraph, which
+    /// take precedence over ENS names.
     async fn default_display_name(&self) -> Option<String> {
         self.model.name.clone()
     }
 
+    /// Where `defaultDisplayName` comes from.
+    async fn name_source(&self) -> Option<IndexerNameSource> {
+        self.model
+            .name_source
+            .as_deref()
+            .and_then(|source| source.parse().ok())
+    }
+
+    /// Where the indexer was configured from. When the same indexer comes from
+    /// several sources, the configuration takes precedence over network
+    /// subgraphs.
+    async fn source(&self) -> Option<IndexerSource> {
+        self.model
+            .source
+            .as_deref()
+            .and_then(|source| source.parse().ok())
+    }
+
+    /// The region of the indexer, as configured in `indexerMetadata`.
+    /// Graphix doesn't interpret it.
+    async fn region(&self) -> Option<String> {
+        self.model.region.clone()
+    }
+
+    /// The labels of the indexer, as configured in `indexerMetadata`.
+    /// Graphix doesn't interpret them.
+    async fn labels(&self) -> Vec<String> {
+        self.model.labels.clone()
+    }
+
+    /// How much GRT the indexer has staked according to the network
+    /// subgraphs. Only known when `consensusWeighting` is `stake`.
+    #[graphql(name = "stakedTokens")]
+    async fn graphql_staked_tokens(&self) -> Option<f64> {
+        self.model.staked_tokens
+    }
+
+    /// When the indexer was last listed by any of the configured sources.
+    async fn last_seen_at(&self) -> chrono::NaiveDateTime {
+        self.model.last_seen_at
+    }
+
+    /// Whether the indexer was seen within `indexerInactiveAfterDays`.
+    /// Inactive indexers keep all their data and become active again when
+    /// they reappear.
+    async fn is_active(&self) -> bool {
+        self.model.is_active
+    }
+
     /// The version of the indexer.
     #[graphql(name = "graphNodeVersion")]
     async fn graphql_graph_node_version(
@@ -151,6 +237,37 @@ impl Indexer {
             Ok(None)
         }
     }
+
+    /// The outcome of the latest query of each kind that Graphix sent to the
+    /// indexer, which tells unreachable indexers apart from disagreeing ones.
+    async fn last_query_status(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<models::IndexerQueryOutcome>, String> {
+        ctx_data(ctx)
+            .store
+            .latest_indexer_query_outcomes(self.model.id)
+            .await
+            .map_err(|err| err.to_string())
+    }
+
+    /// The indexer's allocations, the largest first, according to the network
+    /// subgraphs.
+    async fn allocations(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            default = false,
+            desc = "Whether to include allocations that are no longer active."
+        )]
+        include_closed: bool,
+    ) -> Result<Vec<models::Allocation>, String> {
+        ctx_data(ctx)
+            .store
+            .allocations(Some(&self.model.address), None, include_closed)
+            .await
+            .map_err(|err| err.to_string())
+    }
 }
 
 #[derive(derive_more::From)]
@@ -360,11 +477,49 @@ impl ProofOfIndexing {
     async fn graphql_indexer(&self, ctx: &Context<'_>) -> Result<Indexer, String> {
         self.indexer(ctx_data(ctx)).await
     }
+
+    /// Whether this PoI is at a block that's pinned in the configuration,
+    /// rather than one chosen by the block choice policy.
+    async fn pinned(&self) -> bool {
+        self.model.pinned
+    }
+
+    /// When Graphix collected this PoI from the indexer.
+    async fn collected_at(&self) -> chrono::NaiveDateTime {
+        self.model.created_at
+    }
+
+    /// The status endpoint of the indexer that this PoI was queried from,
+    /// which tells apart indexers that are reachable through several URLs.
+    /// Null for PoIs collected before Graphix recorded it.
+    async fn source_url(&self) -> Option<&str> {
+        self.model.source_url.as_deref()
+    }
+}
+
+/// The PoI that an indexer reported for a subgraph deployment and block.
+#[derive(SimpleObject)]
+pub struct PoiWithLiveness {
+    pub poi: ProofOfIndexing,
+    /// Whether this is still the indexer's live PoI for the deployment.
+    pub live: bool,
+}
+
+/// A PoI with a hash that was looked up, as reported by one indexer for a
+/// subgraph deployment and block.
+#[derive(SimpleObject)]
+pub struct PoiMatch {
+    pub poi: ProofOfIndexing,
+    /// Whether this is the PoI of the majority of indexers for the block, as
+    /// of the most recent PoI of each indexer.
+    pub is_majority: bool,
 }
 
 /// A specific indexer can use `PoiAgreementRatio` to check in how much agreement it is with other
 /// indexers, given its own poi for each deployment. A consensus currently means a majority of
-/// indexers agreeing on a particular POI.
+/// indexers agreeing on a particular POI, or a majority of their stake if `consensusWeighting` is
+/// `stake`, unless a reference indexer that has a POI for the deployment is given or configured,
+/// in which case its POI is the consensus.
 #[derive(SimpleObject, Debug)]
 #[graphql(complex)]
 pub struct PoiAgreementRatio {
@@ -386,6 +541,14 @@ pub struct PoiAgreementRatio {
 
     /// Indicates if the specified indexer's POI is part of the consensus.
     pub in_consensus: bool,
+
+    /// How the majority was weighted. Counts indexers if the stake of any
+    /// indexer with a live POI for the deployment is unknown.
+    pub consensus_weighting: ConsensusWeighting,
+
+    /// The reference indexer whose POI is the consensus. Null if the
+    /// consensus is the majority's POI.
+    pub reference_indexer: Option<IndexerAddress>,
 }
 
 #[ComplexObject]
@@ -403,3 +566,611 @@ impl PoiAgreementRatio {
             .map(Into::into)
     }
 }
+
+/// A PoI value and the indexers that reported it.
+#[derive(SimpleObject, Debug)]
+pub struct SupportedPoi {
+    pub poi: common::PoiBytes,
+    pub indexers: Vec<IndexerAddress>,
+}
+
+/// The PoI that most indexers reported for a subgraph deployment and block,
+/// as of the most recent PoI of each indexer, or the PoI of the trusted
+/// indexer of the deployment's network if it reported one.
+#[derive(SimpleObject, Debug)]
+pub struct ConsensusPoi {
+    /// IPFS CID of the subgraph deployment.
+    pub deployment: String,
+    pub block_number: u64,
+    /// The trusted indexer's PoI, or else the most common PoI. Null if there
+    /// are no PoIs for the block, or if two or more PoIs are tied for most
+    /// common.
+    pub poi: Option<common::PoiBytes>,
+    /// The indexers that reported `poi`.
+    pub supporting_indexers: Vec<IndexerAddress>,
+    pub n_supporting_indexers: u32,
+    /// All other PoIs, each with the indexers that reported it, most common
+    /// first.
+    pub dissenting_pois: Vec<SupportedPoi>,
+    /// Total number of indexers that reported a PoI for the block.
+    pub total_indexers: u32,
+    /// Whether more than the configured `consensusQuorum` fraction of
+    /// indexers reported the most common PoI. Always true if the trusted
+    /// indexer reported a PoI.
+    pub has_consensus: bool,
+    /// The trusted indexer configured for the deployment's network, if it
+    /// reported a PoI for the block, in which case `poi` is its PoI regardless
+    /// of the majority. Null if `poi` is the most common PoI.
+    pub trusted_indexer: Option<IndexerAddress>,
+}
+
+impl ConsensusPoi {
+    /// Builds the consensus out of the PoIs reported for a single block,
+    /// ordered by number of indexers, descending. The PoI of
+    /// `trusted_indexer`, if it reported one, is the consensus.
+    pub fn new(
+        deployment: String,
+        block_number: u64,
+        support: Vec<models::PoiSupport>,
+        quorum: f64,
+        trusted_indexer: Option<IndexerAddress>,
+    ) -> Self {
+        let total_indexers: u32 = support.iter().map(|s| s.indexers.len() as u32).sum();
+        let mut dissenting_pois: Vec<SupportedPoi> = support
+            .into_iter()
+            .map(|s| SupportedPoi {
+                poi: s.poi,
+                indexers: s.indexers,
+            })
+            .collect();
+
+        let trusted_poi = trusted_indexer.and_then(|address| {
+            dissenting_pois
+                .iter()
+                .position(|s| s.indexers.contains(&address))
+        });
+        // A tie for most common PoI is no consensus, so all PoIs dissent.
+        let is_tie = dissenting_pois.len() > 1
+            && dissenting_pois[0].indexers.len() == dissenting_pois[1].indexers.len();
+        let consensus = match trusted_poi {
+            Some(i) => Some(dissenting_pois.remove(i)),
+            None if dissenting_pois.is_empty() || is_tie => None,
+            None => Some(dissenting_pois.remove(0)),
+        };
+
+        let (poi, supporting_indexers) = match consensus {
+            Some(SupportedPoi { poi, indexers }) => (Some(poi), indexers),
+            None => (None, vec![]),
+        };
+        let n_supporting_indexers = supporting_indexers.len() as u32;
+
+        Self {
+            deployment,
+            block_number,
+            has_consensus: trusted_poi.is_some()
+                || (poi.is_some() && n_supporting_indexers as f64 > quorum * total_indexers as f64),
+            trusted_indexer: trusted_poi.and(trusted_indexer),
+            poi,
+            supporting_indexers,
+            n_supporting_indexers,
+            dissenting_pois,
+            total_indexers,
+        }
+    }
+}
+
+/// An indexer that serves a subgraph deployment, with its most recent PoI for
+/// the deployment.
+#[derive(derive_more::From)]
+pub struct IndexerDeploymentInfo {
+    model: models::IndexerDeploymentInfo,
+}
+
+#[Object]
+impl IndexerDeploymentInfo {
+    async fn indexer(&self) -> Indexer {
+        self.model.indexer.clone().into()
+    }
+
+    /// The block of the indexer's most recent PoI for the deployment.
+    async fn latest_block(&self) -> Block {
+        self.model.latest_block.clone().into()
+    }
+
+    /// The indexer's most recent PoI for the deployment.
+    async fn latest_poi(&self) -> ProofOfIndexing {
+        self.model.latest_poi.clone().into()
+    }
+}
+
+/// The indexing status of a subgraph deployment on a specific indexer, as
+/// most recently reported by the indexer.
+pub struct IndexingStatus {
+    model: models::StoredIndexingStatus,
+    blocks_behind: u64,
+}
+
+impl IndexingStatus {
+    pub fn new(model: models::StoredIndexingStatus, blocks_behind: u64) -> Self {
+        Self {
+            model,
+            blocks_behind,
+        }
+    }
+}
+
+#[Object]
+impl IndexingStatus {
+    /// The address of the indexer, encoded as a hex string with a '0x' prefix.
+    async fn indexer_address(&self) -> IndexerAddress {
+        self.model.indexer
+    }
+
+    /// IPFS CID of the subgraph deployment.
+    async fn deployment(&self) -> &str {
+        self.model.deployment.as_str()
+    }
+
+    /// The name of the network indexed by the subgraph deployment.
+    async fn network(&self) -> &str {
+        self.model.network.as_str()
+    }
+
+    /// The most recent block number that the indexer has indexed.
+    async fn latest_block_number(&self) -> u64 {
+        self.model.latest_block_number as u64
+    }
+
+    /// The hash of the most recent block that the indexer has indexed, if
+    /// known.
+    async fn latest_block_hash(&self) -> Option<common::BlockHash> {
+        self.model.latest_block_hash.clone()
+    }
+
+    /// The earliest block number for which the indexer still has data. PoIs
+    /// below this block can't be queried from this indexer, e.g. because it
+    /// pruned them.
+    async fn earliest_block_number(&self) -> u64 {
+        self.model.earliest_block_number as u64
+    }
+
+    /// How many blocks the indexer trails the most advanced indexer of the
+    /// same subgraph deployment, as of their most recent statuses.
+    async fn blocks_behind(&self) -> u64 {
+        self.blocks_behind
+    }
+
+    /// When the indexer last reported this status. Statuses of indexers that
+    /// stopped answering keep their last report.
+    async fn reported_at(&self) -> chrono::NaiveDateTime {
+        self.model.reported_at
+    }
+}
+
+/// A subgraph deployment with the indexers serving it, the consensus on their
+/// live PoIs, its PoI history and recent divergence investigations. Nested
+/// fields take a fixed number of database queries, rather than one per
+/// indexer or PoI.
+#[derive(derive_more::From)]
+pub struct DeploymentDetails {
+    model: models::SgDeployment,
+}
+
+impl DeploymentDetails {
+    fn deployment(&self) -> SubgraphDeployment {
+        self.model.clone().into()
+    }
+}
+
+#[Object]
+impl DeploymentDetails {
+    /// IPFS CID of the subgraph deployment.
+    async fn cid(&self) -> IpfsCid {
+        self.model.cid.clone()
+    }
+
+    /// Human-readable name of the subgraph deployment, if present.
+    async fn name(&self) -> Option<&str> {
+        self.model.name.as_deref()
+    }
+
+    /// Network of the subgraph deployment.
+    async fn network(&self, ctx: &Context<'_>) -> Result<Network, String> {
+        self.deployment().network(ctx_data(ctx)).await
+    }
+
+    /// The indexers that reported an indexing status for the deployment, most
+    /// advanced first.
+    async fn indexers(&self, ctx: &Context<'_>) -> Result<Vec<DeploymentIndexer>, String> {
+        let ctx_data = ctx_data(ctx);
+        let cid = self.model.cid.to_string();
+
+        let mut statuses = ctx_data
+            .store
+            .indexing_statuses(None, Some(&cid))
+            .await
+            .map_err(|err| err.to_string())?;
+        statuses.sort_by_key(|status| std::cmp::Reverse(status.latest_block_number));
+        let latest_block = statuses
+            .first()
+            .map_or(0, |status| status.latest_block_number);
+        let connectivity = ctx_data.indexer_connectivity.borrow().clone();
+
+        Ok(statuses
+            .into_iter()
+            .map(|status| {
+                let circuit_breaker = connectivity
+                    .iter()
+                    .find(|connectivity| connectivity.indexer == status.indexer)
+                    .map(|connectivity| connectivity.state)
+                    .unwrap_or_default();
+                let blocks_behind = (latest_block - status.latest_block_number) as u64;
+                DeploymentIndexer {
+                    status: IndexingStatus::new(status, blocks_behind),
+                    circuit_breaker,
+                }
+            })
+            .collect())
+    }
+
+    /// The live PoIs of the indexers grouped by value, as a consensus for
+    /// each block that any of them is at, ordered by block number. Indexers
+    /// that stopped responding are left out.
+    async fn live_poi_consensus(&self, ctx: &Context<'_>) -> Result<Vec<ConsensusPoi>, String> {
+        let ctx_data = ctx_data(ctx);
+
+        let pois = ctx_data
+            .store
+            .live_pois(
+                None,
+                Some(std::slice::from_ref(&self.model.cid)),
+                None,
+                None,
+                false,
+            )
+            .await
+            .map_err(|err| err.to_string())?;
+        let indexers = ctx_data
+            .loader_indexer
+            .load_many(pois.iter().map(|poi| poi.indexer_id))
+            .await?;
+        let blocks = ctx_data
+            .loader_block
+            .load_many(pois.iter().map(|poi| poi.block_id))
+            .await?;
+
+        let mut indexers_by_block = BTreeMap::<i64, BTreeMap<_, Vec<_>>>::new();
+        for poi in pois {
+            let (Some(indexer), Some(block)) =
+                (indexers.get(&poi.indexer_id), blocks.get(&poi.block_id))
+            else {
+                continue;
+            };
+            indexers_by_block
+                .entry(block.number)
+                .or_default()
+                .entry(poi.poi)
+                .or_default()
+                .push(indexer.address);
+        }
+
+        let network = self.deployment().network(ctx_data).await?;
+        let trusted_indexer = ctx_data
+            .config
+            .chains
+            .get(network.name())
+            .and_then(|chain| chain.trusted_indexer);
+        Ok(indexers_by_block
+            .into_iter()
+            .map(|(block_number, indexers_by_poi)| {
+                let mut support: Vec<_> = indexers_by_poi
+                    .into_iter()
+                    .map(|(poi, mut indexers)| {
+                        indexers.sort();
+                        models::PoiSupport {
+                            block_number,
+                            poi,
+                            indexers,
+                        }
+                    })
+                    .collect();
+                support.sort_by_key(|s| std::cmp::Reverse(s.indexers.len()));
+                ConsensusPoi::new(
+                    self.model.cid.to_string(),
+                    block_number as u64,
+                    support,
+                    ctx_data.config.consensus_quorum,
+                    trusted_indexer,
+                )
+            })
+            .collect())
+    }
+
+    /// All PoIs collected for the deployment, live or not, newest block
+    /// first.
+    async fn poi_history(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 0, desc = "Number of results to skip, for pagination.")] offset: u32,
+        #[graphql(
+            default = 100,
+            validator(maximum = 250),
+            desc = "Upper limit on the number of shown results."
+        )]
+        limit: u16,
+    ) -> Result<Vec<ProofOfIndexing>, String> {
+        let filter = PoiFilter {
+            deployments: vec![self.model.cid.clone()],
+            ..Default::default()
+        };
+        let pois = ctx_data(ctx)
+            .store
+            .search_pois(&filter, Pagination { offset, limit })
+            .await
+            .map_err(|err| err.to_string())?;
+
+        Ok(pois.into_iter().map(Into::into).collect())
+    }
+
+    /// The most recent divergence investigation reports about the
+    /// deployment, newest first, whether it was investigated as a whole or
+    /// through some of its PoIs.
+    async fn divergence_reports(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            default = 10,
+            validator(maximum = 50),
+            desc = "Upper limit on the number of shown results."
+        )]
+        limit: u16,
+    ) -> Result<Vec<common::DivergenceInvestigationReport>, String> {
+        let reports = ctx_data(ctx)
+            .store
+            .deployment_divergence_investigation_reports(&self.model, limit)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        Ok(reports
+            .into_iter()
+            .map(divergence_investigation_report)
+            .collect())
+    }
+}
+
+/// An indexer that serves a subgraph deployment, see
+/// `DeploymentDetails.indexers`.
+#[derive(SimpleObject)]
+pub struct DeploymentIndexer {
+    /// The most recent indexing status that the indexer reported for the
+    /// deployment, with its latest block.
+    pub status: IndexingStatus,
+    /// The state of the indexer's circuit breaker as of the most recent
+    /// polling loop. Always `CLOSED` unless `circuitBreaker.enabled` is set.
+    pub circuit_breaker: CircuitBreakerState,
+}
+
+/// The PoIs of two indexers for a subgraph deployment, at the most recent
+/// block for which Graphix collected PoIs from both.
+#[derive(SimpleObject, Debug)]
+pub struct DeploymentPoiComparison {
+    /// IPFS CID of the subgraph deployment.
+    pub deployment: String,
+    pub block_number: u64,
+    pub block_hash: common::BlockHash,
+    /// The PoI of the first indexer.
+    pub poi1: common::PoiBytes,
+    /// The PoI of the second indexer.
+    pub poi2: common::PoiBytes,
+}
+
+impl From<models::PoiPair> for DeploymentPoiComparison {
+    fn from(pair: models::PoiPair) -> Self {
+        Self {
+            deployment: pair.deployment_cid,
+            block_number: pair.block_number as u64,
+            block_hash: pair.block_hash,
+            poi1: pair.poi1,
+            poi2: pair.poi2,
+        }
+    }
+}
+
+/// A comparison of two indexers' PoIs across all subgraph deployments that
+/// Graphix collected PoIs for from both of them.
+#[derive(SimpleObject, Debug)]
+pub struct IndexerComparison {
+    /// Number of deployments on which the indexers agree.
+    pub n_agreeing_deployments: u32,
+    /// Number of deployments on which the indexers disagree.
+    pub n_disagreeing_deployments: u32,
+    /// Deployments on which the indexers agree.
+    pub agreeing: Vec<DeploymentPoiComparison>,
+    /// Deployments on which the indexers disagree.
+    pub disagreeing: Vec<DeploymentPoiComparison>,
+}
+
+impl FromIterator<DeploymentPoiComparison> for IndexerComparison {
+    fn from_iter<I: IntoIterator<Item = DeploymentPoiComparison>>(iter: I) -> Self {
+        let (agreeing, disagreeing): (Vec<_>, Vec<_>) = iter
+            .into_iter()
+            .partition(|comparison| comparison.poi1 == comparison.poi2);
+
+        Self {
+            n_agreeing_deployments: agreeing.len() as u32,
+            n_disagreeing_deployments: disagreeing.len() as u32,
+            agreeing,
+            disagreeing,
+        }
+    }
+}
+
+/// A page of the subgraph deployments that have PoIs, see
+/// `distinctDeployments`.
+#[derive(SimpleObject)]
+pub struct DeploymentPage {
+    pub deployments: Vec<DeploymentWithIndexerCount>,
+    /// The number of deployments with PoIs across all pages.
+    pub total_count: u64,
+}
+
+impl From<models::DeploymentPage> for DeploymentPage {
+    fn from(page: models::DeploymentPage) -> Self {
+        Self {
+            deployments: page
+                .deployments
+                .into_iter()
+                .map(|deployment| DeploymentWithIndexerCount {
+                    deployment: deployment.deployment.into(),
+                    n_indexers: deployment.n_indexers as u32,
+                })
+                .collect(),
+            total_count: page.total_count as u64,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct DeploymentWithIndexerCount {
+    pub deployment: SubgraphDeployment,
+    /// The number of indexers that reported PoIs for the deployment.
+    pub n_indexers: u32,
+}
+
+/// A backfill of PoIs for past blocks of a subgraph deployment, requested with
+/// `requestPoiBackfill`.
+#[derive(derive_more::From)]
+pub struct PoiBackfillJob {
+    model: models::PoiBackfillJob,
+}
+
+#[Object]
+impl PoiBackfillJob {
+    async fn uuid(&self) -> String {
+        self.model.uuid.to_string()
+    }
+
+    /// IPFS CID of the subgraph deployment.
+    async fn deployment(&self) -> &str {
+        &self.model.deployment
+    }
+
+    async fn from_block(&self) -> u64 {
+        self.model.from_block as u64
+    }
+
+    async fn to_block(&self) -> u64 {
+        self.model.to_block as u64
+    }
+
+    /// PoIs are requested every this many blocks.
+    async fn interval(&self) -> u64 {
+        self.model.block_interval as u64
+    }
+
+    async fn status(&self) -> models::PoiBackfillStatus {
+        self.model.status()
+    }
+
+    /// The number of PoI requests the job sends in total, across all
+    /// indexers. Unknown until the job has started.
+    async fn requests_total(&self) -> Option<u64> {
+        self.model.requests_total.map(|n| n as u64)
+    }
+
+    async fn requests_sent(&self) -> u64 {
+        self.model.requests_sent as u64
+    }
+
+    async fn pois_written(&self) -> u64 {
+        self.model.pois_written as u64
+    }
+
+    /// Why the job failed, if it did.
+    async fn error(&self) -> Option<&str> {
+        self.model.error.as_deref()
+    }
+
+    async fn created_at(&self) -> chrono::NaiveDateTime {
+        self.model.created_at
+    }
+
+    async fn updated_at(&self) -> chrono::NaiveDateTime {
+        self.model.updated_at
+    }
+}
+
+/// A triage status change of a divergence investigation report, made with
+/// `updateDivergenceStatus`.
+#[derive(derive_more::From)]
+pub struct DivergenceTriageEvent {
+    model: models::DivergenceTriageEvent,
+}
+
+#[Object]
+impl DivergenceTriageEvent {
+    async fn status(&self) -> common::DivergenceTriageStatus {
+        self.model.status()
+    }
+
+    async fn note(&self) -> Option<&str> {
+        self.model.note.as_deref()
+    }
+
+    /// The label of the admin API key that changed the status.
+    async fn actor(&self) -> &str {
+        &self.model.actor
+    }
+
+    async fn created_at(&self) -> chrono::NaiveDateTime {
+        self.model.created_at
+    }
+}
+
+/// Deserializes a stored divergence investigation report, along with its
+/// triage.
+pub fn divergence_investigation_report(
+    model: models::DivergenceInvestigationReport,
+) -> common::DivergenceInvestigationReport {
+    let triage_status = model.triage_status();
+    let report: common::DivergenceInvestigationReport =
+        serde_json::from_value(model.report).expect("Can't deserialize report from database");
+    common::DivergenceInvestigationReport {
+        triage_status,
+        triage_note: model.triage_note,
+        ..report
+    }
+}
+
+/// The version and build of this Graphix instance.
+#[derive(SimpleObject)]
+pub struct ServerInfo {
+    pub version: &'static str,
+    /// The commit Graphix was built from, or `unknown` if the build had no
+    /// git metadata.
+    pub git_sha: &'static str,
+    /// When Graphix was built, in RFC 3339 format.
+    pub build_timestamp: &'static str,
+    /// The compiler that built Graphix, as reported by `rustc --version`.
+    pub rustc_version: &'static str,
+}
+
+impl ServerInfo {
+    pub fn current() -> Self {
+        Self {
+            version: crate::GRAPHIX_VERSION,
+            git_sha: crate::GRAPHIX_GIT_SHA,
+            build_timestamp: crate::GRAPHIX_BUILD_TIMESTAMP,
+            rustc_version: crate::GRAPHIX_RUSTC_VERSION,
+        }
+    }
+}
+
+/// The state of this Graphix instance.
+#[derive(SimpleObject)]
+pub struct SystemStatus {
+    /// Whether a main loop iteration has completed since Graphix started.
+    /// Until then the database may have no PoIs at all, or only outdated
+    /// ones, so empty results don't mean that indexers agree.
+    pub initialized: bool,
+}
@@ -1,20 +1,34 @@
 pub mod api_types;
+mod request_metrics;
 mod server;
 
+use std::sync::Arc;
 use std::time::Duration;
 
 use async_graphql::dataloader::DataLoader;
 use async_graphql::{Context, EmptySubscription, Schema, SchemaBuilder};
-use graphix_store::{Store, StoreLoader};
+use graphix_store::{PoiStore, Store, StoreLoader};
+use tokio::sync::watch;
 
+pub use self::request_metrics::GraphQlMetrics;
 use self::server::{MutationRoot, QueryRoot};
 use crate::config::Config;
+use crate::indexing_loop::IndexerConnectivity;
 
 pub type ApiSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
 
 pub struct ApiSchemaContext {
     pub store: Store,
+    /// PoI comparisons, changes and divergences. Backed by `store` unless
+    /// replaced with [`ApiSchemaContext::with_poi_store`].
+    pub poi_store: Arc<dyn PoiStore>,
     pub config: Config,
+    /// Circuit breakers of indexers as of the most recent polling loop. Empty
+    /// unless replaced with [`ApiSchemaContext::with_indexer_connectivity`].
+    pub indexer_connectivity: watch::Receiver<Vec<IndexerConnectivity>>,
+    /// Whether the main loop has completed an iteration since startup. Always
+    /// false unless replaced with [`ApiSchemaContext::with_initialized`].
+    pub initialized: watch::Receiver<bool>,
     pub loader_poi: DataLoader<StoreLoader<graphix_store::models::Poi>>,
     pub loader_network: DataLoader<StoreLoader<graphix_store::models::Network>>,
     pub loader_graph_node_collected_version:
@@ -24,6 +38,8 @@ pub struct ApiSchemaContext {
     pub loader_block: DataLoader<StoreLoader<graphix_store::models::Block>>,
     pub loader_indexer: DataLoader<StoreLoader<graphix_store::models::Indexer>>,
     pub loader_subgraph_deployment: DataLoader<StoreLoader<graphix_store::models::SgDeployment>>,
+    pub loader_sg_deployment_manifest:
+        DataLoader<StoreLoader<graphix_store::models::SgDeploymentManifest>>,
 }
 
 impl ApiSchemaContext {
@@ -46,10 +62,15 @@ impl ApiSchemaContext {
             DataLoader::new(StoreLoader::new(store.clone()), tokio::task::spawn).delay(delay);
         let loader_subgraph_deployment =
             DataLoader::new(StoreLoader::new(store.clone()), tokio::task::spawn).delay(delay);
+        let loader_sg_deployment_manifest =
+            DataLoader::new(StoreLoader::new(store.clone()), tokio::task::spawn).delay(delay);
 
         Self {
+            poi_store: Arc::new(store.clone()),
             store,
             config,
+            indexer_connectivity: watch::channel(vec![]).1,
+            initialized: watch::channel(false).1,
             loader_poi,
             loader_network,
             loader_graph_node_collected_version,
@@ -57,12 +78,42 @@ impl ApiSchemaContext {
             loader_block,
             loader_indexer,
             loader_subgraph_deployment,
+            loader_sg_deployment_manifest,
         }
     }
+
+    pub fn with_poi_store(mut self, poi_store: Arc<dyn PoiStore>) -> Self {
+        self.poi_store = poi_store;
+        self
+    }
+
+    pub fn with_indexer_connectivity(
+        mut self,
+        indexer_connectivity: watch::Receiver<Vec<IndexerConnectivity>>,
+    ) -> Self {
+        self.indexer_connectivity = indexer_connectivity;
+        self
+    }
+
+    pub fn with_initialized(mut self, initialized: watch::Receiver<bool>) -> Self {
+        self.initialized = initialized;
+        self
+    }
+}
+
+/// The admin API key that a request was made with, if any. Passed to
+/// resolvers as request data; admin-only mutations fail without it. See
+/// [`crate::config::GraphQlConfig::admin_api_keys`].
+#[derive(Debug, Clone)]
+pub struct AdminApiKey {
+    /// The label of the key, which identifies whoever used it.
+    pub label: String,
 }
 
 pub fn api_schema_builder() -> SchemaBuilder<QueryRoot, MutationRoot, EmptySubscription> {
-    Schema::build(QueryRoot, MutationRoot, EmptySubscription).enable_federation()
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .enable_federation()
+        .extension(GraphQlMetrics)
 }
 
 pub fn api_schema(ctx: ApiSchemaContext) -> ApiSchema {
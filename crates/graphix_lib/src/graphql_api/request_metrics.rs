@@ -0,0 +1,109 @@
+//! Prometheus metrics for the GraphQL API, labeled by operation name.
+//!
+//! Labels are taken from the parsed query rather than the raw query string,
+//! so that arbitrary queries can't blow up the number of time series.
+//! Operations without a name are labeled `anonymous`, and requests that fail
+//! to parse `invalid`.
+
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextParseQuery, NextRequest,
+};
+use async_graphql::parser::types::{DocumentOperations, ExecutableDocument};
+use async_graphql::{Response, ServerResult, Variables};
+
+use crate::metrics;
+
+const ANONYMOUS: &str = "anonymous";
+const INVALID: &str = "invalid";
+
+/// Records the number, errors and duration of GraphQL requests, as well as
+/// how many are in flight.
+pub struct GraphQlMetrics;
+
+impl ExtensionFactory for GraphQlMetrics {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(GraphQlMetricsExtension::default())
+    }
+}
+
+/// Created for every request, as part of a batch or not.
+#[derive(Default)]
+struct GraphQlMetricsExtension {
+    /// The name of the operation, once the query is parsed.
+    operation: Mutex<Option<String>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for GraphQlMetricsExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let metrics = metrics();
+        metrics.graphql_requests_in_flight.inc();
+        let start = Instant::now();
+        let response = next.run(ctx).await;
+        let duration = start.elapsed();
+        metrics.graphql_requests_in_flight.dec();
+
+        let operation = self
+            .operation
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap_or_else(|| INVALID.to_string());
+        metrics
+            .graphql_requests
+            .with_label_values(&[&operation])
+            .inc();
+        metrics
+            .graphql_request_duration_seconds
+            .with_label_values(&[&operation])
+            .observe(duration.as_secs_f64());
+        if response.is_err() {
+            metrics
+                .graphql_request_errors
+                .with_label_values(&[&operation])
+                .inc();
+        }
+
+        response
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+
+        // Until the operation to execute is known, which it isn't for
+        // requests that fail validation, documents with a single named
+        // operation are labeled with it.
+        let operation = match &document.operations {
+            DocumentOperations::Multiple(operations) if operations.len() == 1 => {
+                operations.keys().next().map(|name| name.to_string())
+            }
+            _ => None,
+        };
+        *self.operation.lock().unwrap() = Some(operation.unwrap_or_else(|| ANONYMOUS.to_string()));
+
+        Ok(document)
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        // By now, the operation name is known to be one of the document's.
+        if let Some(operation_name) = operation_name {
+            *self.operation.lock().unwrap() = Some(operation_name.to_string());
+        }
+
+        next.run(ctx, operation_name).await
+    }
+}
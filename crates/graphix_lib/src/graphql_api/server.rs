@@ -1,13 +1,17 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 
 use anyhow::Context as _;
 use async_graphql::{Context, Object, Result};
 use futures::future::try_join_all;
 use graphix_common_types::*;
-use graphix_store::models::DivergenceInvestigationRequest;
+use graphix_indexer_client::SubgraphDeployment;
+use graphix_store::models::{DivergenceInvestigationRequest, DivergenceStats, TimeBucket};
+use graphix_store::{InvestigationDeduplication, Pagination, PoiFilter, PoiLiveness};
 use uuid::Uuid;
 
-use super::{api_types, ctx_data};
+use super::{api_types, ctx_data, AdminApiKey, ApiSchemaContext};
+use crate::config::ConsensusWeighting;
+use crate::indexing_loop::IndexerConnectivity;
 
 pub struct QueryRoot;
 
@@ -42,8 +46,52 @@ impl QueryRoot {
         Ok(deployments.into_iter().map(Into::into).collect())
     }
 
+    /// Returns a subgraph deployment with the indexers serving it, the
+    /// consensus on their live PoIs, its PoI history and recent divergence
+    /// investigations. Null if the deployment is unknown.
+    async fn deployment(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The IPFS CID of the subgraph deployment")] cid: IpfsCid,
+    ) -> Result<Option<api_types::DeploymentDetails>> {
+        let ctx_data = ctx_data(ctx);
+
+        let filter = inputs::SgDeploymentsQuery {
+            ipfs_cid: Some(cid),
+            ..Default::default()
+        };
+        let deployment = ctx_data.store.sg_deployments(filter).await?.pop();
+
+        Ok(deployment.map(Into::into))
+    }
+
+    /// Returns the subgraph deployments that have any PoIs, ordered by IPFS
+    /// CID, along with how many indexers reported PoIs for each.
+    async fn distinct_deployments(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 0, desc = "Number of results to skip, for pagination.")] offset: u32,
+        #[graphql(
+            default = 100,
+            validator(maximum = 250),
+            desc = "Upper limit on the number of shown results."
+        )]
+        limit: u16,
+    ) -> Result<api_types::DeploymentPage> {
+        let ctx_data = ctx_data(ctx);
+
+        let page = ctx_data
+            .store
+            .distinct_deployments(Pagination { offset, limit })
+            .await?;
+
+        Ok(page.into())
+    }
+
     /// Fetches all tracked indexers in this Graphix instance and filters them
-    /// according to some filtering rules.
+    /// according to some filtering rules. Inactive indexers, i.e. those that
+    /// no source listed for `indexerInactiveAfterDays`, are left out unless
+    /// `includeInactive` is set.
     async fn indexers(
         &self,
         ctx: &Context<'_>,
@@ -55,18 +103,147 @@ impl QueryRoot {
             desc = "Upper limit on the number of shown results."
         )]
         limit: u16,
+        #[graphql(
+            default = false,
+            desc = "Whether to include indexers that haven't been seen for a while."
+        )]
+        include_inactive: bool,
     ) -> Result<Vec<api_types::Indexer>> {
         let ctx_data = ctx_data(ctx);
 
         let filter = inputs::IndexersQuery {
             address,
             limit: Some(limit),
+            offset: None,
+            include_inactive,
         };
         let indexers = ctx_data.store.indexers(filter).await?;
 
         Ok(indexers.into_iter().map(Into::into).collect())
     }
 
+    /// Returns the indexers that currently serve the given subgraph
+    /// deployment, along with their most recent PoIs and blocks. Indexers
+    /// that stopped responding are left out.
+    async fn indexers_for_deployment(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The IPFS CID of the subgraph deployment")] deployment: IpfsCid,
+    ) -> Result<Vec<api_types::IndexerDeploymentInfo>> {
+        let ctx_data = ctx_data(ctx);
+
+        let infos = ctx_data.store.indexers_for_deployment(&deployment).await?;
+
+        Ok(infos.into_iter().map(Into::into).collect())
+    }
+
+    /// Returns the reliability score of the indexer with the given address, a
+    /// value between 0.0 and 1.0 that tracks the exponential moving average of
+    /// its query success rate. Returns `null` if the indexer was never polled.
+    async fn indexer_reliability(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The address of the indexer, encoded as a hex string with a '0x' prefix")]
+        indexer_address: IndexerAddress,
+    ) -> Result<Option<f64>> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data.store.indexer_reliability(&indexer_address).await?)
+    }
+
+    /// Returns the circuit breakers of the indexers whose `indexingStatuses`
+    /// queries failed since their last successful one, as of the most recent
+    /// polling loop. Indexers that aren't listed are queried as usual. Always
+    /// empty unless `circuitBreaker.enabled` is set.
+    async fn indexer_connectivity(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The address of the indexer, encoded as a hex string with a '0x' prefix")]
+        indexer_address: Option<IndexerAddress>,
+    ) -> Result<Vec<IndexerConnectivity>> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data
+            .indexer_connectivity
+            .borrow()
+            .iter()
+            .filter(|connectivity| {
+                indexer_address.map_or(true, |address| connectivity.indexer == address)
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// Returns the most recent indexing status that each indexer reported for
+    /// each subgraph deployment, optionally filtered by indexer and subgraph
+    /// deployment. Indexers that stopped answering keep their last reported
+    /// status, see `reportedAt`.
+    async fn indexing_statuses(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The address of the indexer, encoded as a hex string with a '0x' prefix")]
+        indexer_address: Option<IndexerAddress>,
+        #[graphql(desc = "The IPFS CID of the subgraph deployment")] deployment: Option<IpfsCid>,
+    ) -> Result<Vec<api_types::IndexingStatus>> {
+        let ctx_data = ctx_data(ctx);
+        let deployment = deployment.map(|cid| cid.to_string());
+
+        // The statuses of all indexers are needed to tell how far behind the
+        // requested ones are.
+        let all_statuses = ctx_data
+            .store
+            .indexing_statuses(None, deployment.as_deref())
+            .await?;
+        let mut latest_blocks: HashMap<&str, i64> = HashMap::new();
+        for status in &all_statuses {
+            let latest_block = latest_blocks.entry(status.deployment.as_str()).or_default();
+            *latest_block = (*latest_block).max(status.latest_block_number);
+        }
+        let blocks_behind: Vec<u64> = all_statuses
+            .iter()
+            .map(|status| {
+                (latest_blocks[status.deployment.as_str()] - status.latest_block_number) as u64
+            })
+            .collect();
+
+        let statuses = all_statuses
+            .into_iter()
+            .zip(blocks_behind)
+            .filter(|(status, _)| indexer_address.map_or(true, |address| status.indexer == address))
+            .map(|(status, blocks_behind)| api_types::IndexingStatus::new(status, blocks_behind))
+            .collect();
+
+        Ok(statuses)
+    }
+
+    /// Compares the PoIs of two indexers on every subgraph deployment that
+    /// Graphix collected PoIs for from both, at the most recent block they
+    /// have in common.
+    async fn compare_indexers(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            desc = "The address of the first indexer, encoded as a hex string with a '0x' prefix"
+        )]
+        indexer1: IndexerAddress,
+        #[graphql(
+            desc = "The address of the second indexer, encoded as a hex string with a '0x' prefix"
+        )]
+        indexer2: IndexerAddress,
+    ) -> Result<api_types::IndexerComparison> {
+        let ctx_data = ctx_data(ctx);
+
+        let pairs = ctx_data
+            .poi_store
+            .latest_common_poi_pairs(&indexer1, &indexer2)
+            .await?;
+
+        Ok(pairs
+            .into_iter()
+            .map(api_types::DeploymentPoiComparison::from)
+            .collect())
+    }
+
     /// Filters through all PoIs ever collected by this Graphix
     /// instance, according to some filtering rules specified in `filter`.
     async fn proofs_of_indexing(
@@ -85,6 +262,24 @@ impl QueryRoot {
             desc = "Restricts the query to PoIs that were collected in the given block range."
         )]
         block_range: Option<inputs::BlockRange>,
+        #[graphql(
+            desc = "Restricts the query to PoIs reported by the indexer with this address, encoded as a hex string with a '0x' prefix."
+        )]
+        indexer_address: Option<IndexerAddress>,
+        #[graphql(
+            desc = "Restricts the query to live PoIs if true, and to PoIs that aren't live anymore if false."
+        )]
+        live: Option<bool>,
+        #[graphql(
+            default = false,
+            desc = "Restricts the query to PoIs at blocks that are pinned in the configuration if true. Can't be combined with `live`."
+        )]
+        pinned: bool,
+        #[graphql(
+            desc = "Restricts the query to PoIs with this hash, e.g. to find out which indexers reported it."
+        )]
+        poi: Option<PoiBytes>,
+        #[graphql(default = 0, desc = "Number of results to skip, for pagination.")] offset: u32,
         #[graphql(
             default = 100,
             validator(maximum = 250),
@@ -94,20 +289,363 @@ impl QueryRoot {
     ) -> Result<Vec<api_types::ProofOfIndexing>> {
         let ctx_data = ctx_data(ctx);
 
-        let filter = inputs::PoisQuery {
+        let liveness = match (live, pinned) {
+            (Some(_), true) => return Err("`live` and `pinned` can't be combined".into()),
+            (None, true) => Some(PoiLiveness::Pinned),
+            (live, false) => live.map(|live| match live {
+                true => PoiLiveness::Live,
+                false => PoiLiveness::NotLive,
+            }),
+        };
+        let filter = PoiFilter {
             network,
             deployments,
+            indexer_address,
             block_range,
-            limit: Some(limit),
+            liveness,
+            poi,
         };
         let pois = ctx_data
             .store
-            .pois(&filter.deployments, filter.block_range, filter.limit)
+            .search_pois(&filter, Pagination { offset, limit })
             .await?;
 
         Ok(pois.into_iter().map(Into::into).collect())
     }
 
+    /// Returns the most recent PoI that an indexer reported for a subgraph
+    /// deployment at a block, if Graphix collected one.
+    async fn poi(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The address of the indexer, encoded as a hex string with a '0x' prefix")]
+        indexer_address: IndexerAddress,
+        #[graphql(desc = "The IPFS CID of the subgraph deployment")] deployment: IpfsCid,
+        #[graphql(desc = "The block number")] block: u64,
+    ) -> Result<Option<api_types::PoiWithLiveness>> {
+        let ctx_data = ctx_data(ctx);
+        let poi = ctx_data
+            .store
+            .poi(&indexer_address, &deployment, block)
+            .await?;
+
+        Ok(poi.map(|(poi, live)| api_types::PoiWithLiveness {
+            poi: poi.into(),
+            live,
+        }))
+    }
+
+    /// Returns all PoIs with the given hash, across indexers, subgraph
+    /// deployments and blocks, e.g. to find out which indexers produced
+    /// exactly this PoI. Unknown hashes give an empty list.
+    async fn poi_by_hash(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The PoI, encoded as a hex string with a '0x' prefix.")] poi: PoiBytes,
+    ) -> Result<Vec<api_types::PoiMatch>> {
+        let ctx_data = ctx_data(ctx);
+        let pois = ctx_data.store.pois_with_hash(&poi).await?;
+
+        Ok(pois
+            .into_iter()
+            .map(|(poi, is_majority)| api_types::PoiMatch {
+                poi: poi.into(),
+                is_majority,
+            })
+            .collect())
+    }
+
+    /// Returns the PoI that most indexers reported for a subgraph deployment
+    /// at a block, together with the dissenting PoIs, as of the most recent
+    /// PoI of each indexer. If the `trustedIndexer` configured for the
+    /// deployment's network reported a PoI for the block, that's the
+    /// consensus instead. Null if Graphix collected no PoIs for the block.
+    async fn consensus_poi(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The IPFS CID of the subgraph deployment")] deployment: IpfsCid,
+        #[graphql(desc = "The block number")] block: u64,
+    ) -> Result<Option<api_types::ConsensusPoi>> {
+        let ctx_data = ctx_data(ctx);
+        let support = ctx_data
+            .store
+            .poi_support(&deployment, block..=block)
+            .await?;
+
+        if support.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(api_types::ConsensusPoi::new(
+            deployment.to_string(),
+            block,
+            support,
+            ctx_data.config.consensus_quorum,
+            deployment_trusted_indexer(ctx_data, &deployment).await?,
+        )))
+    }
+
+    /// Same as `consensusPoi`, for all blocks in a range that Graphix
+    /// collected PoIs for, ordered by block number.
+    async fn consensus_pois(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The IPFS CID of the subgraph deployment")] deployment: IpfsCid,
+        #[graphql(desc = "The first block number of the range")] start_block: u64,
+        #[graphql(desc = "The last block number of the range, inclusive")] end_block: u64,
+    ) -> Result<Vec<api_types::ConsensusPoi>> {
+        const MAX_BLOCKS: u64 = 250;
+
+        if end_block < start_block {
+            return Err("`endBlock` must not be lower than `startBlock`".into());
+        }
+        if end_block - start_block >= MAX_BLOCKS {
+            return Err(
+                format!("The block range must not span more than {MAX_BLOCKS} blocks").into(),
+            );
+        }
+
+        let ctx_data = ctx_data(ctx);
+        let support = ctx_data
+            .store
+            .poi_support(&deployment, start_block..=end_block)
+            .await?;
+
+        let mut support_by_block = BTreeMap::<u64, Vec<_>>::new();
+        for s in support {
+            support_by_block
+                .entry(s.block_number as u64)
+                .or_default()
+                .push(s);
+        }
+
+        let trusted_indexer = deployment_trusted_indexer(ctx_data, &deployment).await?;
+        Ok(support_by_block
+            .into_iter()
+            .map(|(block, support)| {
+                api_types::ConsensusPoi::new(
+                    deployment.to_string(),
+                    block,
+                    support,
+                    ctx_data.config.consensus_quorum,
+                    trusted_indexer,
+                )
+            })
+            .collect())
+    }
+
+    /// Returns the most recent PoI changes, i.e. indexers reporting a
+    /// different PoI than before for a block, e.g. after rewinding and
+    /// resyncing. Newest first.
+    async fn poi_changes(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The address of the indexer, encoded as a hex string with a '0x' prefix")]
+        indexer_address: Option<IndexerAddress>,
+        #[graphql(desc = "The IPFS CID of the subgraph deployment")] deployment: Option<IpfsCid>,
+        #[graphql(
+            default = 100,
+            validator(maximum = 250),
+            desc = "Upper limit on the number of shown results."
+        )]
+        limit: u16,
+    ) -> Result<Vec<graphix_store::models::PoiChange>> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data
+            .poi_store
+            .poi_changes(indexer_address.as_ref(), deployment.as_ref(), limit)
+            .await?)
+    }
+
+    /// Returns the subgraph deployments and blocks that indexers currently
+    /// disagree on the PoI for, i.e. the most recent PoIs of the indexers for
+    /// the block still differ. Longest-standing first, which surfaces chronic
+    /// divergences that brief ones would otherwise hide.
+    async fn stale_divergences(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            default = 100,
+            validator(maximum = 250),
+            desc = "Upper limit on the number of shown results."
+        )]
+        limit: u16,
+    ) -> Result<Vec<graphix_store::models::StaleDivergence>> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data
+            .poi_store
+            .stale_divergences(Pagination { offset: 0, limit })
+            .await?)
+    }
+
+    /// Returns the subgraph deployments and block numbers that indexers
+    /// reported different block hashes for, i.e. at least one of them is on a
+    /// reorged fork. Their PoIs at these blocks aren't compared, so these are
+    /// neither in `staleDivergences` nor in `divergenceStats`. Most recently
+    /// detected first.
+    async fn block_hash_mismatches(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The IPFS CID of the subgraph deployment")] deployment: Option<IpfsCid>,
+        #[graphql(
+            default = 100,
+            validator(maximum = 250),
+            desc = "Upper limit on the number of shown results."
+        )]
+        limit: u16,
+    ) -> Result<Vec<graphix_store::models::BlockHashMismatch>> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data
+            .store
+            .block_hash_mismatches(deployment.as_ref(), limit)
+            .await?)
+    }
+
+    /// Returns how many subgraph deployments, blocks and indexers diverged in
+    /// each time bucket between `from` and `to`, based on when Graphix
+    /// collected the PoIs. Oldest bucket first, including empty buckets.
+    async fn divergence_stats(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            desc = "Restricts the statistics to subgraph deployments that index the given chain name."
+        )]
+        network: Option<String>,
+        #[graphql(desc = "The width of the time buckets.")] bucket: TimeBucket,
+        #[graphql(
+            desc = "The start of the time window, in UTC. Rounded down to the start of its bucket."
+        )]
+        from: chrono::NaiveDateTime,
+        #[graphql(desc = "The end of the time window, in UTC, exclusive.")]
+        to: chrono::NaiveDateTime,
+    ) -> Result<Vec<DivergenceStats>> {
+        const MAX_BUCKETS: i32 = 1000;
+
+        if to <= from {
+            return Err("`to` must be later than `from`".into());
+        }
+        if to - from > bucket.duration() * MAX_BUCKETS {
+            return Err(
+                format!("The time window must not span more than {MAX_BUCKETS} buckets").into(),
+            );
+        }
+
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data
+            .store
+            .divergence_stats(network.as_deref(), bucket, from, to)
+            .await?)
+    }
+
+    /// Returns the indexers whose latest block for a subgraph deployment
+    /// hasn't advanced in `stalledIndexerIterations` main loop iterations,
+    /// even though they keep responding. Longest-stalled first.
+    async fn stalled_indexers(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            default = 100,
+            validator(maximum = 250),
+            desc = "Upper limit on the number of shown results."
+        )]
+        limit: u16,
+    ) -> Result<Vec<graphix_store::models::StalledIndexer>> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data
+            .store
+            .stalled_indexers(ctx_data.config.stalled_indexer_iterations, limit)
+            .await?)
+    }
+
+    /// Returns the most recent block choices for a subgraph deployment, i.e.
+    /// which block Graphix queried PoIs at in each main loop iteration and
+    /// which blocks it chose from. Newest first.
+    async fn block_choice_trace(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The IPFS CID of the subgraph deployment")] deployment: IpfsCid,
+        #[graphql(
+            default = 100,
+            validator(maximum = 250),
+            desc = "Upper limit on the number of shown results."
+        )]
+        limit: u16,
+    ) -> Result<Vec<graphix_store::models::BlockChoiceTrace>> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data
+            .store
+            .block_choice_traces(&deployment, limit)
+            .await?)
+    }
+
+    /// Returns the summaries of the most recent main loop iterations, newest
+    /// first: when they ran, how long each phase took, how many indexers
+    /// responded, how many PoIs were collected and which writes failed.
+    async fn recent_loop_iterations(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            default = 10,
+            validator(maximum = 250),
+            desc = "Upper limit on the number of shown results."
+        )]
+        limit: u16,
+    ) -> Result<Vec<graphix_store::models::LoopIteration>> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data.store.recent_loop_iterations(limit).await?)
+    }
+
+    /// Returns how many indexers each source (the configuration, each network
+    /// subgraph and the interceptors) listed in the most recent main loop
+    /// iterations, before deduplication, newest first.
+    async fn recent_indexer_discovery_counts(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            default = 10,
+            validator(maximum = 250),
+            desc = "Upper limit on the number of shown results."
+        )]
+        limit: u16,
+    ) -> Result<Vec<graphix_store::models::IndexerDiscoveryCount>> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data
+            .store
+            .recent_indexer_discovery_counts(limit)
+            .await?)
+    }
+
+    /// Returns the subgraph deployments that Graphix tracks because indexers
+    /// have active allocations on them, and through which network subgraphs
+    /// it found them. Empty unless deployment discovery is enabled, in which
+    /// case Graphix tracks no other deployments.
+    async fn tracked_deployments(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<graphix_store::models::TrackedDeployment>> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data.store.tracked_deployments().await?)
+    }
+
+    /// Returns the subgraph deployments that operators tagged as watched with
+    /// the `watchDeployment` mutation, sorted by IPFS CID.
+    async fn watched_deployments(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<graphix_store::models::WatchedDeployment>> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data.store.watched_deployments().await?)
+    }
+
     /// Same as [`QueryRoot::proofs_of_indexing`], but only returns PoIs that
     /// are "live" i.e. they are the most recent PoI collected for their
     /// subgraph deployment.
@@ -115,6 +653,11 @@ impl QueryRoot {
         &self,
         ctx: &Context<'_>,
         filter: inputs::PoisQuery,
+        #[graphql(
+            default = false,
+            desc = "Whether to include stale PoIs, i.e. live PoIs of indexers that have since stopped responding."
+        )]
+        include_stale: bool,
     ) -> Result<Vec<api_types::ProofOfIndexing>> {
         let ctx_data = ctx_data(ctx);
         let pois = ctx_data
@@ -124,6 +667,7 @@ impl QueryRoot {
                 Some(&filter.deployments),
                 filter.block_range,
                 filter.limit,
+                include_stale,
             )
             .await?;
 
@@ -134,6 +678,10 @@ impl QueryRoot {
         &self,
         ctx: &Context<'_>,
         indexer_address: IndexerAddress,
+        #[graphql(
+            desc = "Grade PoIs against this indexer's PoIs instead of the majority. Deployments it doesn't serve fall back to the majority. Defaults to the `trustedIndexer` configured for the deployment's network, if any."
+        )]
+        reference_indexer: Option<IndexerAddress>,
     ) -> Result<Vec<api_types::PoiAgreementRatio>> {
         let ctx_data = ctx_data(ctx);
 
@@ -148,7 +696,7 @@ impl QueryRoot {
         // Query all live POIs for the specific deployments.
         let all_deployment_pois = ctx_data
             .store
-            .live_pois(None, Some(&deployment_cids), None, None)
+            .live_pois(None, Some(&deployment_cids), None, None, false)
             .await?;
 
         // Convert POIs to ProofOfIndexing and group by deployment
@@ -171,33 +719,63 @@ impl QueryRoot {
         let mut agreement_ratios: Vec<api_types::PoiAgreementRatio> = Vec::new();
 
         for poi in indexer_pois {
+            let deployment = poi.deployment(ctx_data).await?;
             let deployment_pois = deployment_to_pois
-                .get(&poi.deployment(ctx_data).await?.cid().to_string())
+                .get(&deployment.cid().to_string())
                 .context("inconsistent pois table, no pois for deployment")?;
 
+            let reference_indexer = match reference_indexer {
+                Some(address) => Some(address),
+                None => trusted_indexer(ctx_data, &deployment).await,
+            };
+            let mut reference_poi = None;
+            if let Some(reference_indexer) = reference_indexer {
+                for dp in deployment_pois {
+                    if dp.indexer(ctx_data).await?.address() == reference_indexer {
+                        reference_poi = Some(dp.hash());
+                    }
+                }
+            }
+            let reference_indexer = reference_poi.and(reference_indexer);
+
             let total_indexers = deployment_pois.len() as u32;
 
-            // Calculate POI agreement by creating a map to count unique POIs and their occurrence.
+            // Calculate POI agreement by creating a map to count unique POIs and their occurrence,
+            // and another one to sum up their weights.
+            let stakes = match ctx_data.config.consensus_weighting {
+                ConsensusWeighting::Count => None,
+                ConsensusWeighting::Stake => indexer_stakes(ctx_data, deployment_pois).await?,
+            };
             let mut poi_counts: BTreeMap<PoiBytes, u32> = BTreeMap::new();
-            for dp in deployment_pois {
+            let mut poi_weights: BTreeMap<PoiBytes, f64> = BTreeMap::new();
+            for (i, dp) in deployment_pois.iter().enumerate() {
                 *poi_counts.entry(dp.hash()).or_insert(0) += 1;
+                *poi_weights.entry(dp.hash()).or_insert(0.0) +=
+                    stakes.as_ref().map_or(1.0, |stakes| stakes[i]);
             }
+            let total_weight: f64 = poi_weights.values().sum();
 
-            // Define consensus and agreement based on the map.
-            let (max_poi, max_poi_count) = poi_counts
+            // Define consensus and agreement based on the maps.
+            let (max_poi, max_poi_weight) = poi_weights
                 .iter()
-                .max_by_key(|(_, &v)| v)
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
                 .context("inconsistent pois table, no pois")?;
 
-            let has_consensus = *max_poi_count > total_indexers / 2;
-
             let n_agreeing_indexers = *poi_counts
                 .get(&poi.hash())
                 .context("inconsistent pois table, no matching poi")?;
 
             let n_disagreeing_indexers = total_indexers - n_agreeing_indexers;
 
-            let in_consensus = has_consensus && max_poi == &poi.hash();
+            // The reference indexer's PoI, when there is one, is the consensus
+            // by definition.
+            let (has_consensus, in_consensus) = match reference_poi {
+                Some(reference_poi) => (true, reference_poi == poi.hash()),
+                None => {
+                    let has_consensus = *max_poi_weight > total_weight / 2.0;
+                    (has_consensus, has_consensus && max_poi == &poi.hash())
+                }
+            };
 
             let ratio = api_types::PoiAgreementRatio {
                 poi_id: poi.model.id,
@@ -206,6 +784,11 @@ impl QueryRoot {
                 n_disagreeing_indexers,
                 has_consensus,
                 in_consensus,
+                consensus_weighting: match stakes {
+                    Some(_) => ConsensusWeighting::Stake,
+                    None => ConsensusWeighting::Count,
+                },
+                reference_indexer,
             };
 
             agreement_ratios.push(ratio);
@@ -224,15 +807,12 @@ impl QueryRoot {
     ) -> Result<Option<DivergenceInvestigationReport>> {
         let ctx_data = ctx_data(ctx);
 
-        if let Some(report_json) = ctx_data
+        if let Some(report) = ctx_data
             .store
             .divergence_investigation_report(&uuid)
             .await?
         {
-            Ok(
-                serde_json::from_value(report_json)
-                    .expect("Can't deserialize report from database"),
-            )
+            Ok(Some(api_types::divergence_investigation_report(report)))
         } else if ctx_data
             .store
             .divergence_investigation_request_exists(&uuid)
@@ -242,13 +822,59 @@ impl QueryRoot {
                 uuid,
                 status: DivergenceInvestigationStatus::InProgress,
                 bisection_runs: vec![],
+                pairwise_findings: vec![],
+                no_divergence_found: false,
                 error: None,
+                triage_status: Default::default(),
+                triage_note: None,
             }))
         } else {
             Ok(None)
         }
     }
 
+    /// Returns the most recent divergence investigation reports, newest
+    /// first. Investigations that haven't produced a report yet aren't
+    /// included.
+    async fn divergence_investigation_reports(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "Only return reports with one of these triage statuses.")] status: Option<
+            Vec<DivergenceTriageStatus>,
+        >,
+        #[graphql(
+            default = 50,
+            validator(maximum = 250),
+            desc = "Upper limit on the number of shown results."
+        )]
+        limit: u16,
+    ) -> Result<Vec<DivergenceInvestigationReport>> {
+        let ctx_data = ctx_data(ctx);
+
+        let reports = ctx_data
+            .store
+            .divergence_investigation_reports(status.as_deref(), limit)
+            .await?;
+
+        Ok(reports
+            .into_iter()
+            .map(api_types::divergence_investigation_report)
+            .collect())
+    }
+
+    /// Returns the triage status changes of a divergence investigation
+    /// report, oldest first.
+    async fn divergence_triage_history(
+        &self,
+        ctx: &Context<'_>,
+        uuid: Uuid,
+    ) -> Result<Vec<api_types::DivergenceTriageEvent>> {
+        let ctx_data = ctx_data(ctx);
+        let events = ctx_data.store.divergence_triage_events(&uuid).await?;
+
+        Ok(events.into_iter().map(Into::into).collect())
+    }
+
     /// Returns all networks known to Graphix. Subgraphs indexing other networks
     /// won't be available in this Graphix database.
     async fn networks(&self, ctx: &Context<'_>) -> Result<Vec<api_types::Network>> {
@@ -257,6 +883,36 @@ impl QueryRoot {
 
         Ok(networks.into_iter().map(Into::into).collect())
     }
+
+    /// Fetches a PoI backfill job, to follow its progress.
+    async fn poi_backfill_job(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The UUID of the job, as returned by the `requestPoiBackfill` mutation.")]
+        uuid: Uuid,
+    ) -> Result<Option<api_types::PoiBackfillJob>> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data
+            .store
+            .poi_backfill_job(&uuid)
+            .await?
+            .map(Into::into))
+    }
+
+    /// Returns the version and build of this Graphix instance, e.g. to tell
+    /// which commit it runs.
+    async fn server_info(&self) -> api_types::ServerInfo {
+        api_types::ServerInfo::current()
+    }
+
+    /// Returns whether this Graphix instance has data to serve yet. The same
+    /// as the `/ready` endpoint.
+    async fn system_status(&self, ctx: &Context<'_>) -> api_types::SystemStatus {
+        api_types::SystemStatus {
+            initialized: *ctx_data(ctx).initialized.borrow(),
+        }
+    }
 }
 
 async fn live_pois(
@@ -267,27 +923,84 @@ async fn live_pois(
 
     let pois = ctx_data
         .store
-        .live_pois(Some(&indexer_address), None, None, None)
+        .live_pois(Some(&indexer_address), None, None, None, false)
         .await?;
 
     Ok(pois.into_iter().map(Into::into).collect())
 }
 
+/// The stakes of the indexers of `pois`, in the same order. `None` if the
+/// stake of any of them is unknown or if they have no stake at all, in which
+/// case indexers should be counted instead.
+async fn indexer_stakes(
+    ctx_data: &ApiSchemaContext,
+    pois: &[api_types::ProofOfIndexing],
+) -> Result<Option<Vec<f64>>> {
+    let mut stakes = vec![];
+    for poi in pois {
+        match poi.indexer(ctx_data).await?.staked_tokens() {
+            Some(staked_tokens) => stakes.push(staked_tokens),
+            None => return Ok(None),
+        }
+    }
+
+    if stakes.iter().sum::<f64>() > 0.0 {
+        Ok(Some(stakes))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The trusted indexer configured for the deployment's network, if any.
+async fn trusted_indexer(
+    ctx_data: &ApiSchemaContext,
+    deployment: &api_types::SubgraphDeployment,
+) -> Option<IndexerAddress> {
+    let network = deployment.network(ctx_data).await.ok()?;
+    ctx_data.config.chains.get(network.name())?.trusted_indexer
+}
+
+/// Same as [`trusted_indexer`], for a deployment that's only known by its
+/// IPFS CID. `None` if the deployment itself is unknown.
+async fn deployment_trusted_indexer(
+    ctx_data: &ApiSchemaContext,
+    cid: &IpfsCid,
+) -> Result<Option<IndexerAddress>> {
+    let filter = inputs::SgDeploymentsQuery {
+        ipfs_cid: Some(cid.clone()),
+        ..Default::default()
+    };
+    let Some(deployment) = ctx_data.store.sg_deployments(filter).await?.pop() else {
+        return Ok(None);
+    };
+
+    Ok(trusted_indexer(ctx_data, &deployment.into()).await)
+}
+
 pub struct MutationRoot;
 
 #[Object]
 impl MutationRoot {
     /// Launches a divergence investigation, which is a process of comparing
     /// two or more PoIs (up to four) and running a binary search to find the first
-    /// diverging block.
+    /// diverging block. Alternatively, all indexers of a subgraph deployment can be
+    /// investigated. Their latest live PoIs are grouped by value, and the
+    /// first indexer of each group is bisected against the first indexer of
+    /// every other group, with the results in the report's
+    /// `pairwiseFindings`. Only the four largest groups are investigated. If
+    /// all indexers agree, the report's `noDivergenceFound` is set.
     async fn launch_divergence_investigation(
         &self,
         ctx: &Context<'_>,
         #[graphql(
             validator(min_items = 2, max_items = 4),
-            desc = "A list of PoI hashes that should be investigated for divergence. If this list contains more than two PoIs, a new bisection run will be performed for each unordered pair of PoIs."
+            desc = "A list of PoI hashes that should be investigated for divergence. If this list contains more than two PoIs, a new bisection run will be performed for each unordered pair of PoIs. Either this or `deployment` must be given."
+        )]
+        pois: Option<Vec<PoiBytes>>,
+        #[graphql(
+            desc = "The subgraph deployment whose indexers should all be investigated for divergence. Either this or `pois` must be given."
         )]
-        pois: Vec<PoiBytes>,
+        deployment: Option<IpfsCid>,
         #[graphql(
             default = true,
             desc = "Indicates whether to collect `graph-node`'s block cache contents during bisection runs to include in the report."
@@ -303,31 +1016,154 @@ impl MutationRoot {
             desc = "Indicates whether to collect `graph-node`'s entity changes during bisection runs to include in the report."
         )]
         query_entity_changes: bool,
+        #[graphql(
+            desc = "A client-provided key that identifies the request, e.g. the ID of an alert. Requests with the same key as an earlier one return its investigation instead of launching a new one."
+        )]
+        idempotency_key: Option<String>,
     ) -> Result<DivergenceInvestigationReport> {
         let ctx_data = ctx_data(ctx);
         let store = &ctx_data.store;
 
+        let (pois, key, deployment_ids) = match (pois, &deployment) {
+            (Some(pois), None) => {
+                let key = store
+                    .divergence_investigation_deduplication_key(&pois)
+                    .await?;
+                let deployment_ids = store.divergence_investigation_deployments(&pois).await?;
+                (pois, key, deployment_ids)
+            }
+            (None, Some(deployment)) => {
+                let deployment_ids = store
+                    .sg_deployments(inputs::SgDeploymentsQuery {
+                        ipfs_cid: Some(deployment.clone()),
+                        ..Default::default()
+                    })
+                    .await?
+                    .into_iter()
+                    .map(|deployment| deployment.id)
+                    .collect();
+                (
+                    vec![],
+                    Some(InvestigationDeduplication::deployment_key(deployment)),
+                    deployment_ids,
+                )
+            }
+            _ => return Err("exactly one of `pois` and `deployment` must be given".into()),
+        };
+
+        // Investigations of the same indexers and deployments that are still
+        // pending or running (or, with a cooldown, completed recently) are
+        // returned instead of launching a duplicate.
+        let deduplication = InvestigationDeduplication {
+            idempotency_key,
+            key,
+            cooldown: ctx_data
+                .config
+                .divergence_investigation_cooldown_in_seconds
+                .map(|seconds| chrono::Duration::seconds(seconds as i64)),
+        };
         let req = DivergenceInvestigationRequest {
             pois,
+            deployment,
             query_block_caches,
             query_eth_call_caches,
             query_entity_changes,
         };
         let request_serialized = serde_json::to_value(req).unwrap();
         let uuid = store
-            .create_divergence_investigation_request(request_serialized)
+            .create_divergence_investigation_request(
+                request_serialized,
+                &deduplication,
+                &deployment_ids,
+            )
             .await?;
 
+        if !store.divergence_investigation_request_exists(&uuid).await? {
+            if let Some(report) = store.divergence_investigation_report(&uuid).await? {
+                return Ok(api_types::divergence_investigation_report(report));
+            }
+        }
+
         let report = DivergenceInvestigationReport {
             uuid: uuid.clone(),
             status: DivergenceInvestigationStatus::Pending,
             bisection_runs: vec![],
+            pairwise_findings: vec![],
+            no_divergence_found: false,
             error: None,
+            triage_status: Default::default(),
+            triage_note: None,
         };
 
         Ok(report)
     }
 
+    /// Changes the triage status of a divergence investigation report, e.g.
+    /// once it's known to be a `graph-node` bug, and records the change in
+    /// `divergenceTriageHistory`. Requires an admin API key.
+    async fn update_divergence_status(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The UUID of the divergence investigation report.")] uuid: Uuid,
+        status: DivergenceTriageStatus,
+        #[graphql(desc = "Free-text notes, e.g. a link to an issue.")] note: Option<String>,
+    ) -> Result<DivergenceInvestigationReport> {
+        let Some(admin_api_key) = ctx.data_opt::<AdminApiKey>() else {
+            return Err("this mutation requires an admin API key".into());
+        };
+        let ctx_data = ctx_data(ctx);
+        let store = &ctx_data.store;
+
+        let updated = store
+            .update_divergence_triage(&uuid, status, note.as_deref(), &admin_api_key.label)
+            .await?;
+        if !updated {
+            return Err(format!("no divergence investigation report with UUID {uuid}").into());
+        }
+
+        let report = store
+            .divergence_investigation_report(&uuid)
+            .await?
+            .context("divergence investigation report vanished")?;
+        Ok(api_types::divergence_investigation_report(report))
+    }
+
+    /// Requests PoIs for past blocks of a subgraph deployment from all
+    /// indexers that can provide them, every `interval` blocks from
+    /// `fromBlock` to `toBlock`. The backfill happens in the background; a
+    /// request that overlaps an unfinished backfill of the same deployment is
+    /// coalesced with it, in which case that backfill is returned. Backfills
+    /// may not cover more blocks than `poiBackfill.maxRequestsPerJob`.
+    async fn request_poi_backfill(
+        &self,
+        ctx: &Context<'_>,
+        deployment: IpfsCid,
+        from_block: u64,
+        to_block: u64,
+        #[graphql(validator(minimum = 1))] interval: u64,
+    ) -> Result<api_types::PoiBackfillJob> {
+        let ctx_data = ctx_data(ctx);
+        if from_block > to_block {
+            return Err("`fromBlock` must not be after `toBlock`".into());
+        }
+        // Every block needs at least one PoI request.
+        let max_blocks = ctx_data.config.poi_backfill.max_requests_per_job as u64;
+        let blocks = (to_block - from_block) / interval + 1;
+        if blocks > max_blocks {
+            return Err(format!(
+                "the backfill covers {blocks} blocks, more than the limit of {max_blocks}"
+            )
+            .into());
+        }
+
+        let job = ctx_data
+            .store
+            .request_poi_backfill(&deployment, from_block, to_block, interval, max_blocks)
+            .await?;
+
+        Ok(job.into())
+    }
+
     async fn set_deployment_name(
         &self,
         ctx: &Context<'_>,
@@ -336,6 +1172,8 @@ impl MutationRoot {
     ) -> Result<Deployment> {
         let ctx_data = ctx_data(ctx);
         let store = &ctx_data.store;
+        // Either form of the CID is accepted, see `IpfsCid`.
+        let deployment_ipfs_cid = SubgraphDeployment::new(deployment_ipfs_cid).0;
 
         store
             .set_deployment_name(&deployment_ipfs_cid, &name)
@@ -346,6 +1184,35 @@ impl MutationRoot {
         })
     }
 
+    /// Tags a subgraph deployment as watched, e.g. to focus dashboards on it.
+    /// Tagging a deployment that's already watched changes nothing.
+    async fn watch_deployment(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The IPFS CID of the subgraph deployment")] deployment: IpfsCid,
+        #[graphql(desc = "Who is tagging the deployment, e.g. a name or a team.")]
+        watched_by: Option<String>,
+    ) -> Result<graphix_store::models::WatchedDeployment> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data
+            .store
+            .watch_deployment(&deployment, watched_by.as_deref())
+            .await?)
+    }
+
+    /// Removes the watched tag of a subgraph deployment. Returns whether it
+    /// was watched.
+    async fn unwatch_deployment(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The IPFS CID of the subgraph deployment")] deployment: IpfsCid,
+    ) -> Result<bool> {
+        let ctx_data = ctx_data(ctx);
+
+        Ok(ctx_data.store.unwatch_deployment(&deployment).await?)
+    }
+
     /// Completely deletes a network and all related data (PoIs, indexers, subgraphs, etc.).
     async fn delete_network(&self, ctx: &Context<'_>, network: String) -> Result<String> {
         let ctx_data = ctx_data(ctx);
@@ -3,26 +3,53 @@
 //!  2. Query PoIs for recent common blocks across all indexers.
 //!  3. Store the PoIs in the database.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::RangeInclusive;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
-use graphix_common_types::GraphNodeCollectedVersion;
+use graphix_common_types::{GraphNodeCollectedVersion, IndexerAddress, PoiBytes};
 use graphix_indexer_client::{
-    IndexerClient, IndexerId, IndexingStatus, PoiRequest, ProofOfIndexing, SubgraphDeployment,
+    BlockPointer, IndexerClient, IndexerId, IndexingStatus, PoiRequest, ProofOfIndexing,
+    QueryOutcome, SubgraphDeployment,
 };
+use graphix_store::models::BlockChoice;
+use rand::Rng;
 use tracing::*;
 
-use crate::block_choice::BlockChoicePolicy;
+use crate::block_choice::{BlockChoicePolicy, PinnedBlocksConfig};
+use crate::config::{ChainConfig, CircuitBreakerConfig};
 use crate::PrometheusMetrics;
 
-/// Queries all `indexingStatuses` for all the given indexers.
+/// The outcome of one kind of query, for every indexer that was sent one during
+/// a main loop iteration.
+#[derive(Debug, Clone)]
+pub struct QuerySummary {
+    /// The kind of query, e.g. `indexingStatuses`.
+    pub query_name: &'static str,
+    pub outcomes: HashMap<Arc<dyn IndexerClient>, QueryOutcome>,
+}
+
+impl QuerySummary {
+    fn new(query_name: &'static str) -> Self {
+        Self {
+            query_name,
+            outcomes: HashMap::new(),
+        }
+    }
+}
+
+/// Queries all `indexingStatuses` for all the given indexers, or only those
+/// of `deployment_allowlist` unless it's empty.
 #[instrument(skip_all)]
 pub async fn query_indexing_statuses(
     indexers: &[Arc<dyn IndexerClient>],
     metrics: &PrometheusMetrics,
-) -> Vec<IndexingStatus> {
+    query_spread: Duration,
+    deployment_allowlist: &[SubgraphDeployment],
+) -> (Vec<IndexingStatus>, QuerySummary) {
     let indexers_count = indexers.len();
     debug!(
         indexers_count = indexers_count,
@@ -31,7 +58,23 @@ pub async fn query_indexing_statuses(
 
     let indexing_statuses_results = indexers
         .iter()
-        .map(|indexer| async move { (indexer.clone(), indexer.clone().indexing_statuses().await) })
+        .zip(staggered_delays(indexers.len(), query_spread))
+        .map(|(indexer, delay)| {
+            let span = debug_span!("query_indexer_statuses", indexer = %indexer.address_string());
+            async move {
+                wait(delay).await;
+                let statuses = if deployment_allowlist.is_empty() {
+                    indexer.clone().indexing_statuses().await
+                } else {
+                    indexer
+                        .clone()
+                        .indexing_statuses_of(deployment_allowlist)
+                        .await
+                };
+                (indexer.clone(), statuses)
+            }
+            .instrument(span)
+        })
         .collect::<FuturesUnordered<_>>()
         .collect::<Vec<_>>()
         .await;
@@ -39,12 +82,14 @@ pub async fn query_indexing_statuses(
     assert_eq!(indexing_statuses_results.len(), indexers.len());
 
     let mut indexing_statuses = vec![];
+    let mut summary = QuerySummary::new("indexingStatuses");
     let mut query_successes = 0;
     let mut query_failures = 0;
 
     for (indexer, query_result) in indexing_statuses_results {
         match query_result {
             Ok(statuses) => {
+                let statuses = deduplicate_statuses(&indexer, statuses);
                 query_successes += 1;
                 metrics
                     .indexing_statuses_requests
@@ -57,6 +102,12 @@ pub async fn query_indexing_statuses(
                     statuses = %statuses.len(),
                     "Successfully queried indexing statuses"
                 );
+                summary.outcomes.insert(
+                    indexer,
+                    QueryOutcome::Success {
+                        results: statuses.len(),
+                    },
+                );
                 indexing_statuses.extend(statuses);
             }
 
@@ -68,11 +119,22 @@ pub async fn query_indexing_statuses(
                     .unwrap()
                     .inc();
 
+                metrics
+                    .indexer_query_errors
+                    .with_label_values(&[
+                        &indexer.address_string(),
+                        "indexingStatuses",
+                        error.kind(),
+                    ])
+                    .inc();
+
                 debug!(
                     indexer_id = %indexer.address_string(),
                     %error,
+                    kind = error.kind(),
                     "Failed to query indexing statuses"
                 );
+                summary.outcomes.insert(indexer, QueryOutcome::from(&error));
             }
         }
     }
@@ -87,14 +149,123 @@ pub async fn query_indexing_statuses(
         "Finished querying indexing statuses for all indexers"
     );
 
+    (indexing_statuses, summary)
+}
+
+/// Keeps a single status per subgraph deployment of an indexer, the one with
+/// the highest latest block, as some `graph-node` versions report the same
+/// deployment twice, which would otherwise count twice in majorities. The
+/// order of the statuses is kept.
+fn deduplicate_statuses(
+    indexer: &Arc<dyn IndexerClient>,
+    statuses: Vec<IndexingStatus>,
+) -> Vec<IndexingStatus> {
+    let mut deduplicated: Vec<IndexingStatus> = Vec::with_capacity(statuses.len());
+    let mut positions = HashMap::<SubgraphDeployment, usize>::new();
+    let mut dropped = 0;
+    for status in statuses {
+        match positions.get(&status.deployment) {
+            Some(&i) => {
+                dropped += 1;
+                if status.latest_block.number > deduplicated[i].latest_block.number {
+                    deduplicated[i] = status;
+                }
+            }
+            None => {
+                positions.insert(status.deployment.clone(), deduplicated.len());
+                deduplicated.push(status);
+            }
+        }
+    }
+
+    if dropped > 0 {
+        warn!(
+            indexer_id = %indexer.address_string(),
+            dropped,
+            "Indexer reported some deployments more than once, keeping the status with the highest latest block"
+        );
+    }
+    deduplicated
+}
+
+/// Spreads `count` queries evenly over `spread`, so that indexers don't all get
+/// queried at once: each query gets its own slot within the window, and starts
+/// at a random point within it. All delays are zero if `spread` is.
+fn staggered_delays(count: usize, spread: Duration) -> Vec<Duration> {
+    if spread.is_zero() || count == 0 {
+        return vec![Duration::ZERO; count];
+    }
+
+    let count = u32::try_from(count).unwrap_or(u32::MAX);
+    let slot = spread / count;
+    let mut rng = rand::thread_rng();
+    (0..count)
+        .map(|i| slot * i + rng.gen_range(Duration::ZERO..=slot))
+        .collect()
+}
+
+async fn wait(delay: Duration) {
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// The maximum number of `graphix_blocks_behind` series to export when no
+/// allow-list of deployments is configured.
+pub const MAX_BLOCKS_BEHIND_SERIES: usize = 1000;
+
+/// For each indexing status, returns how many blocks the indexer trails the
+/// most advanced indexer of the same subgraph deployment, in the same order as
+/// `indexing_statuses`.
+pub fn blocks_behind(indexing_statuses: &[IndexingStatus]) -> Vec<u64> {
+    let mut latest_blocks: HashMap<&SubgraphDeployment, u64> = HashMap::new();
+    for status in indexing_statuses {
+        let latest_block = latest_blocks.entry(&status.deployment).or_default();
+        *latest_block = (*latest_block).max(status.latest_block.number);
+    }
+
     indexing_statuses
+        .iter()
+        .map(|status| latest_blocks[&status.deployment] - status.latest_block.number)
+        .collect()
+}
+
+/// Exports [`blocks_behind`] as the `graphix_blocks_behind` gauge, replacing
+/// the previous iteration's values. Only deployments in `allowed_deployments`
+/// are exported, if given; otherwise only the
+/// [`MAX_BLOCKS_BEHIND_SERIES`] statuses that trail the most.
+pub fn export_blocks_behind(
+    indexing_statuses: &[IndexingStatus],
+    allowed_deployments: Option<&[String]>,
+    metrics: &PrometheusMetrics,
+) {
+    let mut series: Vec<_> = indexing_statuses
+        .iter()
+        .zip(blocks_behind(indexing_statuses))
+        .filter(|(status, _)| {
+            allowed_deployments.map_or(true, |allowed| allowed.contains(&status.deployment.0))
+        })
+        .collect();
+    if allowed_deployments.is_none() && series.len() > MAX_BLOCKS_BEHIND_SERIES {
+        series.sort_by_key(|(_, blocks_behind)| std::cmp::Reverse(*blocks_behind));
+        series.truncate(MAX_BLOCKS_BEHIND_SERIES);
+    }
+
+    metrics.blocks_behind.reset();
+    for (status, blocks_behind) in series {
+        metrics
+            .blocks_behind
+            .with_label_values(&[&status.indexer.address_string(), status.deployment.as_str()])
+            .set(blocks_behind.try_into().unwrap_or(i64::MAX));
+    }
 }
 
 /// Queries all `indexers` for their `graph-node` versions.
 #[instrument(skip_all)]
 pub async fn query_graph_node_versions(
     indexers: &[Arc<dyn IndexerClient>],
-    _metrics: &PrometheusMetrics,
+    metrics: &PrometheusMetrics,
+    query_spread: Duration,
 ) -> HashMap<Arc<dyn IndexerClient>, anyhow::Result<GraphNodeCollectedVersion>> {
     let span = span!(Level::TRACE, "query_graph_node_versions");
     let _enter_span = span.enter();
@@ -103,7 +274,15 @@ pub async fn query_graph_node_versions(
 
     let graph_node_versions_results = indexers
         .iter()
-        .map(|indexer| async move { (indexer.clone(), indexer.clone().version().await) })
+        .zip(staggered_delays(indexers.len(), query_spread))
+        .map(|(indexer, delay)| {
+            let span = debug_span!("query_indexer_version", indexer = %indexer.address_string());
+            async move {
+                wait(delay).await;
+                (indexer.clone(), indexer.clone().version().await)
+            }
+            .instrument(span)
+        })
         .collect::<FuturesUnordered<_>>()
         .collect::<Vec<_>>()
         .await;
@@ -123,15 +302,21 @@ pub async fn query_graph_node_versions(
                 );
             }
             Err(error) => {
+                metrics
+                    .indexer_query_errors
+                    .with_label_values(&[&indexer.address_string(), "version", error.kind()])
+                    .inc();
+
                 trace!(
                     indexer_id = %indexer.address_string(),
                     %error,
+                    kind = error.kind(),
                     "Failed to query graph-node version"
                 );
             }
         }
 
-        versions.insert(indexer, version_result);
+        versions.insert(indexer, version_result.map_err(Into::into));
     }
 
     info!(
@@ -142,11 +327,59 @@ pub async fn query_graph_node_versions(
     versions
 }
 
+#[instrument(skip_all)]
+/// Records which block [`query_proofs_of_indexing`] chooses for each
+/// deployment in `indexing_statuses`, and which blocks it chooses from, so that
+/// the decisions can be audited later. Sorted by deployment.
+pub fn block_choices(
+    indexing_statuses: &[IndexingStatus],
+    block_choice_policy: &BlockChoicePolicy,
+) -> Vec<BlockChoice> {
+    let mut statuses_by_deployment: BTreeMap<&SubgraphDeployment, Vec<&IndexingStatus>> =
+        BTreeMap::new();
+    for status in indexing_statuses {
+        statuses_by_deployment
+            .entry(&status.deployment)
+            .or_default()
+            .push(status);
+    }
+
+    statuses_by_deployment
+        .into_iter()
+        .map(|(deployment, statuses)| {
+            let mut candidate_blocks: Vec<u64> = statuses
+                .iter()
+                .map(|status| status.latest_block.number)
+                .collect();
+            candidate_blocks.sort_unstable();
+            candidate_blocks.dedup();
+
+            BlockChoice {
+                deployment: deployment.0.clone(),
+                policy: block_choice_policy.name().to_string(),
+                candidate_blocks,
+                chosen_block: block_choice_policy.choose_block(statuses.into_iter()),
+            }
+        })
+        .collect()
+}
+
+/// Queries PoIs at the block that `block_choice_policy` chooses for each
+/// deployment, and at the `pinned_blocks` that each indexer has reached.
+/// Returns the PoIs at chosen blocks and at pinned blocks separately; PoIs at
+/// blocks that are both are returned twice.
+///
+/// Chosen blocks are moved back by the finality offset of the deployment's
+/// network in `chains`, if any.
 #[instrument(skip_all)]
 pub async fn query_proofs_of_indexing(
     indexing_statuses: Vec<IndexingStatus>,
     block_choice_policy: BlockChoicePolicy,
-) -> Vec<ProofOfIndexing> {
+    pinned_blocks: &PinnedBlocksConfig,
+    chains: &HashMap<String, ChainConfig>,
+    metrics: &PrometheusMetrics,
+    query_spread: Duration,
+) -> (Vec<ProofOfIndexing>, Vec<ProofOfIndexing>, QuerySummary) {
     info!("Query POIs for recent common blocks across indexers");
 
     // Identify all indexers
@@ -174,54 +407,1012 @@ pub async fn query_proofs_of_indexing(
             )
         }));
 
+    // Deployments served by a single indexer can't be cross-checked
+    let mut singleton_deployments: Vec<&str> = statuses_by_deployment
+        .iter()
+        .filter(|(_, statuses)| statuses.len() == 1)
+        .map(|(deployment, _)| deployment.as_str())
+        .collect();
+    singleton_deployments.sort_unstable();
+    metrics
+        .singleton_deployments
+        .set(singleton_deployments.len() as i64);
+    if !singleton_deployments.is_empty() {
+        info!(
+            count = singleton_deployments.len(),
+            deployments = ?singleton_deployments,
+            "Some deployments are only served by a single indexer"
+        );
+    }
+
     // For each deployment, chooose a block on which to query the Poi
     let latest_blocks: HashMap<SubgraphDeployment, Option<u64>> =
         HashMap::from_iter(deployments.iter().map(|deployment| {
             (
                 deployment.clone(),
                 statuses_by_deployment.get(deployment).and_then(|statuses| {
-                    block_choice_policy.choose_block(statuses.iter().copied())
+                    let chosen_block =
+                        block_choice_policy.choose_block(statuses.iter().copied())?;
+                    let finality_offset = statuses
+                        .first()
+                        .and_then(|status| chains.get(&status.network))
+                        .map_or(0, |chain| chain.finality_offset);
+                    Some(apply_finality_offset(
+                        chosen_block,
+                        finality_offset,
+                        statuses,
+                    ))
                 }),
             )
         }));
 
     // Fetch POIs for the most recent common blocks
+    let latest_blocks = &latest_blocks;
+    let statuses_by_deployment = &statuses_by_deployment;
     indexers
         .iter()
-        .map(|indexer| async {
-            let poi_requests = latest_blocks
-                .iter()
-                .filter(|(deployment, &block_number)| {
-                    statuses_by_deployment
-                        .get(*deployment)
-                        .expect("bug in matching deployments to latest blocks and indexers")
-                        .iter()
-                        .any(|status| {
-                            status.indexer.eq(indexer)
-                                && Some(status.latest_block.number) >= block_number
-                        })
-                })
-                .filter_map(|(deployment, block_number)| {
-                    block_number.map(|block_number| PoiRequest {
+        .zip(staggered_delays(indexers.len(), query_spread))
+        .map(|(indexer, delay)| {
+            let span = debug_span!("query_indexer_pois", indexer = %indexer.address_string());
+            async move {
+                wait(delay).await;
+
+                let chosen_requests = latest_blocks
+                    .iter()
+                    .filter(|(deployment, &block_number)| {
+                        statuses_by_deployment
+                            .get(*deployment)
+                            .expect("bug in matching deployments to latest blocks and indexers")
+                            .iter()
+                            .any(|status| {
+                                // Indexers can't provide PoIs for blocks they
+                                // haven't reached yet or have pruned already.
+                                status.indexer.eq(indexer)
+                                    && block_number.is_some_and(|block_number| {
+                                        (status.earliest_block_num..=status.latest_block.number)
+                                            .contains(&block_number)
+                                    })
+                            })
+                    })
+                    .filter_map(|(deployment, block_number)| {
+                        block_number.map(|block_number| (deployment.clone(), block_number))
+                    })
+                    .collect::<HashSet<_>>();
+                let pinned_requests = statuses_by_deployment
+                    .values()
+                    .flatten()
+                    .filter(|status| status.indexer.eq(indexer))
+                    .flat_map(|status| {
+                        pinned_blocks
+                            .blocks(status)
+                            .into_iter()
+                            .map(|block_number| (status.deployment.clone(), block_number))
+                    })
+                    .collect::<HashSet<_>>();
+
+                let poi_requests = chosen_requests
+                    .union(&pinned_requests)
+                    .map(|(deployment, block_number)| PoiRequest {
                         deployment: deployment.clone(),
-                        block_number,
+                        block_number: *block_number,
                     })
-                })
-                .collect::<Vec<_>>();
+                    .collect::<Vec<_>>();
+                let requested = poi_requests.len();
+                let all_pois = indexer.clone().proofs_of_indexing(poi_requests).await;
 
-            let pois = indexer.clone().proofs_of_indexing(poi_requests).await;
+                let (mut pois, mut pinned_pois) = (vec![], vec![]);
+                for poi in all_pois.iter() {
+                    let request = (poi.deployment.clone(), poi.block.number);
+                    if pinned_requests.contains(&request) {
+                        pinned_pois.push(poi.clone());
+                        if !chosen_requests.contains(&request) {
+                            continue;
+                        }
+                    }
+                    pois.push(poi.clone());
+                }
 
-            debug!(
-                id = %indexer.address_string(), pois = %pois.len(),
-                "Successfully queried POIs from indexer"
-            );
+                debug!(
+                    id = %indexer.address_string(), pois = %all_pois.len(),
+                    "Successfully queried POIs from indexer"
+                );
 
-            pois
+                // Indexer clients don't report failed PoI requests, which
+                // only show up as missing PoIs.
+                let outcome = if requested > 0 && all_pois.is_empty() {
+                    QueryOutcome::Failure {
+                        error: format!("none of the {} requested PoIs were returned", requested),
+                        kind: None,
+                    }
+                } else {
+                    QueryOutcome::Success {
+                        results: all_pois.len(),
+                    }
+                };
+
+                (indexer.clone(), pois, pinned_pois, outcome)
+            }
+            .instrument(span)
         })
         .collect::<FuturesUnordered<_>>()
         .collect::<Vec<_>>()
         .await
         .into_iter()
-        .flatten()
-        .collect::<Vec<_>>()
+        .fold(
+            (vec![], vec![], QuerySummary::new("proofsOfIndexing")),
+            |(mut all_pois, mut all_pinned_pois, mut summary),
+             (indexer, pois, pinned_pois, outcome)| {
+                all_pois.extend(pois);
+                all_pinned_pois.extend(pinned_pois);
+                summary.outcomes.insert(indexer, outcome);
+                (all_pois, all_pinned_pois, summary)
+            },
+        )
+}
+
+/// Moves `chosen_block` back by `finality_offset` blocks, but not below the
+/// earliest block of any of the indexers that can provide a PoI at
+/// `chosen_block`, so that none of them drop out of the comparison.
+fn apply_finality_offset(
+    chosen_block: u64,
+    finality_offset: u64,
+    statuses: &[&IndexingStatus],
+) -> u64 {
+    let earliest_block = statuses
+        .iter()
+        .map(|status| status.earliest_block_num)
+        .filter(|&earliest_block_num| earliest_block_num <= chosen_block)
+        .max()
+        .unwrap_or(0);
+    chosen_block
+        .saturating_sub(finality_offset)
+        .max(earliest_block)
+}
+
+/// Keeps only the indexing statuses of `tracked_deployments`, if deployment
+/// discovery is enabled. Otherwise, all deployments are tracked.
+pub fn tracked_indexing_statuses(
+    indexing_statuses: Vec<IndexingStatus>,
+    tracked_deployments: Option<&HashSet<SubgraphDeployment>>,
+) -> Vec<IndexingStatus> {
+    match tracked_deployments {
+        Some(tracked_deployments) => indexing_statuses
+            .into_iter()
+            .filter(|status| tracked_deployments.contains(&status.deployment))
+            .collect(),
+        None => indexing_statuses,
+    }
+}
+
+/// Keeps the indexing statuses of at most `max_deployments_per_indexer`
+/// subgraph deployments of each indexer, so that indexers with thousands of
+/// deployments don't make iterations overrun the polling period. Deployments
+/// that more indexers serve are preferred, then those with more
+/// `allocated_tokens`. Deployments in `deployment_allowlist` are always kept
+/// and don't count towards the cap.
+///
+/// The dropped deployments are counted by indexer and listed at debug level.
+pub fn cap_deployments_per_indexer(
+    indexing_statuses: Vec<IndexingStatus>,
+    max_deployments_per_indexer: usize,
+    deployment_allowlist: &[SubgraphDeployment],
+    allocated_tokens: &HashMap<SubgraphDeployment, f64>,
+    metrics: &PrometheusMetrics,
+) -> Vec<IndexingStatus> {
+    let mut indexers_by_deployment: HashMap<&SubgraphDeployment, usize> = HashMap::new();
+    let mut deployments_by_indexer: BTreeMap<IndexerAddress, Vec<&SubgraphDeployment>> =
+        BTreeMap::new();
+    for status in &indexing_statuses {
+        *indexers_by_deployment
+            .entry(&status.deployment)
+            .or_default() += 1;
+        if !deployment_allowlist.contains(&status.deployment) {
+            deployments_by_indexer
+                .entry(status.indexer.address())
+                .or_default()
+                .push(&status.deployment);
+        }
+    }
+
+    let tokens =
+        |deployment: &SubgraphDeployment| allocated_tokens.get(deployment).copied().unwrap_or(0.0);
+    let mut dropped: HashSet<(IndexerAddress, SubgraphDeployment)> = HashSet::new();
+    for (indexer, mut deployments) in deployments_by_indexer {
+        if deployments.len() <= max_deployments_per_indexer {
+            continue;
+        }
+
+        deployments.sort_by(|a, b| {
+            indexers_by_deployment[b]
+                .cmp(&indexers_by_deployment[a])
+                .then(tokens(b).total_cmp(&tokens(a)))
+                .then(a.cmp(b))
+        });
+        let over_cap = &deployments[max_deployments_per_indexer..];
+        debug!(
+            indexer = %indexer,
+            dropped = ?over_cap.iter().map(|deployment| deployment.as_str()).collect::<Vec<_>>(),
+            "Not querying PoIs of deployments beyond maxDeploymentsPerIndexer"
+        );
+        metrics
+            .deployments_over_cap
+            .with_label_values(&[&indexer.to_string()])
+            .inc_by(over_cap.len() as u64);
+        dropped.extend(
+            over_cap
+                .iter()
+                .map(|deployment| (indexer, (*deployment).clone())),
+        );
+    }
+
+    if dropped.is_empty() {
+        return indexing_statuses;
+    }
+    indexing_statuses
+        .into_iter()
+        .filter(|status| !dropped.contains(&(status.indexer.address(), status.deployment.clone())))
+        .collect()
+}
+
+/// Keeps track of when PoIs were last queried for each subgraph deployment, so
+/// that deployments with a polling period override are polled at their own
+/// pace. All other deployments are polled on every main loop iteration.
+#[derive(Debug, Default)]
+pub struct DeploymentPollingSchedule {
+    polling_periods: HashMap<String, Duration>,
+    last_polled: HashMap<SubgraphDeployment, Instant>,
+}
+
+impl DeploymentPollingSchedule {
+    pub fn new(polling_periods_in_seconds: &HashMap<String, u64>) -> Self {
+        let mut schedule = Self {
+            polling_periods: HashMap::new(),
+            last_polled: HashMap::new(),
+        };
+        schedule.set_polling_periods(polling_periods_in_seconds);
+        schedule
+    }
+
+    /// Replaces the per-deployment polling periods, e.g. after a
+    /// configuration reload. When deployments were last polled is kept.
+    pub fn set_polling_periods(&mut self, polling_periods_in_seconds: &HashMap<String, u64>) {
+        self.polling_periods = polling_periods_in_seconds
+            .iter()
+            .map(|(deployment, secs)| (deployment.clone(), Duration::from_secs(*secs)))
+            .collect();
+    }
+
+    /// Returns the indexing statuses of the deployments that are due for
+    /// polling at `now`, and records them as polled.
+    pub fn due_indexing_statuses(
+        &mut self,
+        indexing_statuses: &[IndexingStatus],
+        now: Instant,
+    ) -> Vec<IndexingStatus> {
+        let due_deployments: HashSet<&SubgraphDeployment> = indexing_statuses
+            .iter()
+            .map(|status| &status.deployment)
+            .filter(|deployment| {
+                match (
+                    self.polling_periods.get(deployment.as_str()),
+                    self.last_polled.get(*deployment),
+                ) {
+                    (Some(period), Some(last_polled)) => {
+                        now.duration_since(*last_polled) >= *period
+                    }
+                    _ => true,
+                }
+            })
+            .collect();
+
+        for deployment in &due_deployments {
+            self.last_polled.insert((*deployment).clone(), now);
+        }
+
+        indexing_statuses
+            .iter()
+            .filter(|status| due_deployments.contains(&status.deployment))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Turns the outcome of a single main loop iteration into a reliability sample
+/// between 0.0 and 1.0 for each indexer, to be folded into the stored
+/// reliability scores.
+///
+/// An indexer earns half a point for answering `indexingStatuses` and the other
+/// half for serving PoIs, which it gets for free if none of its deployments
+/// were polled (see `polled_statuses`). Note that indexers that successfully
+/// report no indexing statuses at all are indistinguishable from failing ones.
+pub fn indexer_reliability_samples(
+    indexers: &[Arc<dyn IndexerClient>],
+    indexing_statuses: &[IndexingStatus],
+    polled_statuses: &[IndexingStatus],
+    pois: &[ProofOfIndexing],
+) -> HashMap<Arc<dyn IndexerClient>, f64> {
+    let indexers_with_statuses: HashSet<_> = indexing_statuses
+        .iter()
+        .map(|status| status.indexer.address())
+        .collect();
+    let polled_indexers: HashSet<_> = polled_statuses
+        .iter()
+        .map(|status| status.indexer.address())
+        .collect();
+    let indexers_with_pois: HashSet<_> = pois
+        .iter()
+        .map(|poi| poi.indexer.address())
+        .chain(indexers_with_statuses.difference(&polled_indexers).copied())
+        .collect();
+
+    indexers
+        .iter()
+        .map(|indexer| {
+            let address = indexer.address();
+            let sample = match (
+                indexers_with_statuses.contains(&address),
+                indexers_with_pois.contains(&address),
+            ) {
+                (false, _) => 0.0,
+                (true, false) => 0.5,
+                (true, true) => 1.0,
+            };
+
+            (indexer.clone(), sample)
+        })
+        .collect()
+}
+
+/// Determines, for each indexer, whether its PoIs agree with those of the
+/// trusted indexer of their network, if one is configured. PoIs of deployments
+/// that the trusted indexer doesn't serve are compared against the majority
+/// instead, when there is one.
+///
+/// Indexers without any comparable PoIs are left out, and so are the trusted
+/// indexers themselves.
+pub fn agreement_with_trusted_indexers(
+    indexing_statuses: &[IndexingStatus],
+    pois: &[ProofOfIndexing],
+    trusted_indexers: &HashMap<String, IndexerAddress>,
+) -> HashMap<Arc<dyn IndexerClient>, bool> {
+    let networks: HashMap<&SubgraphDeployment, &str> = indexing_statuses
+        .iter()
+        .map(|status| (&status.deployment, status.network.as_str()))
+        .collect();
+
+    let mut pois_by_block: HashMap<(&SubgraphDeployment, u64), Vec<&ProofOfIndexing>> =
+        HashMap::new();
+    for poi in pois {
+        pois_by_block
+            .entry((&poi.deployment, poi.block.number))
+            .or_default()
+            .push(poi);
+    }
+
+    let mut agreement = HashMap::new();
+    for ((deployment, _), pois) in pois_by_block {
+        let Some(trusted_indexer) = networks
+            .get(deployment)
+            .and_then(|network| trusted_indexers.get(*network))
+        else {
+            continue;
+        };
+
+        let reference_poi = match pois
+            .iter()
+            .find(|poi| poi.indexer.address() == *trusted_indexer)
+        {
+            Some(poi) => Some(poi.proof_of_indexing),
+            None => majority_poi(&pois),
+        };
+        let Some(reference_poi) = reference_poi else {
+            continue;
+        };
+
+        for poi in pois {
+            if poi.indexer.address() == *trusted_indexer {
+                continue;
+            }
+
+            let agrees = agreement.entry(poi.indexer.clone()).or_insert(true);
+            *agrees &= poi.proof_of_indexing == reference_poi;
+        }
+    }
+
+    agreement
+}
+
+/// The PoI reported by more than half of the given PoIs, if any.
+fn majority_poi(pois: &[&ProofOfIndexing]) -> Option<PoiBytes> {
+    let mut counts: HashMap<PoiBytes, usize> = HashMap::new();
+    for poi in pois {
+        *counts.entry(poi.proof_of_indexing).or_default() += 1;
+    }
+
+    counts
+        .into_iter()
+        .find(|(_, count)| *count > pois.len() / 2)
+        .map(|(poi, _)| poi)
+}
+
+/// A change in whether indexers agree on the PoIs of a subgraph deployment, as
+/// observed by [`DivergenceTracker`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DivergenceEvent {
+    /// Indexers started to report different PoIs for the deployment.
+    New {
+        deployment: SubgraphDeployment,
+        /// The first block at which the indexers were seen to diverge.
+        block_number: u64,
+    },
+    /// Indexers agree again on the deployment, or the ones that diverged
+    /// stopped serving it.
+    Resolved {
+        deployment: SubgraphDeployment,
+        /// The first block at which the indexers were seen to diverge.
+        block_number: u64,
+        /// How long the divergence persisted.
+        duration: Duration,
+    },
+    /// Indexers reported different block hashes for a block of the
+    /// deployment, so their PoIs at that block aren't compared.
+    BlockHashMismatch {
+        deployment: SubgraphDeployment,
+        block_number: u64,
+    },
+}
+
+/// The PoIs in `pois` at blocks that indexers report different hashes for,
+/// and whose hash isn't the one reported by most indexers, i.e. they're likely
+/// on a reorged fork. Such PoIs aren't comparable with the others, so they're
+/// never divergences. If no hash is reported more often than any other, all
+/// PoIs at the block are mismatches.
+pub fn block_hash_mismatches(pois: &[ProofOfIndexing]) -> Vec<ProofOfIndexing> {
+    let minority_blocks = minority_blocks(pois);
+
+    pois.iter()
+        .filter(|poi| minority_blocks.contains(&(&poi.deployment, &poi.block)))
+        .cloned()
+        .collect()
+}
+
+/// The blocks (by number and hash) of each deployment that are outnumbered by
+/// another hash reported for the same block number.
+fn minority_blocks(pois: &[ProofOfIndexing]) -> BTreeSet<(&SubgraphDeployment, &BlockPointer)> {
+    let mut counts_by_number: BTreeMap<(&SubgraphDeployment, u64), HashMap<&BlockPointer, usize>> =
+        BTreeMap::new();
+    for poi in pois {
+        *counts_by_number
+            .entry((&poi.deployment, poi.block.number))
+            .or_default()
+            .entry(&poi.block)
+            .or_default() += 1;
+    }
+
+    let mut minority_blocks = BTreeSet::new();
+    for ((deployment, _), counts) in counts_by_number {
+        if counts.len() < 2 {
+            continue;
+        }
+
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        let majority_block = match counts[..] {
+            [(block, first), (_, second), ..] if first > second => Some(block),
+            _ => None,
+        };
+        minority_blocks.extend(
+            counts
+                .into_iter()
+                .map(|(block, _)| block)
+                .filter(|block| Some(*block) != majority_block)
+                .map(|block| (deployment, block)),
+        );
+    }
+
+    minority_blocks
+}
+
+/// Keeps track of the subgraph deployments on which indexers disagree across
+/// main loop iterations.
+#[derive(Debug, Default)]
+pub struct DivergenceTracker {
+    divergences: BTreeMap<SubgraphDeployment, Divergence>,
+    /// The block hash mismatches seen during the last iteration in which each
+    /// deployment's PoIs were queried.
+    block_hash_mismatches: BTreeMap<SubgraphDeployment, BTreeSet<u64>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Divergence {
+    block_number: u64,
+    since: Instant,
+}
+
+impl DivergenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compares the PoIs of a main loop iteration with the divergences known
+    /// from previous iterations. Deployments whose PoIs weren't queried during
+    /// this iteration keep their state, unless no indexer reports an indexing
+    /// status for them anymore.
+    ///
+    /// Blocks that indexers report different hashes for are reported as
+    /// [`DivergenceEvent::BlockHashMismatch`] once. The PoIs of indexers whose
+    /// hash is in the minority (see [`block_hash_mismatches`]) are ignored,
+    /// while the remaining ones are still compared.
+    pub fn update(
+        &mut self,
+        indexing_statuses: &[IndexingStatus],
+        pois: &[ProofOfIndexing],
+        now: Instant,
+    ) -> Vec<DivergenceEvent> {
+        let mut events = vec![];
+
+        let minority_blocks = minority_blocks(pois);
+        let mut mismatches_by_deployment: BTreeMap<&SubgraphDeployment, BTreeSet<u64>> = pois
+            .iter()
+            .map(|poi| (&poi.deployment, BTreeSet::new()))
+            .collect();
+        for &(deployment, block) in &minority_blocks {
+            mismatches_by_deployment
+                .entry(deployment)
+                .or_default()
+                .insert(block.number);
+        }
+        for (deployment, block_numbers) in mismatches_by_deployment {
+            let known = self
+                .block_hash_mismatches
+                .get(deployment)
+                .cloned()
+                .unwrap_or_default();
+            events.extend(block_numbers.difference(&known).map(|&block_number| {
+                DivergenceEvent::BlockHashMismatch {
+                    deployment: deployment.clone(),
+                    block_number,
+                }
+            }));
+            self.block_hash_mismatches
+                .insert(deployment.clone(), block_numbers);
+        }
+
+        // PoIs are only comparable at the same block number and hash. Indexers
+        // on a minority fork are left out, so that the others are still compared.
+        let mut pois_by_block: BTreeMap<(&SubgraphDeployment, &BlockPointer), HashSet<PoiBytes>> =
+            BTreeMap::new();
+        for poi in pois
+            .iter()
+            .filter(|poi| !minority_blocks.contains(&(&poi.deployment, &poi.block)))
+        {
+            pois_by_block
+                .entry((&poi.deployment, &poi.block))
+                .or_default()
+                .insert(poi.proof_of_indexing);
+        }
+
+        // For every deployment with PoIs, the first block at which they differ.
+        let mut diverging_blocks: BTreeMap<&SubgraphDeployment, Option<u64>> = BTreeMap::new();
+        for ((deployment, block), pois) in pois_by_block {
+            let diverging_block = diverging_blocks.entry(deployment).or_default();
+            if pois.len() > 1 && diverging_block.is_none() {
+                *diverging_block = Some(block.number);
+            }
+        }
+
+        for (deployment, diverging_block) in diverging_blocks {
+            match (diverging_block, self.divergences.contains_key(deployment)) {
+                (Some(block_number), false) => {
+                    self.divergences.insert(
+                        deployment.clone(),
+                        Divergence {
+                            block_number,
+                            since: now,
+                        },
+                    );
+                    events.push(DivergenceEvent::New {
+                        deployment: deployment.clone(),
+                        block_number,
+                    });
+                }
+                (None, true) => events.extend(self.resolve(deployment, now)),
+                _ => {}
+            }
+        }
+
+        let served: HashSet<&SubgraphDeployment> = indexing_statuses
+            .iter()
+            .map(|status| &status.deployment)
+            .collect();
+        let unserved: Vec<SubgraphDeployment> = self
+            .divergences
+            .keys()
+            .filter(|deployment| !served.contains(deployment))
+            .cloned()
+            .collect();
+        for deployment in unserved {
+            events.extend(self.resolve(&deployment, now));
+        }
+        self.block_hash_mismatches
+            .retain(|deployment, _| served.contains(deployment));
+
+        events
+    }
+
+    fn resolve(
+        &mut self,
+        deployment: &SubgraphDeployment,
+        now: Instant,
+    ) -> Option<DivergenceEvent> {
+        let divergence = self.divergences.remove(deployment)?;
+
+        Some(DivergenceEvent::Resolved {
+            deployment: deployment.clone(),
+            block_number: divergence.block_number,
+            duration: now.saturating_duration_since(divergence.since),
+        })
+    }
+}
+
+/// Logs divergence events and counts them in Prometheus metrics.
+pub fn export_divergence_events(events: &[DivergenceEvent], metrics: &PrometheusMetrics) {
+    for event in events {
+        match event {
+            DivergenceEvent::New {
+                deployment,
+                block_number,
+            } => {
+                warn!(
+                    deployment = %deployment.as_str(),
+                    block_number,
+                    "Indexers diverge on deployment"
+                );
+                metrics.divergence_events.with_label_values(&["new"]).inc();
+            }
+            DivergenceEvent::Resolved {
+                deployment,
+                block_number,
+                duration,
+            } => {
+                info!(
+                    deployment = %deployment.as_str(),
+                    block_number,
+                    duration_seconds = duration.as_secs(),
+                    "Divergence on deployment resolved"
+                );
+                metrics
+                    .divergence_events
+                    .with_label_values(&["resolved"])
+                    .inc();
+                metrics
+                    .divergence_duration_seconds
+                    .observe(duration.as_secs_f64());
+            }
+            DivergenceEvent::BlockHashMismatch {
+                deployment,
+                block_number,
+            } => {
+                warn!(
+                    deployment = %deployment.as_str(),
+                    block_number,
+                    "Indexers report different block hashes, at least one of them is on a reorged fork"
+                );
+                metrics.block_hash_mismatches.inc();
+            }
+        }
+    }
+}
+
+/// The state of the circuit breaker of an indexer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, async_graphql::Enum)]
+pub enum CircuitBreakerState {
+    /// The indexer is queried as usual.
+    #[default]
+    Closed,
+    /// The indexer failed too many times in a row, and is skipped until its
+    /// backoff period is over.
+    Open,
+    /// The backoff period is over, and the indexer is queried once more to
+    /// find out whether it recovered.
+    HalfOpen,
+}
+
+impl CircuitBreakerState {
+    const ALL: [Self; 3] = [Self::Closed, Self::Open, Self::HalfOpen];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// The circuit breaker of an indexer, as returned by the
+/// `indexerConnectivity` GraphQL query.
+#[derive(Debug, Clone, async_graphql::SimpleObject)]
+pub struct IndexerConnectivity {
+    pub indexer: IndexerAddress,
+    pub state: CircuitBreakerState,
+    /// Number of main loop iterations in a row in which the indexer's
+    /// `indexingStatuses` query failed.
+    pub consecutive_failures: u32,
+    /// When the indexer will be queried again, if its circuit breaker is open.
+    pub retry_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Keeps track of the circuit breakers of indexers across main loop
+/// iterations. Indexers whose `indexingStatuses` queries failed too many times
+/// in a row, or returned a response that was too large, are skipped for a
+/// backoff period, then probed once: if the probe succeeds the indexer is
+/// queried as usual again, otherwise it's skipped for twice as long, up to a
+/// maximum.
+#[derive(Debug, Default)]
+pub struct CircuitBreakers {
+    breakers: HashMap<IndexerAddress, CircuitBreaker>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct CircuitBreaker {
+    state: CircuitBreakerState,
+    consecutive_failures: u32,
+    /// How long the indexer was skipped the last time, if the breaker isn't
+    /// closed.
+    backoff: Duration,
+    /// Until when the indexer is skipped, if the breaker is open.
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreakers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the `indexers` that should be queried at `now`, i.e. all of
+    /// them except those with open circuit breakers. Breakers whose backoff
+    /// period is over become half-open. Breakers of indexers that are gone
+    /// are dropped, and all of them are if circuit breaking is disabled.
+    pub fn allowed_indexers(
+        &mut self,
+        indexers: &[Arc<dyn IndexerClient>],
+        config: &CircuitBreakerConfig,
+        now: Instant,
+    ) -> Vec<Arc<dyn IndexerClient>> {
+        if !config.enabled {
+            self.breakers.clear();
+            return indexers.to_vec();
+        }
+
+        let addresses: HashSet<IndexerAddress> =
+            indexers.iter().map(|indexer| indexer.address()).collect();
+        self.breakers
+            .retain(|address, _| addresses.contains(address));
+
+        indexers
+            .iter()
+            .filter(|indexer| {
+                let Some(breaker) = self.breakers.get_mut(&indexer.address()) else {
+                    return true;
+                };
+                match breaker.open_until {
+                    Some(open_until) if now < open_until => false,
+                    Some(_) => {
+                        breaker.state = CircuitBreakerState::HalfOpen;
+                        breaker.open_until = None;
+                        true
+                    }
+                    None => true,
+                }
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Updates the circuit breakers with the outcomes of the `indexingStatuses`
+    /// queries of a main loop iteration. Indexers that weren't queried keep
+    /// their state.
+    pub fn record(&mut self, summary: &QuerySummary, config: &CircuitBreakerConfig, now: Instant) {
+        if !config.enabled {
+            return;
+        }
+
+        let initial_backoff = Duration::from_secs(config.initial_backoff_seconds);
+        let max_backoff = Duration::from_secs(config.max_backoff_seconds);
+        for (indexer, outcome) in &summary.outcomes {
+            let address = indexer.address();
+            if let QueryOutcome::Success { .. } = outcome {
+                if let Some(breaker) = self.breakers.remove(&address) {
+                    if breaker.state != CircuitBreakerState::Closed {
+                        info!(
+                            indexer = %address,
+                            "Indexer recovered, closing its circuit breaker"
+                        );
+                    }
+                }
+                continue;
+            }
+
+            // Oversized responses cost a lot of memory and bandwidth every
+            // time, so one is enough to skip the indexer for a while.
+            let too_large = matches!(
+                outcome,
+                QueryOutcome::Failure {
+                    kind: Some("ResponseTooLarge"),
+                    ..
+                }
+            );
+            let breaker = self.breakers.entry(address).or_default();
+            breaker.consecutive_failures += 1;
+            let backoff = match breaker.state {
+                CircuitBreakerState::HalfOpen => (breaker.backoff * 2).min(max_backoff),
+                CircuitBreakerState::Closed
+                    if too_large || breaker.consecutive_failures >= config.failure_threshold =>
+                {
+                    initial_backoff.min(max_backoff)
+                }
+                _ => continue,
+            };
+
+            warn!(
+                indexer = %address,
+                consecutive_failures = breaker.consecutive_failures,
+                backoff_seconds = backoff.as_secs(),
+                "Opening circuit breaker of failing indexer"
+            );
+            breaker.state = CircuitBreakerState::Open;
+            breaker.backoff = backoff;
+            breaker.open_until = Some(now + backoff);
+        }
+    }
+
+    /// Returns the circuit breakers of all indexers whose queries failed since
+    /// their last successful one, sorted by indexer address.
+    pub fn connectivity(&self, now: Instant) -> Vec<IndexerConnectivity> {
+        let mut connectivity: Vec<IndexerConnectivity> = self
+            .breakers
+            .iter()
+            .map(|(address, breaker)| IndexerConnectivity {
+                indexer: *address,
+                state: breaker.state,
+                consecutive_failures: breaker.consecutive_failures,
+                retry_at: breaker.open_until.map(|open_until| {
+                    let remaining = open_until.saturating_duration_since(now);
+                    chrono::Utc::now() + chrono::Duration::seconds(remaining.as_secs() as i64)
+                }),
+            })
+            .collect();
+        connectivity.sort_by_key(|connectivity| connectivity.indexer);
+        connectivity
+    }
+}
+
+/// Exports the state of every indexer's circuit breaker as the
+/// `graphix_indexer_circuit_breaker_state` gauge, replacing the previous
+/// iteration's values. Indexers without a tracked breaker are closed.
+pub fn export_circuit_breaker_states(
+    indexers: &[Arc<dyn IndexerClient>],
+    circuit_breakers: &CircuitBreakers,
+    metrics: &PrometheusMetrics,
+) {
+    metrics.circuit_breaker_state.reset();
+    for indexer in indexers {
+        let address = indexer.address();
+        let current = circuit_breakers
+            .breakers
+            .get(&address)
+            .map_or(CircuitBreakerState::Closed, |breaker| breaker.state);
+        for state in CircuitBreakerState::ALL {
+            metrics
+                .circuit_breaker_state
+                .with_label_values(&[&indexer.address_string(), state.as_str()])
+                .set((state == current) as i64);
+        }
+    }
+}
+
+/// Plans the PoI requests of a backfill of `deployment`: every `block_interval`
+/// blocks from `from_block` to `to_block`, one request to each indexer whose
+/// indexing status shows that it can provide a PoI for the block, i.e. that it
+/// has neither pruned the block nor not reached it yet. The range is clamped
+/// to the blocks that the indexing statuses cover, but the number of requests
+/// may still be large; see [`backfill_poi_request_count`] to check it first.
+pub fn backfill_poi_requests(
+    indexing_statuses: &[IndexingStatus],
+    deployment: &SubgraphDeployment,
+    from_block: u64,
+    to_block: u64,
+    block_interval: u64,
+) -> Vec<(Arc<dyn IndexerClient>, PoiRequest)> {
+    let statuses: Vec<&IndexingStatus> = indexing_statuses
+        .iter()
+        .filter(|status| status.deployment == *deployment)
+        .collect();
+    let Some(blocks) = statuses
+        .iter()
+        .filter_map(|status| backfill_blocks(status, from_block, to_block, block_interval))
+        .reduce(|a, b| (*a.start()).min(*b.start())..=(*a.end()).max(*b.end()))
+    else {
+        return vec![];
+    };
+    let step = usize::try_from(block_interval.max(1)).unwrap_or(usize::MAX);
+
+    blocks
+        .step_by(step)
+        .flat_map(|block_number| {
+            statuses
+                .iter()
+                .filter(move |status| {
+                    (status.earliest_block_num..=status.latest_block.number).contains(&block_number)
+                })
+                .map(move |status| {
+                    let request = PoiRequest {
+                        deployment: deployment.clone(),
+                        block_number,
+                    };
+                    (status.indexer.clone(), request)
+                })
+        })
+        .collect()
+}
+
+/// The number of PoI requests that [`backfill_poi_requests`] plans for the
+/// same arguments, computed without planning them.
+pub fn backfill_poi_request_count(
+    indexing_statuses: &[IndexingStatus],
+    deployment: &SubgraphDeployment,
+    from_block: u64,
+    to_block: u64,
+    block_interval: u64,
+) -> u64 {
+    let step = block_interval.max(1);
+    indexing_statuses
+        .iter()
+        .filter(|status| status.deployment == *deployment)
+        .filter_map(|status| backfill_blocks(status, from_block, to_block, block_interval))
+        .map(|blocks| (blocks.end() - blocks.start()) / step + 1)
+        .fold(0, u64::saturating_add)
+}
+
+/// The first and last block of a backfill that the indexer of `status` can
+/// provide a PoI for, if any. Backfilled blocks are `from_block` plus
+/// multiples of `block_interval`.
+fn backfill_blocks(
+    status: &IndexingStatus,
+    from_block: u64,
+    to_block: u64,
+    block_interval: u64,
+) -> Option<RangeInclusive<u64>> {
+    let step = block_interval.max(1);
+    let lowest = from_block.max(status.earliest_block_num);
+    let highest = to_block.min(status.latest_block.number);
+    if lowest > highest {
+        return None;
+    }
+
+    // Round `lowest` up and `highest` down to backfilled blocks.
+    let first = from_block.checked_add((lowest - from_block).div_ceil(step).checked_mul(step)?)?;
+    let last = from_block + (highest - from_block) / step * step;
+    (first <= last).then_some(first..=last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn staggered_delays_are_within_spread() {
+        assert_eq!(staggered_delays(3, Duration::ZERO), vec![Duration::ZERO; 3]);
+        assert!(staggered_delays(0, Duration::from_secs(10)).is_empty());
+
+        let spread = Duration::from_secs(10);
+        let delays = staggered_delays(4, spread);
+        for (i, delay) in delays.into_iter().enumerate() {
+            // Each query starts within its own slot.
+            let slot_start = spread / 4 * i as u32;
+            assert!(delay >= slot_start);
+            assert!(delay <= slot_start + spread / 4);
+        }
+    }
 }
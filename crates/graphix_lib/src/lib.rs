@@ -2,6 +2,7 @@ pub mod block_choice;
 pub mod config;
 pub mod graphql_api;
 pub mod indexing_loop;
+pub mod manifest;
 mod prometheus_metrics;
 
 #[cfg(feature = "tests")]
@@ -10,3 +11,22 @@ pub mod test_utils;
 pub use prometheus_metrics::{metrics, PrometheusExporter, PrometheusMetrics};
 
 pub const GRAPHIX_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// The commit that Graphix was built from, or `unknown` for builds without git
+/// metadata, e.g. from a source tarball. Set by the build script, which takes
+/// it from the `GRAPHIX_GIT_SHA` environment variable if set.
+pub const GRAPHIX_GIT_SHA: &str = env!("GRAPHIX_GIT_SHA");
+/// When Graphix was built, in RFC 3339 format.
+pub const GRAPHIX_BUILD_TIMESTAMP: &str = env!("GRAPHIX_BUILD_TIMESTAMP");
+/// The output of `rustc --version` for the compiler that built Graphix.
+pub const GRAPHIX_RUSTC_VERSION: &str = env!("GRAPHIX_RUSTC_VERSION");
+/// [`GRAPHIX_VERSION`] along with the build metadata, for `--version`.
+pub const GRAPHIX_LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (commit ",
+    env!("GRAPHIX_GIT_SHA"),
+    ", built ",
+    env!("GRAPHIX_BUILD_TIMESTAMP"),
+    " with ",
+    env!("GRAPHIX_RUSTC_VERSION"),
+    ")"
+);
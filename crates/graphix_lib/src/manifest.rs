@@ -0,0 +1,181 @@
+//! Metadata from the manifests of subgraph deployments, which are fetched from
+//! an IPFS gateway.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use graphix_common_types::IpfsCid;
+use graphix_store::models::{IntId, NewSgDeploymentManifest};
+use serde::Deserialize;
+use url::Url;
+
+/// A client for an IPFS HTTP gateway.
+#[derive(Debug, Clone)]
+pub struct IpfsGateway {
+    url: Url,
+    client: reqwest::Client,
+}
+
+impl IpfsGateway {
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Fetches the file with the given CID.
+    pub async fn cat(&self, cid: &IpfsCid) -> anyhow::Result<String> {
+        let url = format!("{}/ipfs/{}", self.url.as_str().trim_end_matches('/'), cid);
+
+        Ok(self
+            .client
+            .get(url)
+            .timeout(Self::TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)
+    }
+
+    /// Fetches the manifest of a subgraph deployment, and its GraphQL schema
+    /// to count the entity types.
+    pub async fn deployment_manifest(
+        &self,
+        deployment: &IpfsCid,
+    ) -> anyhow::Result<DeploymentManifest> {
+        let manifest = self
+            .cat(deployment)
+            .await
+            .context("failed to fetch manifest")?;
+        let mut manifest = DeploymentManifest::parse(&manifest)?;
+
+        let schema = self
+            .cat(&manifest.schema)
+            .await
+            .context("failed to fetch schema")?;
+        manifest.entity_count = Some(count_entity_types(&schema));
+
+        Ok(manifest)
+    }
+}
+
+/// The parts of a subgraph manifest that Graphix is interested in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeploymentManifest {
+    pub description: Option<String>,
+    pub repository: Option<String>,
+    pub spec_version: String,
+    /// The network of the first data source.
+    pub network: Option<String>,
+    /// The earliest start block of all data sources.
+    pub start_block: Option<u64>,
+    pub features: Vec<String>,
+    /// The CID of the subgraph's GraphQL schema.
+    pub schema: IpfsCid,
+    /// Only known once the schema has been fetched too.
+    pub entity_count: Option<usize>,
+}
+
+impl DeploymentManifest {
+    /// Parses a manifest as it is stored on IPFS, i.e. with files referenced
+    /// by IPFS links.
+    pub fn parse(yaml: &str) -> anyhow::Result<Self> {
+        let raw: RawManifest = serde_yaml::from_str(yaml).context("invalid manifest")?;
+
+        Ok(Self {
+            description: raw.description,
+            repository: raw.repository,
+            spec_version: raw.spec_version,
+            network: raw.data_sources.iter().find_map(|ds| ds.network.clone()),
+            start_block: raw
+                .data_sources
+                .iter()
+                .map(|ds| ds.source.start_block.unwrap_or(0))
+                .min(),
+            features: raw.features,
+            schema: raw.schema.file.cid()?,
+            entity_count: None,
+        })
+    }
+
+    pub fn into_model(self, sg_deployment_id: IntId) -> NewSgDeploymentManifest {
+        NewSgDeploymentManifest {
+            sg_deployment_id,
+            description: self.description,
+            repository: self.repository,
+            spec_version: Some(self.spec_version),
+            network: self.network,
+            start_block: self.start_block.and_then(|n| n.try_into().ok()),
+            features: Some(self.features),
+            entity_count: self.entity_count.and_then(|n| n.try_into().ok()),
+        }
+    }
+}
+
+/// Counts the types annotated with `@entity` in a subgraph's GraphQL schema.
+pub fn count_entity_types(schema: &str) -> usize {
+    schema
+        .lines()
+        // Strip comments.
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .map(|line| {
+            line.match_indices("@entity")
+                .filter(|(i, directive)| {
+                    // Not some other directive that starts with `@entity`.
+                    !line[i + directive.len()..]
+                        .starts_with(|c: char| c.is_alphanumeric() || c == '_')
+                })
+                .count()
+        })
+        .sum()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawManifest {
+    spec_version: String,
+    description: Option<String>,
+    repository: Option<String>,
+    #[serde(default)]
+    features: Vec<String>,
+    schema: RawSchema,
+    #[serde(default)]
+    data_sources: Vec<RawDataSource>,
+}
+
+#[derive(Deserialize)]
+struct RawSchema {
+    file: IpfsLink,
+}
+
+#[derive(Deserialize)]
+struct RawDataSource {
+    network: Option<String>,
+    #[serde(default)]
+    source: RawSource,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSource {
+    start_block: Option<u64>,
+}
+
+/// Files are linked as `{ "/": "/ipfs/<CID>" }` in manifests on IPFS.
+#[derive(Deserialize)]
+struct IpfsLink {
+    #[serde(rename = "/")]
+    path: String,
+}
+
+impl IpfsLink {
+    fn cid(&self) -> anyhow::Result<IpfsCid> {
+        let cid = self.path.trim_start_matches("/ipfs/");
+        cid.parse()
+            .with_context(|| format!("invalid IPFS link: {}", self.path))
+    }
+}
@@ -1,3 +1,4 @@
+use std::future::Future;
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 use std::sync::OnceLock;
 
@@ -9,6 +10,38 @@ use prometheus_exporter::prometheus;
 pub struct PrometheusMetrics {
     pub indexing_statuses_requests: prometheus::IntCounterVec,
     pub public_proofs_of_indexing_requests: prometheus::IntCounterVec,
+    pub indexer_query_errors: prometheus::IntCounterVec,
+    pub indexer_responses_too_large: prometheus::IntCounterVec,
+    pub last_loop_completion_timestamp_seconds: prometheus::GaugeVec,
+    pub loop_iteration_duration_seconds: prometheus::HistogramVec,
+    pub loop_iterations_with_write_pois_errors: prometheus::IntCounter,
+    pub store_write_duration_seconds: prometheus::HistogramVec,
+    pub store_write_failures: prometheus::IntCounterVec,
+    pub store_pool_connections: prometheus::IntGaugeVec,
+    pub store_pool_wait_seconds: prometheus::Histogram,
+    pub poi_cache_hits: prometheus::IntCounter,
+    pub poi_cache_misses: prometheus::IntCounter,
+    pub rate_limiter_wait_seconds: prometheus::Histogram,
+    pub indexers_deduplicated: prometheus::IntCounterVec,
+    pub discovered_indexers: prometheus::IntGaugeVec,
+    pub poi_mutations: prometheus::IntCounter,
+    pub pois_written: prometheus::IntCounterVec,
+    pub pois_written_per_iteration: prometheus::IntGauge,
+    pub deployments_over_cap: prometheus::IntCounterVec,
+    pub agrees_with_trusted: prometheus::IntGaugeVec,
+    pub blocks_behind: prometheus::IntGaugeVec,
+    pub singleton_deployments: prometheus::IntGauge,
+    pub divergence_events: prometheus::IntCounterVec,
+    pub divergence_duration_seconds: prometheus::Histogram,
+    pub block_hash_mismatches: prometheus::IntCounter,
+    pub stalled_indexers: prometheus::IntGauge,
+    pub circuit_breaker_state: prometheus::IntGaugeVec,
+    pub graphql_rejected_requests: prometheus::IntCounterVec,
+    pub graphql_requests: prometheus::IntCounterVec,
+    pub graphql_request_errors: prometheus::IntCounterVec,
+    pub graphql_request_duration_seconds: prometheus::HistogramVec,
+    pub graphql_requests_in_flight: prometheus::IntGauge,
+    pub build_info: prometheus::IntGaugeVec,
 }
 
 static METRICS: OnceLock<PrometheusMetrics> = OnceLock::new();
@@ -20,7 +53,9 @@ pub fn metrics() -> &'static PrometheusMetrics {
 }
 
 impl PrometheusMetrics {
-    fn new(registry: prometheus::Registry) -> Self {
+    /// Registers all metrics with `registry`. Everything but tests that need
+    /// metrics of their own should use [`metrics`] instead.
+    pub fn new(registry: prometheus::Registry) -> Self {
         let indexing_statuses_requests = prometheus::register_int_counter_vec_with_registry!(
             "indexing_statuses_requests",
             "Number of indexingStatuses requests",
@@ -36,12 +71,306 @@ impl PrometheusMetrics {
                 registry
             )
             .unwrap();
+        let indexer_query_errors = prometheus::register_int_counter_vec_with_registry!(
+            "graphix_indexer_query_errors_total",
+            "Number of failed queries to indexers, by query and kind of error (e.g. Timeout or Http)",
+            &["indexer", "query", "kind"],
+            registry
+        )
+        .unwrap();
+        let indexer_responses_too_large = prometheus::register_int_counter_vec_with_registry!(
+            "graphix_indexer_response_too_large_total",
+            "Number of indexer responses that were discarded for exceeding maxIndexerResponseBytes",
+            &["indexer"],
+            registry
+        )
+        .unwrap();
+        let last_loop_completion_timestamp_seconds = prometheus::register_gauge_vec_with_registry!(
+            "graphix_last_loop_completion_timestamp_seconds",
+            "Unix timestamp of the last successful iteration of each polling task",
+            &["task"],
+            registry
+        )
+        .unwrap();
+        let loop_iteration_duration_seconds = prometheus::register_histogram_vec_with_registry!(
+            "graphix_loop_iteration_duration_seconds",
+            "Duration of the iterations of each polling task, excluding the sleep between them",
+            &["task"],
+            registry
+        )
+        .unwrap();
+        let loop_iterations_with_write_pois_errors =
+            prometheus::register_int_counter_with_registry!(
+                "graphix_loop_iterations_with_write_pois_errors_total",
+                "Number of main loop iterations that failed to write PoIs to the database",
+                registry
+            )
+            .unwrap();
+        let store_write_duration_seconds = prometheus::register_histogram_vec_with_registry!(
+            "graphix_store_write_duration_seconds",
+            "Duration of database write operations",
+            &["operation"],
+            registry
+        )
+        .unwrap();
+        let store_write_failures = prometheus::register_int_counter_vec_with_registry!(
+            "graphix_store_write_failures_total",
+            "Number of failed database write operations",
+            &["operation"],
+            registry
+        )
+        .unwrap();
+        let store_pool_connections = prometheus::register_int_gauge_vec_with_registry!(
+            "graphix_store_pool_connections",
+            "Number of database connections in the pool, by state (in_use or idle), and the maximum (max)",
+            &["state"],
+            registry
+        )
+        .unwrap();
+        let store_pool_wait_seconds = prometheus::register_histogram_with_registry!(
+            "graphix_store_pool_wait_seconds",
+            "How long it took to get a connection from the database connection pool",
+            vec![0.0, 0.001, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0],
+            registry
+        )
+        .unwrap();
+        let poi_cache_hits = prometheus::register_int_counter_with_registry!(
+            "graphix_poi_cache_hits_total",
+            "Number of PoI requests served from the in-memory PoI cache",
+            registry
+        )
+        .unwrap();
+        let poi_cache_misses = prometheus::register_int_counter_with_registry!(
+            "graphix_poi_cache_misses_total",
+            "Number of PoI requests that missed the in-memory PoI cache",
+            registry
+        )
+        .unwrap();
+        let rate_limiter_wait_seconds = prometheus::register_histogram_with_registry!(
+            "graphix_rate_limiter_wait_seconds",
+            "How long requests to indexers waited for the per-host rate limiter",
+            // From zero (i.e. no wait at all) to about half a minute.
+            vec![0.0, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0],
+            registry
+        )
+        .unwrap();
+        let indexers_deduplicated = prometheus::register_int_counter_vec_with_registry!(
+            "graphix_indexers_deduplicated_total",
+            "Number of duplicate indexers that were merged into another one with the same address, by the source that won (config, network_subgraph or unknown)",
+            &["source"],
+            registry
+        )
+        .unwrap();
+        let discovered_indexers = prometheus::register_int_gauge_vec_with_registry!(
+            "graphix_discovered_indexers",
+            "Number of indexers found by each source (config, network subgraph endpoint or interceptor) before deduplication",
+            &["source"],
+            registry
+        )
+        .unwrap();
+
+        let poi_mutations = prometheus::register_int_counter_with_registry!(
+            "graphix_poi_mutations_total",
+            "Number of PoIs that changed since they were last reported for the same block",
+            registry
+        )
+        .unwrap();
+        let pois_written = prometheus::register_int_counter_vec_with_registry!(
+            "graphix_pois_written_total",
+            "Number of PoIs written to the database, by liveness (live, not_live or pinned)",
+            &["liveness"],
+            registry
+        )
+        .unwrap();
+        let pois_written_per_iteration = prometheus::register_int_gauge_with_registry!(
+            "graphix_pois_written_per_iteration",
+            "Number of PoIs written to the database during the last main loop iteration",
+            registry
+        )
+        .unwrap();
+
+        let deployments_over_cap = prometheus::register_int_counter_vec_with_registry!(
+            "graphix_deployments_over_cap_total",
+            "Number of subgraph deployments of an indexer whose PoIs weren't queried because of maxDeploymentsPerIndexer",
+            &["indexer"],
+            registry
+        )
+        .unwrap();
+
+        let agrees_with_trusted = prometheus::register_int_gauge_vec_with_registry!(
+            "graphix_agrees_with_trusted",
+            "Whether the indexer's PoIs agree with those of the trusted indexer of their network (1) or not (0)",
+            &["indexer"],
+            registry
+        )
+        .unwrap();
+
+        let blocks_behind = prometheus::register_int_gauge_vec_with_registry!(
+            "graphix_blocks_behind",
+            "Number of blocks the indexer trails the most advanced indexer of the subgraph deployment",
+            &["indexer", "deployment"],
+            registry
+        )
+        .unwrap();
+
+        let singleton_deployments = prometheus::register_int_gauge_with_registry!(
+            "graphix_singleton_deployments",
+            "Number of subgraph deployments whose PoIs were last queried from a single indexer",
+            registry
+        )
+        .unwrap();
+
+        let divergence_events = prometheus::register_int_counter_vec_with_registry!(
+            "graphix_divergence_events_total",
+            "Number of subgraph deployments on which indexers started to diverge (new) or agree again (resolved)",
+            &["kind"],
+            registry
+        )
+        .unwrap();
+
+        let divergence_duration_seconds = prometheus::register_histogram_with_registry!(
+            "graphix_divergence_duration_seconds",
+            "How long divergences between indexers persisted before they were resolved",
+            // From one minute to about a day and a half.
+            prometheus::exponential_buckets(60.0, 2.0, 12).unwrap(),
+            registry
+        )
+        .unwrap();
+
+        let block_hash_mismatches = prometheus::register_int_counter_with_registry!(
+            "graphix_block_hash_mismatches_total",
+            "Number of subgraph deployment blocks for which indexers reported different block hashes",
+            registry
+        )
+        .unwrap();
+
+        let stalled_indexers = prometheus::register_int_gauge_with_registry!(
+            "graphix_stalled_indexers",
+            "Number of indexer and subgraph deployment pairs whose latest block stopped advancing",
+            registry
+        )
+        .unwrap();
+
+        let circuit_breaker_state = prometheus::register_int_gauge_vec_with_registry!(
+            "graphix_indexer_circuit_breaker_state",
+            "Whether the circuit breaker of the indexer is in the given state (1) or not (0)",
+            &["indexer", "state"],
+            registry
+        )
+        .unwrap();
+
+        let graphql_rejected_requests = prometheus::register_int_counter_vec_with_registry!(
+            "graphix_graphql_rejected_requests_total",
+            "Number of GraphQL API requests that were rejected, by reason (e.g. rate_limit)",
+            &["reason"],
+            registry
+        )
+        .unwrap();
+
+        let graphql_requests = prometheus::register_int_counter_vec_with_registry!(
+            "graphix_graphql_requests_total",
+            "Number of GraphQL API requests, by operation name",
+            &["operation"],
+            registry
+        )
+        .unwrap();
+        let graphql_request_errors = prometheus::register_int_counter_vec_with_registry!(
+            "graphix_graphql_request_errors_total",
+            "Number of GraphQL API requests whose response has errors, by operation name",
+            &["operation"],
+            registry
+        )
+        .unwrap();
+        let graphql_request_duration_seconds = prometheus::register_histogram_vec_with_registry!(
+            "graphix_graphql_request_duration_seconds",
+            "Duration of GraphQL API requests, by operation name",
+            &["operation"],
+            registry
+        )
+        .unwrap();
+        let graphql_requests_in_flight = prometheus::register_int_gauge_with_registry!(
+            "graphix_graphql_requests_in_flight",
+            "Number of GraphQL API requests being handled",
+            registry
+        )
+        .unwrap();
+
+        let build_info = prometheus::register_int_gauge_vec_with_registry!(
+            "graphix_build_info",
+            "Always 1, labeled with the version, commit, build time and compiler of Graphix",
+            &["version", "git_sha", "build_timestamp", "rustc_version"],
+            registry
+        )
+        .unwrap();
+        build_info
+            .with_label_values(&[
+                crate::GRAPHIX_VERSION,
+                crate::GRAPHIX_GIT_SHA,
+                crate::GRAPHIX_BUILD_TIMESTAMP,
+                crate::GRAPHIX_RUSTC_VERSION,
+            ])
+            .set(1);
 
         Self {
             indexing_statuses_requests,
             public_proofs_of_indexing_requests,
+            indexer_query_errors,
+            indexer_responses_too_large,
+            last_loop_completion_timestamp_seconds,
+            loop_iteration_duration_seconds,
+            loop_iterations_with_write_pois_errors,
+            store_write_duration_seconds,
+            store_write_failures,
+            store_pool_connections,
+            store_pool_wait_seconds,
+            poi_cache_hits,
+            poi_cache_misses,
+            rate_limiter_wait_seconds,
+            indexers_deduplicated,
+            discovered_indexers,
+            poi_mutations,
+            pois_written,
+            pois_written_per_iteration,
+            deployments_over_cap,
+            agrees_with_trusted,
+            blocks_behind,
+            singleton_deployments,
+            divergence_events,
+            divergence_duration_seconds,
+            block_hash_mismatches,
+            stalled_indexers,
+            circuit_breaker_state,
+            graphql_rejected_requests,
+            graphql_requests,
+            graphql_request_errors,
+            graphql_request_duration_seconds,
+            graphql_requests_in_flight,
+            build_info,
         }
     }
+
+    /// Awaits a database write operation, recording its duration and whether
+    /// it failed under the given `operation` label.
+    pub async fn observe_store_write<T>(
+        &self,
+        operation: &str,
+        write: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        let timer = self
+            .store_write_duration_seconds
+            .with_label_values(&[operation])
+            .start_timer();
+        let result = write.await;
+        timer.observe_duration();
+
+        if result.is_err() {
+            self.store_write_failures
+                .with_label_values(&[operation])
+                .inc();
+        }
+
+        result
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,224 @@
+//! Seeded test fixtures with known PoI divergences.
+//!
+//! Unlike [`super::gen::gen_indexers`], which generates arbitrary indexers,
+//! [`TestFixtureBuilder`] generates indexers from a declarative description
+//! and knows where their PoIs diverge, so that tests can assert against the
+//! ground truth. The same seed always generates the same fixture, which makes
+//! it possible to turn a failing randomized test case into a regression test.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use graphix_common_types::PoiBytes;
+use graphix_indexer_client::{BlockPointer, IndexerClient, SubgraphDeployment};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::gen::{gen_block, gen_deployments, gen_poi_bytes};
+use super::mocks::{DeploymentDetails, MockIndexer, MockIndexerBuilder, PartialProofOfIndexing};
+
+/// Indexer `indexer` reports PoIs that differ from everyone else's for
+/// deployment `deployment` from block `from_block` onwards. Indexers and
+/// deployments are referred to by their index in the fixture.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub indexer: usize,
+    pub deployment: usize,
+    pub from_block: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct TestFixtureBuilder {
+    seed: u64,
+    indexers: usize,
+    deployments: usize,
+    blocks: u64,
+    divergences: Vec<Divergence>,
+    random_divergences: usize,
+}
+
+impl TestFixtureBuilder {
+    /// Two indexers that serve one deployment with ten blocks, and agree on
+    /// all of them.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            indexers: 2,
+            deployments: 1,
+            blocks: 10,
+            divergences: vec![],
+            random_divergences: 0,
+        }
+    }
+
+    pub fn indexers(mut self, indexers: usize) -> Self {
+        self.indexers = indexers;
+        self
+    }
+
+    /// At most as many as [`gen_deployments`] generates.
+    pub fn deployments(mut self, deployments: usize) -> Self {
+        assert!(
+            deployments <= gen_deployments().len(),
+            "too many deployments"
+        );
+        self.deployments = deployments;
+        self
+    }
+
+    pub fn blocks(mut self, blocks: u64) -> Self {
+        self.blocks = blocks;
+        self
+    }
+
+    /// Makes `indexer` diverge on `deployment` from `from_block` onwards.
+    pub fn diverge(mut self, indexer: usize, deployment: usize, from_block: u64) -> Self {
+        self.divergences.push(Divergence {
+            indexer,
+            deployment,
+            from_block,
+        });
+        self
+    }
+
+    /// Adds this many divergences of random indexers on random deployments,
+    /// chosen based on the seed. Divergences never start at the first block,
+    /// so that bisections always have an agreeing lower bound.
+    pub fn random_divergences(mut self, divergences: usize) -> Self {
+        self.random_divergences = divergences;
+        self
+    }
+
+    pub fn build(self) -> TestFixture {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        let blocks: Vec<BlockPointer> = (0..self.blocks).map(gen_block).collect();
+        let deployments: Vec<SubgraphDeployment> = gen_deployments()
+            .into_iter()
+            .take(self.deployments)
+            .collect();
+
+        let mut divergences = self.divergences;
+        if self.indexers > 0 && self.deployments > 0 && self.blocks > 1 {
+            for _ in 0..self.random_divergences {
+                divergences.push(Divergence {
+                    indexer: rng.gen_range(0..self.indexers),
+                    deployment: rng.gen_range(0..self.deployments),
+                    from_block: rng.gen_range(1..self.blocks),
+                });
+            }
+        }
+
+        // Only the earliest divergence of an indexer on a deployment matters.
+        let mut first_divergences = BTreeMap::new();
+        for divergence in &divergences {
+            let from_block = first_divergences
+                .entry((divergence.indexer, divergence.deployment))
+                .or_insert(divergence.from_block);
+            *from_block = divergence.from_block.min(*from_block);
+        }
+
+        let canonical_pois: Vec<Vec<PoiBytes>> = deployments
+            .iter()
+            .map(|_| blocks.iter().map(|_| gen_poi_bytes(&mut rng)).collect())
+            .collect();
+
+        let indexers = (0..self.indexers)
+            .map(|indexer| {
+                let deployment_details = deployments
+                    .iter()
+                    .enumerate()
+                    .map(|(deployment_index, deployment)| {
+                        let from_block = first_divergences.get(&(indexer, deployment_index));
+                        let pois = blocks
+                            .iter()
+                            .zip(&canonical_pois[deployment_index])
+                            .map(|(block, canonical_poi)| PartialProofOfIndexing {
+                                block: block.clone(),
+                                proof_of_indexing: match from_block {
+                                    Some(from_block) if block.number >= *from_block => {
+                                        gen_divergent_poi_bytes(&mut rng, canonical_poi)
+                                    }
+                                    _ => *canonical_poi,
+                                },
+                            })
+                            .collect();
+
+                        DeploymentDetails {
+                            deployment: deployment.clone(),
+                            network: "mainnet".into(),
+                            latest_block: blocks.last().cloned().unwrap_or_else(|| gen_block(0)),
+                            canonical_pois: pois,
+                            earliest_block_num: 0,
+                        }
+                    })
+                    .collect();
+
+                Arc::new(
+                    MockIndexerBuilder::new(format!("indexer-{}", indexer))
+                        .deployments(deployment_details)
+                        .build(),
+                )
+            })
+            .collect();
+
+        TestFixture {
+            seed: self.seed,
+            indexers,
+            deployments,
+            blocks,
+            divergences: first_divergences,
+        }
+    }
+}
+
+fn gen_divergent_poi_bytes(rng: &mut StdRng, canonical_poi: &PoiBytes) -> PoiBytes {
+    loop {
+        let poi = gen_poi_bytes(rng);
+        if poi != *canonical_poi {
+            return poi;
+        }
+    }
+}
+
+/// Mock indexers generated by [`TestFixtureBuilder`], along with the expected
+/// divergences between them.
+#[derive(Clone, Debug)]
+pub struct TestFixture {
+    pub seed: u64,
+    pub indexers: Vec<Arc<MockIndexer>>,
+    pub deployments: Vec<SubgraphDeployment>,
+    pub blocks: Vec<BlockPointer>,
+    /// The first diverging block of every indexer and deployment (by index)
+    /// that diverges.
+    divergences: BTreeMap<(usize, usize), u64>,
+}
+
+impl TestFixture {
+    pub fn indexer_clients(&self) -> Vec<Arc<dyn IndexerClient>> {
+        self.indexers
+            .iter()
+            .map(|indexer| indexer.clone() as Arc<dyn IndexerClient>)
+            .collect()
+    }
+
+    /// The block from which `indexer1` and `indexer2` report different PoIs
+    /// for `deployment`, if any. Diverging indexers never agree with each
+    /// other after they diverge, so this is the earlier of their first
+    /// diverging blocks.
+    pub fn first_diverging_block(
+        &self,
+        indexer1: usize,
+        indexer2: usize,
+        deployment: usize,
+    ) -> Option<u64> {
+        if indexer1 == indexer2 {
+            return None;
+        }
+
+        [indexer1, indexer2]
+            .iter()
+            .filter_map(|indexer| self.divergences.get(&(*indexer, deployment)).copied())
+            .min()
+    }
+}
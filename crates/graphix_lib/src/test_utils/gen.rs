@@ -1,13 +1,15 @@
 use std::iter::repeat_with;
 use std::sync::Arc;
+use std::time::Duration;
 
 use graphix_common_types::{BlockHash, PoiBytes};
 use graphix_indexer_client::{BlockPointer, IndexerClient, SubgraphDeployment};
 use rand::distributions::Alphanumeric;
+use rand::rngs::StdRng;
 use rand::seq::IteratorRandom;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 
-use super::mocks::{DeploymentDetails, MockIndexer, PartialProofOfIndexing};
+use super::mocks::{DeploymentDetails, MockFaults, MockIndexerBuilder, PartialProofOfIndexing};
 
 pub fn gen_deployments() -> Vec<SubgraphDeployment> {
     vec![
@@ -21,18 +23,19 @@ pub fn gen_deployments() -> Vec<SubgraphDeployment> {
     .collect()
 }
 
+/// Generates the block with the given number, whose hash is derived from the
+/// number.
+pub fn gen_block(number: u64) -> BlockPointer {
+    let mut hash = [0u8; 32];
+    hash[24..32].clone_from_slice(&number.to_be_bytes());
+    BlockPointer {
+        number,
+        hash: Some(BlockHash::from(hash.to_vec())),
+    }
+}
+
 pub fn gen_blocks() -> Vec<BlockPointer> {
-    let block_hash = |n: u64| -> BlockHash {
-        let mut buf = [0u8; 32];
-        buf[24..32].clone_from_slice(&n.to_be_bytes());
-        buf.to_vec().into()
-    };
-    (0..10)
-        .map(|number| BlockPointer {
-            number,
-            hash: Some(block_hash(number)),
-        })
-        .collect()
+    (0..10).map(gen_block).collect()
 }
 
 pub fn gen_poi_bytes<R>(rng: &mut R) -> PoiBytes
@@ -58,7 +61,56 @@ where
         .collect()
 }
 
-pub fn gen_indexers<R>(mut rng: &mut R, max_indexers: usize) -> Vec<Arc<dyn IndexerClient>>
+/// How often [`gen_indexers`] makes generated indexers misbehave. All
+/// probabilities are in `0.0..=1.0`; the default generates well-behaved
+/// indexers only.
+#[derive(Clone, Debug, Default)]
+pub struct FaultRates {
+    /// Probability of an indexer responding with artificial latency of up to
+    /// `max_latency`.
+    pub latency: f64,
+    pub max_latency: Duration,
+    /// Probability of an indexer failing PoI requests for any one of its
+    /// deployments.
+    pub failing_poi_deployment: f64,
+    /// Probability of an indexer returning corrupted PoIs.
+    pub corrupt_pois: f64,
+    /// Probability of an indexer returning fewer PoIs than requested.
+    pub partial_pois: f64,
+}
+
+impl FaultRates {
+    fn gen_faults<R>(&self, rng: &mut R, deployments: &[SubgraphDeployment]) -> MockFaults
+    where
+        R: Rng,
+    {
+        // Only consume randomness for faults that are enabled, so that
+        // seeded indexers without faults stay the same.
+        let mut gen_bool = |p: f64| p > 0.0 && rng.gen_bool(p);
+
+        let latency = gen_bool(self.latency).then(|| (Duration::ZERO, self.max_latency));
+        let failing_poi_deployments = deployments
+            .iter()
+            .filter(|_| gen_bool(self.failing_poi_deployment))
+            .cloned()
+            .collect();
+        let corrupt_pois = gen_bool(self.corrupt_pois);
+        let partial_pois = gen_bool(self.partial_pois);
+
+        MockFaults {
+            latency,
+            failing_poi_deployments,
+            corrupt_pois,
+            max_pois_per_call: partial_pois.then(|| rng.gen_range(0..deployments.len().max(1))),
+        }
+    }
+}
+
+pub fn gen_indexers<R>(
+    mut rng: &mut R,
+    max_indexers: usize,
+    fault_rates: &FaultRates,
+) -> Vec<Arc<dyn IndexerClient>>
 where
     R: Rng,
 {
@@ -94,13 +146,23 @@ where
                 earliest_block_num: blocks[0].number,
             })
             .collect();
+        let fail_indexing_statuses = rng.gen_bool(0.1);
+        let faults = fault_rates.gen_faults(&mut rng, &random_deployments);
 
-        Arc::new(MockIndexer {
-            name: id,
-            deployment_details,
-            fail_indexing_statuses: rng.gen_bool(0.1),
-        }) as Arc<dyn IndexerClient>
+        MockIndexerBuilder::new(id)
+            .deployments(deployment_details)
+            .fail_indexing_statuses(fail_indexing_statuses)
+            .faults(faults)
+            .client()
     })
     .take(number_of_indexers)
     .collect()
 }
+
+/// Like [`gen_indexers`], but deterministically seeded. Calling this again
+/// with the same `seed` generates the exact same indexers, which makes it
+/// possible to replay failing randomized tests.
+pub fn gen_indexers_seeded(seed: u64, max_indexers: usize) -> Vec<Arc<dyn IndexerClient>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    gen_indexers(&mut rng, max_indexers, &FaultRates::default())
+}
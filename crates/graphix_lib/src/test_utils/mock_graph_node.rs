@@ -0,0 +1,220 @@
+//! An HTTP server that speaks enough of `graph-node`'s index-node GraphQL API
+//! to test [`RealIndexer`] end to end, including the (de)serialization of
+//! requests and responses that [`MockIndexer`] alone skips.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use graphix_indexer_client::{
+    IndexerClient, IndexingStatus, PoiRequest, ProofOfIndexing, RealIndexer, SubgraphDeployment,
+};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+use super::mocks::MockIndexer;
+use crate::metrics;
+
+/// Ways in which a [`MockGraphNode`] misbehaves at the HTTP level, on top of
+/// the [`MockIndexer`]'s own faults. The default is a well-behaved server.
+#[derive(Clone, Debug, Default)]
+pub struct HttpFaults {
+    /// Delays every response by this much.
+    pub latency: Option<Duration>,
+    /// Responds to every request with HTTP 500.
+    pub internal_server_error: bool,
+    /// Responds to every request with a truncated JSON body.
+    pub malformed_json: bool,
+    /// Pads every response with this many bytes of leading whitespace,
+    /// streamed without a `Content-Length` header, so that clients can only
+    /// tell the size of the body by reading it.
+    pub padding_bytes: Option<usize>,
+    /// Repeats every indexing status and PoI in responses this many more
+    /// times, regardless of what was requested.
+    pub repeated_results: usize,
+}
+
+/// Serves the behavior of a [`MockIndexer`] over HTTP, i.e. the
+/// `indexingStatuses`, `publicProofsOfIndexing` and `version` queries, on an
+/// ephemeral port.
+#[derive(Debug)]
+pub struct MockGraphNode {
+    indexer: Arc<MockIndexer>,
+    address: SocketAddr,
+}
+
+impl MockGraphNode {
+    /// Starts the server, which runs in the background until the Tokio
+    /// runtime shuts down.
+    pub async fn start(indexer: MockIndexer, faults: HttpFaults) -> anyhow::Result<Self> {
+        let indexer = Arc::new(indexer);
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+
+        let router = Router::new()
+            .route("/status", post(handle_request))
+            .with_state((indexer.clone(), faults));
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        Ok(Self { indexer, address })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.address.port()
+    }
+
+    /// The URL of the index-node GraphQL endpoint.
+    pub fn endpoint(&self) -> String {
+        format!("http://{}/status", self.address)
+    }
+
+    /// A real indexer client for this server, with the same name and address
+    /// as the mock indexer.
+    pub fn indexer_client(&self) -> Arc<RealIndexer> {
+        Arc::new(RealIndexer::new(
+            self.indexer.name().map(|name| name.into_owned()),
+            self.indexer.address(),
+            self.endpoint(),
+            metrics().public_proofs_of_indexing_requests.clone(),
+        ))
+    }
+}
+
+async fn handle_request(
+    State((indexer, faults)): State<(Arc<MockIndexer>, HttpFaults)>,
+    Json(request): Json<Value>,
+) -> Response {
+    if let Some(latency) = faults.latency {
+        tokio::time::sleep(latency).await;
+    }
+
+    if faults.internal_server_error {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error").into_response();
+    }
+    if faults.malformed_json {
+        return (
+            [(header::CONTENT_TYPE, "application/json")],
+            r#"{"data": {"#,
+        )
+            .into_response();
+    }
+
+    let body = match graphql_data(indexer, &request, faults.repeated_results).await {
+        Ok(data) => json!({ "data": data }),
+        Err(err) => json!({ "errors": [{ "message": err.to_string() }] }),
+    };
+    match faults.padding_bytes {
+        Some(padding_bytes) => {
+            let mut chunks: Vec<Vec<u8>> = vec![b' '; padding_bytes]
+                .chunks(1024)
+                .map(<[u8]>::to_vec)
+                .collect();
+            chunks.push(body.to_string().into_bytes());
+            let stream = futures::stream::iter(chunks.into_iter().map(Ok::<_, Infallible>));
+            (
+                [(header::CONTENT_TYPE, "application/json")],
+                Body::from_stream(stream),
+            )
+                .into_response()
+        }
+        None => Json(body).into_response(),
+    }
+}
+
+/// Answers the queries that [`RealIndexer`] sends, by their operation name.
+async fn graphql_data(
+    indexer: Arc<MockIndexer>,
+    request: &Value,
+    repeated_results: usize,
+) -> anyhow::Result<Value> {
+    let repeat = |results: Vec<Value>| -> Vec<Value> {
+        results
+            .iter()
+            .flat_map(|result| std::iter::repeat(result).take(repeated_results + 1))
+            .cloned()
+            .collect()
+    };
+
+    match request["operationName"].as_str() {
+        Some("Typename") => Ok(json!({ "__typename": "Query" })),
+        Some("IndexingStatuses") => {
+            let mut statuses = indexer.indexing_statuses().await?;
+            if let Some(subgraphs) = request["variables"]["subgraphs"].as_array() {
+                statuses.retain(|status| subgraphs.contains(&json!(status.deployment.as_str())));
+            }
+            let statuses: Vec<Value> = statuses.iter().map(indexing_status_json).collect();
+            Ok(json!({ "indexingStatuses": repeat(statuses) }))
+        }
+        Some("ProofsOfIndexing") => {
+            let requests = request["variables"]["requests"]
+                .as_array()
+                .context("missing PoI requests")?
+                .iter()
+                .map(poi_request)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let pois = indexer.proofs_of_indexing(requests).await;
+            let pois: Vec<Value> = pois.iter().map(poi_json).collect();
+            Ok(json!({ "publicProofsOfIndexing": repeat(pois) }))
+        }
+        Some("IndexerVersion") => {
+            let version = indexer.version().await?;
+            Ok(json!({
+                "version": { "version": version.version, "commit": version.commit }
+            }))
+        }
+        // Like `graph-node` versions without a `Feature` enum.
+        Some("SupportedFeatures") => Ok(json!({ "__type": null })),
+        operation => Err(anyhow!("unsupported operation {:?}", operation)),
+    }
+}
+
+fn poi_request(request: &Value) -> anyhow::Result<PoiRequest> {
+    Ok(PoiRequest {
+        deployment: SubgraphDeployment(
+            request["deployment"]
+                .as_str()
+                .context("missing deployment")?
+                .to_string(),
+        ),
+        block_number: request["blockNumber"]
+            .as_str()
+            .context("missing block number")?
+            .parse()?,
+    })
+}
+
+fn indexing_status_json(status: &IndexingStatus) -> Value {
+    json!({
+        "subgraph": status.deployment.as_str(),
+        "chains": [{
+            "__typename": "EthereumIndexingStatus",
+            "network": status.network,
+            "latestBlock": {
+                "number": status.latest_block.number.to_string(),
+                "hash": status.latest_block.hash.as_ref().map(ToString::to_string),
+            },
+            "earliestBlock": {
+                "number": status.earliest_block_num.to_string(),
+            },
+        }],
+    })
+}
+
+fn poi_json(poi: &ProofOfIndexing) -> Value {
+    json!({
+        "deployment": poi.deployment.as_str(),
+        "block": {
+            "number": poi.block.number.to_string(),
+            "hash": poi.block.hash.as_ref().map(ToString::to_string),
+        },
+        "proofOfIndexing": poi.proof_of_indexing.to_string(),
+    })
+}
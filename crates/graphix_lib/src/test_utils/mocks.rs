@@ -1,13 +1,14 @@
 use std::borrow::Cow;
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::anyhow;
 use async_trait::async_trait;
 use graphix_common_types::{GraphNodeCollectedVersion, IndexerAddress, PoiBytes};
 use graphix_indexer_client::{
-    BlockPointer, CachedEthereumCall, EntityChanges, IndexerClient, IndexingStatus, PoiRequest,
-    ProofOfIndexing, SubgraphDeployment,
+    BlockPointer, CachedEthereumCall, EntityChanges, IndexerClient, IndexerError, IndexingStatus,
+    PoiRequest, ProofOfIndexing, SubgraphDeployment,
 };
+use rand::Rng;
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct DeploymentDetails {
@@ -23,6 +24,83 @@ pub struct MockIndexer {
     pub name: String,
     pub deployment_details: Vec<DeploymentDetails>,
     pub fail_indexing_statuses: bool,
+    pub faults: MockFaults,
+}
+
+/// Ways in which a [`MockIndexer`] misbehaves, to exercise error handling
+/// without a live indexer. The default is a well-behaved indexer.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MockFaults {
+    /// Every call takes a random amount of time between these bounds.
+    pub latency: Option<(Duration, Duration)>,
+    /// PoI requests for these deployments fail, i.e. return no PoIs.
+    pub failing_poi_deployments: Vec<SubgraphDeployment>,
+    /// Returns PoIs that differ from the canonical ones in every byte. PoIs
+    /// that are too short can't be represented by [`PoiBytes`]; those are
+    /// rejected when parsing indexer responses already.
+    pub corrupt_pois: bool,
+    /// Returns at most this many PoIs per call, however many are requested.
+    pub max_pois_per_call: Option<usize>,
+}
+
+/// Builds [`MockIndexer`]s, so that tests only spell out what they care
+/// about.
+#[derive(Clone, Debug)]
+pub struct MockIndexerBuilder {
+    indexer: MockIndexer,
+}
+
+impl MockIndexerBuilder {
+    /// A well-behaved indexer that doesn't index any deployments.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            indexer: MockIndexer {
+                name: name.into(),
+                deployment_details: vec![],
+                fail_indexing_statuses: false,
+                faults: MockFaults::default(),
+            },
+        }
+    }
+
+    pub fn deployment(mut self, details: DeploymentDetails) -> Self {
+        self.indexer.deployment_details.push(details);
+        self
+    }
+
+    pub fn deployments(mut self, details: impl IntoIterator<Item = DeploymentDetails>) -> Self {
+        self.indexer.deployment_details.extend(details);
+        self
+    }
+
+    pub fn fail_indexing_statuses(mut self, fail: bool) -> Self {
+        self.indexer.fail_indexing_statuses = fail;
+        self
+    }
+
+    pub fn faults(mut self, faults: MockFaults) -> Self {
+        self.indexer.faults = faults;
+        self
+    }
+
+    pub fn build(self) -> MockIndexer {
+        self.indexer
+    }
+
+    /// Like [`Self::build`], but ready to be passed wherever indexers are
+    /// expected.
+    pub fn client(self) -> Arc<dyn IndexerClient> {
+        Arc::new(self.build())
+    }
+}
+
+impl MockIndexer {
+    async fn simulate_latency(&self) {
+        if let Some((min, max)) = self.faults.latency {
+            let latency = rand::thread_rng().gen_range(min..=max.max(min));
+            tokio::time::sleep(latency).await;
+        }
+    }
 }
 
 #[async_trait]
@@ -37,9 +115,13 @@ impl IndexerClient for MockIndexer {
         <[u8; 20]>::try_from(addr).unwrap().into()
     }
 
-    async fn indexing_statuses(self: Arc<Self>) -> Result<Vec<IndexingStatus>, anyhow::Error> {
+    async fn indexing_statuses(self: Arc<Self>) -> Result<Vec<IndexingStatus>, IndexerError> {
+        self.simulate_latency().await;
+
         if self.fail_indexing_statuses {
-            Err(anyhow!("boo"))
+            Err(IndexerError::GraphQl {
+                errors: vec!["boo".to_string()],
+            })
         } else {
             Ok(self
                 .deployment_details
@@ -56,7 +138,7 @@ impl IndexerClient for MockIndexer {
         }
     }
 
-    async fn ping(self: Arc<Self>) -> anyhow::Result<()> {
+    async fn ping(self: Arc<Self>) -> Result<(), IndexerError> {
         Ok(())
     }
 
@@ -64,9 +146,16 @@ impl IndexerClient for MockIndexer {
         self: Arc<Self>,
         requests: Vec<PoiRequest>,
     ) -> Vec<ProofOfIndexing> {
-        // TODO: Introduce discrepancies from canonical POIs into the mix
+        self.simulate_latency().await;
+
         requests
             .into_iter()
+            .filter(|request| {
+                !self
+                    .faults
+                    .failing_poi_deployments
+                    .contains(&request.deployment)
+            })
             .filter_map(|request| {
                 self.deployment_details
                     .iter()
@@ -80,28 +169,38 @@ impl IndexerClient for MockIndexer {
                     .find(|poi| poi.block.number.eq(&request.block_number))
                     .map(|poi| (detail, poi))
             })
-            .map(|(deployment_detail, poi)| ProofOfIndexing {
-                indexer: self.clone(),
-                deployment: deployment_detail.deployment.clone(),
-                block: poi.block.clone(),
-                proof_of_indexing: poi.proof_of_indexing,
+            .map(|(deployment_detail, poi)| {
+                let proof_of_indexing = if self.faults.corrupt_pois {
+                    poi.proof_of_indexing.0.map(|byte| !byte).into()
+                } else {
+                    poi.proof_of_indexing
+                };
+
+                ProofOfIndexing {
+                    indexer: self.clone(),
+                    deployment: deployment_detail.deployment.clone(),
+                    block: poi.block.clone(),
+                    proof_of_indexing,
+                }
             })
+            .take(self.faults.max_pois_per_call.unwrap_or(usize::MAX))
             .collect::<Vec<_>>()
     }
 
-    async fn version(self: Arc<Self>) -> anyhow::Result<GraphNodeCollectedVersion> {
+    async fn version(self: Arc<Self>) -> Result<GraphNodeCollectedVersion, IndexerError> {
         Ok(GraphNodeCollectedVersion {
             version: Some("0.0.0".to_string()),
             commit: Some("no-commit-hash".to_string()),
             error_response: None,
             collected_at: chrono::Utc::now().naive_utc(),
+            supported_features: Some(vec![]),
         })
     }
 
     async fn subgraph_api_versions(
         self: Arc<Self>,
         _subgraph_id: &str,
-    ) -> anyhow::Result<Vec<String>> {
+    ) -> Result<Vec<String>, IndexerError> {
         Ok(vec![])
     }
 
@@ -109,7 +208,7 @@ impl IndexerClient for MockIndexer {
         self: Arc<Self>,
         _network: &str,
         _block_hash: &[u8],
-    ) -> anyhow::Result<Vec<CachedEthereumCall>> {
+    ) -> Result<Vec<CachedEthereumCall>, IndexerError> {
         Ok(vec![])
     }
 
@@ -117,7 +216,7 @@ impl IndexerClient for MockIndexer {
         self: Arc<Self>,
         _network: &str,
         _block_hash: &[u8],
-    ) -> anyhow::Result<Option<serde_json::Value>> {
+    ) -> Result<Option<serde_json::Value>, IndexerError> {
         Ok(None)
     }
 
@@ -125,7 +224,7 @@ impl IndexerClient for MockIndexer {
         self: Arc<Self>,
         _subgraph_id: &str,
         _block_number: u64,
-    ) -> anyhow::Result<EntityChanges> {
+    ) -> Result<EntityChanges, IndexerError> {
         Ok(EntityChanges {
             updates: Default::default(),
             deletions: Default::default(),
@@ -1,5 +1,8 @@
+pub mod fixture;
 pub mod gen;
+pub mod mock_graph_node;
 pub mod mocks;
+pub mod store;
 
 use std::env;
 use std::sync::Arc;
@@ -38,6 +41,8 @@ pub fn test_indexer_from_url(url: impl Into<String>) -> Arc<impl IndexerClient>
         name: Some(url.host().unwrap().to_string()),
         address,
         index_node_endpoint: url.join("status").unwrap(),
+        http_proxy: None,
+        accept_invalid_certs: false,
     };
     Arc::new(RealIndexer::new(
         conf.name,
@@ -57,6 +62,16 @@ pub fn test_deployment_id(deployment: impl Into<String>) -> SubgraphDeployment {
     SubgraphDeployment(deployment.into())
 }
 
+/// Derives a seed from [`TEST_SEED`] and `seed_extra` (e.g. the iteration
+/// number of a randomized test) and prints it, so that a failing test case can
+/// be replayed with the exact same data e.g. via
+/// [`gen::gen_indexers_seeded`].
+pub fn test_seed(seed_extra: u64) -> u64 {
+    let seed = TEST_SEED.wrapping_add(seed_extra);
+    println!("Generating test data with seed {}", seed);
+    seed
+}
+
 pub fn fast_rng(seed_extra: u64) -> SmallRng {
-    SmallRng::seed_from_u64(*TEST_SEED + seed_extra)
+    SmallRng::seed_from_u64(test_seed(seed_extra))
 }
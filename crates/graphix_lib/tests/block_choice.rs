@@ -0,0 +1,73 @@
+use graphix_indexer_client::{BlockPointer, IndexingStatus};
+use graphix_lib::block_choice::{BlockChoicePolicy, ReferenceBlock, TieBreak};
+use graphix_lib::test_utils::gen::{gen_block, gen_deployments};
+use graphix_lib::test_utils::mocks::MockIndexerBuilder;
+use quickcheck_macros::quickcheck;
+
+#[quickcheck]
+fn chosen_block_is_never_ahead_of_all_indexers(
+    statuses: Vec<IndexingStatus>,
+    reference_block: u64,
+) -> bool {
+    let policies = [
+        BlockChoicePolicy::Earliest,
+        BlockChoicePolicy::MaxSyncedBlocks {
+            tie_break: TieBreak::Highest,
+        },
+        BlockChoicePolicy::MaxSyncedBlocks {
+            tie_break: TieBreak::Lowest,
+        },
+        BlockChoicePolicy::MaxSyncedBlocks {
+            tie_break: TieBreak::MostRecentHash,
+        },
+        BlockChoicePolicy::ReferenceBlock(ReferenceBlock::Fixed(reference_block)),
+    ];
+    let latest_block = statuses
+        .iter()
+        .map(|status| status.latest_block.number)
+        .max();
+
+    policies.iter().all(|policy| {
+        match (policy.choose_block(statuses.iter()), latest_block) {
+            (None, _) => true,
+            (Some(block), Some(latest_block)) => block <= latest_block,
+            // No indexers, no block.
+            (Some(_), None) => false,
+        }
+    })
+}
+
+#[test]
+fn ties_are_broken_by_the_tie_break() {
+    let deployment = gen_deployments()[0].clone();
+    let status = |name: &str, number: u64, with_hash: bool| IndexingStatus {
+        indexer: MockIndexerBuilder::new(name).client(),
+        deployment: deployment.clone(),
+        network: "mainnet".into(),
+        latest_block: match with_hash {
+            true => gen_block(number),
+            false => BlockPointer { number, hash: None },
+        },
+        earliest_block_num: 0,
+    };
+    let choose = |tie_break, statuses: &[IndexingStatus]| {
+        BlockChoicePolicy::MaxSyncedBlocks { tie_break }.choose_block(statuses.iter())
+    };
+
+    // Both blocks add up to 200 synced blocks, and the order of the statuses
+    // doesn't matter.
+    let statuses = [status("a", 100, true), status("b", 200, false)];
+    let reversed = [statuses[1].clone(), statuses[0].clone()];
+    for statuses in [&statuses[..], &reversed[..]] {
+        assert_eq!(choose(TieBreak::Highest, statuses), Some(200));
+        assert_eq!(choose(TieBreak::Lowest, statuses), Some(100));
+        assert_eq!(choose(TieBreak::MostRecentHash, statuses), Some(100));
+    }
+
+    let statuses = [status("a", 100, false), status("b", 200, false)];
+    assert_eq!(choose(TieBreak::MostRecentHash, &statuses[..]), Some(200));
+    assert_eq!(
+        BlockChoicePolicy::default().choose_block(statuses.iter()),
+        Some(200)
+    );
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use graphix_indexer_client::{IndexerClient, IndexerError, QueryOutcome};
+use graphix_lib::config::CircuitBreakerConfig;
+use graphix_lib::indexing_loop::{CircuitBreakerState, CircuitBreakers, QuerySummary};
+use graphix_lib::test_utils::mocks::MockIndexerBuilder;
+
+fn summary(outcomes: &[(&Arc<dyn IndexerClient>, bool)]) -> QuerySummary {
+    QuerySummary {
+        query_name: "indexingStatuses",
+        outcomes: outcomes
+            .iter()
+            .map(|(indexer, success)| {
+                let outcome = if *success {
+                    QueryOutcome::Success { results: 1 }
+                } else {
+                    QueryOutcome::Failure {
+                        error: "connection refused".to_string(),
+                        kind: None,
+                    }
+                };
+                ((*indexer).clone(), outcome)
+            })
+            .collect::<HashMap<_, _>>(),
+    }
+}
+
+#[test]
+fn failing_indexers_are_skipped_with_exponential_backoff() {
+    let indexer = |name: &str| MockIndexerBuilder::new(name).client();
+    let (a, b) = (indexer("a"), indexer("b"));
+    let indexers = vec![a.clone(), b.clone()];
+    let config = CircuitBreakerConfig {
+        enabled: true,
+        failure_threshold: 2,
+        initial_backoff_seconds: 60,
+        max_backoff_seconds: 150,
+    };
+    let secs = Duration::from_secs;
+
+    let mut breakers = CircuitBreakers::new();
+    let start = Instant::now();
+    let allowed_addresses = |breakers: &mut CircuitBreakers, now: Instant| {
+        breakers
+            .allowed_indexers(&indexers, &config, now)
+            .iter()
+            .map(|indexer| indexer.address())
+            .collect::<Vec<_>>()
+    };
+
+    // `a` fails once, which isn't enough to open its breaker.
+    breakers.record(&summary(&[(&a, false), (&b, true)]), &config, start);
+    assert_eq!(allowed_addresses(&mut breakers, start).len(), 2);
+    let connectivity = breakers.connectivity(start);
+    assert_eq!(connectivity.len(), 1);
+    assert_eq!(connectivity[0].state, CircuitBreakerState::Closed);
+    assert_eq!(connectivity[0].consecutive_failures, 1);
+
+    // After the second failure in a row, `a` is skipped for the initial backoff.
+    breakers.record(&summary(&[(&a, false), (&b, true)]), &config, start);
+    assert_eq!(
+        breakers.connectivity(start)[0].state,
+        CircuitBreakerState::Open
+    );
+    assert!(breakers.connectivity(start)[0].retry_at.is_some());
+    assert_eq!(
+        allowed_addresses(&mut breakers, start + secs(59)),
+        vec![b.address()]
+    );
+
+    // Once the backoff is over, `a` is probed, and fails again: it's skipped
+    // for twice as long.
+    let now = start + secs(60);
+    assert_eq!(allowed_addresses(&mut breakers, now).len(), 2);
+    assert_eq!(
+        breakers.connectivity(now)[0].state,
+        CircuitBreakerState::HalfOpen
+    );
+    breakers.record(&summary(&[(&a, false), (&b, true)]), &config, now);
+    assert_eq!(
+        breakers.connectivity(now)[0].state,
+        CircuitBreakerState::Open
+    );
+    assert_eq!(breakers.connectivity(now)[0].consecutive_failures, 3);
+    assert_eq!(allowed_addresses(&mut breakers, now + secs(119)).len(), 1);
+    assert_eq!(allowed_addresses(&mut breakers, now + secs(120)).len(), 2);
+
+    // The backoff doesn't grow beyond the maximum.
+    let now = now + secs(120);
+    breakers.record(&summary(&[(&a, false), (&b, true)]), &config, now);
+    assert_eq!(allowed_addresses(&mut breakers, now + secs(149)).len(), 1);
+    assert_eq!(allowed_addresses(&mut breakers, now + secs(150)).len(), 2);
+
+    // A successful probe closes the breaker.
+    let now = now + secs(150);
+    breakers.record(&summary(&[(&a, true), (&b, true)]), &config, now);
+    assert!(breakers.connectivity(now).is_empty());
+    assert_eq!(allowed_addresses(&mut breakers, now).len(), 2);
+}
+
+#[test]
+fn disabled_circuit_breakers_never_skip_indexers() {
+    let a = MockIndexerBuilder::new("a")
+        .fail_indexing_statuses(true)
+        .client();
+    let config = CircuitBreakerConfig {
+        failure_threshold: 1,
+        ..Default::default()
+    };
+
+    let mut breakers = CircuitBreakers::new();
+    let now = Instant::now();
+    for _ in 0..10 {
+        assert_eq!(
+            breakers.allowed_indexers(&[a.clone()], &config, now).len(),
+            1
+        );
+        breakers.record(&summary(&[(&a, false)]), &config, now);
+    }
+    assert!(breakers.connectivity(now).is_empty());
+}
+
+#[test]
+fn oversized_responses_open_the_breaker_right_away() {
+    let a = MockIndexerBuilder::new("a").client();
+    let config = CircuitBreakerConfig {
+        enabled: true,
+        failure_threshold: 5,
+        initial_backoff_seconds: 60,
+        max_backoff_seconds: 600,
+    };
+    let summary = QuerySummary {
+        query_name: "indexingStatuses",
+        outcomes: HashMap::from([(
+            a.clone(),
+            QueryOutcome::from(&IndexerError::ResponseTooLarge { limit: 1024 }),
+        )]),
+    };
+
+    let mut breakers = CircuitBreakers::new();
+    let now = Instant::now();
+    breakers.record(&summary, &config, now);
+    let connectivity = breakers.connectivity(now);
+    assert_eq!(connectivity[0].state, CircuitBreakerState::Open);
+    assert_eq!(connectivity[0].consecutive_failures, 1);
+    assert!(breakers.allowed_indexers(&[a], &config, now).is_empty());
+}
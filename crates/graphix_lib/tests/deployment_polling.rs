@@ -0,0 +1,134 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use graphix_indexer_client::{IndexerId, IndexingStatus};
+use graphix_lib::indexing_loop::{
+    cap_deployments_per_indexer, query_indexing_statuses, tracked_indexing_statuses,
+    DeploymentPollingSchedule,
+};
+use graphix_lib::metrics;
+use graphix_lib::test_utils::gen::{gen_deployments, gen_indexers_seeded};
+use graphix_lib::test_utils::test_seed;
+
+#[tokio::test]
+async fn deployments_are_polled_at_their_own_pace() {
+    let indexers = gen_indexers_seeded(test_seed(0), 10);
+    let (indexing_statuses, _) =
+        query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+
+    let slow_deployment = gen_deployments().remove(0);
+    let mut schedule =
+        DeploymentPollingSchedule::new(&HashMap::from([(slow_deployment.0.clone(), 60)]));
+    let is_slow = |status: &IndexingStatus| status.deployment == slow_deployment;
+    let slow_statuses = indexing_statuses
+        .iter()
+        .filter(|status| is_slow(status))
+        .count();
+
+    let start = Instant::now();
+    let due = schedule.due_indexing_statuses(&indexing_statuses, start);
+    assert_eq!(due.len(), indexing_statuses.len());
+
+    // Too soon for the slow deployment.
+    let due = schedule.due_indexing_statuses(&indexing_statuses, start + Duration::from_secs(30));
+    assert_eq!(due.len(), indexing_statuses.len() - slow_statuses);
+    assert!(!due.iter().any(is_slow));
+
+    let due = schedule.due_indexing_statuses(&indexing_statuses, start + Duration::from_secs(60));
+    assert_eq!(due.len(), indexing_statuses.len());
+}
+
+#[tokio::test]
+async fn only_tracked_deployments_are_polled() {
+    let indexers = gen_indexers_seeded(test_seed(0), 10);
+    let (indexing_statuses, _) =
+        query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+
+    let all = tracked_indexing_statuses(indexing_statuses.clone(), None);
+    assert_eq!(all, indexing_statuses);
+
+    let tracked_deployment = gen_deployments().remove(0);
+    let tracked = tracked_indexing_statuses(
+        indexing_statuses.clone(),
+        Some(&HashSet::from([tracked_deployment.clone()])),
+    );
+    assert_eq!(
+        tracked.len(),
+        indexing_statuses
+            .iter()
+            .filter(|status| status.deployment == tracked_deployment)
+            .count()
+    );
+    assert!(tracked
+        .iter()
+        .all(|status| status.deployment == tracked_deployment));
+
+    let none = tracked_indexing_statuses(indexing_statuses, Some(&HashSet::new()));
+    assert!(none.is_empty());
+}
+
+#[tokio::test]
+async fn deployments_per_indexer_are_capped() {
+    let indexers = gen_indexers_seeded(test_seed(0), 10);
+    let (indexing_statuses, _) =
+        query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+    let n_indexers = |statuses: &[IndexingStatus], status: &IndexingStatus| {
+        statuses
+            .iter()
+            .filter(|other| other.deployment == status.deployment)
+            .count()
+    };
+
+    let uncapped = cap_deployments_per_indexer(
+        indexing_statuses.clone(),
+        usize::MAX,
+        &[],
+        &HashMap::new(),
+        metrics(),
+    );
+    assert_eq!(uncapped, indexing_statuses);
+
+    let capped = cap_deployments_per_indexer(
+        indexing_statuses.clone(),
+        1,
+        &[],
+        &HashMap::new(),
+        metrics(),
+    );
+    for indexer in &indexers {
+        let served: Vec<_> = indexing_statuses
+            .iter()
+            .filter(|status| status.indexer.address() == indexer.address())
+            .collect();
+        let kept: Vec<_> = capped
+            .iter()
+            .filter(|status| status.indexer.address() == indexer.address())
+            .collect();
+        assert_eq!(kept.len(), served.len().min(1));
+
+        // The kept deployment is one of those served by the most indexers.
+        if let Some(kept) = kept.first() {
+            assert!(served
+                .iter()
+                .all(|status| n_indexers(&indexing_statuses, status)
+                    <= n_indexers(&indexing_statuses, kept)));
+        }
+    }
+
+    // Allow-listed deployments are never dropped.
+    let allowlisted = gen_deployments().remove(0);
+    let capped = cap_deployments_per_indexer(
+        indexing_statuses.clone(),
+        0,
+        &[allowlisted.clone()],
+        &HashMap::new(),
+        metrics(),
+    );
+    assert_eq!(
+        capped,
+        indexing_statuses
+            .into_iter()
+            .filter(|status| status.deployment == allowlisted)
+            .collect::<Vec<_>>()
+    );
+}
@@ -0,0 +1,204 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use graphix_indexer_client::{BlockPointer, IndexerClient, IndexingStatus, ProofOfIndexing};
+use graphix_lib::indexing_loop::{block_hash_mismatches, DivergenceEvent, DivergenceTracker};
+use graphix_lib::test_utils::gen::{gen_blocks, gen_deployments};
+use graphix_lib::test_utils::mocks::MockIndexerBuilder;
+
+#[test]
+fn divergences_are_reported_until_resolved() {
+    let deployments = gen_deployments();
+    let blocks = gen_blocks();
+    let indexer = |name: &str| MockIndexerBuilder::new(name).client();
+    let (a, b, c) = (indexer("a"), indexer("b"), indexer("c"));
+
+    let status = |indexer: &Arc<dyn IndexerClient>, deployment: usize| IndexingStatus {
+        indexer: indexer.clone(),
+        deployment: deployments[deployment].clone(),
+        network: "mainnet".into(),
+        latest_block: blocks[5].clone(),
+        earliest_block_num: blocks[0].number,
+    };
+    let poi = |indexer: &Arc<dyn IndexerClient>, deployment: usize, block: usize, poi: u8| {
+        ProofOfIndexing {
+            indexer: indexer.clone(),
+            deployment: deployments[deployment].clone(),
+            block: blocks[block].clone(),
+            proof_of_indexing: [poi; 32].into(),
+        }
+    };
+
+    let mut tracker = DivergenceTracker::new();
+    let start = Instant::now();
+    let statuses = vec![status(&a, 0), status(&b, 0), status(&c, 0), status(&a, 1)];
+
+    // Indexers agree at first.
+    let events = tracker.update(&statuses, &[poi(&a, 0, 1, 1), poi(&b, 0, 1, 1)], start);
+    assert!(events.is_empty());
+
+    // Then `b` diverges, which is only reported once.
+    let pois = [poi(&a, 0, 2, 1), poi(&b, 0, 2, 2), poi(&c, 0, 2, 1)];
+    let events = tracker.update(&statuses, &pois, start + Duration::from_secs(60));
+    assert_eq!(
+        events,
+        vec![DivergenceEvent::New {
+            deployment: deployments[0].clone(),
+            block_number: blocks[2].number,
+        }]
+    );
+    let pois = [poi(&a, 0, 3, 1), poi(&b, 0, 3, 2)];
+    let events = tracker.update(&statuses, &pois, start + Duration::from_secs(120));
+    assert!(events.is_empty());
+
+    // Deployments that weren't polled keep their state.
+    let events = tracker.update(&statuses, &[], start + Duration::from_secs(180));
+    assert!(events.is_empty());
+
+    // Indexers agree again.
+    let pois = [poi(&a, 0, 4, 1), poi(&b, 0, 4, 1)];
+    let events = tracker.update(&statuses, &pois, start + Duration::from_secs(240));
+    assert_eq!(
+        events,
+        vec![DivergenceEvent::Resolved {
+            deployment: deployments[0].clone(),
+            block_number: blocks[2].number,
+            duration: Duration::from_secs(180),
+        }]
+    );
+}
+
+#[test]
+fn divergences_are_resolved_when_the_divergent_indexer_vanishes() {
+    let deployments = gen_deployments();
+    let blocks = gen_blocks();
+    let indexer = |name: &str| MockIndexerBuilder::new(name).client();
+    let (a, b) = (indexer("a"), indexer("b"));
+
+    let status = |indexer: &Arc<dyn IndexerClient>| IndexingStatus {
+        indexer: indexer.clone(),
+        deployment: deployments[0].clone(),
+        network: "mainnet".into(),
+        latest_block: blocks[5].clone(),
+        earliest_block_num: blocks[0].number,
+    };
+    let poi = |indexer: &Arc<dyn IndexerClient>, poi: u8| ProofOfIndexing {
+        indexer: indexer.clone(),
+        deployment: deployments[0].clone(),
+        block: blocks[1].clone(),
+        proof_of_indexing: [poi; 32].into(),
+    };
+
+    let mut tracker = DivergenceTracker::new();
+    let start = Instant::now();
+
+    let events = tracker.update(&[status(&a), status(&b)], &[poi(&a, 1), poi(&b, 2)], start);
+    assert_eq!(events.len(), 1);
+
+    // `b` stops serving the deployment, and so does `a` later on.
+    let events = tracker.update(&[status(&a)], &[poi(&a, 1)], start + Duration::from_secs(1));
+    assert!(matches!(events[..], [DivergenceEvent::Resolved { .. }]));
+    let events = tracker.update(&[status(&a), status(&b)], &[poi(&a, 1), poi(&b, 2)], start);
+    assert_eq!(events.len(), 1);
+    let events = tracker.update(&[], &[], start + Duration::from_secs(1));
+    assert!(matches!(events[..], [DivergenceEvent::Resolved { .. }]));
+}
+
+#[test]
+fn block_hash_mismatches_are_not_divergences() {
+    let deployments = gen_deployments();
+    let blocks = gen_blocks();
+    let indexer = |name: &str| MockIndexerBuilder::new(name).client();
+    let (a, b) = (indexer("a"), indexer("b"));
+
+    let status = |indexer: &Arc<dyn IndexerClient>| IndexingStatus {
+        indexer: indexer.clone(),
+        deployment: deployments[0].clone(),
+        network: "mainnet".into(),
+        latest_block: blocks[5].clone(),
+        earliest_block_num: blocks[0].number,
+    };
+    let poi = |indexer: &Arc<dyn IndexerClient>, block: BlockPointer, poi: u8| ProofOfIndexing {
+        indexer: indexer.clone(),
+        deployment: deployments[0].clone(),
+        block,
+        proof_of_indexing: [poi; 32].into(),
+    };
+    // `b` is on a fork.
+    let forked_block = BlockPointer {
+        number: blocks[1].number,
+        hash: Some(vec![0xff; 32].into()),
+    };
+    let pois = [poi(&a, blocks[1].clone(), 1), poi(&b, forked_block, 2)];
+
+    assert_eq!(block_hash_mismatches(&pois).len(), 2);
+    assert!(block_hash_mismatches(&pois[..1]).is_empty());
+
+    // The mismatch is reported once, but not as a divergence.
+    let mut tracker = DivergenceTracker::new();
+    let start = Instant::now();
+    let statuses = [status(&a), status(&b)];
+    let events = tracker.update(&statuses, &pois, start);
+    assert_eq!(
+        events,
+        vec![DivergenceEvent::BlockHashMismatch {
+            deployment: deployments[0].clone(),
+            block_number: blocks[1].number,
+        }]
+    );
+    let events = tracker.update(&statuses, &pois, start + Duration::from_secs(60));
+    assert!(events.is_empty());
+}
+
+#[test]
+fn indexers_on_the_majority_fork_are_still_compared() {
+    let deployments = gen_deployments();
+    let blocks = gen_blocks();
+    let indexer = |name: &str| MockIndexerBuilder::new(name).client();
+    let (a, b, c) = (indexer("a"), indexer("b"), indexer("c"));
+
+    let status = |indexer: &Arc<dyn IndexerClient>| IndexingStatus {
+        indexer: indexer.clone(),
+        deployment: deployments[0].clone(),
+        network: "mainnet".into(),
+        latest_block: blocks[5].clone(),
+        earliest_block_num: blocks[0].number,
+    };
+    let poi = |indexer: &Arc<dyn IndexerClient>, block: BlockPointer, poi: u8| ProofOfIndexing {
+        indexer: indexer.clone(),
+        deployment: deployments[0].clone(),
+        block,
+        proof_of_indexing: [poi; 32].into(),
+    };
+    // `c` is on a fork, while `a` and `b` disagree on the canonical chain.
+    let forked_block = BlockPointer {
+        number: blocks[1].number,
+        hash: Some(vec![0xff; 32].into()),
+    };
+    let pois = [
+        poi(&a, blocks[1].clone(), 1),
+        poi(&b, blocks[1].clone(), 2),
+        poi(&c, forked_block, 3),
+    ];
+
+    let mismatches = block_hash_mismatches(&pois);
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].indexer.address(), c.address());
+
+    let mut tracker = DivergenceTracker::new();
+    let statuses = [status(&a), status(&b), status(&c)];
+    let events = tracker.update(&statuses, &pois, Instant::now());
+    assert_eq!(
+        events,
+        vec![
+            DivergenceEvent::BlockHashMismatch {
+                deployment: deployments[0].clone(),
+                block_number: blocks[1].number,
+            },
+            DivergenceEvent::New {
+                deployment: deployments[0].clone(),
+                block_number: blocks[1].number,
+            },
+        ]
+    );
+}
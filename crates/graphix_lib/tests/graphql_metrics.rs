@@ -0,0 +1,38 @@
+use graphix_lib::graphql_api::api_schema_builder;
+use graphix_lib::metrics;
+
+#[tokio::test]
+async fn graphql_requests_are_labeled_by_operation() {
+    // `serverInfo` needs no database.
+    let schema = api_schema_builder().finish();
+    let requests = |operation: &str| {
+        metrics()
+            .graphql_requests
+            .with_label_values(&[operation])
+            .get()
+    };
+    let errors = |operation: &str| {
+        metrics()
+            .graphql_request_errors
+            .with_label_values(&[operation])
+            .get()
+    };
+
+    assert!(schema
+        .execute("query Version { serverInfo { version } }")
+        .await
+        .is_ok());
+    assert!(schema.execute("{ serverInfo { gitSha } }").await.is_ok());
+    assert!(schema.execute("{ serverInfo {").await.is_err());
+    assert!(schema.execute("query Broken { nope }").await.is_err());
+
+    assert_eq!(requests("Version"), 1);
+    assert_eq!(errors("Version"), 0);
+    assert_eq!(requests("anonymous"), 1);
+    assert_eq!(requests("invalid"), 1);
+    assert_eq!(errors("invalid"), 1);
+    // Requests that fail validation are still labeled by their operation.
+    assert_eq!(requests("Broken"), 1);
+    assert_eq!(errors("Broken"), 1);
+    assert_eq!(metrics().graphql_requests_in_flight.get(), 0);
+}
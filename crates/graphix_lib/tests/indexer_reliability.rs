@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use graphix_lib::block_choice::{BlockChoicePolicy, PinnedBlocksConfig};
+use graphix_lib::indexing_loop::{
+    indexer_reliability_samples, query_indexing_statuses, query_proofs_of_indexing,
+};
+use graphix_lib::metrics;
+use graphix_lib::test_utils::gen::gen_indexers_seeded;
+use graphix_lib::test_utils::test_seed;
+
+#[tokio::test]
+async fn reliability_samples_are_bounded() {
+    for i in 0..100 {
+        let indexers = gen_indexers_seeded(test_seed(i), i as usize);
+
+        let (indexing_statuses, _) =
+            query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+        let (pois, _, _) = query_proofs_of_indexing(
+            indexing_statuses.clone(),
+            BlockChoicePolicy::Earliest,
+            &PinnedBlocksConfig::default(),
+            &HashMap::new(),
+            metrics(),
+            Duration::ZERO,
+        )
+        .await;
+
+        let samples =
+            indexer_reliability_samples(&indexers, &indexing_statuses, &indexing_statuses, &pois);
+        assert_eq!(samples.len(), indexers.len());
+
+        for (indexer, sample) in samples {
+            assert!((0.0..=1.0).contains(&sample));
+            if indexer.clone().indexing_statuses().await.is_err() {
+                assert_eq!(sample, 0.0);
+            }
+        }
+    }
+}
@@ -1,19 +1,19 @@
+use std::time::Duration;
+
 use futures::stream::FuturesUnordered;
 use futures::{future, StreamExt};
-use graphix_indexer_client::IndexingStatus;
-use graphix_lib::indexing_loop::query_indexing_statuses;
+use graphix_indexer_client::{IndexingStatus, QueryOutcome};
+use graphix_lib::indexing_loop::{self, query_indexing_statuses};
 use graphix_lib::metrics;
-use graphix_lib::test_utils::fast_rng;
 use graphix_lib::test_utils::gen::*;
+use graphix_lib::test_utils::mocks::{DeploymentDetails, MockIndexerBuilder};
+use graphix_lib::test_utils::test_seed;
 
 #[tokio::test]
 async fn indexing_statuses() {
     // Run the test 100 times to increase likelyhood that randomness triggers a bug
     for i in 0..100 {
-        let mut rng = fast_rng(i);
-        let max_indexers = i;
-
-        let indexers = gen_indexers(&mut rng, max_indexers as usize);
+        let indexers = gen_indexers_seeded(test_seed(i), i as usize);
 
         let expected_statuses = indexers
             .iter()
@@ -29,11 +29,94 @@ async fn indexing_statuses() {
             .flatten()
             .collect::<Vec<_>>();
 
-        let queried_statuses: Vec<IndexingStatus> = query_indexing_statuses(&indexers, metrics())
-            .await
-            .into_iter()
-            .collect();
+        let queried_statuses: Vec<IndexingStatus> =
+            query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[])
+                .await
+                .0
+                .into_iter()
+                .collect();
 
         assert_eq!(expected_statuses, queried_statuses);
     }
 }
+
+#[tokio::test]
+async fn blocks_behind() {
+    for i in 0..100 {
+        let indexers = gen_indexers_seeded(test_seed(i), i as usize);
+        let (statuses, _) =
+            query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+        let blocks_behind = indexing_loop::blocks_behind(&statuses);
+        assert_eq!(blocks_behind.len(), statuses.len());
+
+        for (status, blocks_behind) in statuses.iter().zip(&blocks_behind) {
+            let max_latest_block = statuses
+                .iter()
+                .filter(|other| other.deployment == status.deployment)
+                .map(|other| other.latest_block.number)
+                .max()
+                .unwrap();
+            assert_eq!(
+                *blocks_behind,
+                max_latest_block - status.latest_block.number
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn indexing_statuses_summary() {
+    for i in 0..100 {
+        let indexers = gen_indexers_seeded(test_seed(i), i as usize);
+        let (statuses, summary) =
+            query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+
+        assert_eq!(summary.query_name, "indexingStatuses");
+        assert_eq!(summary.outcomes.len(), indexers.len());
+        for (indexer, outcome) in &summary.outcomes {
+            let indexer_statuses = statuses
+                .iter()
+                .filter(|status| status.indexer == *indexer)
+                .count();
+            match outcome {
+                QueryOutcome::Success { results } => assert_eq!(*results, indexer_statuses),
+                QueryOutcome::Failure { .. } => assert_eq!(indexer_statuses, 0),
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn duplicate_statuses_are_dropped() {
+    let deployments = gen_deployments();
+    let blocks = gen_blocks();
+    let details = |deployment: usize, block: usize| DeploymentDetails {
+        deployment: deployments[deployment].clone(),
+        network: "mainnet".into(),
+        latest_block: blocks[block].clone(),
+        canonical_pois: vec![],
+        earliest_block_num: blocks[0].number,
+    };
+    let indexer = MockIndexerBuilder::new("duplicates")
+        .deployments([details(0, 3), details(1, 2), details(0, 5), details(0, 4)])
+        .client();
+
+    let (statuses, summary) =
+        query_indexing_statuses(&[indexer.clone()], metrics(), Duration::ZERO, &[]).await;
+
+    let deployments_and_blocks = statuses
+        .iter()
+        .map(|status| (status.deployment.clone(), status.latest_block.number))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        deployments_and_blocks,
+        vec![
+            (deployments[0].clone(), blocks[5].number),
+            (deployments[1].clone(), blocks[2].number),
+        ]
+    );
+    assert!(matches!(
+        summary.outcomes[&indexer],
+        QueryOutcome::Success { results: 2 }
+    ));
+}
@@ -0,0 +1,81 @@
+use graphix_lib::manifest::{count_entity_types, DeploymentManifest};
+
+const MANIFEST: &str = r#"
+specVersion: 0.0.5
+description: Test subgraph
+repository: https://github.com/graphprotocol/example-subgraph
+features:
+  - grafting
+  - nonFatalErrors
+schema:
+  file:
+    /: /ipfs/QmNY7gDNXHECV8SXoEY7hbfg4BX1aDMxTBDiFuG4huaSGA
+dataSources:
+  - kind: ethereum/contract
+    name: Gravity
+    network: mainnet
+    source:
+      address: "0x2E645469f354BB4F5c8a05B3b30A929361cf77eC"
+      startBlock: 6175244
+  - kind: ethereum/contract
+    name: Gravity2
+    network: mainnet
+    source:
+      address: "0x2E645469f354BB4F5c8a05B3b30A929361cf77eC"
+      startBlock: 5000000
+"#;
+
+#[test]
+fn parse_manifest() {
+    let manifest = DeploymentManifest::parse(MANIFEST).unwrap();
+
+    assert_eq!(manifest.description.as_deref(), Some("Test subgraph"));
+    assert_eq!(manifest.spec_version, "0.0.5");
+    assert_eq!(manifest.network.as_deref(), Some("mainnet"));
+    assert_eq!(manifest.start_block, Some(5000000));
+    assert_eq!(manifest.features, vec!["grafting", "nonFatalErrors"]);
+    assert_eq!(
+        manifest.schema.to_string(),
+        "QmNY7gDNXHECV8SXoEY7hbfg4BX1aDMxTBDiFuG4huaSGA"
+    );
+    assert_eq!(manifest.entity_count, None);
+}
+
+#[test]
+fn parse_manifest_without_optional_fields() {
+    let manifest = DeploymentManifest::parse(
+        "specVersion: 0.0.4\nschema:\n  file:\n    /: /ipfs/QmNY7gDNXHECV8SXoEY7hbfg4BX1aDMxTBDiFuG4huaSGA\n",
+    )
+    .unwrap();
+
+    assert_eq!(manifest.description, None);
+    assert_eq!(manifest.network, None);
+    assert_eq!(manifest.start_block, None);
+    assert!(manifest.features.is_empty());
+}
+
+#[test]
+fn parse_manifest_with_invalid_schema_link() {
+    let manifest = MANIFEST.replace("QmNY7gDNXHECV8SXoEY7hbfg4BX1aDMxTBDiFuG4huaSGA", "foo");
+    assert!(DeploymentManifest::parse(&manifest).is_err());
+}
+
+#[test]
+fn entity_types() {
+    let schema = r#"
+        type Gravatar @entity {
+          id: ID!
+          owner: Bytes!
+        }
+
+        type Transfer @entity(immutable: true) {
+          id: ID!
+        }
+
+        # type Unused @entity {
+        type NotAnEntity @entityLike {
+          id: ID!
+        }
+    "#;
+    assert_eq!(count_entity_types(schema), 2);
+}
@@ -0,0 +1,335 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use graphix_indexer_client::{
+    IndexerClient, IndexerError, IndexingStatus, PoiRequest, ProofOfIndexing, RealIndexer,
+};
+use graphix_lib::block_choice::{BlockChoicePolicy, PinnedBlocksConfig};
+use graphix_lib::test_utils::fast_rng;
+use graphix_lib::test_utils::gen::{gen_blocks, gen_deployments, gen_pois};
+use graphix_lib::test_utils::mock_graph_node::{HttpFaults, MockGraphNode};
+use graphix_lib::test_utils::mocks::{DeploymentDetails, MockIndexer, MockIndexerBuilder};
+use graphix_lib::{indexing_loop, metrics};
+
+fn mock_indexer(name: &str, seed: u64) -> MockIndexer {
+    let mut rng = fast_rng(seed);
+    let blocks = gen_blocks();
+
+    MockIndexerBuilder::new(name)
+        .deployments(
+            gen_deployments()
+                .into_iter()
+                .take(2)
+                .map(|deployment| DeploymentDetails {
+                    deployment,
+                    network: "mainnet".into(),
+                    latest_block: blocks[5].clone(),
+                    canonical_pois: gen_pois(blocks[..=5].to_vec(), &mut rng),
+                    earliest_block_num: blocks[1].number,
+                }),
+        )
+        .build()
+}
+
+fn status_key(status: &IndexingStatus) -> (String, String, u64, u64) {
+    (
+        status.deployment.0.clone(),
+        status.network.clone(),
+        status.latest_block.number,
+        status.earliest_block_num,
+    )
+}
+
+fn poi_key(poi: &ProofOfIndexing) -> (String, String, u64, String) {
+    (
+        poi.indexer.address_string(),
+        poi.deployment.0.clone(),
+        poi.block.number,
+        poi.proof_of_indexing.to_string(),
+    )
+}
+
+#[tokio::test]
+async fn indexing_statuses_over_http() {
+    let mock = mock_indexer("a", 0);
+    let server = MockGraphNode::start(mock.clone(), HttpFaults::default())
+        .await
+        .unwrap();
+    assert_ne!(server.port(), 0);
+
+    let expected = Arc::new(mock).indexing_statuses().await.unwrap();
+    let actual = server.indexer_client().indexing_statuses().await.unwrap();
+
+    assert_eq!(actual.len(), 2);
+    assert_eq!(
+        actual.iter().map(status_key).collect::<BTreeSet<_>>(),
+        expected.iter().map(status_key).collect::<BTreeSet<_>>(),
+    );
+}
+
+#[tokio::test]
+async fn allowlisted_indexing_statuses_over_http() {
+    let mock = mock_indexer("a", 0);
+    let server = MockGraphNode::start(mock.clone(), HttpFaults::default())
+        .await
+        .unwrap();
+    let deployment = mock.deployment_details[1].deployment.clone();
+
+    let (statuses, _) = indexing_loop::query_indexing_statuses(
+        &[server.indexer_client() as Arc<dyn IndexerClient>],
+        metrics(),
+        Duration::ZERO,
+        &[deployment.clone()],
+    )
+    .await;
+
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses[0].deployment, deployment);
+}
+
+#[tokio::test]
+async fn indexing_loop_over_http() {
+    let mocks = [mock_indexer("a", 1), mock_indexer("b", 2)];
+    let mut servers = vec![];
+    for mock in &mocks {
+        servers.push(
+            MockGraphNode::start(mock.clone(), HttpFaults::default())
+                .await
+                .unwrap(),
+        );
+    }
+
+    let query_pois = |indexers: Vec<Arc<dyn IndexerClient>>| async move {
+        let (statuses, _) =
+            indexing_loop::query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+        let (pois, _, _) = indexing_loop::query_proofs_of_indexing(
+            statuses,
+            BlockChoicePolicy::default(),
+            &PinnedBlocksConfig::default(),
+            &HashMap::new(),
+            metrics(),
+            Duration::ZERO,
+        )
+        .await;
+        pois.iter().map(poi_key).collect::<BTreeSet<_>>()
+    };
+
+    let expected = query_pois(
+        mocks
+            .iter()
+            .map(|mock| Arc::new(mock.clone()) as Arc<dyn IndexerClient>)
+            .collect(),
+    )
+    .await;
+    let actual = query_pois(
+        servers
+            .iter()
+            .map(|server| server.indexer_client() as Arc<dyn IndexerClient>)
+            .collect(),
+    )
+    .await;
+
+    // Both indexers, both deployments.
+    assert_eq!(actual.len(), 4);
+    assert_eq!(actual, expected);
+}
+
+#[tokio::test]
+async fn version_over_http() {
+    let server = MockGraphNode::start(mock_indexer("a", 3), HttpFaults::default())
+        .await
+        .unwrap();
+
+    let version = server.indexer_client().version().await.unwrap();
+    assert_eq!(version.version.as_deref(), Some("0.0.0"));
+    assert_eq!(version.commit.as_deref(), Some("no-commit-hash"));
+    assert_eq!(version.supported_features, None);
+}
+
+#[tokio::test]
+async fn http_faults() {
+    let statuses = |faults: HttpFaults, mock: MockIndexer| async move {
+        let server = MockGraphNode::start(mock, faults).await.unwrap();
+        server.indexer_client().indexing_statuses().await
+    };
+
+    let result = statuses(
+        HttpFaults {
+            internal_server_error: true,
+            ..Default::default()
+        },
+        mock_indexer("a", 4),
+    )
+    .await;
+    assert_eq!(result.unwrap_err(), IndexerError::Http { status: 500 });
+
+    let result = statuses(
+        HttpFaults {
+            malformed_json: true,
+            ..Default::default()
+        },
+        mock_indexer("a", 4),
+    )
+    .await;
+    assert_eq!(result.unwrap_err().kind(), "Deserialization");
+
+    // GraphQL errors.
+    let result = statuses(
+        HttpFaults::default(),
+        MockIndexer {
+            fail_indexing_statuses: true,
+            ..mock_indexer("a", 4)
+        },
+    )
+    .await;
+    let error = result.unwrap_err();
+    assert_eq!(error.kind(), "GraphQl");
+    assert!(error.to_string().contains("boo"));
+
+    // Oversized responses, streamed or with a `Content-Length`.
+    let result = statuses(
+        HttpFaults {
+            padding_bytes: Some(100_000),
+            ..Default::default()
+        },
+        mock_indexer("a", 4),
+    )
+    .await;
+    assert_eq!(result.unwrap().len(), 2);
+    let server = MockGraphNode::start(
+        mock_indexer("a", 4),
+        HttpFaults {
+            padding_bytes: Some(100_000),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let client = Arc::new(
+        Arc::try_unwrap(server.indexer_client())
+            .unwrap()
+            .with_max_response_bytes(50_000)
+            .with_responses_too_large(metrics().indexer_responses_too_large.clone()),
+    );
+    let responses_too_large = metrics()
+        .indexer_responses_too_large
+        .with_label_values(&[&client.address_string()]);
+    let count = responses_too_large.get();
+    let error = client.indexing_statuses().await.unwrap_err();
+    assert_eq!(error, IndexerError::ResponseTooLarge { limit: 50_000 });
+    assert_eq!(responses_too_large.get(), count + 1);
+    let server = MockGraphNode::start(mock_indexer("a", 4), HttpFaults::default())
+        .await
+        .unwrap();
+    let client = Arc::new(
+        Arc::try_unwrap(server.indexer_client())
+            .unwrap()
+            .with_max_response_bytes(10),
+    );
+    let error = client.indexing_statuses().await.unwrap_err();
+    assert_eq!(error.kind(), "ResponseTooLarge");
+
+    // Nothing listens on port 1.
+    let unreachable = Arc::new(RealIndexer::new(
+        None,
+        mock_indexer("a", 4).address(),
+        "http://127.0.0.1:1/status".to_string(),
+        metrics().public_proofs_of_indexing_requests.clone(),
+    ));
+    let error = unreachable.indexing_statuses().await.unwrap_err();
+    assert_eq!(error.kind(), "Connection");
+
+    // Slow responses still arrive.
+    let result = statuses(
+        HttpFaults {
+            latency: Some(Duration::from_millis(50)),
+            ..Default::default()
+        },
+        mock_indexer("a", 4),
+    )
+    .await;
+    assert_eq!(result.unwrap().len(), 2);
+
+    // Failed PoI requests are left out.
+    let mock = mock_indexer("a", 4);
+    let server = MockGraphNode::start(
+        mock.clone(),
+        HttpFaults {
+            internal_server_error: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let indexer: Arc<dyn IndexerClient> = server.indexer_client();
+    let statuses = Arc::new(mock).indexing_statuses().await.unwrap();
+    let (pois, _, _) = indexing_loop::query_proofs_of_indexing(
+        statuses
+            .into_iter()
+            .map(|status| IndexingStatus {
+                indexer: indexer.clone(),
+                ..status
+            })
+            .collect(),
+        BlockChoicePolicy::default(),
+        &PinnedBlocksConfig::default(),
+        &HashMap::new(),
+        metrics(),
+        Duration::ZERO,
+    )
+    .await;
+    assert!(pois.is_empty());
+}
+
+#[tokio::test]
+async fn implausibly_many_results() {
+    let mock = mock_indexer("a", 5);
+    let deployments: Vec<_> = mock
+        .deployment_details
+        .iter()
+        .map(|details| details.deployment.clone())
+        .collect();
+    let poi_request = PoiRequest {
+        deployment: deployments[0].clone(),
+        block_number: mock.deployment_details[0].latest_block.number,
+    };
+    let server = |repeated_results| {
+        MockGraphNode::start(
+            mock.clone(),
+            HttpFaults {
+                repeated_results,
+                ..Default::default()
+            },
+        )
+    };
+
+    // Some `graph-node` versions report deployments twice, which is fine.
+    let server_with_duplicates = server(1).await.unwrap();
+    let indexer = server_with_duplicates.indexer_client();
+    let statuses = indexer.clone().indexing_statuses_of(&deployments).await;
+    assert_eq!(statuses.unwrap().len(), 4);
+    let pois = indexer.proofs_of_indexing(vec![poi_request.clone()]).await;
+    assert_eq!(pois.len(), 2);
+
+    let broken_server = server(2).await.unwrap();
+    let indexer = broken_server.indexer_client();
+    let error = indexer
+        .clone()
+        .indexing_statuses_of(&deployments)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error,
+        IndexerError::TooManyResults {
+            requested: 2,
+            returned: 6
+        }
+    );
+    assert!(indexer
+        .proofs_of_indexing(vec![poi_request])
+        .await
+        .is_empty());
+    // Without a filter, there's nothing to compare against.
+    assert_eq!(indexer.indexing_statuses().await.unwrap().len(), 6);
+}
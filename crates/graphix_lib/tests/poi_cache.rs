@@ -0,0 +1,169 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use graphix_indexer_client::{IndexerClient, PoiCache, PoiRangeRequest, ProofOfIndexing};
+use graphix_lib::block_choice::{BlockChoicePolicy, PinnedBlocksConfig};
+use graphix_lib::config::with_poi_cache;
+use graphix_lib::test_utils::gen::{gen_blocks, gen_deployments, gen_indexers_seeded};
+use graphix_lib::test_utils::mocks::{
+    DeploymentDetails, MockIndexerBuilder, PartialProofOfIndexing,
+};
+use graphix_lib::test_utils::test_seed;
+use graphix_lib::{indexing_loop, metrics};
+use prometheus::IntCounter;
+
+fn poi_cache(capacity: usize, ttl: Duration) -> Arc<PoiCache> {
+    Arc::new(PoiCache::new(
+        capacity,
+        ttl,
+        IntCounter::new("hits", "hits").unwrap(),
+        IntCounter::new("misses", "misses").unwrap(),
+    ))
+}
+
+#[tokio::test]
+async fn cached_pois_match_uncached_pois() {
+    for i in 0..100 {
+        let indexers = gen_indexers_seeded(test_seed(i), i as usize);
+        let cache = poi_cache(10_000, Duration::from_secs(60));
+        let cached_indexers = with_poi_cache(indexers.clone(), &cache);
+
+        let (indexing_statuses, _) =
+            indexing_loop::query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+        let expected_pois = indexing_loop::query_proofs_of_indexing(
+            indexing_statuses,
+            BlockChoicePolicy::Earliest,
+            &PinnedBlocksConfig::default(),
+            &HashMap::new(),
+            metrics(),
+            Duration::ZERO,
+        )
+        .await
+        .0
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+
+        // The first round fills the cache, the second one is served from it.
+        for _ in 0..2 {
+            let (indexing_statuses, _) = indexing_loop::query_indexing_statuses(
+                &cached_indexers,
+                metrics(),
+                Duration::ZERO,
+                &[],
+            )
+            .await;
+            let pois = indexing_loop::query_proofs_of_indexing(
+                indexing_statuses,
+                BlockChoicePolicy::Earliest,
+                &PinnedBlocksConfig::default(),
+                &HashMap::new(),
+                metrics(),
+                Duration::ZERO,
+            )
+            .await
+            .0
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+
+            assert_eq!(pois, expected_pois);
+            assert_eq!(cache.len(), expected_pois.len());
+        }
+    }
+}
+
+#[tokio::test]
+async fn cache_is_bounded() {
+    for i in 0..100 {
+        let indexers = gen_indexers_seeded(test_seed(i), i as usize);
+        let cache = poi_cache(1, Duration::from_secs(60));
+        let cached_indexers = with_poi_cache(indexers, &cache);
+
+        let (indexing_statuses, _) = indexing_loop::query_indexing_statuses(
+            &cached_indexers,
+            metrics(),
+            Duration::ZERO,
+            &[],
+        )
+        .await;
+        let (pois, _, _) = indexing_loop::query_proofs_of_indexing(
+            indexing_statuses,
+            BlockChoicePolicy::Earliest,
+            &PinnedBlocksConfig::default(),
+            &HashMap::new(),
+            metrics(),
+            Duration::ZERO,
+        )
+        .await;
+
+        assert_eq!(cache.len(), pois.len().min(1));
+    }
+}
+
+#[tokio::test]
+async fn poi_ranges_are_served_from_the_cache() {
+    let deployments = gen_deployments();
+    let blocks = gen_blocks();
+    let indexer: Arc<dyn IndexerClient> = MockIndexerBuilder::new("a")
+        .deployment(DeploymentDetails {
+            deployment: deployments[0].clone(),
+            network: "mainnet".into(),
+            latest_block: blocks[5].clone(),
+            canonical_pois: blocks[..6]
+                .iter()
+                .map(|block| PartialProofOfIndexing {
+                    block: block.clone(),
+                    proof_of_indexing: [1; 32].into(),
+                })
+                .collect(),
+            earliest_block_num: blocks[0].number,
+        })
+        .client();
+    let hits = IntCounter::new("hits", "hits").unwrap();
+    let cache = Arc::new(PoiCache::new(
+        10_000,
+        Duration::from_secs(60),
+        hits.clone(),
+        IntCounter::new("misses", "misses").unwrap(),
+    ));
+    let cached_indexer = with_poi_cache(vec![indexer], &cache).remove(0);
+
+    let block_numbers = |pois: Vec<ProofOfIndexing>| {
+        pois.into_iter()
+            .map(|poi| poi.block.number)
+            .collect::<BTreeSet<_>>()
+    };
+    let request = |from_block: u64, to_block: u64, step: u64| PoiRangeRequest {
+        deployment: deployments[0].clone(),
+        from_block,
+        to_block,
+        step,
+    };
+
+    let pois = cached_indexer
+        .clone()
+        .proofs_of_indexing_range(request(1, 5, 2))
+        .await
+        .unwrap();
+    assert_eq!(block_numbers(pois), BTreeSet::from([1, 3, 5]));
+    assert_eq!(cache.len(), 3);
+    assert_eq!(hits.get(), 0);
+
+    // Cached blocks are served from the cache, the rest from the indexer.
+    let pois = cached_indexer
+        .clone()
+        .proofs_of_indexing_range(request(0, 9, 1))
+        .await
+        .unwrap();
+    assert_eq!(block_numbers(pois), BTreeSet::from([0, 1, 2, 3, 4, 5]));
+    assert_eq!(cache.len(), 6);
+    assert_eq!(hits.get(), 3);
+
+    let pois = cached_indexer
+        .clone()
+        .proofs_of_indexing_range(request(0, 5, 1))
+        .await
+        .unwrap();
+    assert_eq!(block_numbers(pois), BTreeSet::from([0, 1, 2, 3, 4, 5]));
+    assert_eq!(hits.get(), 9);
+}
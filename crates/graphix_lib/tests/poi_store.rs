@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use graphix_common_types::inputs::BlockRange;
+use graphix_indexer_client::{IndexerClient, ProofOfIndexing};
+use graphix_lib::test_utils::gen::{gen_blocks, gen_deployments};
+use graphix_lib::test_utils::mocks::MockIndexerBuilder;
+use graphix_store::{InMemoryPoiStore, Pagination, PoiLiveness, PoiStore};
+
+#[tokio::test]
+async fn in_memory_poi_store() {
+    let deployments = gen_deployments();
+    let blocks = gen_blocks();
+    let indexer = |name: &str| MockIndexerBuilder::new(name).client();
+    let poi = |indexer: &Arc<dyn IndexerClient>, deployment: usize, block: usize, poi: u8| {
+        ProofOfIndexing {
+            indexer: indexer.clone(),
+            deployment: deployments[deployment].clone(),
+            block: blocks[block].clone(),
+            proof_of_indexing: [poi; 32].into(),
+        }
+    };
+    let (a, b) = (indexer("a"), indexer("b"));
+    let store: Arc<dyn PoiStore> = Arc::new(InMemoryPoiStore::new());
+
+    let pois = vec![
+        poi(&a, 0, 1, 1),
+        poi(&b, 0, 1, 1),
+        poi(&a, 1, 2, 3),
+        poi(&b, 1, 2, 4),
+        // Only `a` has a PoI at this block, so it's not comparable.
+        poi(&a, 0, 3, 5),
+    ];
+    assert_eq!(store.write_pois(pois, PoiLiveness::Live).await.unwrap(), 0);
+
+    let pairs = store
+        .latest_common_poi_pairs(&a.address(), &b.address())
+        .await
+        .unwrap();
+    let summary: Vec<_> = pairs
+        .iter()
+        .map(|pair| {
+            (
+                pair.deployment_cid.as_str(),
+                pair.block_number,
+                pair.poi1 == pair.poi2,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (deployments[0].as_str(), 1, true),
+            (deployments[1].as_str(), 2, false)
+        ]
+    );
+    let pagination = Pagination {
+        offset: 0,
+        limit: 100,
+    };
+    let divergences = store.stale_divergences(pagination).await.unwrap();
+    assert_eq!(divergences.len(), 1);
+    assert_eq!(divergences[0].deployment, deployments[1].as_str());
+    assert_eq!(divergences[0].block_number, 2);
+    let mut addresses = vec![a.address(), b.address()];
+    addresses.sort();
+    assert_eq!(divergences[0].indexers, addresses);
+
+    // `b` changes its mind about the second deployment.
+    let changes = store
+        .write_pois(vec![poi(&b, 1, 2, 3)], PoiLiveness::Live)
+        .await
+        .unwrap();
+    assert_eq!(changes, 1);
+    let pairs = store
+        .latest_common_poi_pairs(&a.address(), &b.address())
+        .await
+        .unwrap();
+    assert!(pairs.iter().all(|pair| pair.poi1 == pair.poi2));
+    assert!(store
+        .stale_divergences(pagination)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let changes = store.poi_changes(None, None, 100).await.unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].indexer_address, b.address());
+    assert_eq!(changes[0].deployment, deployments[1].as_str());
+    assert_eq!(changes[0].block_number, 2);
+    assert_eq!(changes[0].old_poi, [4; 32].into());
+    assert_eq!(changes[0].new_poi, [3; 32].into());
+    assert!(store
+        .poi_changes(Some(&a.address()), None, 100)
+        .await
+        .unwrap()
+        .is_empty());
+
+    assert_eq!(store.read_pois(&[], None, None).await.unwrap().len(), 6);
+    assert_eq!(store.read_pois(&[], None, Some(2)).await.unwrap().len(), 2);
+    let block_range = BlockRange {
+        start: Some(2),
+        end: Some(2),
+    };
+    assert_eq!(
+        store
+            .read_pois(&[], Some(block_range), None)
+            .await
+            .unwrap()
+            .len(),
+        3
+    );
+}
@@ -1,24 +1,40 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
 
-use graphix_lib::block_choice::BlockChoicePolicy;
-use graphix_lib::test_utils::fast_rng;
-use graphix_lib::test_utils::gen::gen_indexers;
-use graphix_lib::{indexing_loop, metrics};
+use graphix_indexer_client::{
+    IndexerError, PoiRangeRequest, PoiRequest, SubgraphDeployment, MAX_POI_RANGE_BLOCKS,
+};
+use graphix_lib::block_choice::{
+    BlockChoicePolicy, PinnedBlocks, PinnedBlocksConfig, ReferenceBlock,
+};
+use graphix_lib::config::ChainConfig;
+use graphix_lib::test_utils::gen::{gen_blocks, gen_deployments, gen_indexers_seeded, gen_pois};
+use graphix_lib::test_utils::mocks::{
+    DeploymentDetails, MockFaults, MockIndexerBuilder, PartialProofOfIndexing,
+};
+use graphix_lib::test_utils::{fast_rng, test_seed};
+use graphix_lib::{indexing_loop, metrics, PrometheusMetrics};
 use itertools::Itertools;
 
 #[tokio::test]
 async fn proofs_of_indexing() {
     // Run th test 100 times to increase likelyhood that randomness triggers a bug
     for i in 0..100 {
-        let mut rng = fast_rng(i);
         let max_indexers = i;
-        let indexers = gen_indexers(&mut rng, max_indexers as usize);
+        let indexers = gen_indexers_seeded(test_seed(i), max_indexers as usize);
 
-        let indexing_statuses = indexing_loop::query_indexing_statuses(&indexers, metrics()).await;
-        let pois =
-            indexing_loop::query_proofs_of_indexing(indexing_statuses, BlockChoicePolicy::Earliest);
+        let (indexing_statuses, _) =
+            indexing_loop::query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+        let pois = indexing_loop::query_proofs_of_indexing(
+            indexing_statuses,
+            BlockChoicePolicy::Earliest,
+            &PinnedBlocksConfig::default(),
+            &HashMap::new(),
+            metrics(),
+            Duration::ZERO,
+        );
 
-        let actual_pois = pois.await.into_iter().collect::<BTreeSet<_>>();
+        let actual_pois = pois.await.0.into_iter().collect::<BTreeSet<_>>();
 
         // Assert that for every deployment, the POIs are for the same block
         // (across all indexers)
@@ -31,3 +47,538 @@ async fn proofs_of_indexing() {
         // NOTE: Add more assertions later.
     }
 }
+
+#[tokio::test]
+async fn singleton_deployments() {
+    let mut rng = fast_rng(0);
+    let deployments = gen_deployments();
+    let blocks = gen_blocks();
+    let mut deployment_details = |deployment: &SubgraphDeployment| DeploymentDetails {
+        deployment: deployment.clone(),
+        network: "mainnet".into(),
+        latest_block: blocks[5].clone(),
+        canonical_pois: gen_pois(blocks.clone(), &mut rng),
+        earliest_block_num: blocks[0].number,
+    };
+    // Only `a` serves the second deployment.
+    let indexers = vec![
+        MockIndexerBuilder::new("a")
+            .deployments([
+                deployment_details(&deployments[0]),
+                deployment_details(&deployments[1]),
+            ])
+            .client(),
+        MockIndexerBuilder::new("b")
+            .deployment(deployment_details(&deployments[0]))
+            .client(),
+    ];
+
+    // The global metrics are shared with the other tests, which run
+    // concurrently.
+    let metrics = PrometheusMetrics::new(prometheus::Registry::new());
+    let (indexing_statuses, _) =
+        indexing_loop::query_indexing_statuses(&indexers, &metrics, Duration::ZERO, &[]).await;
+    indexing_loop::query_proofs_of_indexing(
+        indexing_statuses,
+        BlockChoicePolicy::Earliest,
+        &PinnedBlocksConfig::default(),
+        &HashMap::new(),
+        &metrics,
+        Duration::ZERO,
+    )
+    .await;
+    assert_eq!(metrics.singleton_deployments.get(), 1);
+}
+
+#[tokio::test]
+async fn no_pois_below_earliest_block() {
+    let mut rng = fast_rng(0);
+    let deployment = gen_deployments().remove(0);
+    let blocks = gen_blocks();
+    let mut indexer = |name: &str, earliest_block: usize, latest_block: usize| {
+        MockIndexerBuilder::new(name)
+            .deployment(DeploymentDetails {
+                deployment: deployment.clone(),
+                network: "mainnet".into(),
+                latest_block: blocks[latest_block].clone(),
+                canonical_pois: gen_pois(blocks.clone(), &mut rng),
+                earliest_block_num: blocks[earliest_block].number,
+            })
+            .client()
+    };
+    let indexers = vec![indexer("full", 0, 2), indexer("pruned", 3, 5)];
+
+    let (indexing_statuses, _) =
+        indexing_loop::query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+    let (pois, _, _) = indexing_loop::query_proofs_of_indexing(
+        indexing_statuses,
+        BlockChoicePolicy::Earliest,
+        &PinnedBlocksConfig::default(),
+        &HashMap::new(),
+        metrics(),
+        Duration::ZERO,
+    )
+    .await;
+
+    // Block #2 was chosen, which the pruned indexer doesn't have anymore.
+    assert_eq!(pois.len(), 1);
+    assert_eq!(pois[0].indexer.name().as_deref(), Some("full"));
+    assert_eq!(pois[0].block, blocks[2]);
+}
+
+#[tokio::test]
+async fn pois_at_reference_block() {
+    let mut rng = fast_rng(0);
+    let deployment = gen_deployments().remove(0);
+    let blocks = gen_blocks();
+    let mut indexer = |name: &str, earliest_block: usize, latest_block: usize| {
+        MockIndexerBuilder::new(name)
+            .deployment(DeploymentDetails {
+                deployment: deployment.clone(),
+                network: "mainnet".into(),
+                latest_block: blocks[latest_block].clone(),
+                canonical_pois: gen_pois(blocks.clone(), &mut rng),
+                earliest_block_num: blocks[earliest_block].number,
+            })
+            .client()
+    };
+    let indexers = vec![
+        indexer("full", 0, 5),
+        indexer("behind", 0, 2),
+        indexer("pruned", 3, 5),
+    ];
+    let (indexing_statuses, _) =
+        indexing_loop::query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+
+    let policy = BlockChoicePolicy::ReferenceBlock(ReferenceBlock::Fixed(4));
+    let (pois, _, _) = indexing_loop::query_proofs_of_indexing(
+        indexing_statuses.clone(),
+        policy,
+        &PinnedBlocksConfig::default(),
+        &HashMap::new(),
+        metrics(),
+        Duration::ZERO,
+    )
+    .await;
+    let indexers_with_pois: BTreeSet<_> = pois
+        .iter()
+        .map(|poi| poi.indexer.name().unwrap().into_owned())
+        .collect();
+    assert_eq!(
+        indexers_with_pois,
+        BTreeSet::from(["full".to_string(), "pruned".to_string()])
+    );
+    assert!(pois.iter().all(|poi| poi.block == blocks[4]));
+
+    // No reference block for the deployment's network.
+    let policy = BlockChoicePolicy::ReferenceBlock(ReferenceBlock::PerNetwork(
+        [("gnosis".to_string(), 4)].into(),
+    ));
+    let (pois, _, _) = indexing_loop::query_proofs_of_indexing(
+        indexing_statuses,
+        policy,
+        &PinnedBlocksConfig::default(),
+        &HashMap::new(),
+        metrics(),
+        Duration::ZERO,
+    )
+    .await;
+    assert!(pois.is_empty());
+}
+
+#[tokio::test]
+async fn pois_at_finality_offset() {
+    let mut rng = fast_rng(0);
+    let deployment = gen_deployments().remove(0);
+    let blocks = gen_blocks();
+    let mut indexer = |name: &str, earliest_block: usize, latest_block: usize| {
+        MockIndexerBuilder::new(name)
+            .deployment(DeploymentDetails {
+                deployment: deployment.clone(),
+                network: "mainnet".into(),
+                latest_block: blocks[latest_block].clone(),
+                canonical_pois: gen_pois(blocks.clone(), &mut rng),
+                earliest_block_num: blocks[earliest_block].number,
+            })
+            .client()
+    };
+    let indexers = vec![indexer("full", 0, 8), indexer("pruned", 6, 8)];
+    let (indexing_statuses, _) =
+        indexing_loop::query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+
+    let pois_with_offset = |network: &str, finality_offset: u64| {
+        let chains = HashMap::from([(
+            network.to_string(),
+            ChainConfig {
+                caip2: None,
+                speed: None,
+                block_explorer_url_template_for_block: None,
+                trusted_indexer: None,
+                finality_offset,
+            },
+        )]);
+        let indexing_statuses = indexing_statuses.clone();
+        async move {
+            let (pois, _, _) = indexing_loop::query_proofs_of_indexing(
+                indexing_statuses,
+                BlockChoicePolicy::Earliest,
+                &PinnedBlocksConfig::default(),
+                &chains,
+                metrics(),
+                Duration::ZERO,
+            )
+            .await;
+            pois
+        }
+    };
+
+    // Block #8 is chosen, and the offset moves it back.
+    let pois = pois_with_offset("mainnet", 1).await;
+    assert_eq!(pois.len(), 2);
+    assert!(pois.iter().all(|poi| poi.block == blocks[7]));
+
+    // The offset doesn't go below the earliest block of the pruned indexer.
+    let pois = pois_with_offset("mainnet", 5).await;
+    assert_eq!(pois.len(), 2);
+    assert!(pois.iter().all(|poi| poi.block == blocks[6]));
+
+    // Offsets of other networks don't apply.
+    let pois = pois_with_offset("gnosis", 5).await;
+    assert!(pois.iter().all(|poi| poi.block == blocks[8]));
+}
+
+#[tokio::test]
+async fn pois_at_pinned_blocks() {
+    let mut rng = fast_rng(0);
+    let deployment = gen_deployments().remove(0);
+    let blocks = gen_blocks();
+    let mut indexer = |name: &str, earliest_block: usize, latest_block: usize| {
+        MockIndexerBuilder::new(name)
+            .deployment(DeploymentDetails {
+                deployment: deployment.clone(),
+                network: "mainnet".into(),
+                latest_block: blocks[latest_block].clone(),
+                canonical_pois: gen_pois(blocks.clone(), &mut rng),
+                earliest_block_num: blocks[earliest_block].number,
+            })
+            .client()
+    };
+    let indexers = vec![
+        indexer("full", 0, 5),
+        indexer("behind", 0, 2),
+        indexer("pruned", 3, 5),
+    ];
+    let (indexing_statuses, _) =
+        indexing_loop::query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+    let pinned_pois = |pinned_blocks: PinnedBlocksConfig| {
+        let indexing_statuses = indexing_statuses.clone();
+        async move {
+            let (_, pinned_pois, _) = indexing_loop::query_proofs_of_indexing(
+                indexing_statuses,
+                BlockChoicePolicy::default(),
+                &pinned_blocks,
+                &HashMap::new(),
+                metrics(),
+                Duration::ZERO,
+            )
+            .await;
+            pinned_pois
+                .into_iter()
+                .map(|poi| (poi.indexer.name().unwrap().into_owned(), poi.block.number))
+                .collect::<BTreeSet<_>>()
+        }
+    };
+    let pair = |name: &str, block_number| (name.to_string(), block_number);
+
+    // Only pinned blocks that indexers have reached and not pruned.
+    let per_network = PinnedBlocksConfig {
+        networks: [("mainnet".to_string(), PinnedBlocks::Blocks(vec![1, 4]))].into(),
+        ..Default::default()
+    };
+    assert_eq!(
+        pinned_pois(per_network.clone()).await,
+        BTreeSet::from([
+            pair("full", 1),
+            pair("full", 4),
+            pair("behind", 1),
+            pair("pruned", 4),
+        ])
+    );
+
+    // Deployments take precedence over networks, and only the most recent
+    // multiple is queried.
+    let per_deployment = PinnedBlocksConfig {
+        deployments: [(deployment.0.clone(), PinnedBlocks::Every { every: 2 })].into(),
+        ..per_network
+    };
+    assert_eq!(
+        pinned_pois(per_deployment).await,
+        BTreeSet::from([pair("full", 4), pair("behind", 2), pair("pruned", 4)])
+    );
+
+    assert!(pinned_pois(PinnedBlocksConfig::default()).await.is_empty());
+}
+
+#[tokio::test]
+async fn backfill_requests() {
+    let mut rng = fast_rng(0);
+    let deployment = gen_deployments().remove(0);
+    let blocks = gen_blocks();
+    let mut indexer = |name: &str, earliest_block: usize, latest_block: usize| {
+        MockIndexerBuilder::new(name)
+            .deployment(DeploymentDetails {
+                deployment: deployment.clone(),
+                network: "mainnet".into(),
+                latest_block: blocks[latest_block].clone(),
+                canonical_pois: gen_pois(blocks.clone(), &mut rng),
+                earliest_block_num: blocks[earliest_block].number,
+            })
+            .client()
+    };
+    let indexers = vec![indexer("behind", 0, 4), indexer("pruned", 3, 9)];
+    let (indexing_statuses, _) =
+        indexing_loop::query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+
+    let requests = indexing_loop::backfill_poi_requests(&indexing_statuses, &deployment, 0, 9, 2);
+    let requested_blocks: BTreeSet<_> = requests
+        .iter()
+        .map(|(indexer, request)| {
+            assert_eq!(request.deployment, deployment);
+            (indexer.name().unwrap().into_owned(), request.block_number)
+        })
+        .collect();
+    assert_eq!(
+        requested_blocks,
+        BTreeSet::from([
+            ("behind".to_string(), 0),
+            ("behind".to_string(), 2),
+            ("behind".to_string(), 4),
+            ("pruned".to_string(), 4),
+            ("pruned".to_string(), 6),
+            ("pruned".to_string(), 8),
+        ])
+    );
+    assert_eq!(requests.len(), requested_blocks.len());
+
+    // Request counts are known without planning the requests.
+    let count = |from_block, to_block, block_interval| {
+        let requests = indexing_loop::backfill_poi_requests(
+            &indexing_statuses,
+            &deployment,
+            from_block,
+            to_block,
+            block_interval,
+        );
+        let count = indexing_loop::backfill_poi_request_count(
+            &indexing_statuses,
+            &deployment,
+            from_block,
+            to_block,
+            block_interval,
+        );
+        assert_eq!(requests.len() as u64, count);
+        count
+    };
+    assert_eq!(count(0, 9, 2), 6);
+    assert_eq!(count(1, 9, 3), 4);
+    assert_eq!(count(5, 4, 1), 0);
+    // Ranges are clamped to the blocks that indexers can provide PoIs for.
+    assert_eq!(count(0, u64::MAX, 1), 12);
+    assert_eq!(count(u64::MAX - 1, u64::MAX, 1), 0);
+
+    // Other deployments aren't backfilled.
+    let other_deployment = gen_deployments().remove(1);
+    assert!(
+        indexing_loop::backfill_poi_requests(&indexing_statuses, &other_deployment, 0, 9, 1)
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn poi_ranges() {
+    let deployments = gen_deployments();
+    let blocks = gen_blocks();
+    let indexer = MockIndexerBuilder::new("a")
+        .deployment(DeploymentDetails {
+            deployment: deployments[0].clone(),
+            network: "mainnet".into(),
+            latest_block: blocks[5].clone(),
+            canonical_pois: blocks[..6]
+                .iter()
+                .map(|block| PartialProofOfIndexing {
+                    block: block.clone(),
+                    proof_of_indexing: [1; 32].into(),
+                })
+                .collect(),
+            earliest_block_num: blocks[0].number,
+        })
+        .client();
+
+    let block_numbers = |pois: Vec<graphix_indexer_client::ProofOfIndexing>| {
+        pois.into_iter()
+            .map(|poi| poi.block.number)
+            .collect::<BTreeSet<_>>()
+    };
+    let request = |from_block: u64, to_block: u64, step: u64| PoiRangeRequest {
+        deployment: deployments[0].clone(),
+        from_block,
+        to_block,
+        step,
+    };
+
+    // Blocks without PoIs are left out.
+    let pois = indexer
+        .clone()
+        .proofs_of_indexing_range(request(0, 9, 1))
+        .await
+        .unwrap();
+    assert_eq!(block_numbers(pois), BTreeSet::from([0, 1, 2, 3, 4, 5]));
+
+    // Only every `step` blocks are requested.
+    let pois = indexer
+        .clone()
+        .proofs_of_indexing_range(request(1, 5, 2))
+        .await
+        .unwrap();
+    assert_eq!(block_numbers(pois), BTreeSet::from([1, 3, 5]));
+
+    // A step of 0 is treated as 1.
+    assert_eq!(
+        request(2, 4, 0).block_numbers().collect::<Vec<_>>(),
+        vec![2, 3, 4]
+    );
+    assert_eq!(request(2, 4, 0).block_count(), 3);
+    assert_eq!(request(4, 2, 1).block_count(), 0);
+
+    // Ranges are capped, however far apart their blocks are.
+    let max_range = request(0, (MAX_POI_RANGE_BLOCKS - 1) * 10, 10);
+    assert_eq!(max_range.block_count(), MAX_POI_RANGE_BLOCKS);
+    assert!(indexer
+        .clone()
+        .proofs_of_indexing_range(max_range)
+        .await
+        .is_ok());
+    let error = indexer
+        .clone()
+        .proofs_of_indexing_range(request(0, MAX_POI_RANGE_BLOCKS * 10, 10))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error,
+        IndexerError::RangeTooLarge {
+            blocks: MAX_POI_RANGE_BLOCKS + 1,
+            limit: MAX_POI_RANGE_BLOCKS
+        }
+    );
+    assert!(request(0, u64::MAX, 1).poi_requests().is_err());
+}
+
+#[tokio::test]
+async fn mock_faults() {
+    let deployments = gen_deployments();
+    let blocks = gen_blocks();
+    let deployment_details = |deployment: &SubgraphDeployment| DeploymentDetails {
+        deployment: deployment.clone(),
+        network: "mainnet".into(),
+        latest_block: blocks[5].clone(),
+        canonical_pois: blocks[..6]
+            .iter()
+            .map(|block| PartialProofOfIndexing {
+                block: block.clone(),
+                proof_of_indexing: [1; 32].into(),
+            })
+            .collect(),
+        earliest_block_num: blocks[0].number,
+    };
+    let indexer = |faults: MockFaults| {
+        MockIndexerBuilder::new("a")
+            .deployments(deployments[..2].iter().map(deployment_details))
+            .faults(faults)
+            .client()
+    };
+    let requests = deployments[..2]
+        .iter()
+        .map(|deployment| PoiRequest {
+            deployment: deployment.clone(),
+            block_number: blocks[1].number,
+        })
+        .collect::<Vec<_>>();
+
+    let pois = indexer(MockFaults {
+        latency: Some((Duration::from_millis(1), Duration::from_millis(5))),
+        ..Default::default()
+    })
+    .proofs_of_indexing(requests.clone())
+    .await;
+    assert_eq!(pois.len(), 2);
+
+    let pois = indexer(MockFaults {
+        failing_poi_deployments: vec![deployments[1].clone()],
+        ..Default::default()
+    })
+    .proofs_of_indexing(requests.clone())
+    .await;
+    assert_eq!(
+        pois.iter().map(|poi| &poi.deployment).collect::<Vec<_>>(),
+        vec![&deployments[0]]
+    );
+
+    let pois = indexer(MockFaults {
+        corrupt_pois: true,
+        ..Default::default()
+    })
+    .proofs_of_indexing(requests.clone())
+    .await;
+    assert_eq!(pois.len(), 2);
+    assert!(pois.iter().all(|poi| poi.proof_of_indexing.0 == [!1; 32]));
+
+    let pois = indexer(MockFaults {
+        max_pois_per_call: Some(1),
+        ..Default::default()
+    })
+    .proofs_of_indexing(requests)
+    .await;
+    assert_eq!(pois.len(), 1);
+}
+
+#[tokio::test]
+async fn block_choices_match_queried_blocks() {
+    for i in 0..20 {
+        let indexers = gen_indexers_seeded(test_seed(i), 10);
+
+        let (indexing_statuses, _) =
+            indexing_loop::query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+        let block_choices =
+            indexing_loop::block_choices(&indexing_statuses, &BlockChoicePolicy::default());
+        let (pois, _, _) = indexing_loop::query_proofs_of_indexing(
+            indexing_statuses.clone(),
+            BlockChoicePolicy::default(),
+            &PinnedBlocksConfig::default(),
+            &HashMap::new(),
+            metrics(),
+            Duration::ZERO,
+        )
+        .await;
+
+        let deployments = indexing_statuses
+            .iter()
+            .map(|status| status.deployment.as_str())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(block_choices.len(), deployments.len());
+        for block_choice in &block_choices {
+            assert_eq!(block_choice.policy, "maxSyncedBlocks");
+            assert!(block_choice
+                .candidate_blocks
+                .windows(2)
+                .all(|w| w[0] < w[1]));
+            if let Some(chosen_block) = block_choice.chosen_block {
+                assert!(block_choice.candidate_blocks.contains(&chosen_block));
+            }
+            // Every PoI of the deployment is for the chosen block.
+            assert!(pois
+                .iter()
+                .filter(|poi| poi.deployment.as_str() == block_choice.deployment)
+                .all(|poi| Some(poi.block.number) == block_choice.chosen_block));
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use graphix_indexer_client::{HostRateLimiter, IndexerClient, RealIndexer};
+use graphix_lib::metrics;
+use graphix_lib::test_utils::gen::{gen_blocks, gen_deployments};
+use graphix_lib::test_utils::mock_graph_node::{HttpFaults, MockGraphNode};
+use graphix_lib::test_utils::mocks::{DeploymentDetails, MockIndexer, MockIndexerBuilder};
+use prometheus::{Histogram, HistogramOpts};
+
+fn rate_limiter(max_requests_per_second: f64) -> Arc<HostRateLimiter> {
+    Arc::new(HostRateLimiter::new(
+        max_requests_per_second,
+        Histogram::with_opts(HistogramOpts::new("wait", "wait")).unwrap(),
+    ))
+}
+
+fn mock_indexer(name: &str) -> MockIndexer {
+    MockIndexerBuilder::new(name)
+        .deployment(DeploymentDetails {
+            deployment: gen_deployments().remove(0),
+            network: "mainnet".into(),
+            latest_block: gen_blocks()[5].clone(),
+            canonical_pois: vec![],
+            earliest_block_num: 0,
+        })
+        .build()
+}
+
+#[tokio::test]
+async fn requests_beyond_the_rate_wait() {
+    let limiter = rate_limiter(20.0);
+
+    // A second's worth of requests goes through right away.
+    let start = Instant::now();
+    for _ in 0..20 {
+        limiter.acquire("a.example.com").await;
+    }
+    assert!(start.elapsed() < Duration::from_millis(200));
+
+    let start = Instant::now();
+    for _ in 0..10 {
+        limiter.acquire("a.example.com").await;
+    }
+    assert!(start.elapsed() >= Duration::from_millis(450));
+
+    // Other hosts have buckets of their own.
+    let start = Instant::now();
+    limiter.acquire("b.example.com").await;
+    assert!(start.elapsed() < Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn indexers_on_the_same_host_share_the_limit() {
+    let limiter = rate_limiter(5.0);
+    let mut indexers = vec![];
+    let mut servers = vec![];
+    for name in ["a", "b"] {
+        let server = MockGraphNode::start(mock_indexer(name), HttpFaults::default())
+            .await
+            .unwrap();
+        let mock = mock_indexer(name);
+        indexers.push(Arc::new(
+            RealIndexer::new(
+                Some(name.to_string()),
+                mock.address(),
+                server.endpoint(),
+                metrics().public_proofs_of_indexing_requests.clone(),
+            )
+            .with_rate_limiter(limiter.clone()),
+        ));
+        servers.push(server);
+    }
+
+    // 5 requests are sent right away, the other 5 are spread over a second.
+    let start = Instant::now();
+    for _ in 0..5 {
+        for indexer in &indexers {
+            indexer.clone().indexing_statuses().await.unwrap();
+        }
+    }
+    assert!(start.elapsed() >= Duration::from_millis(900));
+}
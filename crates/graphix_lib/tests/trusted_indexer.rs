@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use graphix_lib::block_choice::{BlockChoicePolicy, PinnedBlocksConfig};
+use graphix_lib::graphql_api::api_types::ConsensusPoi;
+use graphix_lib::indexing_loop::{
+    agreement_with_trusted_indexers, query_indexing_statuses, query_proofs_of_indexing,
+};
+use graphix_lib::metrics;
+use graphix_lib::test_utils::gen::{gen_blocks, gen_deployments};
+use graphix_lib::test_utils::mocks::{
+    DeploymentDetails, MockIndexerBuilder, PartialProofOfIndexing,
+};
+use graphix_store::models::PoiSupport;
+
+#[tokio::test]
+async fn agreement_with_trusted_indexer() {
+    let deployments = gen_deployments();
+    let blocks = gen_blocks();
+    // Serves the given deployments, with the given PoI byte at every block.
+    let indexer = |name: &str, served: &[(usize, u8)]| {
+        MockIndexerBuilder::new(name)
+            .deployments(served.iter().map(|&(deployment, poi)| {
+                DeploymentDetails {
+                    deployment: deployments[deployment].clone(),
+                    network: "mainnet".into(),
+                    latest_block: blocks[5].clone(),
+                    canonical_pois: blocks
+                        .iter()
+                        .map(|block| PartialProofOfIndexing {
+                            block: block.clone(),
+                            proof_of_indexing: [poi; 32].into(),
+                        })
+                        .collect(),
+                    earliest_block_num: blocks[0].number,
+                }
+            }))
+            .client()
+    };
+
+    // The trusted indexer only serves the first deployment, where it's in the
+    // minority. The second deployment falls back to the majority.
+    let trusted = indexer("trusted", &[(0, 1)]);
+    let indexers = vec![
+        trusted.clone(),
+        indexer("agrees", &[(0, 1), (1, 3)]),
+        indexer("disagrees", &[(0, 2), (1, 3)]),
+        indexer("minority", &[(0, 1), (1, 4)]),
+        indexer("majority", &[(0, 2), (1, 3)]),
+    ];
+
+    let (indexing_statuses, _) =
+        query_indexing_statuses(&indexers, metrics(), Duration::ZERO, &[]).await;
+    let (pois, _, _) = query_proofs_of_indexing(
+        indexing_statuses.clone(),
+        BlockChoicePolicy::Earliest,
+        &PinnedBlocksConfig::default(),
+        &HashMap::new(),
+        metrics(),
+        Duration::ZERO,
+    )
+    .await;
+
+    let agreement = |trusted_indexers: HashMap<String, _>| {
+        agreement_with_trusted_indexers(&indexing_statuses, &pois, &trusted_indexers)
+            .into_iter()
+            .map(|(indexer, agrees)| (indexer.name().unwrap().into_owned(), agrees))
+            .collect::<HashMap<_, _>>()
+    };
+
+    let expected = [
+        ("agrees".to_string(), true),
+        ("disagrees".to_string(), false),
+        ("minority".to_string(), false),
+        ("majority".to_string(), false),
+    ];
+    assert_eq!(
+        agreement(HashMap::from([("mainnet".to_string(), trusted.address())])),
+        HashMap::from(expected)
+    );
+
+    // No trusted indexer on the network, nothing to compare against.
+    assert!(agreement(HashMap::from([("sepolia".to_string(), trusted.address())])).is_empty());
+}
+
+#[test]
+fn trusted_indexer_poi_is_the_consensus() {
+    let address = |byte: u8| [byte; 20].into();
+    let support = || {
+        vec![
+            PoiSupport {
+                block_number: 5,
+                poi: [1; 32].into(),
+                indexers: vec![address(1), address(2), address(3)],
+            },
+            PoiSupport {
+                block_number: 5,
+                poi: [2; 32].into(),
+                indexers: vec![address(4)],
+            },
+        ]
+    };
+    let consensus =
+        |trusted_indexer| ConsensusPoi::new("Qm".to_string(), 5, support(), 0.5, trusted_indexer);
+
+    // The trusted indexer is in the minority, but its PoI wins.
+    let trusted = consensus(Some(address(4)));
+    assert_eq!(trusted.poi, Some([2; 32].into()));
+    assert_eq!(trusted.supporting_indexers, vec![address(4)]);
+    assert_eq!(trusted.dissenting_pois.len(), 1);
+    assert_eq!(trusted.dissenting_pois[0].indexers.len(), 3);
+    assert!(trusted.has_consensus);
+    assert_eq!(trusted.trusted_indexer, Some(address(4)));
+
+    // Without a PoI of the trusted indexer, the majority wins.
+    let majority = consensus(Some(address(5)));
+    assert_eq!(majority.poi, Some([1; 32].into()));
+    assert_eq!(majority.n_supporting_indexers, 3);
+    assert!(majority.has_consensus);
+    assert_eq!(majority.trusted_indexer, None);
+}
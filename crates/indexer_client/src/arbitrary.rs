@@ -0,0 +1,72 @@
+//! [`Arbitrary`] implementations of the core types, for property tests.
+
+use std::sync::Arc;
+
+use graphix_common_types::{IndexerAddress, IpfsCid, PoiBytes};
+use quickcheck::{Arbitrary, Gen};
+
+use crate::{
+    BlockPointer, IndexerClient, IndexingStatus, ProofOfIndexing, RealIndexer, SubgraphDeployment,
+};
+
+/// An indexer with an arbitrary address. It's never queried, so its endpoint
+/// doesn't matter.
+fn arbitrary_indexer(g: &mut Gen) -> Arc<dyn IndexerClient> {
+    let public_poi_requests = prometheus::IntCounterVec::new(
+        prometheus::Opts::new("public_proofs_of_indexing_requests", "Unused"),
+        &["indexer", "success"],
+    )
+    .unwrap();
+
+    Arc::new(RealIndexer::new(
+        None,
+        IndexerAddress::arbitrary(g),
+        "http://localhost/status".to_string(),
+        public_poi_requests,
+    ))
+}
+
+impl Arbitrary for BlockPointer {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self {
+            number: u64::arbitrary(g),
+            hash: Option::arbitrary(g),
+        }
+    }
+}
+
+impl Arbitrary for SubgraphDeployment {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self::new(IpfsCid::arbitrary(g).to_string())
+    }
+}
+
+impl Arbitrary for IndexingStatus {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let latest_block = BlockPointer::arbitrary(g);
+        // Indexers can't have pruned blocks they haven't reached yet.
+        let earliest_block_num = u64::arbitrary(g).min(latest_block.number);
+
+        Self {
+            indexer: arbitrary_indexer(g),
+            deployment: SubgraphDeployment::arbitrary(g),
+            network: g
+                .choose(&["mainnet", "gnosis", "arbitrum-one"])
+                .unwrap()
+                .to_string(),
+            latest_block,
+            earliest_block_num,
+        }
+    }
+}
+
+impl Arbitrary for ProofOfIndexing {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Self {
+            indexer: arbitrary_indexer(g),
+            deployment: SubgraphDeployment::arbitrary(g),
+            block: BlockPointer::arbitrary(g),
+            proof_of_indexing: PoiBytes::arbitrary(g),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::error::Error as _;
+
+use thiserror::Error;
+
+/// Why a query to an indexer failed. Converts into [`anyhow::Error`] like any
+/// other error, for callers that don't care about the details.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum IndexerError {
+    /// The indexer didn't respond in time.
+    #[error("request timed out")]
+    Timeout,
+    /// The indexer's host name couldn't be resolved.
+    #[error("DNS resolution failed: {message}")]
+    Dns { message: String },
+    /// The TLS handshake failed, e.g. because of an invalid certificate.
+    #[error("TLS error: {message}")]
+    Tls { message: String },
+    /// The connection failed for another reason, e.g. because it was refused
+    /// or reset.
+    #[error("connection failed: {message}")]
+    Connection { message: String },
+    /// The indexer responded with an unsuccessful HTTP status, e.g. 429 Too
+    /// Many Requests, and no GraphQL errors.
+    #[error("HTTP status {status}")]
+    Http { status: u16 },
+    /// The indexer responded with GraphQL errors.
+    #[error("indexer returned errors: {}", errors.join(", "))]
+    GraphQl { errors: Vec<String> },
+    /// The indexer's response couldn't be parsed.
+    #[error("invalid response: {message}")]
+    Deserialization { message: String },
+    /// The indexer's response body was larger than the configured maximum, so
+    /// it was discarded without reading it in full.
+    #[error("response larger than {limit} bytes")]
+    ResponseTooLarge { limit: usize },
+    /// The indexer returned implausibly many results for what was requested,
+    /// e.g. statuses of deployments that weren't asked for.
+    #[error("{returned} results for {requested} requested")]
+    TooManyResults { requested: usize, returned: usize },
+    /// A range of PoIs covered more blocks than can be requested at once, see
+    /// [`crate::MAX_POI_RANGE_BLOCKS`]. The indexer wasn't queried.
+    #[error("range of {blocks} blocks, more than the limit of {limit}")]
+    RangeTooLarge { blocks: u64, limit: u64 },
+}
+
+impl IndexerError {
+    /// The name of the variant, e.g. `Timeout`, as recorded in the database
+    /// and in metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Timeout => "Timeout",
+            Self::Dns { .. } => "Dns",
+            Self::Tls { .. } => "Tls",
+            Self::Connection { .. } => "Connection",
+            Self::Http { .. } => "Http",
+            Self::GraphQl { .. } => "GraphQl",
+            Self::Deserialization { .. } => "Deserialization",
+            Self::ResponseTooLarge { .. } => "ResponseTooLarge",
+            Self::TooManyResults { .. } => "TooManyResults",
+            Self::RangeTooLarge { .. } => "RangeTooLarge",
+        }
+    }
+
+    pub fn deserialization(err: impl ToString) -> Self {
+        Self::Deserialization {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<reqwest::Error> for IndexerError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            return Self::Timeout;
+        }
+        if err.is_decode() {
+            return Self::deserialization(err);
+        }
+        if let Some(status) = err.status() {
+            return Self::Http {
+                status: status.as_u16(),
+            };
+        }
+
+        // `reqwest` doesn't tell DNS and TLS failures apart from other
+        // connection errors, and its own message only says that sending the
+        // request failed. The underlying errors are more helpful.
+        let mut message = err.to_string();
+        let mut source = err.source();
+        while let Some(err) = source {
+            let err_message = err.to_string();
+            // Some errors include their source in their message already.
+            if !message.contains(&err_message) {
+                message.push_str(": ");
+                message.push_str(&err_message);
+            }
+            source = err.source();
+        }
+
+        let lowercase = message.to_lowercase();
+        if lowercase.contains("dns error") || lowercase.contains("failed to lookup address") {
+            Self::Dns { message }
+        } else if ["tls", "ssl", "certificate", "handshake"]
+            .iter()
+            .any(|needle| lowercase.contains(needle))
+        {
+            Self::Tls { message }
+        } else {
+            Self::Connection { message }
+        }
+    }
+}
@@ -7,7 +7,10 @@ use async_trait::async_trait;
 use graphix_common_types::{GraphNodeCollectedVersion, IndexerAddress};
 
 use super::{CachedEthereumCall, EntityChanges};
-use crate::{IndexerClient, IndexingStatus, PoiRequest, ProofOfIndexing};
+use crate::{
+    IndexerClient, IndexerError, IndexerNameSource, IndexerSource, IndexingStatus, PoiRangeRequest,
+    PoiRequest, ProofOfIndexing, SubgraphDeployment,
+};
 
 /// Pretends to be an indexer by routing requests a
 /// [`RealIndexer`](crate::indexer::RealIndexer) and then intercepting the
@@ -23,6 +26,33 @@ impl IndexerInterceptor {
     pub fn new(target: Arc<dyn IndexerClient>, poi_byte: u8) -> Self {
         Self { target, poi_byte }
     }
+
+    fn hijack_statuses(self: &Arc<Self>, statuses: Vec<IndexingStatus>) -> Vec<IndexingStatus> {
+        statuses
+            .into_iter()
+            .map(|status| IndexingStatus {
+                indexer: self.clone(),
+                deployment: status.deployment,
+                network: status.network,
+                latest_block: status.latest_block,
+                earliest_block_num: status.earliest_block_num,
+            })
+            .collect()
+    }
+
+    fn hijack_pois(self: &Arc<Self>, pois: Vec<ProofOfIndexing>) -> Vec<ProofOfIndexing> {
+        pois.into_iter()
+            .map(|poi| {
+                let divergent_poi = [self.poi_byte; 32].into();
+                ProofOfIndexing {
+                    indexer: self.clone(),
+                    deployment: poi.deployment,
+                    block: poi.block,
+                    proof_of_indexing: divergent_poi,
+                }
+            })
+            .collect()
+    }
 }
 
 #[async_trait]
@@ -34,30 +64,44 @@ impl IndexerClient for IndexerInterceptor {
             .map(|name| Cow::Owned(format!("interceptor-{}", name)))
     }
 
+    fn name_source(&self) -> Option<IndexerNameSource> {
+        self.target.name_source()
+    }
+
+    fn source(&self) -> Option<IndexerSource> {
+        self.target.source()
+    }
+
     fn address(&self) -> IndexerAddress {
         self.target.address()
     }
 
-    async fn ping(self: Arc<Self>) -> anyhow::Result<()> {
+    fn endpoint(&self) -> Option<&str> {
+        self.target.endpoint()
+    }
+
+    async fn ping(self: Arc<Self>) -> Result<(), IndexerError> {
         self.target.clone().ping().await
     }
 
-    async fn indexing_statuses(self: Arc<Self>) -> Result<Vec<IndexingStatus>, anyhow::Error> {
+    async fn indexing_statuses(self: Arc<Self>) -> Result<Vec<IndexingStatus>, IndexerError> {
         let statuses = self.target.clone().indexing_statuses().await?;
-        let hijacked_statuses = statuses
-            .into_iter()
-            .map(|status| IndexingStatus {
-                indexer: self.clone(),
-                deployment: status.deployment,
-                network: status.network,
-                latest_block: status.latest_block,
-                earliest_block_num: status.earliest_block_num,
-            })
-            .collect();
-        Ok(hijacked_statuses)
+        Ok(self.hijack_statuses(statuses))
+    }
+
+    async fn indexing_statuses_of(
+        self: Arc<Self>,
+        deployments: &[SubgraphDeployment],
+    ) -> Result<Vec<IndexingStatus>, IndexerError> {
+        let statuses = self
+            .target
+            .clone()
+            .indexing_statuses_of(deployments)
+            .await?;
+        Ok(self.hijack_statuses(statuses))
     }
 
-    async fn version(self: Arc<Self>) -> anyhow::Result<GraphNodeCollectedVersion> {
+    async fn version(self: Arc<Self>) -> Result<GraphNodeCollectedVersion, IndexerError> {
         self.target.clone().version().await
     }
 
@@ -66,24 +110,25 @@ impl IndexerClient for IndexerInterceptor {
         requests: Vec<PoiRequest>,
     ) -> Vec<ProofOfIndexing> {
         let pois = self.target.clone().proofs_of_indexing(requests).await;
+        self.hijack_pois(pois)
+    }
 
-        pois.into_iter()
-            .map(|poi| {
-                let divergent_poi = [self.poi_byte; 32].into();
-                ProofOfIndexing {
-                    indexer: self.clone(),
-                    deployment: poi.deployment,
-                    block: poi.block,
-                    proof_of_indexing: divergent_poi,
-                }
-            })
-            .collect()
+    async fn proofs_of_indexing_range(
+        self: Arc<Self>,
+        request: PoiRangeRequest,
+    ) -> Result<Vec<ProofOfIndexing>, IndexerError> {
+        let pois = self
+            .target
+            .clone()
+            .proofs_of_indexing_range(request)
+            .await?;
+        Ok(self.hijack_pois(pois))
     }
 
     async fn subgraph_api_versions(
         self: Arc<Self>,
         subgraph_id: &str,
-    ) -> anyhow::Result<Vec<String>> {
+    ) -> Result<Vec<String>, IndexerError> {
         self.target.clone().subgraph_api_versions(subgraph_id).await
     }
 
@@ -91,7 +136,7 @@ impl IndexerClient for IndexerInterceptor {
         self: Arc<Self>,
         network: &str,
         block_hash: &[u8],
-    ) -> anyhow::Result<Vec<CachedEthereumCall>> {
+    ) -> Result<Vec<CachedEthereumCall>, IndexerError> {
         self.target
             .clone()
             .cached_eth_calls(network, block_hash)
@@ -102,7 +147,7 @@ impl IndexerClient for IndexerInterceptor {
         self: Arc<Self>,
         network: &str,
         block_hash: &[u8],
-    ) -> anyhow::Result<Option<serde_json::Value>> {
+    ) -> Result<Option<serde_json::Value>, IndexerError> {
         self.target
             .clone()
             .block_cache_contents(network, block_hash)
@@ -113,7 +158,7 @@ impl IndexerClient for IndexerInterceptor {
         self: Arc<Self>,
         subgraph_id: &str,
         block_number: u64,
-    ) -> anyhow::Result<EntityChanges> {
+    ) -> Result<EntityChanges, IndexerError> {
         self.target
             .clone()
             .entity_changes(subgraph_id, block_number)
@@ -1,4 +1,9 @@
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
+mod error;
 mod interceptor;
+mod poi_cache;
+mod rate_limiter;
 mod real_indexer;
 
 use std::borrow::Cow;
@@ -6,13 +11,19 @@ use std::collections::HashMap;
 use std::fmt::{self, Debug};
 use std::hash::Hash;
 use std::ops::Deref;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use anyhow::anyhow;
 use async_trait::async_trait;
-use graphix_common_types::{BlockHash, GraphNodeCollectedVersion, IndexerAddress, PoiBytes};
+pub use error::IndexerError;
+use graphix_common_types::{
+    BlockHash, GraphNodeCollectedVersion, IndexerAddress, IpfsCid, PoiBytes,
+};
 pub use interceptor::IndexerInterceptor;
-pub use real_indexer::RealIndexer;
+pub use poi_cache::{CachingIndexer, PoiCache};
+pub use rate_limiter::HostRateLimiter;
+pub use real_indexer::{RealIndexer, DEFAULT_MAX_RESPONSE_BYTES};
 use serde::Serialize;
 
 /// An indexer is a `graph-node` instance that can be queried for information.
@@ -28,19 +39,62 @@ pub trait IndexerClient: Send + Sync + Debug {
     /// Human-readable name of the indexer.
     fn name(&self) -> Option<Cow<str>>;
 
-    async fn ping(self: Arc<Self>) -> anyhow::Result<()>;
+    /// Where [`IndexerClient::name`] comes from, if the indexer has a name.
+    fn name_source(&self) -> Option<IndexerNameSource> {
+        None
+    }
+
+    /// Where the indexer was configured from, if known.
+    fn source(&self) -> Option<IndexerSource> {
+        None
+    }
+
+    /// The URL of the indexer's status endpoint, if it has one.
+    fn endpoint(&self) -> Option<&str> {
+        None
+    }
+
+    async fn ping(self: Arc<Self>) -> Result<(), IndexerError>;
 
-    async fn indexing_statuses(self: Arc<Self>) -> anyhow::Result<Vec<IndexingStatus>>;
+    async fn indexing_statuses(self: Arc<Self>) -> Result<Vec<IndexingStatus>, IndexerError>;
+
+    /// Like [`IndexerClient::indexing_statuses`], but only for the given
+    /// deployments, so that indexers with many deployments don't have to send
+    /// all of their statuses. By default, the statuses are filtered after
+    /// querying all of them.
+    async fn indexing_statuses_of(
+        self: Arc<Self>,
+        deployments: &[SubgraphDeployment],
+    ) -> Result<Vec<IndexingStatus>, IndexerError> {
+        let statuses = self.indexing_statuses().await?;
+        Ok(statuses
+            .into_iter()
+            .filter(|status| deployments.contains(&status.deployment))
+            .collect())
+    }
 
     async fn proofs_of_indexing(self: Arc<Self>, requests: Vec<PoiRequest>)
         -> Vec<ProofOfIndexing>;
 
-    async fn version(self: Arc<Self>) -> anyhow::Result<GraphNodeCollectedVersion>;
+    /// Fetches the PoIs of a range of blocks at once, e.g. to pre-fetch the
+    /// candidate blocks of a bisection. Blocks for which the indexer has no
+    /// PoI are left out. Ranges of more than [`MAX_POI_RANGE_BLOCKS`] blocks
+    /// fail without querying the indexer. By default, this is the same as
+    /// requesting every block with [`IndexerClient::proofs_of_indexing`].
+    async fn proofs_of_indexing_range(
+        self: Arc<Self>,
+        request: PoiRangeRequest,
+    ) -> Result<Vec<ProofOfIndexing>, IndexerError> {
+        let requests = request.poi_requests()?;
+        Ok(self.proofs_of_indexing(requests).await)
+    }
+
+    async fn version(self: Arc<Self>) -> Result<GraphNodeCollectedVersion, IndexerError>;
 
     async fn subgraph_api_versions(
         self: Arc<Self>,
         subgraph_id: &str,
-    ) -> anyhow::Result<Vec<String>>;
+    ) -> Result<Vec<String>, IndexerError>;
 
     /// Convenience wrapper around calling [`IndexerClient::proofs_of_indexing`] for a
     /// single POI.
@@ -61,21 +115,21 @@ pub trait IndexerClient: Send + Sync + Debug {
         self: Arc<Self>,
         network: &str,
         block_hash: &[u8],
-    ) -> anyhow::Result<Vec<CachedEthereumCall>>;
+    ) -> Result<Vec<CachedEthereumCall>, IndexerError>;
 
     /// Returns the block cache contents for the given block hash.
     async fn block_cache_contents(
         self: Arc<Self>,
         network: &str,
         block_hash: &[u8],
-    ) -> anyhow::Result<Option<serde_json::Value>>;
+    ) -> Result<Option<serde_json::Value>, IndexerError>;
 
     /// Returns the entity changes for the given block number.
     async fn entity_changes(
         self: Arc<Self>,
         subgraph_id: &str,
         block_number: u64,
-    ) -> anyhow::Result<EntityChanges>;
+    ) -> Result<EntityChanges, IndexerError>;
 }
 
 /// Graphix defines an indexer's ID as either its Ethereum address (if it has
@@ -193,6 +247,18 @@ impl fmt::Display for BlockPointer {
 #[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub struct SubgraphDeployment(pub String);
 
+impl SubgraphDeployment {
+    /// Canonicalizes deployment IDs that are valid IPFS CIDs (see [`IpfsCid`]),
+    /// so that the CIDv0 and CIDv1 forms of a deployment ID are equal.
+    pub fn new(id: impl Into<String>) -> Self {
+        let id = id.into();
+        match IpfsCid::from_str(&id) {
+            Ok(cid) => Self(cid.to_string()),
+            Err(_) => Self(id),
+        }
+    }
+}
+
 impl Deref for SubgraphDeployment {
     type Target = String;
 
@@ -243,6 +309,8 @@ pub trait WritablePoi {
     fn indexer_id(&self) -> Self::IndexerId;
     fn block(&self) -> &BlockPointer;
     fn proof_of_indexing(&self) -> &PoiBytes;
+    /// The URL that the PoI was queried from, if known.
+    fn source_url(&self) -> Option<&str>;
 }
 
 impl WritablePoi for ProofOfIndexing {
@@ -263,6 +331,10 @@ impl WritablePoi for ProofOfIndexing {
     fn proof_of_indexing(&self) -> &PoiBytes {
         &self.proof_of_indexing
     }
+
+    fn source_url(&self) -> Option<&str> {
+        self.indexer.endpoint()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
@@ -284,3 +356,144 @@ pub struct PoiRequest {
     pub deployment: SubgraphDeployment,
     pub block_number: u64,
 }
+
+/// The most blocks that a [`PoiRangeRequest`] may cover, so that a single
+/// request can't make Graphix fetch and hold arbitrarily many PoIs.
+pub const MAX_POI_RANGE_BLOCKS: u64 = 1000;
+
+/// A request for the PoIs of every `step` blocks from `from_block` to
+/// `to_block` (inclusive) of a subgraph deployment.
+#[derive(Debug, Clone)]
+pub struct PoiRangeRequest {
+    pub deployment: SubgraphDeployment,
+    pub from_block: u64,
+    pub to_block: u64,
+    /// A step of 0 is treated as 1.
+    pub step: u64,
+}
+
+impl PoiRangeRequest {
+    pub fn block_numbers(&self) -> impl Iterator<Item = u64> {
+        let step = usize::try_from(self.step.max(1)).unwrap_or(usize::MAX);
+        (self.from_block..=self.to_block).step_by(step)
+    }
+
+    /// The number of blocks in the range, i.e. of [`Self::block_numbers`].
+    pub fn block_count(&self) -> u64 {
+        match self.to_block.checked_sub(self.from_block) {
+            Some(distance) => distance / self.step.max(1) + 1,
+            None => 0,
+        }
+    }
+
+    /// Splits the range into single-block requests. Fails for ranges of more
+    /// than [`MAX_POI_RANGE_BLOCKS`] blocks.
+    pub fn poi_requests(&self) -> Result<Vec<PoiRequest>, IndexerError> {
+        let blocks = self.block_count();
+        if blocks > MAX_POI_RANGE_BLOCKS {
+            return Err(IndexerError::RangeTooLarge {
+                blocks,
+                limit: MAX_POI_RANGE_BLOCKS,
+            });
+        }
+
+        Ok(self
+            .block_numbers()
+            .map(|block_number| PoiRequest {
+                deployment: self.deployment.clone(),
+                block_number,
+            })
+            .collect())
+    }
+}
+
+/// The outcome of querying a single indexer, e.g. for its indexing statuses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryOutcome {
+    /// The query succeeded and returned this many results.
+    Success { results: usize },
+    Failure {
+        error: String,
+        /// The [`IndexerError::kind`] of the error, if the indexer client
+        /// reported one.
+        kind: Option<&'static str>,
+    },
+}
+
+impl From<&IndexerError> for QueryOutcome {
+    fn from(error: &IndexerError) -> Self {
+        Self::Failure {
+            error: error.to_string(),
+            kind: Some(error.kind()),
+        }
+    }
+}
+
+/// Where the human-readable name of an indexer comes from. Variants are
+/// ordered by precedence, lowest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, async_graphql::Enum)]
+pub enum IndexerNameSource {
+    /// The ENS name of the indexer's account, according to the network
+    /// subgraph.
+    Ens,
+    /// The indexer's `defaultDisplayName` in the network subgraph.
+    NetworkSubgraph,
+    /// Set explicitly in the Graphix configuration.
+    Config,
+}
+
+impl IndexerNameSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ens => "ens",
+            Self::NetworkSubgraph => "network_subgraph",
+            Self::Config => "config",
+        }
+    }
+}
+
+impl std::str::FromStr for IndexerNameSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ens" => Ok(Self::Ens),
+            "network_subgraph" => Ok(Self::NetworkSubgraph),
+            "config" => Ok(Self::Config),
+            _ => Err(anyhow!("unknown indexer name source: {}", s)),
+        }
+    }
+}
+
+/// Where an indexer was configured from. The same indexer can come from
+/// several sources, in which case Graphix keeps the one with the highest
+/// precedence. Variants are ordered by precedence, lowest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, async_graphql::Enum)]
+pub enum IndexerSource {
+    /// Found in a network subgraph.
+    NetworkSubgraph,
+    /// Listed explicitly in the Graphix configuration, including indexers
+    /// that are configured by address and looked up in a network subgraph.
+    Config,
+}
+
+impl IndexerSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NetworkSubgraph => "network_subgraph",
+            Self::Config => "config",
+        }
+    }
+}
+
+impl std::str::FromStr for IndexerSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "network_subgraph" => Ok(Self::NetworkSubgraph),
+            "config" => Ok(Self::Config),
+            _ => Err(anyhow!("unknown indexer source: {}", s)),
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! An in-memory, size-bounded cache for PoI responses.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use graphix_common_types::{GraphNodeCollectedVersion, IndexerAddress, PoiBytes};
+
+use super::{CachedEthereumCall, EntityChanges};
+use crate::{
+    BlockPointer, IndexerClient, IndexerError, IndexerNameSource, IndexerSource, IndexingStatus,
+    PoiRangeRequest, PoiRequest, ProofOfIndexing, SubgraphDeployment,
+};
+
+/// Uniquely identifies a PoI request sent to a specific indexer. Interceptors
+/// share their target's address, so the name is part of the key too.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PoiCacheKey {
+    indexer_address: IndexerAddress,
+    indexer_name: Option<String>,
+    deployment: SubgraphDeployment,
+    block_number: u64,
+}
+
+impl PoiCacheKey {
+    fn new(
+        indexer: &dyn IndexerClient,
+        deployment: &SubgraphDeployment,
+        block_number: u64,
+    ) -> Self {
+        Self {
+            indexer_address: indexer.address(),
+            indexer_name: indexer.name().map(Cow::into_owned),
+            deployment: deployment.clone(),
+            block_number,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PoiCacheEntry {
+    block: BlockPointer,
+    proof_of_indexing: PoiBytes,
+    inserted_at: Instant,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct PoiCacheInner {
+    entries: HashMap<PoiCacheKey, PoiCacheEntry>,
+    /// Keys ordered by the logical time of their last use, least recently
+    /// used first.
+    recency: BTreeMap<u64, PoiCacheKey>,
+    clock: u64,
+}
+
+impl PoiCacheInner {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn remove(&mut self, key: &PoiCacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+        }
+    }
+}
+
+/// A least-recently-used cache of PoI responses, shared by all indexers.
+/// Entries expire after a fixed TTL, so that PoIs for the same block aren't
+/// fetched over and over again e.g. during bisection.
+#[derive(Debug)]
+pub struct PoiCache {
+    capacity: usize,
+    ttl: Duration,
+    inner: Mutex<PoiCacheInner>,
+    // Metrics
+    // -------
+    hits: prometheus::IntCounter,
+    misses: prometheus::IntCounter,
+}
+
+impl PoiCache {
+    pub fn new(
+        capacity: usize,
+        ttl: Duration,
+        hits: prometheus::IntCounter,
+        misses: prometheus::IntCounter,
+    ) -> Self {
+        Self {
+            capacity,
+            ttl,
+            inner: Mutex::new(PoiCacheInner::default()),
+            hits,
+            misses,
+        }
+    }
+
+    /// The number of (possibly expired) entries currently in the cache.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, key: &PoiCacheKey) -> Option<(BlockPointer, PoiBytes)> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let is_fresh = match inner.entries.get(key) {
+            Some(entry) => entry.inserted_at.elapsed() < self.ttl,
+            None => {
+                self.misses.inc();
+                return None;
+            }
+        };
+        if !is_fresh {
+            inner.remove(key);
+            self.misses.inc();
+            return None;
+        }
+
+        let now = inner.tick();
+        let entry = inner.entries.get_mut(key).unwrap();
+        let previously_used = std::mem::replace(&mut entry.last_used, now);
+        let value = (entry.block.clone(), entry.proof_of_indexing);
+        inner.recency.remove(&previously_used);
+        inner.recency.insert(now, key.clone());
+
+        self.hits.inc();
+        Some(value)
+    }
+
+    fn insert(&self, key: PoiCacheKey, block: BlockPointer, proof_of_indexing: PoiBytes) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(&key);
+
+        while inner.entries.len() >= self.capacity {
+            let Some((_, lru_key)) = inner.recency.pop_first() else {
+                break;
+            };
+            inner.entries.remove(&lru_key);
+        }
+
+        let now = inner.tick();
+        inner.recency.insert(now, key.clone());
+        inner.entries.insert(
+            key,
+            PoiCacheEntry {
+                block,
+                proof_of_indexing,
+                inserted_at: Instant::now(),
+                last_used: now,
+            },
+        );
+    }
+}
+
+/// Wraps an indexer and memoizes its PoI responses in a [`PoiCache`]. All
+/// other requests are forwarded to the wrapped indexer as-is.
+#[derive(Debug)]
+pub struct CachingIndexer {
+    target: Arc<dyn IndexerClient>,
+    cache: Arc<PoiCache>,
+}
+
+impl CachingIndexer {
+    pub fn new(target: Arc<dyn IndexerClient>, cache: Arc<PoiCache>) -> Self {
+        Self { target, cache }
+    }
+
+    /// Answers `requests` from the cache where possible, and returns the
+    /// requests that aren't cached.
+    fn cached_pois(
+        self: &Arc<Self>,
+        requests: Vec<PoiRequest>,
+    ) -> (Vec<ProofOfIndexing>, Vec<PoiRequest>) {
+        let mut pois = vec![];
+        let mut uncached_requests = vec![];
+
+        for request in requests {
+            let key = PoiCacheKey::new(self.as_ref(), &request.deployment, request.block_number);
+            match self.cache.get(&key) {
+                Some((block, proof_of_indexing)) => pois.push(ProofOfIndexing {
+                    indexer: self.clone(),
+                    deployment: request.deployment,
+                    block,
+                    proof_of_indexing,
+                }),
+                None => uncached_requests.push(request),
+            }
+        }
+
+        (pois, uncached_requests)
+    }
+
+    /// Caches PoIs fetched from the target, and makes them point back to the
+    /// cache.
+    fn cache_pois(self: &Arc<Self>, pois: Vec<ProofOfIndexing>) -> Vec<ProofOfIndexing> {
+        pois.into_iter()
+            .map(|poi| {
+                let key = PoiCacheKey::new(self.as_ref(), &poi.deployment, poi.block.number);
+                self.cache
+                    .insert(key, poi.block.clone(), poi.proof_of_indexing);
+                ProofOfIndexing {
+                    indexer: self.clone(),
+                    ..poi
+                }
+            })
+            .collect()
+    }
+
+    /// PoIs are requested through the indexers found in indexing statuses,
+    /// so these must point back to the cache.
+    fn with_own_statuses(self: &Arc<Self>, statuses: Vec<IndexingStatus>) -> Vec<IndexingStatus> {
+        statuses
+            .into_iter()
+            .map(|status| IndexingStatus {
+                indexer: self.clone(),
+                ..status
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl IndexerClient for CachingIndexer {
+    fn name(&self) -> Option<Cow<str>> {
+        self.target.name()
+    }
+
+    fn name_source(&self) -> Option<IndexerNameSource> {
+        self.target.name_source()
+    }
+
+    fn source(&self) -> Option<IndexerSource> {
+        self.target.source()
+    }
+
+    fn endpoint(&self) -> Option<&str> {
+        self.target.endpoint()
+    }
+
+    fn address(&self) -> IndexerAddress {
+        self.target.address()
+    }
+
+    async fn ping(self: Arc<Self>) -> Result<(), IndexerError> {
+        self.target.clone().ping().await
+    }
+
+    async fn indexing_statuses(self: Arc<Self>) -> Result<Vec<IndexingStatus>, IndexerError> {
+        let statuses = self.target.clone().indexing_statuses().await?;
+        Ok(self.with_own_statuses(statuses))
+    }
+
+    async fn indexing_statuses_of(
+        self: Arc<Self>,
+        deployments: &[SubgraphDeployment],
+    ) -> Result<Vec<IndexingStatus>, IndexerError> {
+        let statuses = self
+            .target
+            .clone()
+            .indexing_statuses_of(deployments)
+            .await?;
+        Ok(self.with_own_statuses(statuses))
+    }
+
+    async fn version(self: Arc<Self>) -> Result<GraphNodeCollectedVersion, IndexerError> {
+        self.target.clone().version().await
+    }
+
+    async fn proofs_of_indexing(
+        self: Arc<Self>,
+        requests: Vec<PoiRequest>,
+    ) -> Vec<ProofOfIndexing> {
+        let (mut pois, uncached_requests) = self.cached_pois(requests);
+        if uncached_requests.is_empty() {
+            return pois;
+        }
+
+        let fetched_pois = self
+            .target
+            .clone()
+            .proofs_of_indexing(uncached_requests)
+            .await;
+        pois.extend(self.cache_pois(fetched_pois));
+        pois
+    }
+
+    async fn proofs_of_indexing_range(
+        self: Arc<Self>,
+        request: PoiRangeRequest,
+    ) -> Result<Vec<ProofOfIndexing>, IndexerError> {
+        let (mut pois, uncached_requests) = self.cached_pois(request.poi_requests()?);
+        if uncached_requests.is_empty() {
+            return Ok(pois);
+        }
+
+        // Only forward the whole range when nothing is cached, so the target
+        // can still batch it.
+        let fetched_pois = if pois.is_empty() {
+            self.target
+                .clone()
+                .proofs_of_indexing_range(request)
+                .await?
+        } else {
+            self.target
+                .clone()
+                .proofs_of_indexing(uncached_requests)
+                .await
+        };
+        pois.extend(self.cache_pois(fetched_pois));
+        Ok(pois)
+    }
+
+    async fn subgraph_api_versions(
+        self: Arc<Self>,
+        subgraph_id: &str,
+    ) -> Result<Vec<String>, IndexerError> {
+        self.target.clone().subgraph_api_versions(subgraph_id).await
+    }
+
+    async fn cached_eth_calls(
+        self: Arc<Self>,
+        network: &str,
+        block_hash: &[u8],
+    ) -> Result<Vec<CachedEthereumCall>, IndexerError> {
+        self.target
+            .clone()
+            .cached_eth_calls(network, block_hash)
+            .await
+    }
+
+    async fn block_cache_contents(
+        self: Arc<Self>,
+        network: &str,
+        block_hash: &[u8],
+    ) -> Result<Option<serde_json::Value>, IndexerError> {
+        self.target
+            .clone()
+            .block_cache_contents(network, block_hash)
+            .await
+    }
+
+    async fn entity_changes(
+        self: Arc<Self>,
+        subgraph_id: &str,
+        block_number: u64,
+    ) -> Result<EntityChanges, IndexerError> {
+        self.target
+            .clone()
+            .entity_changes(subgraph_id, block_number)
+            .await
+    }
+}
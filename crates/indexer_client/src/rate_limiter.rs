@@ -0,0 +1,75 @@
+//! A per-host rate limiter for outgoing requests to indexers.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct TokenBucket {
+    /// May be negative, in which case that many requests are already waiting
+    /// for tokens.
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// A token bucket per host, shared by all indexers, so that hosts that back
+/// many indexers or deployments don't receive more than a fixed number of
+/// requests per second. Requests beyond the rate wait for their turn rather
+/// than fail. Up to a second's worth of requests may be sent at once.
+#[derive(Debug)]
+pub struct HostRateLimiter {
+    max_requests_per_second: f64,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+    // Metrics
+    // -------
+    wait_seconds: prometheus::Histogram,
+}
+
+impl HostRateLimiter {
+    pub fn new(max_requests_per_second: f64, wait_seconds: prometheus::Histogram) -> Self {
+        assert!(
+            max_requests_per_second > 0.0,
+            "rate limit must be greater than 0"
+        );
+
+        Self {
+            max_requests_per_second,
+            buckets: Mutex::new(HashMap::new()),
+            wait_seconds,
+        }
+    }
+
+    /// Waits until a request to `host` may be sent.
+    pub async fn acquire(&self, host: &str) {
+        let wait = self.reserve(host, Instant::now());
+        self.wait_seconds.observe(wait.as_secs_f64());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Takes a token from the bucket of `host`, and returns how long to wait
+    /// until it's actually available.
+    fn reserve(&self, host: &str, now: Instant) -> Duration {
+        let capacity = self.max_requests_per_second.max(1.0);
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets
+            .entry(host.to_string())
+            .or_insert_with(|| TokenBucket {
+                tokens: capacity,
+                refilled_at: now,
+            });
+
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens =
+            (bucket.tokens + elapsed.as_secs_f64() * self.max_requests_per_second).min(capacity);
+        bucket.refilled_at = now;
+        bucket.tokens -= 1.0;
+
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / self.max_requests_per_second)
+        }
+    }
+}
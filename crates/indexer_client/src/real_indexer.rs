@@ -2,7 +2,7 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use anyhow::{anyhow, Context};
+use anyhow::anyhow;
 use async_trait::async_trait;
 use graphix_common_types::IndexerAddress;
 use graphql_client::{GraphQLQuery, Response};
@@ -12,20 +12,38 @@ use tracing::*;
 
 use super::{CachedEthereumCall, EntityChanges, IndexerClient};
 use crate::{
-    GraphNodeCollectedVersion, IndexerId, IndexingStatus, PoiRequest, ProofOfIndexing, WithIndexer,
+    GraphNodeCollectedVersion, HostRateLimiter, IndexerError, IndexerId, IndexerNameSource,
+    IndexerSource, IndexingStatus, PoiRangeRequest, PoiRequest, ProofOfIndexing,
+    SubgraphDeployment, WithIndexer,
 };
 
 const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+/// The default of [`RealIndexer::with_max_response_bytes`], generous enough for
+/// the indexing statuses of `graph-node` instances with tens of thousands of
+/// deployments.
+pub const DEFAULT_MAX_RESPONSE_BYTES: usize = 20 * 1024 * 1024;
+/// Queries that ask for specific items, e.g. the statuses of some
+/// deployments, fail with [`IndexerError::TooManyResults`] if they return
+/// more than this many results per item. Not 1, because some `graph-node`
+/// versions report deployments twice.
+const MAX_RESULTS_PER_REQUESTED_ITEM: usize = 2;
+/// Graph Node implements a limit of 10 PoI requests per query.
+const MAX_POI_REQUESTS_PER_QUERY: usize = 10;
 
 #[derive(Debug)]
 pub struct RealIndexer {
     address: IndexerAddress,
     name: Option<String>,
+    name_source: Option<IndexerNameSource>,
+    source: Option<IndexerSource>,
     endpoint: String,
     client: reqwest::Client,
+    rate_limiter: Option<Arc<HostRateLimiter>>,
+    max_response_bytes: usize,
     // Metrics
     // -------
     public_poi_requests: prometheus::IntCounterVec,
+    responses_too_large: Option<prometheus::IntCounterVec>,
 }
 
 impl RealIndexer {
@@ -37,14 +55,82 @@ impl RealIndexer {
         public_poi_requests: prometheus::IntCounterVec,
     ) -> Self {
         Self {
-            name,
+            // Missing names are `None` rather than empty strings.
+            name: name.filter(|name| !name.trim().is_empty()),
+            name_source: None,
+            source: None,
             address,
             endpoint,
             client: reqwest::Client::new(),
+            rate_limiter: None,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
             public_poi_requests,
+            responses_too_large: None,
         }
     }
 
+    /// Records where the indexer's name comes from, if it has one.
+    pub fn with_name_source(mut self, name_source: IndexerNameSource) -> Self {
+        self.name_source = Some(name_source);
+        self
+    }
+
+    /// Replaces the indexer's name, e.g. with one from the configuration.
+    pub fn with_name(self, name: String, name_source: IndexerNameSource) -> Self {
+        Self {
+            name: Some(name).filter(|name| !name.trim().is_empty()),
+            ..self
+        }
+        .with_name_source(name_source)
+    }
+
+    /// Records where the indexer was configured from.
+    pub fn with_source(mut self, source: IndexerSource) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Sends requests with the given HTTP client instead of a default one,
+    /// e.g. to share default headers and connection pools between indexers.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Waits for `rate_limiter` before every request, which is shared with
+    /// other indexers on the same host.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<HostRateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Fails queries whose response bodies are larger than `max_response_bytes`
+    /// with [`IndexerError::ResponseTooLarge`], so that misbehaving indexers
+    /// can't exhaust memory.
+    pub fn with_max_response_bytes(mut self, max_response_bytes: usize) -> Self {
+        self.max_response_bytes = max_response_bytes;
+        self
+    }
+
+    /// Counts the responses that exceed `max_response_bytes`, by indexer
+    /// address.
+    pub fn with_responses_too_large(
+        mut self,
+        responses_too_large: prometheus::IntCounterVec,
+    ) -> Self {
+        self.responses_too_large = Some(responses_too_large);
+        self
+    }
+
+    /// The host of the indexer's endpoint, which is what requests are rate
+    /// limited by. Falls back to the whole endpoint if it's not a valid URL.
+    fn host(&self) -> String {
+        reqwest::Url::parse(&self.endpoint)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| self.endpoint.clone())
+    }
+
     /// Internal utility method to make a GraphQL query to the indexer. `error`
     /// and `data` fields are treated as mutually exclusive (which is generally
     /// a good assumption, but some callers may want more control over error
@@ -52,7 +138,11 @@ impl RealIndexer {
     async fn graphql_query<I: Serialize, O: DeserializeOwned>(
         &self,
         request: I,
-    ) -> anyhow::Result<O> {
+    ) -> Result<O, IndexerError> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire(&self.host()).await;
+        }
+
         let response_raw = self
             .client
             .post(self.endpoint.clone())
@@ -60,26 +150,109 @@ impl RealIndexer {
             .json(&request)
             .send()
             .await?;
+        let status = response_raw.status();
+        let body = match read_body(response_raw, self.max_response_bytes).await {
+            Ok(body) => body,
+            Err(err @ IndexerError::ResponseTooLarge { .. }) => {
+                warn!(
+                    indexer = %self.address_string(),
+                    limit = self.max_response_bytes,
+                    "Indexer response too large, discarding it"
+                );
+                if let Some(responses_too_large) = &self.responses_too_large {
+                    responses_too_large
+                        .with_label_values(&[&self.address_string()])
+                        .inc();
+                }
+                return Err(err);
+            }
+            Err(err) => return Err(err),
+        };
 
-        let response: Response<O> = response_raw.json().await?;
+        // `graph-node` responds to some invalid queries with both GraphQL
+        // errors and an unsuccessful status. The errors say more.
+        let response = serde_json::from_slice::<Response<O>>(&body);
+        if let Ok(Response {
+            errors: Some(errors),
+            ..
+        }) = &response
+        {
+            let errors: Vec<String> = errors.iter().map(|e| e.message.clone()).collect();
+            warn!(errors = %errors.join(","), "Indexer returned errors");
+            return Err(IndexerError::GraphQl { errors });
+        }
+        if !status.is_success() {
+            return Err(IndexerError::Http {
+                status: status.as_u16(),
+            });
+        }
 
-        if let Some(errors) = response.errors {
-            let errors = errors
-                .iter()
-                .map(|e| e.message.clone())
-                .collect::<Vec<_>>()
-                .join(",");
-            warn!(%errors, "Indexer returned errors");
-            return Err(anyhow::anyhow!("Indexer returned errors: {}", errors));
+        response
+            .map_err(IndexerError::deserialization)?
+            .data
+            .ok_or_else(|| IndexerError::deserialization("indexer returned no data"))
+    }
+
+    /// graph-node doesn't list the features it supports directly, but its
+    /// index-node API has a `Feature` enum with all of them. Returns `None` for
+    /// graph-node versions that don't have it.
+    async fn supported_features(&self) -> Result<Option<Vec<String>>, IndexerError> {
+        let request = graphql_client::QueryBody {
+            variables: (),
+            query: gql_types::SUPPORTED_FEATURES_QUERY,
+            operation_name: "SupportedFeatures",
+        };
+
+        let response: gql_types::SupportedFeaturesResponse = self.graphql_query(request).await?;
+
+        Ok(response
+            .feature_enum
+            .and_then(|feature_enum| feature_enum.enum_values)
+            .map(|values| values.into_iter().map(|value| value.name).collect()))
+    }
+
+    /// Queries the indexing statuses of `subgraphs`, or of all deployments
+    /// if `None`.
+    async fn query_indexing_statuses(
+        self: Arc<Self>,
+        subgraphs: Option<Vec<String>>,
+    ) -> Result<Vec<IndexingStatus>, IndexerError> {
+        let requested = subgraphs.as_ref().map(Vec::len);
+        let request =
+            gql_types::IndexingStatuses::build_query(gql_types::indexing_statuses::Variables {
+                subgraphs,
+            });
+
+        let response: gql_types::indexing_statuses::ResponseData =
+            self.graphql_query(request).await?;
+        if let Some(requested) = requested {
+            check_result_count(requested, response.indexing_statuses.len())?;
+        }
+
+        let mut statuses = vec![];
+        for indexing_status in response.indexing_statuses {
+            let deployment = indexing_status.subgraph.clone();
+
+            match WithIndexer::new(self.clone(), indexing_status).try_into() {
+                Ok(status) => statuses.push(status),
+                Err(e) => {
+                    warn!(
+                        address = %self.address_string(),
+                        %e,
+                        %deployment,
+                        "Failed to parse indexing status, skipping deployment"
+                    );
+                }
+            }
         }
 
-        response.data.context("Indexer returned no data")
+        Ok(statuses)
     }
 
     async fn proofs_of_indexing_batch(
         self: Arc<Self>,
         requests: &[PoiRequest],
-    ) -> Result<Vec<ProofOfIndexing>, anyhow::Error> {
+    ) -> Result<Vec<ProofOfIndexing>, IndexerError> {
         use gql_types::proofs_of_indexing::{
             PublicProofOfIndexingRequest, ResponseData, Variables,
         };
@@ -94,14 +267,51 @@ impl RealIndexer {
         });
 
         let response: ResponseData = self.graphql_query(request).await?;
+        check_result_count(requests.len(), response.public_proofs_of_indexing.len())?;
 
         // Parse POI results
         response
             .public_proofs_of_indexing
             .into_iter()
             .map(|result| WithIndexer::new(self.clone(), result).try_into())
-            .collect::<Result<Vec<_>, _>>()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map_err(IndexerError::deserialization)
+    }
+}
+
+/// Reads the body of `response`, failing as soon as it turns out to be larger
+/// than `max_bytes`: right away if its `Content-Length` says so, and otherwise
+/// once that many bytes have been read.
+async fn read_body(
+    mut response: reqwest::Response,
+    max_bytes: usize,
+) -> Result<Vec<u8>, IndexerError> {
+    let too_large = IndexerError::ResponseTooLarge { limit: max_bytes };
+    let content_length = response.content_length();
+    if content_length.map_or(false, |length| length > max_bytes as u64) {
+        return Err(too_large);
+    }
+
+    let mut body = Vec::with_capacity(content_length.unwrap_or(0) as usize);
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max_bytes {
+            return Err(too_large);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Fails if an indexer returned more than [`MAX_RESULTS_PER_REQUESTED_ITEM`]
+/// results for each of the `requested` items.
+fn check_result_count(requested: usize, returned: usize) -> Result<(), IndexerError> {
+    if returned > requested.saturating_mul(MAX_RESULTS_PER_REQUESTED_ITEM) {
+        return Err(IndexerError::TooManyResults {
+            requested,
+            returned,
+        });
     }
+    Ok(())
 }
 
 #[async_trait]
@@ -117,37 +327,34 @@ impl IndexerClient for RealIndexer {
         }
     }
 
-    async fn ping(self: Arc<Self>) -> anyhow::Result<()> {
-        let request = gql_types::Typename::build_query(gql_types::typename::Variables);
-        self.graphql_query(request).await?;
-        Ok(())
+    fn name_source(&self) -> Option<IndexerNameSource> {
+        self.name.as_ref().and(self.name_source)
     }
 
-    async fn indexing_statuses(self: Arc<Self>) -> Result<Vec<IndexingStatus>, anyhow::Error> {
-        let request =
-            gql_types::IndexingStatuses::build_query(gql_types::indexing_statuses::Variables);
+    fn source(&self) -> Option<IndexerSource> {
+        self.source
+    }
 
-        let response: gql_types::indexing_statuses::ResponseData =
-            self.graphql_query(request).await?;
+    fn endpoint(&self) -> Option<&str> {
+        Some(&self.endpoint)
+    }
 
-        let mut statuses = vec![];
-        for indexing_status in response.indexing_statuses {
-            let deployment = indexing_status.subgraph.clone();
+    async fn ping(self: Arc<Self>) -> Result<(), IndexerError> {
+        let request = gql_types::Typename::build_query(gql_types::typename::Variables);
+        self.graphql_query(request).await?;
+        Ok(())
+    }
 
-            match WithIndexer::new(self.clone(), indexing_status).try_into() {
-                Ok(status) => statuses.push(status),
-                Err(e) => {
-                    warn!(
-                        address = %self.address_string(),
-                        %e,
-                        %deployment,
-                        "Failed to parse indexing status, skipping deployment"
-                    );
-                }
-            }
-        }
+    async fn indexing_statuses(self: Arc<Self>) -> Result<Vec<IndexingStatus>, IndexerError> {
+        self.query_indexing_statuses(None).await
+    }
 
-        Ok(statuses)
+    async fn indexing_statuses_of(
+        self: Arc<Self>,
+        deployments: &[SubgraphDeployment],
+    ) -> Result<Vec<IndexingStatus>, IndexerError> {
+        let subgraphs = deployments.iter().map(|d| d.as_str().to_string()).collect();
+        self.query_indexing_statuses(Some(subgraphs)).await
     }
 
     async fn proofs_of_indexing(
@@ -187,7 +394,7 @@ impl IndexerClient for RealIndexer {
                         .inc();
 
                     debug!(
-                        id = %self.address_string(), %error,
+                        id = %self.address_string(), %error, kind = error.kind(),
                         "Failed to query POIs batch from indexer"
                     );
 
@@ -208,10 +415,47 @@ impl IndexerClient for RealIndexer {
         pois
     }
 
+    /// Requests up to [`MAX_POI_REQUESTS_PER_QUERY`] blocks per query, and
+    /// falls back to one block per query for batches that fail, e.g. because
+    /// of the `proofOfIndexing` null value error mentioned above.
+    async fn proofs_of_indexing_range(
+        self: Arc<Self>,
+        request: PoiRangeRequest,
+    ) -> Result<Vec<ProofOfIndexing>, IndexerError> {
+        let mut pois = vec![];
+
+        for requests in request.poi_requests()?.chunks(MAX_POI_REQUESTS_PER_QUERY) {
+            match self.clone().proofs_of_indexing_batch(requests).await {
+                Ok(batch_pois) => {
+                    self.public_poi_requests
+                        .get_metric_with_label_values(&[&self.address_string(), "1"])
+                        .unwrap()
+                        .inc();
+
+                    pois.extend(batch_pois);
+                }
+                Err(error) => {
+                    self.public_poi_requests
+                        .get_metric_with_label_values(&[&self.address_string(), "0"])
+                        .unwrap()
+                        .inc();
+
+                    debug!(
+                        id = %self.address_string(), %error, kind = error.kind(),
+                        "Failed to query POIs range batch from indexer, falling back to single blocks"
+                    );
+                    pois.extend(self.clone().proofs_of_indexing(requests.to_vec()).await);
+                }
+            }
+        }
+
+        Ok(pois)
+    }
+
     async fn subgraph_api_versions(
         self: Arc<Self>,
         subgraph_id: &str,
-    ) -> anyhow::Result<Vec<String>> {
+    ) -> Result<Vec<String>, IndexerError> {
         let request = gql_types::SubgraphApiVersions::build_query(
             gql_types::subgraph_api_versions::Variables {
                 subgraph_id: subgraph_id.to_string(),
@@ -228,17 +472,27 @@ impl IndexerClient for RealIndexer {
             .collect())
     }
 
-    async fn version(self: Arc<Self>) -> anyhow::Result<GraphNodeCollectedVersion> {
+    async fn version(self: Arc<Self>) -> Result<GraphNodeCollectedVersion, IndexerError> {
         let request = gql_types::IndexerVersion::build_query(gql_types::indexer_version::Variables);
 
         let response: gql_types::indexer_version::ResponseData =
             self.graphql_query(request).await?;
 
+        // Not knowing the supported features shouldn't discard the version.
+        let supported_features = match self.supported_features().await {
+            Ok(features) => features,
+            Err(err) => {
+                debug!(error = %err, "Failed to query supported graph-node features");
+                None
+            }
+        };
+
         Ok(GraphNodeCollectedVersion {
             version: Some(response.version.version),
             commit: Some(response.version.commit),
             error_response: None,
             collected_at: chrono::Utc::now().naive_utc(),
+            supported_features,
         })
     }
 
@@ -246,7 +500,7 @@ impl IndexerClient for RealIndexer {
         self: Arc<Self>,
         network: &str,
         block_hash: &[u8],
-    ) -> anyhow::Result<Vec<CachedEthereumCall>> {
+    ) -> Result<Vec<CachedEthereumCall>, IndexerError> {
         let request = gql_types::CachedEthereumCalls::build_query(
             gql_types::cached_ethereum_calls::Variables {
                 network: network.to_string(),
@@ -268,7 +522,8 @@ impl IndexerClient for RealIndexer {
                     contract_address: gql_types::decode_bytes(&eth_call.contract_address)?,
                 })
             })
-            .collect::<anyhow::Result<Vec<CachedEthereumCall>>>()?;
+            .collect::<anyhow::Result<Vec<CachedEthereumCall>>>()
+            .map_err(IndexerError::deserialization)?;
 
         Ok(eth_calls)
     }
@@ -277,7 +532,7 @@ impl IndexerClient for RealIndexer {
         self: Arc<Self>,
         network: &str,
         block_hash: &[u8],
-    ) -> anyhow::Result<Option<serde_json::Value>> {
+    ) -> Result<Option<serde_json::Value>, IndexerError> {
         let request = gql_types::BlockData::build_query(gql_types::block_data::Variables {
             network: network.to_string(),
             block_hash: hex::encode(block_hash),
@@ -292,7 +547,7 @@ impl IndexerClient for RealIndexer {
         self: Arc<Self>,
         subgraph_id: &str,
         block_number: u64,
-    ) -> anyhow::Result<EntityChanges> {
+    ) -> Result<EntityChanges, IndexerError> {
         let request = gql_types::EntityChangesInBlock::build_query(
             gql_types::entity_changes_in_block::Variables {
                 subgraph_id: subgraph_id.to_string(),
@@ -307,14 +562,14 @@ impl IndexerClient for RealIndexer {
         for entity_type_updates in response.entity_changes_in_block.updates {
             updates
                 .insert(entity_type_updates.type_, entity_type_updates.entities)
-                .ok_or_else(|| anyhow!("duplicate entity types"))?;
+                .ok_or_else(|| IndexerError::deserialization("duplicate entity types"))?;
         }
 
         let mut deletions = HashMap::new();
         for entity_type_deletions in response.entity_changes_in_block.deletions {
             deletions
                 .insert(entity_type_deletions.type_, entity_type_deletions.entities)
-                .ok_or_else(|| anyhow!("duplicate entity types"))?;
+                .ok_or_else(|| IndexerError::deserialization("duplicate entity types"))?;
         }
 
         Ok(EntityChanges { updates, deletions })
@@ -328,6 +583,28 @@ mod gql_types {
     use crate::{BlockPointer, SubgraphDeployment};
 
     pub type JSONObject = serde_json::Value;
+
+    /// Introspection isn't supported by `graphql_client`'s code generation,
+    /// hence the hand-written query and response types.
+    pub const SUPPORTED_FEATURES_QUERY: &str =
+        r#"query SupportedFeatures { __type(name: "Feature") { enumValues { name } } }"#;
+
+    #[derive(Debug, serde::Deserialize)]
+    pub struct SupportedFeaturesResponse {
+        #[serde(rename = "__type")]
+        pub feature_enum: Option<EnumType>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    pub struct EnumType {
+        #[serde(rename = "enumValues")]
+        pub enum_values: Option<Vec<EnumValue>>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    pub struct EnumValue {
+        pub name: String,
+    }
     pub type BigInt = String;
     pub type Bytes = String;
 
@@ -379,7 +656,15 @@ mod gql_types {
             ) => match (latest_block, earliest_block) {
                 (Some(block), Some(earliest_block)) => (BlockPointer {
                     number: block.number.parse()?,
-                    hash: Some(str::parse::<BlockHash>(block.hash.as_str()).map_err(|e| anyhow!("invalid block hash: {}", e))?),
+                    hash: Some(BlockHash::parse_block_hash(block.hash.as_str()).map_err(|e| {
+                        anyhow!(
+                            "invalid block hash {} for deployment {} from indexer {}: {}",
+                            block.hash,
+                            self.inner.subgraph,
+                            self.indexer.address_string(),
+                            e
+                        )
+                    })?),
                 }, earliest_block.number.parse()?),
                 _ => {
                     return Err(anyhow!("deployment has not started indexing yet"));
@@ -389,7 +674,7 @@ mod gql_types {
 
             Ok(IndexingStatus {
                 indexer: self.indexer,
-                deployment: SubgraphDeployment(self.inner.subgraph),
+                deployment: SubgraphDeployment::new(self.inner.subgraph),
                 network: chain.network.clone(),
                 latest_block,
                 earliest_block_num,
@@ -414,18 +699,30 @@ mod gql_types {
         type Error = anyhow::Error;
 
         fn try_into(self) -> Result<ProofOfIndexing, Self::Error> {
+            let block_hash = self
+                .inner
+                .block
+                .hash
+                .as_deref()
+                .map(|hash| {
+                    BlockHash::parse_block_hash(hash).map_err(|e| {
+                        anyhow!(
+                            "invalid block hash {} for deployment {} from indexer {}: {}",
+                            hash,
+                            self.inner.deployment,
+                            self.indexer.address_string(),
+                            e
+                        )
+                    })
+                })
+                .transpose()?;
+
             Ok(ProofOfIndexing {
                 indexer: self.indexer,
-                deployment: SubgraphDeployment(self.inner.deployment.clone()),
+                deployment: SubgraphDeployment::new(self.inner.deployment.clone()),
                 block: BlockPointer {
                     number: self.inner.block.number.parse()?,
-                    hash: self
-                        .inner
-                        .block
-                        .hash
-                        .map(|hash_string| str::parse::<BlockHash>(hash_string.as_str()))
-                        .transpose()
-                        .map_err(|e| anyhow!("invalid block hash: {}", e))?,
+                    hash: block_hash,
                 },
                 proof_of_indexing: str::parse::<PoiBytes>(self.inner.proof_of_indexing.as_str())
                     .map_err(|e| anyhow!("invalid PoI value: {}", e))?,
@@ -6,13 +6,19 @@ use std::time::Duration;
 
 use anyhow::anyhow;
 use graphix_common_types::IndexerAddress;
-use graphix_indexer_client::{IndexerClient as IndexerTrait, RealIndexer};
+use graphix_indexer_client::{
+    HostRateLimiter, IndexerClient as IndexerTrait, IndexerNameSource, IndexerSource, RealIndexer,
+    DEFAULT_MAX_RESPONSE_BYTES,
+};
 use prometheus::IntCounterVec;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use tracing::warn;
+use tracing::{info, warn};
 use url::Url;
 
+/// GRT has 18 decimals, like ether.
+const GRT_DECIMALS: f64 = 1e18;
+
 /// A GraphQL client that can query the network subgraph and extract useful
 /// data.
 ///
@@ -24,13 +30,17 @@ pub struct NetworkSubgraphClient {
     endpoint: Url,
     timeout: Duration,
     client: reqwest::Client,
+    rate_limiter: Option<Arc<HostRateLimiter>>,
+    max_indexer_response_bytes: usize,
     // Metrics
     // -------
     public_poi_requests: IntCounterVec,
+    indexer_responses_too_large: Option<IntCounterVec>,
 }
 
 impl NetworkSubgraphClient {
     const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+    const PAGE_SIZE: usize = 100;
 
     /// Creates a new [`NetworkSubgraphClient`] with the given endpoint.
     pub fn new(endpoint: Url, public_poi_requests: IntCounterVec) -> Self {
@@ -38,7 +48,10 @@ impl NetworkSubgraphClient {
             endpoint,
             timeout: Self::DEFAULT_TIMEOUT,
             client: reqwest::Client::new(),
+            rate_limiter: None,
+            max_indexer_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
             public_poi_requests,
+            indexer_responses_too_large: None,
         }
     }
 
@@ -50,22 +63,65 @@ impl NetworkSubgraphClient {
         self
     }
 
+    /// Sets the HTTP client for requests to the network subgraph, which is
+    /// also used by the indexers it returns.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Rate limits the requests of the indexers this client returns with
+    /// `rate_limiter`. Requests to the network subgraph itself aren't limited.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<HostRateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Limits the size of the responses to the indexers this client returns,
+    /// see [`RealIndexer::with_max_response_bytes`].
+    pub fn with_max_indexer_response_bytes(mut self, max_response_bytes: usize) -> Self {
+        self.max_indexer_response_bytes = max_response_bytes;
+        self
+    }
+
+    /// Counts the oversized responses to the indexers this client returns,
+    /// see [`RealIndexer::with_responses_too_large`].
+    pub fn with_indexer_responses_too_large(mut self, responses_too_large: IntCounterVec) -> Self {
+        self.indexer_responses_too_large = Some(responses_too_large);
+        self
+    }
+
+    /// An indexer with the given status endpoint that shares this client's
+    /// HTTP client, rate limiter and response size limit.
+    fn real_indexer(&self, address: IndexerAddress, endpoint: String) -> RealIndexer {
+        let mut indexer =
+            RealIndexer::new(None, address, endpoint, self.public_poi_requests.clone())
+                .with_client(self.client.clone())
+                .with_max_response_bytes(self.max_indexer_response_bytes)
+                .with_source(IndexerSource::NetworkSubgraph);
+        if let Some(responses_too_large) = &self.indexer_responses_too_large {
+            indexer = indexer.with_responses_too_large(responses_too_large.clone());
+        }
+        match &self.rate_limiter {
+            Some(rate_limiter) => indexer.with_rate_limiter(rate_limiter.clone()),
+            None => indexer,
+        }
+    }
+
     pub async fn indexers_by_staked_tokens(&self) -> anyhow::Result<Vec<Arc<dyn IndexerTrait>>> {
-        let response_data: GraphqlResponseTopIndexers = self
-            .graphql_query_no_errors(
+        let network_indexers = self
+            .paginated_indexers(
                 queries::INDEXERS_BY_STAKED_TOKENS_QUERY,
-                vec![],
+                None,
                 "error(s) querying top indexers from the network subgraph",
             )
             .await?;
 
         let mut indexers: Vec<Arc<dyn IndexerTrait>> = vec![];
-        for indexer in response_data.indexers {
+        for indexer in network_indexers {
             let indexer_id = indexer.id.clone();
-            let real_indexer = indexer_allocation_data_to_real_indexer(
-                IndexerAllocation { indexer },
-                self.public_poi_requests.clone(),
-            );
+            let real_indexer =
+                indexer_allocation_data_to_real_indexer(IndexerAllocation { indexer }, self);
 
             match real_indexer {
                 Ok(indexer) => indexers.push(Arc::new(indexer)),
@@ -80,53 +136,108 @@ impl NetworkSubgraphClient {
         Ok(indexers)
     }
 
+    /// Returns the amount of GRT staked by each indexer in the network,
+    /// including indexers without a URL.
+    pub async fn indexer_stakes(&self) -> anyhow::Result<Vec<(IndexerAddress, f64)>> {
+        let network_indexers = self
+            .paginated_indexers(
+                queries::INDEXERS_BY_STAKED_TOKENS_QUERY,
+                None,
+                "error(s) querying indexer stakes from the network subgraph",
+            )
+            .await?;
+
+        let mut stakes = vec![];
+        for indexer in network_indexers {
+            let Some(staked_tokens) = &indexer.staked_tokens else {
+                continue;
+            };
+            let address = str::parse::<IndexerAddress>(&indexer.id)
+                .map_err(|e| anyhow!("invalid indexer address: {}", e))?;
+            let staked_tokens = staked_tokens
+                .parse::<f64>()
+                .map_err(|e| anyhow!("invalid staked tokens: {}", e))?
+                / GRT_DECIMALS;
+            stakes.push((address, staked_tokens));
+        }
+
+        Ok(stakes)
+    }
+
     pub async fn indexers_by_allocations(
         &self,
         limit: Option<u32>,
     ) -> anyhow::Result<Vec<Arc<dyn IndexerTrait>>> {
-        let page_size = 100;
+        let network_indexers = self
+            .paginated_indexers(
+                queries::INDEXERS_BY_ALLOCATIONS_QUERY,
+                limit,
+                "error(s) querying indexers by allocations from the network subgraph",
+            )
+            .await?;
 
         let mut indexers = Vec::<Arc<dyn IndexerTrait>>::new();
+        for indexer in network_indexers {
+            if let Some(url) = &indexer.url {
+                let address = str::parse::<IndexerAddress>(&indexer.id)
+                    .map_err(|e| anyhow!("invalid indexer address: {}", e))?;
+                let real_indexer =
+                    self.real_indexer(address, Url::parse(&format!("{}/status", url))?.to_string());
+                indexers.push(Arc::new(indexer.apply_name(real_indexer)));
+            }
+        }
+
+        Ok(indexers)
+    }
+
+    /// Fetches indexers page by page with a query that takes `$first` and
+    /// `$skip` variables, until a page comes back incomplete or `limit` is
+    /// reached. A single query would only return as many indexers as
+    /// `graph-node`'s default page size.
+    async fn paginated_indexers(
+        &self,
+        query: &str,
+        limit: Option<u32>,
+        err_msg: &str,
+    ) -> anyhow::Result<Vec<Indexer>> {
+        let mut indexers = vec![];
         loop {
             let response_data: GraphqlResponseTopIndexers = self
                 .graphql_query_no_errors(
-                    queries::INDEXERS_BY_ALLOCATIONS_QUERY,
+                    query,
                     vec![
-                        ("first".to_string(), page_size.into()),
+                        ("first".to_string(), Self::PAGE_SIZE.into()),
                         ("skip".to_string(), indexers.len().into()),
                     ],
-                    "error(s) querying indexers by allocations from the network subgraph",
+                    err_msg,
                 )
                 .await?;
 
             // If we got less than the page size, we're done.
-            let no_more_results = response_data.indexers.len() < page_size;
-
-            for indexer in response_data.indexers {
-                if let Some(url) = indexer.url {
-                    let address = str::parse::<IndexerAddress>(&indexer.id)
-                        .map_err(|e| anyhow!("invalid indexer address: {}", e))?;
-                    let real_indexer = RealIndexer::new(
-                        indexer.default_display_name,
-                        address,
-                        Url::parse(&format!("{}/status", url))?.to_string(),
-                        self.public_poi_requests.clone(),
-                    );
-                    indexers.push(Arc::new(real_indexer));
-                }
-            }
+            let no_more_results = response_data.indexers.len() < Self::PAGE_SIZE;
+
+            indexers.extend(response_data.indexers);
 
             if no_more_results {
                 break;
             }
             if let Some(limit) = limit {
-                if indexers.len() > limit as usize {
-                    indexers.truncate(limit as usize);
+                if indexers.len() >= limit as usize {
                     break;
                 }
             }
         }
 
+        if let Some(limit) = limit {
+            indexers.truncate(limit as usize);
+        }
+
+        info!(
+            endpoint = %self.endpoint,
+            indexers = indexers.len(),
+            "Discovered indexers from the network subgraph"
+        );
+
         Ok(indexers)
     }
 
@@ -135,7 +246,7 @@ impl NetworkSubgraphClient {
     pub async fn indexer_by_address(
         &self,
         address: &IndexerAddress,
-    ) -> anyhow::Result<Arc<dyn IndexerTrait>> {
+    ) -> anyhow::Result<RealIndexer> {
         let hex_encoded_addr_json = serde_json::to_value(address).unwrap();
         let response_data: ResponseData = self
             .graphql_query_no_errors(
@@ -148,29 +259,24 @@ impl NetworkSubgraphClient {
         #[derive(Debug, Deserialize)]
         #[serde(rename_all = "camelCase")]
         struct ResponseData {
-            indexers: Vec<IndexerData>,
-        }
-
-        #[derive(Debug, Deserialize)]
-        #[serde(rename_all = "camelCase")]
-        struct IndexerData {
-            url: String,
-            default_display_name: String,
+            indexers: Vec<Indexer>,
         }
 
         let indexer_data = response_data
             .indexers
             .first()
             .ok_or_else(|| anyhow::anyhow!("No indexer found for address {}", address))?;
+        let url = indexer_data
+            .url
+            .as_ref()
+            .ok_or_else(|| anyhow!("Indexer without URL"))?;
 
-        let indexer = RealIndexer::new(
-            Some(indexer_data.default_display_name.clone()),
+        let indexer = self.real_indexer(
             *address,
-            Url::parse(&format!("{}/status", indexer_data.url))?.to_string(),
-            self.public_poi_requests.clone(),
+            Url::parse(&format!("{}/status", url))?.to_string(),
         );
 
-        Ok(Arc::new(indexer))
+        Ok(indexer_data.apply_name(indexer))
     }
 
     /// Returns all subgraph deployments, ordered by curation signal amounts.
@@ -212,6 +318,96 @@ impl NetworkSubgraphClient {
         Ok(subgraph_deployments)
     }
 
+    /// Returns the subgraph deployments that indexers have active allocations
+    /// on, ordered by IPFS CID, along with how much is allocated to them.
+    pub async fn deployments_by_active_allocations(
+        &self,
+    ) -> anyhow::Result<Vec<DeploymentAllocations>> {
+        let allocations = self.paginated_active_allocations().await?;
+
+        let mut deployments = BTreeMap::<String, DeploymentAllocations>::new();
+        for allocation in allocations {
+            let allocated_tokens = allocation.allocated_tokens()?;
+            let deployment = allocation.subgraph_deployment;
+            let entry = deployments
+                .entry(deployment.ipfs_hash.clone())
+                .or_insert_with(|| DeploymentAllocations {
+                    ipfs_hash: deployment.ipfs_hash,
+                    network: deployment.manifest.and_then(|manifest| manifest.network),
+                    active_allocations: 0,
+                    allocated_tokens: 0.0,
+                });
+            entry.active_allocations += 1;
+            entry.allocated_tokens += allocated_tokens;
+        }
+
+        info!(
+            endpoint = %self.endpoint,
+            deployments = deployments.len(),
+            "Discovered deployments with active allocations from the network subgraph"
+        );
+
+        Ok(deployments.into_values().collect())
+    }
+
+    /// Returns all active allocations, ordered by allocation ID.
+    pub async fn active_allocations(&self) -> anyhow::Result<Vec<ActiveAllocation>> {
+        let allocations = self.paginated_active_allocations().await?;
+
+        let mut active_allocations = vec![];
+        for allocation in allocations {
+            active_allocations.push(ActiveAllocation {
+                allocated_tokens: allocation.allocated_tokens()?,
+                indexer: str::parse::<IndexerAddress>(&allocation.indexer.id)
+                    .map_err(|e| anyhow!("invalid indexer address: {}", e))?,
+                id: allocation.id,
+                ipfs_hash: allocation.subgraph_deployment.ipfs_hash,
+                created_at_epoch: allocation.created_at_epoch,
+            });
+        }
+
+        info!(
+            endpoint = %self.endpoint,
+            allocations = active_allocations.len(),
+            "Queried active allocations from the network subgraph"
+        );
+
+        Ok(active_allocations)
+    }
+
+    /// Pages through allocations by ID rather than with `skip`, which
+    /// graph-node rejects beyond 5000 and which gets slower with every page.
+    async fn paginated_active_allocations(&self) -> anyhow::Result<Vec<Allocation>> {
+        let mut allocations: Vec<Allocation> = vec![];
+        loop {
+            // Every allocation ID is greater than the empty string.
+            let last_id = allocations
+                .last()
+                .map_or(String::new(), |allocation| allocation.id.clone());
+            let response_data: GraphqlResponseAllocations = self
+                .graphql_query_no_errors(
+                    queries::ACTIVE_ALLOCATIONS_QUERY,
+                    vec![
+                        ("first".to_string(), Self::PAGE_SIZE.into()),
+                        ("lastId".to_string(), last_id.into()),
+                    ],
+                    "error(s) querying active allocations from the network subgraph",
+                )
+                .await?;
+
+            // If we got less than the page size, we're done.
+            let no_more_results = response_data.allocations.len() < Self::PAGE_SIZE;
+
+            allocations.extend(response_data.allocations);
+
+            if no_more_results {
+                break;
+            }
+        }
+
+        Ok(allocations)
+    }
+
     /// A wrapper around [`NetworkSubgraphClient::graphql_query`] that requires
     /// no errors in the response, and deserializes the response data into the
     /// given type.
@@ -262,22 +458,18 @@ impl NetworkSubgraphClient {
 
 fn indexer_allocation_data_to_real_indexer(
     indexer_allocation: IndexerAllocation,
-    public_poi_requests: IntCounterVec,
+    network_subgraph: &NetworkSubgraphClient,
 ) -> anyhow::Result<RealIndexer> {
-    let name = indexer_allocation.indexer.default_display_name.clone();
     let indexer = indexer_allocation.indexer;
     let address = str::parse(&indexer.id).map_err(|e| anyhow!("invalid indexer address: {}", e))?;
     let mut url: Url = indexer
         .url
+        .as_ref()
         .ok_or_else(|| anyhow!("Indexer without URL"))?
         .parse()?;
     url.set_path("/status");
-    Ok(RealIndexer::new(
-        name,
-        address,
-        url.to_string(),
-        public_poi_requests,
-    ))
+    let real_indexer = network_subgraph.real_indexer(address, url.to_string());
+    Ok(indexer.apply_name(real_indexer))
 }
 
 #[derive(Serialize)]
@@ -307,6 +499,77 @@ struct GraphqlResponseTopIndexers {
     indexers: Vec<Indexer>,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphqlResponseAllocations {
+    allocations: Vec<Allocation>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Allocation {
+    id: String,
+    /// In the smallest GRT denomination, i.e. 10^-18 GRT.
+    allocated_tokens: String,
+    created_at_epoch: u32,
+    indexer: AllocationIndexer,
+    subgraph_deployment: AllocationDeployment,
+}
+
+impl Allocation {
+    /// In GRT.
+    fn allocated_tokens(&self) -> anyhow::Result<f64> {
+        Ok(self
+            .allocated_tokens
+            .parse::<f64>()
+            .map_err(|e| anyhow!("invalid allocated tokens: {}", e))?
+            / GRT_DECIMALS)
+    }
+}
+
+#[derive(Deserialize)]
+struct AllocationIndexer {
+    id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AllocationDeployment {
+    ipfs_hash: String,
+    manifest: Option<AllocationDeploymentManifest>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AllocationDeploymentManifest {
+    network: Option<String>,
+}
+
+/// An indexer's active allocation on a subgraph deployment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveAllocation {
+    /// The allocation ID, an address encoded as a hex string with a '0x'
+    /// prefix.
+    pub id: String,
+    pub indexer: IndexerAddress,
+    pub ipfs_hash: String,
+    /// In GRT.
+    pub allocated_tokens: f64,
+    /// The epoch in which the allocation was created.
+    pub created_at_epoch: u32,
+}
+
+/// A subgraph deployment and the active allocations on it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeploymentAllocations {
+    pub ipfs_hash: String,
+    /// The network that the deployment indexes, if its manifest says so.
+    pub network: Option<String>,
+    pub active_allocations: u32,
+    /// The sum of all active allocations, in GRT.
+    pub allocated_tokens: f64,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SubgraphDeploymentWithAllocations {
@@ -326,6 +589,51 @@ pub struct Indexer {
     pub id: String,
     pub default_display_name: Option<String>,
     pub url: Option<String>,
+    /// In wei, only queried by some of the queries.
+    #[serde(default)]
+    pub staked_tokens: Option<String>,
+    #[serde(default)]
+    pub account: Option<GraphAccount>,
+}
+
+impl Indexer {
+    /// The indexer's display name if it has one, or else the ENS name of its
+    /// account.
+    pub fn name(&self) -> Option<(String, IndexerNameSource)> {
+        let display_name = self
+            .default_display_name
+            .clone()
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| (name, IndexerNameSource::NetworkSubgraph));
+        let ens_name = self
+            .account
+            .as_ref()
+            .and_then(|account| account.default_name.as_ref())
+            .filter(|name| name.name_system == "ENS")
+            .map(|name| (name.name.clone(), IndexerNameSource::Ens));
+
+        display_name.or(ens_name)
+    }
+
+    fn apply_name(&self, real_indexer: RealIndexer) -> RealIndexer {
+        match self.name() {
+            Some((name, name_source)) => real_indexer.with_name(name, name_source),
+            None => real_indexer,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphAccount {
+    pub default_name: Option<GraphAccountName>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphAccountName {
+    pub name: String,
+    pub name_system: String,
 }
 
 mod queries {
@@ -335,21 +643,191 @@ mod queries {
         include_str!("queries/indexers_by_allocations.graphql");
     pub const DEPLOYMENTS_QUERY: &str = include_str!("queries/deployments.graphql");
     pub const INDEXER_BY_ADDRESS_QUERY: &str = include_str!("queries/indexer_by_address.graphql");
+    pub const ACTIVE_ALLOCATIONS_QUERY: &str = include_str!("queries/active_allocations.graphql");
 }
 
 #[cfg(test)]
 mod tests {
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use tokio::net::TcpListener;
+
     use super::*;
 
-    fn network_sg_client_on_ethereum() -> NetworkSubgraphClient {
+    fn network_sg_client(endpoint: &str) -> NetworkSubgraphClient {
         NetworkSubgraphClient::new(
-            "https://api.thegraph.com/subgraphs/name/graphprotocol/graph-network-mainnet"
-                .parse()
-                .unwrap(),
+            endpoint.parse().unwrap(),
             IntCounterVec::new(prometheus::Opts::new("foo", "bar"), &["a", "b"]).unwrap(),
         )
     }
 
+    fn network_sg_client_on_ethereum() -> NetworkSubgraphClient {
+        network_sg_client(
+            "https://api.thegraph.com/subgraphs/name/graphprotocol/graph-network-mainnet",
+        )
+    }
+
+    /// Starts a fake network subgraph that serves `indexers_count` indexers,
+    /// paginated according to the `first` and `skip` query variables.
+    async fn mock_network_sg_client(indexers_count: usize) -> NetworkSubgraphClient {
+        let handler = move |Json(request): Json<serde_json::Value>| async move {
+            let first = request["variables"]["first"].as_u64().unwrap() as usize;
+            let skip = request["variables"]["skip"].as_u64().unwrap() as usize;
+            let indexers = (skip..indexers_count.min(skip + first))
+                .map(|i| {
+                    serde_json::json!({
+                        "id": format!("0x{:040x}", i),
+                        "url": format!("http://indexer-{}.example.com", i),
+                        "stakedTokens": format!("{}000000000000000000", i),
+                        "defaultDisplayName": null,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            Json(serde_json::json!({ "data": { "indexers": indexers } }))
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/", post(handler)))
+                .await
+                .unwrap();
+        });
+
+        network_sg_client(&endpoint)
+    }
+
+    #[tokio::test]
+    async fn indexers_are_paginated() {
+        for indexers_count in [0, 99, 100, 250] {
+            let client = mock_network_sg_client(indexers_count).await;
+
+            let indexers = client.indexers_by_allocations(None).await.unwrap();
+            assert_eq!(indexers.len(), indexers_count);
+            let indexers = client.indexers_by_staked_tokens().await.unwrap();
+            assert_eq!(indexers.len(), indexers_count);
+
+            // No duplicates across pages.
+            let addresses = indexers
+                .iter()
+                .map(|indexer| indexer.address())
+                .collect::<std::collections::HashSet<_>>();
+            assert_eq!(addresses.len(), indexers_count);
+        }
+    }
+
+    #[tokio::test]
+    async fn paginated_indexers_limit() {
+        let client = mock_network_sg_client(250).await;
+        let indexers = client.indexers_by_allocations(Some(150)).await.unwrap();
+        assert_eq!(indexers.len(), 150);
+    }
+
+    #[tokio::test]
+    async fn indexer_stakes_are_in_grt() {
+        let client = mock_network_sg_client(150).await;
+        let stakes = client.indexer_stakes().await.unwrap();
+        assert_eq!(stakes.len(), 150);
+        assert_eq!(stakes[42].0, format!("0x{:040x}", 42).parse().unwrap());
+        assert_eq!(stakes[42].1, 42.0);
+    }
+
+    #[tokio::test]
+    async fn requests_use_the_given_client() {
+        let handler = |headers: axum::http::HeaderMap| async move {
+            let user_agent = headers[axum::http::header::USER_AGENT].to_str().unwrap();
+            assert_eq!(user_agent, "graphix/test");
+            Json(serde_json::json!({ "data": { "indexers": [] } }))
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/", post(handler)))
+                .await
+                .unwrap();
+        });
+
+        let http_client = reqwest::Client::builder()
+            .user_agent("graphix/test")
+            .build()
+            .unwrap();
+        let client = network_sg_client(&endpoint).with_client(http_client);
+        assert!(client
+            .indexers_by_allocations(None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn indexer_names_fall_back_to_ens() {
+        let handler = |Json(request): Json<serde_json::Value>| async move {
+            let skip = request["variables"]["skip"].as_u64().unwrap();
+            let ens_account = serde_json::json!({
+                "defaultName": { "name": "indexer.eth", "nameSystem": "ENS" }
+            });
+            let indexers = if skip > 0 {
+                vec![]
+            } else {
+                vec![
+                    serde_json::json!({
+                        "id": format!("0x{:040x}", 0),
+                        "url": "http://indexer-0.example.com",
+                        "defaultDisplayName": "Indexer Zero",
+                        "account": ens_account,
+                    }),
+                    serde_json::json!({
+                        "id": format!("0x{:040x}", 1),
+                        "url": "http://indexer-1.example.com",
+                        "defaultDisplayName": "",
+                        "account": ens_account,
+                    }),
+                    serde_json::json!({
+                        "id": format!("0x{:040x}", 2),
+                        "url": "http://indexer-2.example.com",
+                        "defaultDisplayName": null,
+                        "account": { "defaultName": null },
+                    }),
+                ]
+            };
+            Json(serde_json::json!({ "data": { "indexers": indexers } }))
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/", post(handler)))
+                .await
+                .unwrap();
+        });
+
+        let client = network_sg_client(&endpoint);
+        let indexers = client.indexers_by_staked_tokens().await.unwrap();
+        let names = indexers
+            .iter()
+            .map(|indexer| {
+                (
+                    indexer.name().map(|name| name.into_owned()),
+                    indexer.name_source(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                (
+                    Some("Indexer Zero".to_string()),
+                    Some(IndexerNameSource::NetworkSubgraph)
+                ),
+                (
+                    Some("indexer.eth".to_string()),
+                    Some(IndexerNameSource::Ens)
+                ),
+                (None, None),
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn short_timeout_always_fails() {
         // We should never be able to get a response back under 1ms. If we do,
@@ -372,6 +850,83 @@ mod tests {
         assert_eq!(indexers.len(), 10);
     }
 
+    #[tokio::test]
+    async fn deployments_are_aggregated_from_allocations() {
+        // 150 allocations, spread over 3 deployments and across two pages.
+        let handler = |Json(request): Json<serde_json::Value>| async move {
+            let first = request["variables"]["first"].as_u64().unwrap() as usize;
+            let last_id = request["variables"]["lastId"].as_str().unwrap().to_string();
+            assert!(request["variables"].get("skip").is_none());
+            let allocations = (0..150)
+                .filter(|i| format!("0x{:040x}", i) > last_id)
+                .take(first)
+                .map(|i| {
+                    serde_json::json!({
+                        "id": format!("0x{:040x}", i),
+                        "allocatedTokens": "2000000000000000000",
+                        "createdAtEpoch": 800 + i,
+                        "indexer": { "id": format!("0x{:040x}", i % 7) },
+                        "subgraphDeployment": {
+                            "ipfsHash": format!("Qm{}", i % 3),
+                            "manifest": if i % 3 == 0 {
+                                serde_json::Value::Null
+                            } else {
+                                serde_json::json!({ "network": "mainnet" })
+                            },
+                        },
+                    })
+                })
+                .collect::<Vec<_>>();
+            Json(serde_json::json!({ "data": { "allocations": allocations } }))
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/", post(handler)))
+                .await
+                .unwrap();
+        });
+
+        let client = network_sg_client(&endpoint);
+        let deployments = client.deployments_by_active_allocations().await.unwrap();
+        assert_eq!(
+            deployments,
+            vec![
+                DeploymentAllocations {
+                    ipfs_hash: "Qm0".to_string(),
+                    network: None,
+                    active_allocations: 50,
+                    allocated_tokens: 100.0,
+                },
+                DeploymentAllocations {
+                    ipfs_hash: "Qm1".to_string(),
+                    network: Some("mainnet".to_string()),
+                    active_allocations: 50,
+                    allocated_tokens: 100.0,
+                },
+                DeploymentAllocations {
+                    ipfs_hash: "Qm2".to_string(),
+                    network: Some("mainnet".to_string()),
+                    active_allocations: 50,
+                    allocated_tokens: 100.0,
+                },
+            ]
+        );
+
+        let allocations = client.active_allocations().await.unwrap();
+        assert_eq!(allocations.len(), 150);
+        assert_eq!(
+            allocations[122],
+            ActiveAllocation {
+                id: format!("0x{:040x}", 122),
+                indexer: format!("0x{:040x}", 122 % 7).parse().unwrap(),
+                ipfs_hash: "Qm2".to_string(),
+                allocated_tokens: 2.0,
+                created_at_epoch: 922,
+            }
+        );
+    }
+
     #[tokio::test]
     async fn subgraph_deployments_limits() {
         let client = network_sg_client_on_ethereum();
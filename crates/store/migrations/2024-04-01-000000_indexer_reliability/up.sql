@@ -0,0 +1,7 @@
+-- An exponential moving average of each indexer's query success rate, updated
+-- once per main loop iteration.
+CREATE TABLE indexer_reliability (
+  indexer_id INTEGER PRIMARY KEY REFERENCES indexers(id) ON DELETE CASCADE,
+  score DOUBLE PRECISION NOT NULL,
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
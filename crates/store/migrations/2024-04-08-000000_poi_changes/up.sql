@@ -0,0 +1,15 @@
+-- Indexers that rewind and resync may report a different PoI for a block we
+-- already have a PoI for. Each such change is recorded here. PoIs are copied
+-- rather than referenced, so that the history survives pruning.
+CREATE TABLE poi_changes (
+  id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+  indexer_id INTEGER NOT NULL REFERENCES indexers(id) ON DELETE CASCADE,
+  sg_deployment_id INTEGER NOT NULL REFERENCES sg_deployments(id) ON DELETE CASCADE,
+  block_id BIGINT NOT NULL REFERENCES blocks(id) ON DELETE CASCADE,
+  old_poi BYTEA NOT NULL,
+  new_poi BYTEA NOT NULL,
+  detected_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX ON poi_changes (indexer_id);
+CREATE INDEX ON poi_changes (sg_deployment_id);
@@ -0,0 +1 @@
+ALTER TABLE live_pois DROP COLUMN stale;
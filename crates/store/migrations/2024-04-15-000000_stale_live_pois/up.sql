@@ -0,0 +1,3 @@
+-- Live PoIs of indexers that stopped responding are kept around, but flagged
+-- as stale so that they aren't compared against fresh PoIs.
+ALTER TABLE live_pois ADD COLUMN stale BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,18 @@
+-- When a PoI stopped being the live one for its indexer and subgraph
+-- deployment, i.e. when a newer PoI arrived. NULL for PoIs that are still live.
+ALTER TABLE pois ADD COLUMN superseded_at TIMESTAMP;
+
+-- Older PoIs were superseded by the next PoI of the same indexer and subgraph
+-- deployment.
+UPDATE pois
+SET superseded_at = next_pois.created_at
+FROM (
+  SELECT
+    id,
+    LEAD(created_at) OVER (
+      PARTITION BY indexer_id, sg_deployment_id
+      ORDER BY created_at, id
+    ) AS created_at
+  FROM pois
+) next_pois
+WHERE pois.id = next_pois.id AND next_pois.created_at IS NOT NULL;
@@ -0,0 +1,13 @@
+-- The outcome of the most recent queries to each indexer, successful or not,
+-- so that unreachable indexers can be told apart from disagreeing ones. Only
+-- the latest few rows per indexer and query are kept.
+CREATE TABLE indexer_query_errors (
+  id SERIAL PRIMARY KEY,
+  indexer_id INTEGER NOT NULL REFERENCES indexers(id) ON DELETE CASCADE,
+  query_name TEXT NOT NULL,
+  results INTEGER,
+  error TEXT,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX ON indexer_query_errors (indexer_id, query_name, id DESC);
@@ -0,0 +1 @@
+DROP TABLE poi_backfill_jobs;
@@ -0,0 +1,20 @@
+-- Requests to query PoIs for past blocks of a subgraph deployment, which are
+-- handled one at a time by a background worker.
+CREATE TABLE poi_backfill_jobs (
+  uuid UUID PRIMARY KEY,
+  deployment TEXT NOT NULL,
+  from_block BIGINT NOT NULL,
+  to_block BIGINT NOT NULL,
+  block_interval BIGINT NOT NULL,
+  -- One of 'pending', 'in_progress', 'completed' or 'failed'.
+  status TEXT NOT NULL DEFAULT 'pending',
+  -- Only known once the job has started.
+  requests_total BIGINT,
+  requests_sent BIGINT NOT NULL DEFAULT 0,
+  pois_written BIGINT NOT NULL DEFAULT 0,
+  error TEXT,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX ON poi_backfill_jobs (status, created_at);
@@ -0,0 +1 @@
+ALTER TABLE graph_node_collected_versions DROP COLUMN supported_features;
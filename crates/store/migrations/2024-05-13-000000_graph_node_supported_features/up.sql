@@ -0,0 +1,3 @@
+-- The features that graph-node supports, as listed by its index-node API. NULL
+-- for graph-node versions that don't list them.
+ALTER TABLE graph_node_collected_versions ADD COLUMN supported_features TEXT[];
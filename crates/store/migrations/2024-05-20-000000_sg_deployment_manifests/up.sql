@@ -0,0 +1,17 @@
+-- Metadata from the manifests of subgraph deployments, fetched from IPFS in
+-- the background. Failed fetches are recorded too (with `fetched_at` being
+-- NULL), so that they can be retried with backoff.
+CREATE TABLE sg_deployment_manifests (
+  sg_deployment_id INTEGER PRIMARY KEY REFERENCES sg_deployments(id) ON DELETE CASCADE,
+  description TEXT,
+  repository TEXT,
+  spec_version TEXT,
+  network TEXT,
+  start_block BIGINT,
+  features TEXT[],
+  entity_count INTEGER,
+  fetched_at TIMESTAMP,
+  fetch_attempts INTEGER NOT NULL DEFAULT 0,
+  last_error TEXT,
+  next_attempt_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,7 @@
+-- Where the name of an indexer comes from: 'config', 'network_subgraph' or
+-- 'ens'. NULL for indexers without a name.
+ALTER TABLE indexers ADD COLUMN name_source TEXT;
+
+-- Indexers without a display name in the network subgraph used to be stored
+-- with an empty name.
+UPDATE indexers SET name = NULL WHERE name = '';
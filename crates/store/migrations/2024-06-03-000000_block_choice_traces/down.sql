@@ -0,0 +1 @@
+DROP TABLE block_choice_traces;
@@ -0,0 +1,13 @@
+-- The block that Graphix chose to query PoIs at for a subgraph deployment in
+-- each main loop iteration, along with the candidate blocks and the policy
+-- that it chose from. Only the latest few rows per deployment are kept.
+CREATE TABLE block_choice_traces (
+  id SERIAL PRIMARY KEY,
+  sg_deployment_id INTEGER NOT NULL REFERENCES sg_deployments(id) ON DELETE CASCADE,
+  policy TEXT NOT NULL,
+  candidate_blocks BIGINT[] NOT NULL,
+  chosen_block BIGINT,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX ON block_choice_traces (sg_deployment_id, id DESC);
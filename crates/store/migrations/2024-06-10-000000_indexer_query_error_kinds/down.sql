@@ -0,0 +1 @@
+ALTER TABLE indexer_query_errors DROP COLUMN error_kind;
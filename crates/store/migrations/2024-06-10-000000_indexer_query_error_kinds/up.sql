@@ -0,0 +1,3 @@
+-- The kind of error that a failed query ran into, e.g. 'Timeout' or 'Http'.
+-- NULL for successful queries and for failures without a known kind.
+ALTER TABLE indexer_query_errors ADD COLUMN error_kind TEXT;
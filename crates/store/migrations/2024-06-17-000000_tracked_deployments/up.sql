@@ -0,0 +1,13 @@
+-- The subgraph deployments that indexers have active allocations on,
+-- according to the network subgraphs with deployment discovery enabled.
+-- Replaced on every indexer refresh. Deployments are referred to by IPFS CID
+-- rather than by `sg_deployments` row, as they might not be known otherwise.
+CREATE TABLE tracked_deployments (
+  ipfs_cid TEXT NOT NULL,
+  network_subgraph TEXT NOT NULL,
+  network TEXT,
+  active_allocations INTEGER NOT NULL,
+  allocated_tokens DOUBLE PRECISION NOT NULL,
+  tracked_since TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (ipfs_cid, network_subgraph)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE indexers DROP COLUMN labels;
+ALTER TABLE indexers DROP COLUMN region;
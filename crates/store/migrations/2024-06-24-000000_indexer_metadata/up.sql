@@ -0,0 +1,4 @@
+-- Operator-provided metadata from the configuration file, which Graphix
+-- passes through to the API without interpreting it.
+ALTER TABLE indexers ADD COLUMN region TEXT;
+ALTER TABLE indexers ADD COLUMN labels TEXT[] NOT NULL DEFAULT '{}';
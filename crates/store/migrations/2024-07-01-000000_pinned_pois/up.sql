@@ -0,0 +1,3 @@
+-- PoIs at blocks pinned in the configuration, which are collected alongside
+-- the live ones but never become live themselves.
+ALTER TABLE pois ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,4 @@
+ALTER TABLE divergence_investigation_reports DROP COLUMN deduplication_key;
+ALTER TABLE divergence_investigation_reports DROP COLUMN idempotency_key;
+ALTER TABLE pending_divergence_investigation_requests DROP COLUMN deduplication_key;
+ALTER TABLE pending_divergence_investigation_requests DROP COLUMN idempotency_key;
@@ -0,0 +1,9 @@
+-- Identical divergence investigation requests are deduplicated, either by a
+-- client-provided idempotency key or by what they investigate.
+ALTER TABLE pending_divergence_investigation_requests ADD COLUMN idempotency_key TEXT;
+ALTER TABLE pending_divergence_investigation_requests ADD COLUMN deduplication_key TEXT;
+ALTER TABLE divergence_investigation_reports ADD COLUMN idempotency_key TEXT;
+ALTER TABLE divergence_investigation_reports ADD COLUMN deduplication_key TEXT;
+
+CREATE INDEX ON divergence_investigation_reports (idempotency_key);
+CREATE INDEX ON divergence_investigation_reports (deduplication_key, created_at);
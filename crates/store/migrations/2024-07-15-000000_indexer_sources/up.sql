@@ -0,0 +1,3 @@
+-- Where an indexer was configured from: 'config' or 'network_subgraph'. NULL
+-- for indexers from other sources, e.g. interceptors.
+ALTER TABLE indexers ADD COLUMN source TEXT;
@@ -0,0 +1,12 @@
+-- The latest block of each indexer and subgraph deployment, and how long it
+-- has been since it last advanced, to detect indexers whose indexing stalled.
+CREATE TABLE indexing_progress (
+  indexer_id INTEGER NOT NULL REFERENCES indexers(id) ON DELETE CASCADE,
+  sg_deployment_id INTEGER NOT NULL REFERENCES sg_deployments(id) ON DELETE CASCADE,
+  latest_block_number BIGINT NOT NULL,
+  -- Main loop iterations in a row in which `latest_block_number` didn't
+  -- advance.
+  iterations_without_progress INTEGER NOT NULL DEFAULT 0,
+  last_advanced_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (indexer_id, sg_deployment_id)
+);
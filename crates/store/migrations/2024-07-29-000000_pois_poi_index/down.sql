@@ -0,0 +1 @@
+DROP INDEX pois_poi_idx;
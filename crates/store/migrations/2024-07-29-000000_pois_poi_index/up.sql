@@ -0,0 +1,3 @@
+-- For looking up PoIs by their hash, e.g. to find all indexers that produced
+-- the same PoI.
+CREATE INDEX pois_poi_idx ON pois (poi);
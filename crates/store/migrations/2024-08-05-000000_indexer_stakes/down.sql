@@ -0,0 +1 @@
+ALTER TABLE indexers DROP COLUMN staked_tokens;
@@ -0,0 +1,3 @@
+-- How much GRT an indexer has staked according to the network subgraphs, for
+-- stake-weighted consensus. NULL if unknown.
+ALTER TABLE indexers ADD COLUMN staked_tokens DOUBLE PRECISION;
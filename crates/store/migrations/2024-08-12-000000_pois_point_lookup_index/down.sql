@@ -0,0 +1 @@
+DROP INDEX pois_indexer_deployment_block_idx;
@@ -0,0 +1,3 @@
+-- For looking up the PoI of an indexer for a subgraph deployment and block,
+-- e.g. during bisection, most recent first.
+CREATE INDEX pois_indexer_deployment_block_idx ON pois (indexer_id, sg_deployment_id, block_id, id DESC);
@@ -0,0 +1 @@
+DROP INDEX pois_created_at_idx;
@@ -0,0 +1,3 @@
+-- For aggregating the PoIs collected in a time window, e.g. for divergence
+-- statistics.
+CREATE INDEX pois_created_at_idx ON pois (created_at);
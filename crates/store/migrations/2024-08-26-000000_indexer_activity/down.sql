@@ -0,0 +1,2 @@
+ALTER TABLE indexers DROP COLUMN is_active;
+ALTER TABLE indexers DROP COLUMN last_seen_at;
@@ -0,0 +1,5 @@
+-- When an indexer was last listed by any of the configured sources. Indexers
+-- that haven't been for a while are flagged as inactive by a periodic task and
+-- hidden by default, but keep all their data.
+ALTER TABLE indexers ADD COLUMN last_seen_at TIMESTAMP NOT NULL DEFAULT NOW();
+ALTER TABLE indexers ADD COLUMN is_active BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,4 @@
+ALTER TABLE indexing_progress DROP COLUMN reported_at;
+ALTER TABLE indexing_progress DROP COLUMN earliest_block_number;
+ALTER TABLE indexing_progress DROP COLUMN latest_block_hash;
+ALTER TABLE indexing_progress DROP COLUMN network;
@@ -0,0 +1,7 @@
+-- The rest of the most recent indexing status of each indexer and subgraph
+-- deployment, for the `indexingStatuses` GraphQL query. NULL networks belong
+-- to rows that weren't reported again since this migration.
+ALTER TABLE indexing_progress ADD COLUMN network TEXT;
+ALTER TABLE indexing_progress ADD COLUMN latest_block_hash BYTEA;
+ALTER TABLE indexing_progress ADD COLUMN earliest_block_number BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE indexing_progress ADD COLUMN reported_at TIMESTAMP NOT NULL DEFAULT NOW();
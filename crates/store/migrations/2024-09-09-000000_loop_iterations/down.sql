@@ -0,0 +1 @@
+DROP TABLE loop_iterations;
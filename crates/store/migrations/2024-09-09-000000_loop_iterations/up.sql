@@ -0,0 +1,20 @@
+-- A summary of each main loop iteration, for the API. Only the latest few
+-- rows are kept.
+CREATE TABLE loop_iterations (
+  id SERIAL PRIMARY KEY,
+  started_at TIMESTAMP NOT NULL,
+  finished_at TIMESTAMP NOT NULL,
+  -- How long each phase of the iteration took.
+  indexing_statuses_seconds DOUBLE PRECISION NOT NULL,
+  pois_seconds DOUBLE PRECISION NOT NULL,
+  writes_seconds DOUBLE PRECISION NOT NULL,
+  n_indexers INTEGER NOT NULL,
+  n_queried_indexers INTEGER NOT NULL,
+  n_responding_indexers INTEGER NOT NULL,
+  n_pois INTEGER NOT NULL,
+  n_pinned_pois INTEGER NOT NULL,
+  -- The errors of failed writes. The iteration failed if the PoIs couldn't
+  -- be written; other writes are best-effort.
+  write_errors TEXT[] NOT NULL,
+  succeeded BOOLEAN NOT NULL
+);
@@ -0,0 +1,8 @@
+-- Subgraph deployments that operators tagged as watched through the API, e.g.
+-- to focus dashboards on them. Deployments are referred to by IPFS CID, like
+-- in `tracked_deployments`, as they might not be known otherwise.
+CREATE TABLE watched_deployments (
+  ipfs_cid TEXT PRIMARY KEY,
+  watched_by TEXT,
+  watched_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,18 @@
+-- The allocations of indexers on subgraph deployments, according to the
+-- network subgraphs. Written on every indexer refresh. Allocations that a
+-- network subgraph stops listing as active are marked as closed rather than
+-- deleted. Indexers and deployments are referred to by address and IPFS CID,
+-- like in `tracked_deployments`, as they might not be known otherwise.
+CREATE TABLE allocations (
+  id TEXT PRIMARY KEY,
+  network_subgraph TEXT NOT NULL,
+  indexer_address BYTEA NOT NULL,
+  ipfs_cid TEXT NOT NULL,
+  allocated_tokens DOUBLE PRECISION NOT NULL,
+  created_at_epoch INTEGER NOT NULL,
+  first_seen_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  closed_at TIMESTAMP
+);
+
+CREATE INDEX allocations_indexer_address_idx ON allocations (indexer_address);
+CREATE INDEX allocations_ipfs_cid_idx ON allocations (ipfs_cid);
@@ -0,0 +1,14 @@
+-- PoIs that indexers reported for the same block number of a subgraph
+-- deployment, but with different block hashes, i.e. at least one of the
+-- indexers is on a reorged fork. Such PoIs aren't comparable and aren't
+-- divergences. Each indexer's PoI is recorded once, when the mismatch is
+-- first seen.
+CREATE TABLE block_hash_mismatches (
+  id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+  indexer_id INTEGER NOT NULL REFERENCES indexers(id) ON DELETE CASCADE,
+  sg_deployment_id INTEGER NOT NULL REFERENCES sg_deployments(id) ON DELETE CASCADE,
+  block_id BIGINT NOT NULL REFERENCES blocks(id) ON DELETE CASCADE,
+  poi BYTEA NOT NULL,
+  detected_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  UNIQUE (sg_deployment_id, block_id, indexer_id)
+);
@@ -0,0 +1 @@
+DROP TABLE indexer_discovery_counts;
@@ -0,0 +1,9 @@
+-- How many indexers each source (the configuration, a network subgraph or the
+-- interceptors) listed in a main loop iteration, before deduplication. Only
+-- the most recent ones are retained.
+CREATE TABLE indexer_discovery_counts (
+  id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+  source TEXT NOT NULL,
+  n_indexers INTEGER NOT NULL,
+  discovered_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,4 @@
+DROP TABLE divergence_triage_events;
+
+ALTER TABLE divergence_investigation_reports DROP COLUMN triage_note;
+ALTER TABLE divergence_investigation_reports DROP COLUMN triage_status;
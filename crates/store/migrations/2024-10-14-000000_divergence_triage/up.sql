@@ -0,0 +1,20 @@
+-- Where each divergence investigation report stands in triage, along with
+-- the note left with the latest status change.
+ALTER TABLE divergence_investigation_reports
+  ADD COLUMN triage_status TEXT NOT NULL DEFAULT 'new';
+ALTER TABLE divergence_investigation_reports ADD COLUMN triage_note TEXT;
+
+CREATE INDEX ON divergence_investigation_reports (triage_status, created_at);
+
+-- Every triage status change, for auditability. `actor` is the label of the
+-- admin API key that made the change.
+CREATE TABLE divergence_triage_events (
+  id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+  report_uuid UUID NOT NULL REFERENCES divergence_investigation_reports (uuid) ON DELETE CASCADE,
+  status TEXT NOT NULL,
+  note TEXT,
+  actor TEXT NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX ON divergence_triage_events (report_uuid);
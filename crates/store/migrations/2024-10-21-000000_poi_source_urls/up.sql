@@ -0,0 +1,3 @@
+-- The status endpoint that each PoI was queried from, for indexers that are
+-- reachable through several URLs. Unknown for PoIs collected before.
+ALTER TABLE pois ADD COLUMN source_url TEXT;
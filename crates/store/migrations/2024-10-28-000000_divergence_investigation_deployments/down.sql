@@ -0,0 +1 @@
+DROP TABLE divergence_investigation_deployments;
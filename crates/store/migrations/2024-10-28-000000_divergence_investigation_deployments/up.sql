@@ -0,0 +1,11 @@
+-- The subgraph deployments that each divergence investigation is about,
+-- recorded when it's requested. Requests and their reports share UUIDs, and
+-- requests are deleted once reported, so this doesn't reference either.
+CREATE TABLE divergence_investigation_deployments (
+  investigation_uuid UUID NOT NULL,
+  sg_deployment_id INTEGER NOT NULL REFERENCES sg_deployments(id) ON DELETE CASCADE,
+  PRIMARY KEY (investigation_uuid, sg_deployment_id)
+);
+
+CREATE INDEX ON divergence_investigation_deployments (sg_deployment_id);
+
@@ -2,20 +2,27 @@
 //! transactions.
 
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
 
-use chrono::Utc;
+use anyhow::Context;
+use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
 use diesel::sql_types;
 use diesel_async::{AsyncPgConnection, RunQueryDsl};
-use graphix_common_types::IpfsCid;
-use graphix_common_types::{inputs, IndexerAddress};
-use graphix_indexer_client::{BlockPointer, IndexerClient, IndexerId, WritablePoi};
+use graphix_common_types::{
+    inputs, DivergenceInvestigationReport, IndexerAddress, IpfsCid, PoiBytes,
+};
+use graphix_indexer_client::{
+    BlockPointer, IndexerClient, IndexerId, IndexingStatus, SubgraphDeployment, WritablePoi,
+};
 use tracing::info;
 
-use super::PoiLiveness;
+use super::{Pagination, PoiFilter, PoiLiveness};
 use crate::models::{
-    self, Indexer as IndexerModel, NewIndexer, NewLivePoi, NewPoi, NewSgDeployment, SgDeployment,
+    self, Indexer as IndexerModel, IntId, NewIndexer, NewLivePoi, NewPoi, NewPoiChange,
+    NewSgDeployment, SgDeployment,
 };
 use crate::schema::{self, live_pois, sg_names};
 
@@ -27,6 +34,7 @@ pub(super) async fn pois(
     block_range: Option<inputs::BlockRange>,
     limit: Option<u16>,
     live_only: bool,
+    include_stale: bool,
 ) -> anyhow::Result<Vec<models::Poi>> {
     #![allow(non_snake_case)]
     use schema::{blocks, indexers, pois, sg_deployments as sgd};
@@ -61,13 +69,20 @@ pub(super) async fn pois(
     let default_indexer_address = IndexerAddress::default();
     let indexer_filter = match indexer_address {
         // Ugly hacks to have the match arms' types match.
-        Some(addr) => indexers::address.eq(addr).or(FALSE),
-        None => indexers::address.eq(&default_indexer_address).or(TRUE),
+        Some(addr) => indexers::address.eq(addr).or(FALSE.clone()),
+        None => indexers::address
+            .eq(&default_indexer_address)
+            .or(TRUE.clone()),
     };
 
     let order_by = (blocks::number.desc(), schema::pois::created_at.desc());
     let limit = limit.map(|l| l as i64).unwrap_or(i64::MAX);
 
+    let stale_filter = match include_stale {
+        true => live_pois::stale.eq(false).or(TRUE),
+        false => live_pois::stale.eq(false).or(FALSE),
+    };
+
     match live_only {
         false => {
             let query = pois::table
@@ -94,44 +109,485 @@ pub(super) async fn pois(
                 .filter(deployments_filter)
                 .filter(blocks_filter)
                 .filter(indexer_filter)
+                .filter(stale_filter)
                 .limit(limit);
             Ok(query.load::<models::Poi>(conn).await?)
         }
     }
 }
 
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn search_pois(
+    conn: &mut AsyncPgConnection,
+    filter: &PoiFilter,
+    pagination: Pagination,
+) -> anyhow::Result<Vec<models::Poi>> {
+    use schema::{blocks, indexers, networks, pois, sg_deployments as sgd};
+
+    let mut query = pois::table
+        .inner_join(sgd::table.inner_join(networks::table))
+        .inner_join(indexers::table)
+        .inner_join(blocks::table)
+        .select(pois::all_columns)
+        .order_by((
+            blocks::number.desc(),
+            pois::created_at.desc(),
+            pois::id.desc(),
+        ))
+        .offset(pagination.offset.into())
+        .limit(pagination.limit.into())
+        .into_boxed();
+
+    if let Some(network) = &filter.network {
+        query = query.filter(networks::name.eq(network.clone()));
+    }
+    if !filter.deployments.is_empty() {
+        query = query.filter(sgd::ipfs_cid.eq_any(filter.deployments.clone()));
+    }
+    if let Some(indexer_address) = filter.indexer_address {
+        query = query.filter(indexers::address.eq(indexer_address));
+    }
+    if let Some(block_range) = &filter.block_range {
+        if let Some(start) = block_range.start {
+            query = query.filter(blocks::number.ge(i64::try_from(start)?));
+        }
+        if let Some(end) = block_range.end {
+            query = query.filter(blocks::number.le(i64::try_from(end)?));
+        }
+    }
+    if let Some(liveness) = filter.liveness {
+        let live_poi_ids = live_pois::table.select(live_pois::poi_id);
+        query = match liveness {
+            PoiLiveness::Live => query.filter(pois::id.eq_any(live_poi_ids)),
+            PoiLiveness::NotLive => query
+                .filter(pois::id.ne_all(live_poi_ids))
+                .filter(pois::pinned.eq(false)),
+            PoiLiveness::Pinned => query.filter(pois::pinned.eq(true)),
+        };
+    }
+    if let Some(poi) = filter.poi {
+        query = query.filter(pois::poi.eq(poi));
+    }
+
+    Ok(query.load::<models::Poi>(conn).await?)
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn poi(
+    conn: &mut AsyncPgConnection,
+    indexer_address: &IndexerAddress,
+    deployment: &IpfsCid,
+    block_number: u64,
+) -> anyhow::Result<Option<(models::Poi, bool)>> {
+    use schema::{blocks, indexers, pois, sg_deployments as sgd};
+
+    let row = pois::table
+        .inner_join(indexers::table)
+        .inner_join(sgd::table)
+        .inner_join(blocks::table)
+        .left_join(live_pois::table)
+        .select((pois::all_columns, live_pois::id.nullable()))
+        .filter(indexers::address.eq(indexer_address))
+        .filter(sgd::ipfs_cid.eq(deployment))
+        .filter(blocks::number.eq(i64::try_from(block_number)?))
+        // Indexers that rewound and resynced may have several PoIs for the
+        // same block.
+        .order_by(pois::id.desc())
+        .first::<(models::Poi, Option<models::IntId>)>(conn)
+        .await
+        .optional()?;
+
+    Ok(row.map(|(poi, live_poi_id)| (poi, live_poi_id.is_some())))
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn live_poi_at(
+    conn: &mut AsyncPgConnection,
+    indexer_address: &IndexerAddress,
+    deployment: &IpfsCid,
+    at: NaiveDateTime,
+) -> anyhow::Result<Option<models::Poi>> {
+    use schema::{indexers, pois, sg_deployments as sgd};
+
+    Ok(pois::table
+        .inner_join(indexers::table)
+        .inner_join(sgd::table)
+        .select(pois::all_columns)
+        .filter(indexers::address.eq(indexer_address))
+        .filter(sgd::ipfs_cid.eq(deployment))
+        .filter(pois::created_at.le(at))
+        .filter(pois::superseded_at.is_null().or(pois::superseded_at.gt(at)))
+        .filter(pois::pinned.eq(false))
+        .order_by((pois::created_at.desc(), pois::id.desc()))
+        .first::<models::Poi>(conn)
+        .await
+        .optional()?)
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn indexers_for_deployment(
+    conn: &mut AsyncPgConnection,
+    deployment: &IpfsCid,
+) -> anyhow::Result<Vec<models::IndexerDeploymentInfo>> {
+    use schema::{blocks, indexers, pois, sg_deployments as sgd};
+
+    // There's at most one live PoI per indexer and deployment, so this never
+    // returns historical PoIs.
+    let rows = pois::table
+        .inner_join(sgd::table)
+        .inner_join(indexers::table)
+        .inner_join(blocks::table)
+        .inner_join(live_pois::table)
+        .select((
+            indexers::all_columns,
+            pois::all_columns,
+            blocks::all_columns,
+        ))
+        .filter(sgd::ipfs_cid.eq(deployment))
+        .filter(live_pois::stale.eq(false))
+        .order_by(indexers::address.asc())
+        .load::<(IndexerModel, models::Poi, models::Block)>(conn)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(indexer, latest_poi, latest_block)| models::IndexerDeploymentInfo {
+                indexer,
+                latest_poi,
+                latest_block,
+            },
+        )
+        .collect())
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn mark_live_pois_stale(
+    conn: &mut AsyncPgConnection,
+    reachable_indexers: &[IndexerAddress],
+) -> anyhow::Result<usize> {
+    use schema::indexers;
+
+    let reachable_indexer_ids = indexers::table
+        .select(indexers::id)
+        .filter(indexers::address.eq_any(reachable_indexers));
+
+    Ok(diesel::update(live_pois::table)
+        .filter(live_pois::indexer_id.ne_all(reachable_indexer_ids))
+        .filter(live_pois::stale.eq(false))
+        .set(live_pois::stale.eq(true))
+        .execute(conn)
+        .await?)
+}
+
+/// Returns the hashes of all PoIs that pending divergence investigation
+/// requests or stored reports refer to.
+async fn investigated_pois(conn: &mut AsyncPgConnection) -> anyhow::Result<Vec<PoiBytes>> {
+    use schema::{
+        divergence_investigation_reports as reports,
+        pending_divergence_investigation_requests as requests,
+    };
+
+    let mut pois = vec![];
+
+    let requests = requests::table
+        .select(requests::request)
+        .load::<serde_json::Value>(conn)
+        .await?;
+    for request in requests {
+        let request: models::DivergenceInvestigationRequest =
+            serde_json::from_value(request).context("invalid divergence investigation request")?;
+        pois.extend(request.pois);
+    }
+
+    let reports = reports::table
+        .select(reports::report)
+        .load::<serde_json::Value>(conn)
+        .await?;
+    for report in reports {
+        let report: DivergenceInvestigationReport =
+            serde_json::from_value(report).context("invalid divergence investigation report")?;
+        pois.extend(
+            report
+                .bisection_runs
+                .iter()
+                .flat_map(|run| [run.poi1, run.poi2]),
+        );
+    }
+
+    Ok(pois)
+}
+
+/// Deletes up to `batch_size` PoIs at a time that were collected before
+/// `created_before`, until none are left, and returns how many were deleted.
+/// With `dry_run`, nothing is deleted and the number of PoIs that would be is
+/// returned instead.
+///
+/// PoIs that divergence investigations refer to are never deleted, and live
+/// PoIs only if `keep_live` is false. Each batch is a separate statement, so
+/// that pruning doesn't hold locks for too long.
+pub(super) async fn prune_pois(
+    conn: &mut AsyncPgConnection,
+    created_before: NaiveDateTime,
+    keep_live: bool,
+    batch_size: u16,
+    dry_run: bool,
+) -> anyhow::Result<u64> {
+    use schema::pois;
+
+    let investigated_pois = investigated_pois(conn).await?;
+    // Same hack as in `pois`, to have both cases be of the same type.
+    let prune_live = if keep_live { "false" } else { "true" };
+    let prunable_pois = || {
+        let keep_live_filter = pois::id
+            .ne_all(live_pois::table.select(live_pois::poi_id))
+            .or(diesel::dsl::sql::<sql_types::Bool>(prune_live));
+
+        pois::table
+            .filter(pois::created_at.lt(created_before))
+            .filter(pois::poi.ne_all(&investigated_pois))
+            .filter(keep_live_filter)
+    };
+
+    if dry_run {
+        let count = prunable_pois().count().get_result::<i64>(conn).await?;
+        return Ok(count as u64);
+    }
+
+    let mut deleted = 0;
+    loop {
+        let ids = prunable_pois()
+            .select(pois::id)
+            .limit(batch_size.into())
+            .load::<models::IntId>(conn)
+            .await?;
+        if ids.is_empty() {
+            break;
+        }
+
+        deleted += diesel::delete(pois::table.filter(pois::id.eq_any(&ids)))
+            .execute(conn)
+            .await? as u64;
+        info!(deleted, "Pruned a batch of PoIs");
+    }
+
+    Ok(deleted)
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn latest_common_poi_pairs(
+    conn: &mut AsyncPgConnection,
+    indexer1: &IndexerAddress,
+    indexer2: &IndexerAddress,
+) -> anyhow::Result<Vec<models::PoiPair>> {
+    use schema::{blocks, indexers, pois, sg_deployments as sgd};
+
+    let (pois1, pois2) = diesel::alias!(pois as pois1, pois as pois2);
+
+    let indexer1_id = indexers::table
+        .select(indexers::id)
+        .filter(indexers::address.eq(indexer1));
+    let indexer2_id = indexers::table
+        .select(indexers::id)
+        .filter(indexers::address.eq(indexer2));
+
+    // Pairs up the two indexers' PoIs for the same deployment and block, then
+    // keeps only the most recent block of each deployment.
+    let query = pois1
+        .inner_join(
+            pois2.on(pois2
+                .field(pois::sg_deployment_id)
+                .eq(pois1.field(pois::sg_deployment_id))
+                .and(pois2.field(pois::block_id).eq(pois1.field(pois::block_id)))),
+        )
+        .inner_join(sgd::table.on(sgd::id.eq(pois1.field(pois::sg_deployment_id))))
+        .inner_join(blocks::table.on(blocks::id.eq(pois1.field(pois::block_id))))
+        .filter(pois1.field(pois::indexer_id).eq_any(indexer1_id))
+        .filter(pois2.field(pois::indexer_id).eq_any(indexer2_id))
+        .select((
+            sgd::ipfs_cid,
+            blocks::number,
+            blocks::hash,
+            pois1.field(pois::poi),
+            pois2.field(pois::poi),
+        ))
+        .distinct_on(sgd::ipfs_cid)
+        .order_by((
+            sgd::ipfs_cid,
+            blocks::number.desc(),
+            pois1.field(pois::created_at).desc(),
+            pois2.field(pois::created_at).desc(),
+        ));
+
+    Ok(query.load::<models::PoiPair>(conn).await?)
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn poi_export_batch(
+    conn: &mut AsyncPgConnection,
+    filter: &inputs::PoisQuery,
+    created_since: Option<NaiveDateTime>,
+    after_id: Option<models::IntId>,
+    batch_size: u16,
+) -> anyhow::Result<Vec<models::PoiExportRow>> {
+    use schema::{blocks, indexers, networks, pois, sg_deployments as sgd};
+
+    let mut query = pois::table
+        .inner_join(sgd::table.inner_join(networks::table))
+        .inner_join(indexers::table)
+        .inner_join(blocks::table)
+        .select((
+            pois::id,
+            pois::created_at,
+            indexers::address,
+            sgd::ipfs_cid,
+            blocks::number,
+            blocks::hash,
+            pois::poi,
+        ))
+        .order_by(pois::id.asc())
+        .limit(batch_size.into())
+        .into_boxed();
+
+    if let Some(after_id) = after_id {
+        query = query.filter(pois::id.gt(after_id));
+    }
+    if let Some(created_since) = created_since {
+        query = query.filter(pois::created_at.ge(created_since));
+    }
+    if !filter.deployments.is_empty() {
+        query = query.filter(sgd::ipfs_cid.eq_any(filter.deployments.clone()));
+    }
+    if let Some(network) = &filter.network {
+        query = query.filter(networks::name.eq(network.clone()));
+    }
+    if let Some(block_range) = &filter.block_range {
+        if let Some(start) = block_range.start {
+            query = query.filter(blocks::number.ge(i64::try_from(start)?));
+        }
+        if let Some(end) = block_range.end {
+            query = query.filter(blocks::number.le(i64::try_from(end)?));
+        }
+    }
+
+    Ok(query.load::<models::PoiExportRow>(conn).await?)
+}
+
 pub async fn write_indexers(
     conn: &mut AsyncPgConnection,
     indexers: &[impl AsRef<dyn IndexerClient>],
 ) -> anyhow::Result<()> {
+    use diesel::upsert::excluded;
     use schema::indexers;
 
-    let insertable_indexers = indexers
-        .iter()
-        .map(|indexer| {
-            let indexer = indexer.as_ref();
-            NewIndexer {
-                address: indexer.address().to_owned(),
-                name: indexer.name().map(|s| s.to_string()),
+    // Postgres can't upsert the same row twice within a statement, so keep one
+    // indexer per address, preferring the best-sourced one.
+    let mut by_address: BTreeMap<IndexerAddress, &dyn IndexerClient> = BTreeMap::new();
+    for indexer in indexers {
+        let indexer = indexer.as_ref();
+        match by_address.entry(indexer.address()) {
+            Entry::Vacant(entry) => {
+                entry.insert(indexer);
             }
+            Entry::Occupied(mut entry) => {
+                if (indexer.source(), indexer.name_source())
+                    > (entry.get().source(), entry.get().name_source())
+                {
+                    entry.insert(indexer);
+                }
+            }
+        }
+    }
+
+    let insertable_indexers = by_address
+        .into_values()
+        .map(|indexer| NewIndexer {
+            address: indexer.address(),
+            name: indexer.name().map(|s| s.to_string()),
+            name_source: indexer.name_source().map(|s| s.as_str().to_string()),
+            source: indexer.source().map(|s| s.as_str().to_string()),
         })
         .collect::<Vec<_>>();
 
+    // Names and sources are refreshed, as they can change in the network
+    // subgraph or the configuration. Indexers that reappear after having been
+    // deactivated are active again.
     diesel::insert_into(indexers::table)
         .values(insertable_indexers)
-        .on_conflict_do_nothing()
+        .on_conflict(indexers::address)
+        .do_update()
+        .set((
+            indexers::name.eq(excluded(indexers::name)),
+            indexers::name_source.eq(excluded(indexers::name_source)),
+            indexers::source.eq(excluded(indexers::source)),
+            indexers::last_seen_at.eq(diesel::dsl::now),
+            indexers::is_active.eq(true),
+        ))
         .execute(conn)
         .await?;
 
     Ok(())
 }
 
+/// Moves the data of subgraph deployment `from` over to `into`, then deletes
+/// `from`. Where both have data of which there can only be one per deployment
+/// (or per indexer and deployment), that of `into` is kept.
+// The caller must make sure that `conn` is within a transaction.
+pub(super) async fn merge_sg_deployments(
+    conn: &mut AsyncPgConnection,
+    from: IntId,
+    into: IntId,
+) -> anyhow::Result<()> {
+    use schema::sg_deployments;
+
+    for statement in [
+        "UPDATE pois SET sg_deployment_id = $2 WHERE sg_deployment_id = $1",
+        "UPDATE poi_changes SET sg_deployment_id = $2 WHERE sg_deployment_id = $1",
+        "UPDATE block_hash_mismatches m SET sg_deployment_id = $2 WHERE sg_deployment_id = $1
+            AND NOT EXISTS (
+                SELECT 1 FROM block_hash_mismatches
+                WHERE sg_deployment_id = $2
+                    AND block_id = m.block_id AND indexer_id = m.indexer_id
+            )",
+        "UPDATE block_choice_traces SET sg_deployment_id = $2 WHERE sg_deployment_id = $1",
+        "UPDATE sg_deployment_api_versions SET sg_deployment_id = $2 WHERE sg_deployment_id = $1",
+        "UPDATE live_pois SET sg_deployment_id = $2 WHERE sg_deployment_id = $1
+            AND indexer_id NOT IN (SELECT indexer_id FROM live_pois WHERE sg_deployment_id = $2)",
+        "UPDATE sg_names SET sg_deployment_id = $2 WHERE sg_deployment_id = $1
+            AND NOT EXISTS (SELECT 1 FROM sg_names WHERE sg_deployment_id = $2)",
+        "UPDATE sg_deployment_manifests SET sg_deployment_id = $2 WHERE sg_deployment_id = $1
+            AND NOT EXISTS (SELECT 1 FROM sg_deployment_manifests WHERE sg_deployment_id = $2)",
+        "UPDATE divergence_investigation_deployments i SET sg_deployment_id = $2
+            WHERE sg_deployment_id = $1
+            AND NOT EXISTS (
+                SELECT 1 FROM divergence_investigation_deployments
+                WHERE sg_deployment_id = $2 AND investigation_uuid = i.investigation_uuid
+            )",
+    ] {
+        diesel::sql_query(statement)
+            .bind::<sql_types::Integer, _>(from)
+            .bind::<sql_types::Integer, _>(into)
+            .execute(conn)
+            .await?;
+    }
+
+    // Whatever couldn't be moved is deleted along with the deployment.
+    diesel::delete(sg_deployments::table.find(from))
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Writes `pois` and records a [`models::PoiChange`] for every PoI that differs
+/// from the one previously stored for the same indexer, deployment and block.
+/// Returns the number of such changes.
 // The caller must make sure that `conn` is within a transaction.
 pub(super) async fn write_pois<W>(
     conn: &mut AsyncPgConnection,
     pois: Vec<W>,
     live: PoiLiveness,
-) -> anyhow::Result<()>
+) -> anyhow::Result<usize>
 where
     W: WritablePoi + Send + Sync,
     W::IndexerId: Send + Sync,
@@ -140,6 +596,7 @@ where
     use schema::pois;
 
     let len = pois.len();
+    let mut poi_changes = vec![];
 
     // Group PoIs by deployment
     let mut grouped_pois: BTreeMap<_, Vec<_>> = BTreeMap::new();
@@ -154,27 +611,99 @@ where
         let sg_deployment_id = get_or_insert_deployment(conn, deployment).await?;
         let block_ptr = poi_group[0].block();
 
-        // Make sure all PoIs have the same block ptr
-        if !poi_group.iter().all(|poi| poi.block() == block_ptr) {
+        // Make sure all live PoIs have the same block number. PoIs at pinned
+        // blocks and backfilled PoIs may be at several blocks per deployment.
+        // Block hashes may differ, if indexers are on different forks.
+        if live == PoiLiveness::Live
+            && !poi_group
+                .iter()
+                .all(|poi| poi.block().number == block_ptr.number)
+        {
             return Err(anyhow::anyhow!(
-                "All PoIs for a given deployment must have the same block"
+                "All live PoIs for a given deployment must have the same block"
             ));
         }
 
-        let block_id = get_or_insert_block(conn, block_ptr).await?;
-
-        let mut new_pois = vec![];
+        let mut pois_to_write = vec![];
 
         for poi in poi_group.iter() {
+            let block_id = get_or_insert_block(conn, poi.block()).await?;
             let indexer_id =
                 get_indexer_id(conn, poi.indexer_id().name(), &poi.indexer_id().address()).await?;
 
+            // Pinned blocks are queried on every iteration, but a pinned PoI
+            // only needs to be stored once.
+            if live == PoiLiveness::Pinned {
+                let already_stored = diesel::select(diesel::dsl::exists(
+                    pois::table
+                        .filter(pois::indexer_id.eq(indexer_id))
+                        .filter(pois::sg_deployment_id.eq(sg_deployment_id))
+                        .filter(pois::block_id.eq(block_id))
+                        .filter(pois::pinned.eq(true))
+                        .filter(pois::poi.eq(*poi.proof_of_indexing())),
+                ))
+                .get_result::<bool>(conn)
+                .await?;
+                if already_stored {
+                    continue;
+                }
+            }
+
+            pois_to_write.push((poi, indexer_id, block_id));
+        }
+
+        if pois_to_write.is_empty() {
+            continue;
+        }
+
+        // Indexers that rewound and resynced may report a different PoI for a
+        // block we already have one for. The most recent PoIs of all indexers
+        // and blocks of the deployment are fetched in a single query.
+        let indexer_ids: Vec<models::IntId> = pois_to_write
+            .iter()
+            .map(|(_, indexer_id, _)| *indexer_id)
+            .collect();
+        let block_ids: Vec<models::BigIntId> = pois_to_write
+            .iter()
+            .map(|(_, _, block_id)| *block_id)
+            .collect();
+        let previous_pois: HashMap<(models::IntId, models::BigIntId), PoiBytes> = pois::table
+            .select((pois::indexer_id, pois::block_id, pois::poi))
+            .filter(pois::sg_deployment_id.eq(sg_deployment_id))
+            .filter(pois::indexer_id.eq_any(&indexer_ids))
+            .filter(pois::block_id.eq_any(&block_ids))
+            .distinct_on((pois::indexer_id, pois::block_id))
+            .order_by((pois::indexer_id, pois::block_id, pois::id.desc()))
+            .load::<(models::IntId, models::BigIntId, PoiBytes)>(conn)
+            .await?
+            .into_iter()
+            .map(|(indexer_id, block_id, poi)| ((indexer_id, block_id), poi))
+            .collect();
+
+        let mut new_pois = vec![];
+
+        for (poi, indexer_id, block_id) in pois_to_write {
+            if let Some(old_poi) = previous_pois.get(&(indexer_id, block_id)) {
+                if *old_poi != *poi.proof_of_indexing() {
+                    poi_changes.push(NewPoiChange {
+                        indexer_id,
+                        sg_deployment_id,
+                        block_id,
+                        old_poi: *old_poi,
+                        new_poi: *poi.proof_of_indexing(),
+                        detected_at: Utc::now().naive_utc(),
+                    });
+                }
+            }
+
             new_pois.push(NewPoi {
                 sg_deployment_id,
                 indexer_id,
                 block_id,
                 poi: *poi.proof_of_indexing(),
                 created_at: Utc::now().naive_utc(),
+                pinned: live == PoiLiveness::Pinned,
+                source_url: poi.source_url().map(str::to_owned),
             });
         }
 
@@ -186,6 +715,25 @@ where
             .await?;
 
         if live == PoiLiveness::Live {
+            // The live PoIs of the indexers we've got new PoIs from have been
+            // superseded.
+            let indexer_ids: Vec<models::IntId> = id_and_indexer
+                .iter()
+                .map(|(_, indexer_id)| *indexer_id)
+                .collect();
+            diesel::update(pois::table)
+                .filter(
+                    pois::id.eq_any(
+                        live_pois::table
+                            .select(live_pois::poi_id)
+                            .filter(live_pois::sg_deployment_id.eq(sg_deployment_id))
+                            .filter(live_pois::indexer_id.eq_any(&indexer_ids)),
+                    ),
+                )
+                .set(pois::superseded_at.eq(Utc::now().naive_utc()))
+                .execute(conn)
+                .await?;
+
             // Clear any live pois for this deployment
             diesel::delete(
                 live_pois::table.filter(live_pois::sg_deployment_id.eq(sg_deployment_id)),
@@ -207,10 +755,467 @@ where
         }
     }
 
-    info!(%len, "Wrote POIs to database");
+    if !poi_changes.is_empty() {
+        insert_into(schema::poi_changes::table)
+            .values(&poi_changes)
+            .execute(conn)
+            .await?;
+    }
+
+    info!(%len, poi_changes = poi_changes.len(), "Wrote POIs to database");
+    Ok(poi_changes.len())
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn poi_changes(
+    conn: &mut AsyncPgConnection,
+    indexer_address: Option<&IndexerAddress>,
+    deployment: Option<&IpfsCid>,
+    limit: u16,
+) -> anyhow::Result<Vec<models::PoiChange>> {
+    use schema::{blocks, indexers, poi_changes, sg_deployments as sgd};
+
+    let mut query = poi_changes::table
+        .inner_join(indexers::table)
+        .inner_join(sgd::table)
+        .inner_join(blocks::table)
+        .select((
+            poi_changes::id,
+            indexers::address,
+            sgd::ipfs_cid,
+            blocks::number,
+            blocks::hash,
+            poi_changes::old_poi,
+            poi_changes::new_poi,
+            poi_changes::detected_at,
+        ))
+        .order_by(poi_changes::id.desc())
+        .limit(limit.into())
+        .into_boxed();
+
+    if let Some(address) = indexer_address {
+        query = query.filter(indexers::address.eq(address));
+    }
+    if let Some(deployment) = deployment {
+        query = query.filter(sgd::ipfs_cid.eq(deployment.to_string()));
+    }
+
+    Ok(query.load::<models::PoiChange>(conn).await?)
+}
+
+/// Records `pois` as being at a block whose hash other indexers disagree on.
+/// PoIs that were already recorded are skipped. Returns the number of newly
+/// recorded PoIs.
+// The caller must make sure that `conn` is within a transaction.
+pub(super) async fn write_block_hash_mismatches<W>(
+    conn: &mut AsyncPgConnection,
+    pois: Vec<W>,
+) -> anyhow::Result<usize>
+where
+    W: WritablePoi + Send + Sync,
+    W::IndexerId: Send + Sync,
+{
+    use schema::block_hash_mismatches;
+
+    if pois.is_empty() {
+        return Ok(0);
+    }
+
+    let mut new_mismatches = vec![];
+    for poi in pois.iter() {
+        new_mismatches.push(models::NewBlockHashMismatch {
+            indexer_id: get_indexer_id(conn, poi.indexer_id().name(), &poi.indexer_id().address())
+                .await?,
+            sg_deployment_id: get_or_insert_deployment(conn, poi.deployment_cid()).await?,
+            block_id: get_or_insert_block(conn, poi.block()).await?,
+            poi: *poi.proof_of_indexing(),
+        });
+    }
+
+    Ok(diesel::insert_into(block_hash_mismatches::table)
+        .values(&new_mismatches)
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?)
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn block_hash_mismatches(
+    conn: &mut AsyncPgConnection,
+    deployment: Option<&IpfsCid>,
+    limit: u16,
+) -> anyhow::Result<Vec<models::BlockHashMismatch>> {
+    // Indexers that switched forks have several rows for the block, of
+    // which only the most recent one counts.
+    let query = diesel::sql_query(
+        "WITH latest_mismatches AS (
+            SELECT DISTINCT ON (m.sg_deployment_id, b.number, m.indexer_id)
+                m.sg_deployment_id, b.number, b.hash, m.indexer_id, m.detected_at
+            FROM block_hash_mismatches m
+            JOIN blocks b ON b.id = m.block_id
+            ORDER BY m.sg_deployment_id, b.number, m.indexer_id, m.id DESC
+        )
+        SELECT sgd.ipfs_cid AS deployment,
+            l.number AS block_number,
+            min(l.detected_at) AS first_detected_at,
+            array_agg(i.address ORDER BY i.address) AS indexers,
+            array_agg(l.hash ORDER BY i.address) AS block_hashes
+        FROM latest_mismatches l
+        JOIN sg_deployments sgd ON sgd.id = l.sg_deployment_id
+        JOIN indexers i ON i.id = l.indexer_id
+        WHERE $1::text IS NULL OR sgd.ipfs_cid = $1
+        GROUP BY sgd.ipfs_cid, l.number
+        ORDER BY first_detected_at DESC, sgd.ipfs_cid, l.number
+        LIMIT $2",
+    );
+
+    Ok(query
+        .bind::<sql_types::Nullable<sql_types::Text>, _>(deployment.map(|cid| cid.to_string()))
+        .bind::<sql_types::BigInt, _>(i64::from(limit))
+        .load::<models::BlockHashMismatch>(conn)
+        .await?)
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn stale_divergences(
+    conn: &mut AsyncPgConnection,
+    pagination: Pagination,
+) -> anyhow::Result<Vec<models::StaleDivergence>> {
+    // A divergence is active if the most recent PoIs of the indexers for a
+    // block differ, and was detected when a second, different PoI was first
+    // collected for the block.
+    let query = diesel::sql_query(
+        "WITH latest_pois AS (
+            SELECT DISTINCT ON (sg_deployment_id, block_id, indexer_id)
+                sg_deployment_id, block_id, indexer_id, poi
+            FROM pois
+            ORDER BY sg_deployment_id, block_id, indexer_id, id DESC
+        ), active_divergences AS (
+            SELECT sg_deployment_id, block_id, array_agg(indexer_id) AS indexer_ids
+            FROM latest_pois
+            GROUP BY sg_deployment_id, block_id
+            HAVING count(DISTINCT poi) > 1
+        ), first_seen_pois AS (
+            SELECT sg_deployment_id, block_id, min(created_at) AS first_seen_at
+            FROM pois
+            JOIN active_divergences USING (sg_deployment_id, block_id)
+            GROUP BY sg_deployment_id, block_id, poi
+        ), first_detected AS (
+            SELECT sg_deployment_id, block_id,
+                (array_agg(first_seen_at ORDER BY first_seen_at))[2] AS first_detected_at
+            FROM first_seen_pois
+            GROUP BY sg_deployment_id, block_id
+        )
+        SELECT sgd.ipfs_cid AS deployment,
+            b.number AS block_number,
+            b.hash AS block_hash,
+            f.first_detected_at,
+            extract(epoch FROM (now() AT TIME ZONE 'UTC') - f.first_detected_at)::bigint
+                AS persisted_for_seconds,
+            array(
+                SELECT i.address FROM indexers i
+                WHERE i.id = ANY(d.indexer_ids)
+                ORDER BY i.address
+            ) AS indexers
+        FROM active_divergences d
+        JOIN first_detected f USING (sg_deployment_id, block_id)
+        JOIN sg_deployments sgd ON sgd.id = d.sg_deployment_id
+        JOIN blocks b ON b.id = d.block_id
+        ORDER BY f.first_detected_at, sgd.ipfs_cid, b.number
+        LIMIT $1 OFFSET $2",
+    );
+
+    Ok(query
+        .bind::<sql_types::BigInt, _>(i64::from(pagination.limit))
+        .bind::<sql_types::BigInt, _>(i64::from(pagination.offset))
+        .load::<models::StaleDivergence>(conn)
+        .await?)
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn divergence_stats(
+    conn: &mut AsyncPgConnection,
+    network: Option<&str>,
+    bucket: models::TimeBucket,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> anyhow::Result<Vec<models::DivergenceStats>> {
+    // Everything is aggregated in the database, as there are far too many
+    // PoIs to load. Buckets without any PoIs are still returned, with zeros.
+    let query = diesel::sql_query(
+        "WITH buckets AS (
+            SELECT bucket_start
+            FROM generate_series(date_trunc($1, $2), $3, ('1 ' || $1)::interval) AS bucket_start
+            WHERE bucket_start < $3
+        ), latest_pois AS (
+            SELECT DISTINCT ON (bucket_start, p.sg_deployment_id, p.block_id, p.indexer_id)
+                date_trunc($1, p.created_at) AS bucket_start,
+                p.sg_deployment_id, p.block_id, p.indexer_id, p.poi
+            FROM pois p
+            JOIN sg_deployments d ON d.id = p.sg_deployment_id
+            JOIN networks n ON n.id = d.network
+            WHERE p.created_at >= $2 AND p.created_at < $3
+                AND ($4::text IS NULL OR n.name = $4)
+            ORDER BY bucket_start, p.sg_deployment_id, p.block_id, p.indexer_id, p.id DESC
+        ), comparisons AS (
+            SELECT bucket_start, sg_deployment_id, block_id,
+                count(DISTINCT poi) > 1 AS diverging
+            FROM latest_pois
+            GROUP BY bucket_start, sg_deployment_id, block_id
+            HAVING count(*) > 1
+        ), diverging_indexers AS (
+            SELECT l.bucket_start, count(DISTINCT l.indexer_id) AS n_diverging_indexers
+            FROM latest_pois l
+            JOIN comparisons c USING (bucket_start, sg_deployment_id, block_id)
+            WHERE c.diverging
+            GROUP BY l.bucket_start
+        )
+        SELECT b.bucket_start,
+            count(DISTINCT c.sg_deployment_id) FILTER (WHERE c.diverging)
+                AS n_diverging_deployments,
+            count(DISTINCT c.sg_deployment_id) AS n_compared_deployments,
+            count(c.block_id) FILTER (WHERE c.diverging) AS n_diverging_blocks,
+            coalesce(max(i.n_diverging_indexers), 0) AS n_diverging_indexers
+        FROM buckets b
+        LEFT JOIN comparisons c USING (bucket_start)
+        LEFT JOIN diverging_indexers i USING (bucket_start)
+        GROUP BY b.bucket_start
+        ORDER BY b.bucket_start",
+    );
+
+    Ok(query
+        .bind::<sql_types::Text, _>(bucket.as_str())
+        .bind::<sql_types::Timestamp, _>(from)
+        .bind::<sql_types::Timestamp, _>(to)
+        .bind::<sql_types::Nullable<sql_types::Text>, _>(network)
+        .load::<models::DivergenceStats>(conn)
+        .await?)
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn update_indexing_progress(
+    conn: &mut AsyncPgConnection,
+    indexer_id: i32,
+    sg_deployment_id: i32,
+    status: &IndexingStatus,
+) -> anyhow::Result<()> {
+    // Rewinds count as progress too, so that the latest block can advance
+    // again from there.
+    diesel::sql_query(
+        "INSERT INTO indexing_progress (indexer_id, sg_deployment_id, latest_block_number,
+            network, latest_block_hash, earliest_block_number)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (indexer_id, sg_deployment_id) DO UPDATE SET
+            latest_block_number = excluded.latest_block_number,
+            network = excluded.network,
+            latest_block_hash = excluded.latest_block_hash,
+            earliest_block_number = excluded.earliest_block_number,
+            reported_at = NOW(),
+            iterations_without_progress = CASE
+                WHEN indexing_progress.latest_block_number = excluded.latest_block_number
+                THEN indexing_progress.iterations_without_progress + 1
+                ELSE 0
+            END,
+            last_advanced_at = CASE
+                WHEN indexing_progress.latest_block_number = excluded.latest_block_number
+                THEN indexing_progress.last_advanced_at
+                ELSE NOW()
+            END",
+    )
+    .bind::<sql_types::Integer, _>(indexer_id)
+    .bind::<sql_types::Integer, _>(sg_deployment_id)
+    .bind::<sql_types::BigInt, _>(status.latest_block.number as i64)
+    .bind::<sql_types::Text, _>(&status.network)
+    .bind::<sql_types::Nullable<sql_types::Binary>, _>(status.latest_block.hash.as_ref())
+    .bind::<sql_types::BigInt, _>(status.earliest_block_num as i64)
+    .execute(conn)
+    .await?;
+
     Ok(())
 }
 
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn indexing_statuses(
+    conn: &mut AsyncPgConnection,
+    indexer: Option<&IndexerAddress>,
+    deployment: Option<&str>,
+) -> anyhow::Result<Vec<models::StoredIndexingStatus>> {
+    let query = diesel::sql_query(
+        "SELECT i.address AS indexer,
+            sgd.ipfs_cid AS deployment,
+            p.network,
+            p.latest_block_number,
+            p.latest_block_hash,
+            p.earliest_block_number,
+            p.reported_at
+        FROM indexing_progress p
+        JOIN indexers i ON i.id = p.indexer_id
+        JOIN sg_deployments sgd ON sgd.id = p.sg_deployment_id
+        WHERE p.network IS NOT NULL
+            AND ($1::bytea IS NULL OR i.address = $1)
+            AND ($2::text IS NULL OR sgd.ipfs_cid = $2)
+        ORDER BY sgd.ipfs_cid, i.address",
+    );
+
+    Ok(query
+        .bind::<sql_types::Nullable<sql_types::Binary>, _>(indexer)
+        .bind::<sql_types::Nullable<sql_types::Text>, _>(deployment)
+        .load::<models::StoredIndexingStatus>(conn)
+        .await?)
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn stalled_indexers(
+    conn: &mut AsyncPgConnection,
+    min_iterations: u32,
+    limit: u16,
+) -> anyhow::Result<Vec<models::StalledIndexer>> {
+    let query = diesel::sql_query(
+        "SELECT i.address AS indexer,
+            sgd.ipfs_cid AS deployment,
+            p.latest_block_number,
+            p.iterations_without_progress,
+            p.last_advanced_at,
+            extract(epoch FROM (now() AT TIME ZONE 'UTC') - p.last_advanced_at)::bigint
+                AS stalled_for_seconds
+        FROM indexing_progress p
+        JOIN indexers i ON i.id = p.indexer_id
+        JOIN sg_deployments sgd ON sgd.id = p.sg_deployment_id
+        WHERE p.iterations_without_progress >= $1
+        ORDER BY p.last_advanced_at, i.address, sgd.ipfs_cid
+        LIMIT $2",
+    );
+
+    Ok(query
+        .bind::<sql_types::BigInt, _>(i64::from(min_iterations))
+        .bind::<sql_types::BigInt, _>(i64::from(limit))
+        .load::<models::StalledIndexer>(conn)
+        .await?)
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn pois_with_hash(
+    conn: &mut AsyncPgConnection,
+    poi: &PoiBytes,
+) -> anyhow::Result<Vec<models::Poi>> {
+    use schema::pois;
+
+    Ok(pois::table
+        .filter(pois::poi.eq(poi))
+        .order_by(pois::id)
+        .load(conn)
+        .await?)
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn poi_majorities(
+    conn: &mut AsyncPgConnection,
+    poi: &PoiBytes,
+) -> anyhow::Result<Vec<models::PoiMajority>> {
+    // Only the most recent PoI of each indexer for a block counts, like for
+    // divergences.
+    let query = diesel::sql_query(
+        "WITH blocks_with_poi AS (
+            SELECT DISTINCT sg_deployment_id, block_id FROM pois WHERE poi = $1
+        ), latest_pois AS (
+            SELECT DISTINCT ON (p.sg_deployment_id, p.block_id, p.indexer_id)
+                p.sg_deployment_id, p.block_id, p.poi
+            FROM pois p
+            JOIN blocks_with_poi USING (sg_deployment_id, block_id)
+            ORDER BY p.sg_deployment_id, p.block_id, p.indexer_id, p.id DESC
+        )
+        SELECT sg_deployment_id, block_id,
+            count(*) FILTER (WHERE poi = $1) * 2 > count(*) AS is_majority
+        FROM latest_pois
+        GROUP BY sg_deployment_id, block_id",
+    );
+
+    Ok(query
+        .bind::<sql_types::Binary, _>(poi)
+        .load::<models::PoiMajority>(conn)
+        .await?)
+}
+
+// This is a single SQL statement, a transaction is not necessary.
+pub(super) async fn poi_support(
+    conn: &mut AsyncPgConnection,
+    deployment: &IpfsCid,
+    block_range: RangeInclusive<u64>,
+) -> anyhow::Result<Vec<models::PoiSupport>> {
+    // Only the most recent PoI of each indexer for a block counts, like for
+    // divergences.
+    let query = diesel::sql_query(
+        "WITH latest_pois AS (
+            SELECT DISTINCT ON (p.block_id, p.indexer_id) p.block_id, p.indexer_id, p.poi
+            FROM pois p
+            JOIN sg_deployments d ON d.id = p.sg_deployment_id
+            JOIN blocks b ON b.id = p.block_id
+            WHERE d.ipfs_cid = $1 AND b.number BETWEEN $2 AND $3
+            ORDER BY p.block_id, p.indexer_id, p.id DESC
+        )
+        SELECT b.number AS block_number, l.poi,
+            array_agg(i.address ORDER BY i.address) AS indexers
+        FROM latest_pois l
+        JOIN blocks b ON b.id = l.block_id
+        JOIN indexers i ON i.id = l.indexer_id
+        GROUP BY b.number, l.poi
+        ORDER BY b.number, count(*) DESC, l.poi",
+    );
+
+    Ok(query
+        .bind::<sql_types::Text, _>(deployment.to_string())
+        .bind::<sql_types::BigInt, _>(i64::try_from(*block_range.start())?)
+        .bind::<sql_types::BigInt, _>(i64::try_from(*block_range.end())?)
+        .load::<models::PoiSupport>(conn)
+        .await?)
+}
+
+pub(super) async fn block_choice_traces(
+    conn: &mut AsyncPgConnection,
+    deployment: &IpfsCid,
+    limit: u16,
+) -> anyhow::Result<Vec<models::BlockChoiceTrace>> {
+    use schema::{block_choice_traces, sg_deployments as sgd};
+
+    Ok(block_choice_traces::table
+        .inner_join(sgd::table)
+        .select((
+            block_choice_traces::id,
+            sgd::ipfs_cid,
+            block_choice_traces::policy,
+            block_choice_traces::candidate_blocks,
+            block_choice_traces::chosen_block,
+            block_choice_traces::created_at,
+        ))
+        .filter(sgd::ipfs_cid.eq(deployment.to_string()))
+        .order_by(block_choice_traces::id.desc())
+        .limit(limit.into())
+        .load::<models::BlockChoiceTrace>(conn)
+        .await?)
+}
+
+pub(super) async fn tracked_deployments(
+    conn: &mut AsyncPgConnection,
+) -> anyhow::Result<Vec<models::TrackedDeployment>> {
+    use schema::tracked_deployments;
+
+    Ok(tracked_deployments::table
+        .select((
+            tracked_deployments::ipfs_cid,
+            tracked_deployments::network_subgraph,
+            tracked_deployments::network,
+            tracked_deployments::active_allocations,
+            tracked_deployments::allocated_tokens,
+            tracked_deployments::tracked_since,
+        ))
+        .order_by((
+            tracked_deployments::ipfs_cid,
+            tracked_deployments::network_subgraph,
+        ))
+        .load::<models::TrackedDeployment>(conn)
+        .await?)
+}
+
 async fn get_or_insert_block(
     conn: &mut AsyncPgConnection,
     block: &BlockPointer,
@@ -269,12 +1274,14 @@ pub async fn get_indexer_id<'a>(
     }
 }
 
-async fn get_or_insert_deployment(
+pub(super) async fn get_or_insert_deployment(
     conn: &mut AsyncPgConnection,
     deployment_cid: &str,
 ) -> Result<i32, anyhow::Error> {
     use schema::sg_deployments;
 
+    let deployment_cid = &SubgraphDeployment::new(deployment_cid).0;
+
     let existing_sg_deployment: Option<SgDeployment> = sg_deployments::table
         .left_join(sg_names::table)
         .select((
@@ -2,37 +2,126 @@
 
 mod diesel_queries;
 mod loader;
+mod poi_store;
 
 use diesel_async::pooled_connection::deadpool::{Object, Pool};
-use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfig};
 use diesel_async::scoped_futures::ScopedFutureExt;
 use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
 #[cfg(tests)]
 pub use diesel_queries;
-use graphix_common_types::{inputs, IndexerAddress, IpfsCid, PoiBytes};
+use graphix_common_types::{inputs, DivergenceTriageStatus, IndexerAddress, IpfsCid, PoiBytes};
 use models::{FailedQueryRow, NewIndexerNetworkSubgraphMetadata, SgDeployment};
 use uuid::Uuid;
 pub mod models;
 mod schema;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Debug;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Error;
+use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
 use diesel_async_migrations::{embed_migrations, EmbeddedMigrations};
-use graphix_indexer_client::{IndexerClient, IndexerId, WritablePoi};
+use graphix_indexer_client::{
+    IndexerClient, IndexerId, IndexingStatus, QueryOutcome, SubgraphDeployment, WritablePoi,
+};
 pub use loader::StoreLoader;
-use tracing::info;
+pub use poi_store::{InMemoryPoiStore, PoiStore};
+use tracing::{info, instrument, warn};
 
 use crate::models::{Indexer as IndexerModel, IntId, NewNetwork, Poi};
 
+/// How many query outcomes are kept per indexer and kind of query.
+const QUERY_OUTCOMES_RETAINED: i64 = 100;
+
+/// How many block choice traces are kept per subgraph deployment.
+const BLOCK_CHOICE_TRACES_RETAINED: i64 = 100;
+
+/// How many main loop iteration summaries are kept.
+const LOOP_ITERATIONS_RETAINED: i64 = 1000;
+
+/// How many indexer discovery counts are kept, across all sources.
+const INDEXER_DISCOVERY_COUNTS_RETAINED: i64 = 10_000;
+
+/// How long to wait before retrying to connect at startup at most; retries
+/// start after a second and back off exponentially.
+const MAX_CONNECT_RETRY_DELAY: Duration = Duration::from_secs(30);
+
 /// An abstraction over all database operations. It uses [`Arc`] internally, so
 /// it's cheaply cloneable.
 #[derive(Clone)]
 pub struct Store {
     pool: Pool<AsyncPgConnection>,
+    connection_timeout: Duration,
+    // Metrics
+    // -------
+    pool_wait_seconds: Option<prometheus::Histogram>,
+}
+
+/// Connection pool options of the [`Store`].
+#[derive(Clone, Debug)]
+pub struct StoreOptions {
+    /// The maximum number of open connections.
+    pub max_connections: usize,
+    /// How many connections to open right away rather than on demand. Only
+    /// applies to [`Store::new_with_options`].
+    pub min_idle: usize,
+    /// How long to wait for a connection, whether an idle one or a new one,
+    /// before failing.
+    pub connection_timeout: Duration,
+    /// Statements that run longer than this are cancelled by Postgres.
+    pub statement_timeout: Option<Duration>,
+    /// How many times to retry connecting to the database before giving up.
+    /// Only applies to [`Store::new_with_options`].
+    pub connect_retries: u32,
+    /// Observes how long it takes to get a connection from the pool.
+    pub pool_wait_seconds: Option<prometheus::Histogram>,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        Self {
+            max_connections: 16,
+            min_idle: 0,
+            connection_timeout: Duration::from_secs(30),
+            statement_timeout: None,
+            connect_retries: 0,
+            pool_wait_seconds: None,
+        }
+    }
+}
+
+/// Checks that `db_url` is a connection string that the Postgres client
+/// understands, i.e. either a `postgres://` or `postgresql://` URL or
+/// `key=value` pairs. Unix domain sockets are given as a `host` that is a
+/// directory, e.g. `postgresql:///graphix?host=/var/run/postgresql` or
+/// `host=/var/run/postgresql dbname=graphix`. Errors don't include the URL,
+/// which may contain a password.
+fn validate_db_url(db_url: &str) -> anyhow::Result<()> {
+    if let Some((scheme, _)) = db_url.split_once("://") {
+        let is_scheme = scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+        // The queries rely on Postgres features throughout, so there's no
+        // SQLite backend to fall back to.
+        anyhow::ensure!(
+            !is_scheme || !scheme.starts_with("sqlite"),
+            "SQLite databases aren't supported, Graphix requires Postgres"
+        );
+        anyhow::ensure!(
+            !is_scheme || scheme == "postgres" || scheme == "postgresql",
+            "unsupported database URL scheme `{scheme}`, expected `postgres` or `postgresql`"
+        );
+    }
+    tokio_postgres::Config::from_str(db_url)
+        .map_err(|err| anyhow::anyhow!("invalid database URL: {err}"))?;
+
+    Ok(())
 }
 
 impl Debug for Store {
@@ -46,18 +135,170 @@ impl Store {
 
     /// Connects to the database and runs all pending migrations.
     pub async fn new(db_url: &str) -> anyhow::Result<Self> {
-        info!("Initializing database connection pool");
+        Self::new_with_options(db_url, StoreOptions::default()).await
+    }
 
-        let manager = AsyncDieselConnectionManager::new(db_url);
-        let pool = Pool::builder(manager).build()?;
-        let store = Self { pool };
+    /// Like [`Store::new`], but with custom connection pool options. Waits
+    /// for the database to become reachable, within
+    /// [`StoreOptions::connect_retries`], and opens
+    /// [`StoreOptions::min_idle`] connections.
+    pub async fn new_with_options(db_url: &str, options: StoreOptions) -> anyhow::Result<Self> {
+        let connect_retries = options.connect_retries;
+        let min_idle = options.min_idle;
+        let store = Self::connect_with_options(db_url, options)?;
+        store.wait_until_reachable(connect_retries).await?;
+
+        // Connections return to the pool when they're dropped.
+        let mut idle_connections = Vec::with_capacity(min_idle);
+        for _ in 0..min_idle {
+            idle_connections.push(store.conn().await?);
+        }
+        drop(idle_connections);
 
         store.run_migrations().await?;
 
+        let normalized = store.normalize_sg_deployment_cids().await?;
+        if normalized > 0 {
+            info!(normalized, "Normalized the CIDs of subgraph deployments");
+        }
+
         Ok(store)
     }
 
-    async fn run_migrations(&self) -> anyhow::Result<()> {
+    /// Like [`Store::new`], but leaves the database schema alone.
+    pub fn connect(db_url: &str) -> anyhow::Result<Self> {
+        Self::connect_with_options(db_url, StoreOptions::default())
+    }
+
+    /// Like [`Store::connect`], but with custom connection pool options.
+    /// Connections are only opened on demand.
+    pub fn connect_with_options(db_url: &str, options: StoreOptions) -> anyhow::Result<Self> {
+        validate_db_url(db_url)?;
+        info!(
+            max_connections = options.max_connections,
+            "Initializing database connection pool"
+        );
+
+        let mut manager_config = ManagerConfig::default();
+        if let Some(statement_timeout) = options.statement_timeout {
+            let statement_timeout_ms = statement_timeout.as_millis();
+            manager_config.custom_setup = Box::new(move |url| {
+                Box::pin(async move {
+                    let mut conn = AsyncPgConnection::establish(url).await?;
+                    diesel::sql_query(format!("SET statement_timeout = {statement_timeout_ms}"))
+                        .execute(&mut conn)
+                        .await
+                        .map_err(ConnectionError::CouldntSetupConfiguration)?;
+                    Ok(conn)
+                })
+            });
+        }
+        let manager = AsyncDieselConnectionManager::new_with_config(db_url, manager_config);
+        let pool = Pool::builder(manager)
+            .max_size(options.max_connections)
+            .build()?;
+
+        Ok(Self {
+            pool,
+            connection_timeout: options.connection_timeout,
+            pool_wait_seconds: options.pool_wait_seconds,
+        })
+    }
+
+    /// Checks that the database accepts connections, retrying up to
+    /// `retries` times with exponential backoff, e.g. while it's still
+    /// starting up.
+    async fn wait_until_reachable(&self, retries: u32) -> anyhow::Result<()> {
+        let mut delay = Duration::from_secs(1);
+        for attempt in 0.. {
+            let result = async {
+                let mut conn = self.conn().await?;
+                diesel::sql_query("SELECT 1").execute(&mut conn).await?;
+                anyhow::Ok(())
+            }
+            .await;
+
+            match result {
+                Ok(()) => break,
+                Err(err) if attempt < retries => {
+                    warn!(
+                        error = %err,
+                        retry_in_seconds = delay.as_secs(),
+                        "Database isn't reachable yet, retrying"
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_CONNECT_RETRY_DELAY);
+                }
+                Err(err) => return Err(err.context("database isn't reachable")),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Rewrites the CIDs of subgraph deployments to their canonical form (see
+    /// [`IpfsCid`]), merging deployments that were stored under both their
+    /// CIDv0 and CIDv1 forms. Returns the number of rewritten deployments.
+    pub async fn normalize_sg_deployment_cids(&self) -> anyhow::Result<usize> {
+        use schema::sg_deployments as sgd;
+
+        let mut conn = self.conn().await?;
+        conn.transaction::<_, Error, _>(|conn| {
+            async move {
+                let deployments: Vec<(IntId, String)> = sgd::table
+                    .select((sgd::id, sgd::ipfs_cid))
+                    .load(conn)
+                    .await?;
+                let mut ids_by_cid: HashMap<String, IntId> = deployments
+                    .iter()
+                    .map(|(id, cid)| (cid.clone(), *id))
+                    .collect();
+
+                let mut normalized = 0;
+                for (id, cid) in deployments {
+                    let canonical = SubgraphDeployment::new(cid.as_str()).0;
+                    if canonical == cid {
+                        continue;
+                    }
+
+                    match ids_by_cid.get(&canonical) {
+                        Some(&canonical_id) => {
+                            diesel_queries::merge_sg_deployments(conn, id, canonical_id).await?;
+                        }
+                        None => {
+                            diesel::update(sgd::table.find(id))
+                                .set(sgd::ipfs_cid.eq(&canonical))
+                                .execute(conn)
+                                .await?;
+                            ids_by_cid.insert(canonical, id);
+                        }
+                    }
+                    normalized += 1;
+                }
+
+                Ok(normalized)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Returns the names of all migrations that haven't been applied to the
+    /// database yet, in the order they would be applied.
+    pub async fn pending_migrations(&self) -> anyhow::Result<Vec<String>> {
+        let mut conn = self.pool.get().await?;
+        let migrations = Self::MIGRATIONS
+            .pending_migrations(&mut conn)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        Ok(migrations
+            .into_iter()
+            .map(|migration| migration.name.to_string())
+            .collect())
+    }
+
+    pub async fn run_migrations(&self) -> anyhow::Result<()> {
         let mut conn = self.pool.get().await?;
 
         // Get a lock for running migrations. Blocks until we get the lock.
@@ -80,12 +321,80 @@ impl Store {
         Ok(())
     }
 
+    /// Returns the exact row count and disk usage of every table in the
+    /// database, ordered by name.
+    pub async fn table_stats(&self) -> anyhow::Result<Vec<models::TableStats>> {
+        // `query_to_xml` lets us count the rows of tables whose names are only
+        // known at runtime, within a single statement.
+        let query = diesel::sql_query(
+            "SELECT c.relname::text AS name,
+                (xpath('/row/count/text()', query_to_xml(
+                    format('SELECT count(*) FROM %I.%I', n.nspname, c.relname),
+                    false, true, ''
+                )))[1]::text::bigint AS row_count,
+                pg_total_relation_size(c.oid) AS total_bytes
+            FROM pg_class c
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+            WHERE c.relkind = 'r' AND n.nspname = current_schema()
+            ORDER BY c.relname",
+        );
+
+        Ok(query
+            .load::<models::TableStats>(&mut self.conn().await?)
+            .await?)
+    }
+
+    /// Deletes PoIs that are older than `older_than`, in batches of
+    /// `batch_size`, and returns how many were deleted (or would be, with
+    /// `dry_run`). PoIs referenced by divergence investigations are always
+    /// kept, and so are live PoIs if `keep_live` is set.
+    pub async fn prune_pois(
+        &self,
+        older_than: Duration,
+        keep_live: bool,
+        batch_size: u16,
+        dry_run: bool,
+    ) -> anyhow::Result<u64> {
+        let created_before = Utc::now().naive_utc() - chrono::Duration::from_std(older_than)?;
+        let mut conn = self.conn().await?;
+        diesel_queries::prune_pois(&mut conn, created_before, keep_live, batch_size, dry_run).await
+    }
+
+    /// Returns a snapshot of the connection pool's utilization.
+    pub fn pool_status(&self) -> PoolStatus {
+        let status = self.pool.status();
+        let idle = status.available.max(0) as usize;
+
+        PoolStatus {
+            in_use: status.size.saturating_sub(idle),
+            idle,
+            max_size: status.max_size,
+        }
+    }
+
+    /// Gets a connection from the pool, opening a new one if none is idle
+    /// and the pool isn't full yet. Fails after the connection timeout.
     pub async fn conn(&self) -> anyhow::Result<Object<AsyncPgConnection>> {
-        Ok(self.pool.get().await?)
+        let timer = self
+            .pool_wait_seconds
+            .as_ref()
+            .map(prometheus::Histogram::start_timer);
+        let result = tokio::time::timeout(self.connection_timeout, self.pool.get()).await;
+        if let Some(timer) = timer {
+            timer.observe_duration();
+        }
+
+        match result {
+            Ok(conn) => Ok(conn?),
+            Err(_) => anyhow::bail!(
+                "timed out after {:?} waiting for a database connection",
+                self.connection_timeout
+            ),
+        }
     }
 
     pub async fn conn_err_string(&self) -> Result<Object<AsyncPgConnection>, String> {
-        Ok(self.pool.get().await.map_err(|e| e.to_string())?)
+        self.conn().await.map_err(|e| e.to_string())
     }
 
     /// Returns subgraph deployments stored in the database that match the
@@ -125,6 +434,64 @@ impl Store {
         Ok(query.load::<SgDeployment>(&mut self.conn().await?).await?)
     }
 
+    /// Returns a page of the subgraph deployments that have any PoIs, ordered
+    /// by IPFS CID, along with how many indexers reported PoIs for each and
+    /// how many such deployments there are in total.
+    pub async fn distinct_deployments(
+        &self,
+        pagination: Pagination,
+    ) -> anyhow::Result<models::DeploymentPage> {
+        use diesel::dsl::count_distinct;
+        use schema::{pois, sg_deployments as sgd};
+
+        let mut conn = self.conn().await?;
+        let indexer_counts = pois::table
+            .inner_join(sgd::table)
+            .group_by((sgd::id, sgd::ipfs_cid))
+            .select((sgd::id, count_distinct(pois::indexer_id)))
+            .order_by(sgd::ipfs_cid.asc())
+            .offset(pagination.offset.into())
+            .limit(pagination.limit.into())
+            .load::<(IntId, i64)>(&mut conn)
+            .await?;
+        let total_count = pois::table
+            .select(count_distinct(pois::sg_deployment_id))
+            .get_result::<i64>(&mut conn)
+            .await?;
+
+        // A second query rather than one per deployment.
+        let ids: Vec<IntId> = indexer_counts.iter().map(|(id, _)| *id).collect();
+        let mut deployments: HashMap<IntId, SgDeployment> = sgd::table
+            .inner_join(schema::networks::table)
+            .left_join(schema::sg_names::table)
+            .select((
+                sgd::id,
+                sgd::ipfs_cid,
+                schema::sg_names::name.nullable(),
+                sgd::network,
+                sgd::created_at,
+            ))
+            .filter(sgd::id.eq_any(ids))
+            .load::<SgDeployment>(&mut conn)
+            .await?
+            .into_iter()
+            .map(|deployment| (deployment.id, deployment))
+            .collect();
+
+        Ok(models::DeploymentPage {
+            deployments: indexer_counts
+                .into_iter()
+                .filter_map(|(id, n_indexers)| {
+                    Some(models::DeploymentWithIndexerCount {
+                        deployment: deployments.remove(&id)?,
+                        n_indexers,
+                    })
+                })
+                .collect(),
+            total_count,
+        })
+    }
+
     pub async fn create_networks_if_missing(&self, networks: &[NewNetwork]) -> anyhow::Result<()> {
         use schema::networks;
 
@@ -149,7 +516,7 @@ impl Store {
 
         diesel::insert_into(sgd::table)
             .values((
-                sgd::ipfs_cid.eq(ipfs_cid),
+                sgd::ipfs_cid.eq(SubgraphDeployment::new(ipfs_cid).0),
                 sgd::network.eq(schema::networks::table
                     .select(schema::networks::id)
                     .filter(schema::networks::name.eq(network_name))
@@ -169,6 +536,7 @@ impl Store {
     ) -> anyhow::Result<()> {
         use schema::{sg_deployments as sgd, sg_names};
 
+        let sg_deployment_id = SubgraphDeployment::new(sg_deployment_id).0;
         diesel::insert_into(sg_names::table)
             .values((
                 sg_names::sg_deployment_id.eq(sgd::table
@@ -187,8 +555,8 @@ impl Store {
         Ok(())
     }
 
-    /// Fetches a Poi from the database.
-    pub async fn poi(&self, poi: &PoiBytes) -> anyhow::Result<Option<Poi>> {
+    /// Fetches a Poi from the database by its hash.
+    pub async fn poi_by_hash(&self, poi: &PoiBytes) -> anyhow::Result<Option<Poi>> {
         use schema::pois;
 
         let query = pois::table
@@ -198,6 +566,49 @@ impl Store {
         Ok(query.get_result(&mut self.conn().await?).await.optional()?)
     }
 
+    /// Returns all PoIs with the given hash, across indexers, subgraph
+    /// deployments and blocks, each along with whether it's the PoI of the
+    /// majority of indexers for its block. Unknown hashes give no PoIs.
+    pub async fn pois_with_hash(&self, poi: &PoiBytes) -> anyhow::Result<Vec<(Poi, bool)>> {
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    let pois = diesel_queries::pois_with_hash(conn, poi).await?;
+                    let majorities: HashMap<_, _> = diesel_queries::poi_majorities(conn, poi)
+                        .await?
+                        .into_iter()
+                        .map(|m| ((m.sg_deployment_id, m.block_id), m.is_majority))
+                        .collect();
+
+                    Ok(pois
+                        .into_iter()
+                        .map(|poi| {
+                            let is_majority = majorities
+                                .get(&(poi.sg_deployment_id, poi.block_id))
+                                .copied()
+                                .unwrap_or(false);
+                            (poi, is_majority)
+                        })
+                        .collect())
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    /// Groups the PoIs of a subgraph deployment at each block in
+    /// `block_range` by their hash, along with the indexers that reported
+    /// them. Ordered by block number, then by number of indexers, most first.
+    pub async fn poi_support(
+        &self,
+        deployment: &IpfsCid,
+        block_range: RangeInclusive<u64>,
+    ) -> anyhow::Result<Vec<models::PoiSupport>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::poi_support(&mut conn, deployment, block_range).await
+    }
+
     pub async fn failed_query(
         &self,
         indexer: &impl IndexerId,
@@ -264,21 +675,32 @@ impl Store {
             .await?)
     }
 
-    /// Returns all indexers stored in the database.
+    /// Returns the indexers stored in the database, in the order they were
+    /// first stored, leaving out inactive ones unless
+    /// `filter.include_inactive` is set.
     pub async fn indexers(
         &self,
         filter: inputs::IndexersQuery,
     ) -> anyhow::Result<Vec<models::Indexer>> {
         use schema::indexers;
 
-        let mut query = indexers::table.select(indexers::all_columns).into_boxed();
+        let mut query = indexers::table
+            .select(indexers::all_columns)
+            .order_by(indexers::id)
+            .into_boxed();
 
         if let Some(address) = filter.address {
             query = query.filter(indexers::address.eq(address));
         }
+        if !filter.include_inactive {
+            query = query.filter(indexers::is_active);
+        }
         if let Some(limit) = filter.limit {
             query = query.limit(limit.into());
         }
+        if let Some(offset) = filter.offset {
+            query = query.offset(offset.into());
+        }
 
         Ok(query.load::<IndexerModel>(&mut self.conn().await?).await?)
     }
@@ -299,17 +721,61 @@ impl Store {
             block_range,
             limit,
             false,
+            false,
         )
         .await
     }
 
-    /// Like `pois`, but only returns live pois.
+    /// Returns the PoIs matching all of `filter`'s criteria, most recent
+    /// blocks first.
+    pub async fn search_pois(
+        &self,
+        filter: &PoiFilter,
+        pagination: Pagination,
+    ) -> anyhow::Result<Vec<Poi>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::search_pois(&mut conn, filter, pagination).await
+    }
+
+    /// Returns up to `batch_size` PoIs matching `filter` (its `limit` is
+    /// ignored) that were collected at or after `created_since`, ordered by
+    /// ID and starting after `after_id`. Meant to be called repeatedly with
+    /// the ID of the last returned row, so that large amounts of PoIs can be
+    /// exported without loading them all into memory.
+    pub async fn poi_export_batch(
+        &self,
+        filter: &inputs::PoisQuery,
+        created_since: Option<NaiveDateTime>,
+        after_id: Option<IntId>,
+        batch_size: u16,
+    ) -> anyhow::Result<Vec<models::PoiExportRow>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::poi_export_batch(&mut conn, filter, created_since, after_id, batch_size)
+            .await
+    }
+
+    /// For every subgraph deployment that both indexers have PoIs for, returns
+    /// their PoIs at the most recent block they have in common, ordered by
+    /// deployment.
+    pub async fn latest_common_poi_pairs(
+        &self,
+        indexer1: &IndexerAddress,
+        indexer2: &IndexerAddress,
+    ) -> anyhow::Result<Vec<models::PoiPair>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::latest_common_poi_pairs(&mut conn, indexer1, indexer2).await
+    }
+
+    /// Like `pois`, but only returns live pois. Stale ones, i.e. those of
+    /// indexers that have since become unreachable, are only returned if
+    /// `include_stale` is set.
     pub async fn live_pois(
         &self,
         indexer_address: Option<&IndexerAddress>,
         sg_deployments_cids: Option<&[IpfsCid]>,
         block_range: Option<inputs::BlockRange>,
         limit: Option<u16>,
+        include_stale: bool,
     ) -> anyhow::Result<Vec<Poi>> {
         let mut conn = self.conn().await?;
         diesel_queries::pois(
@@ -319,11 +785,63 @@ impl Store {
             block_range,
             limit,
             true,
+            include_stale,
         )
         .await
     }
 
-    pub async fn write_pois<W>(&self, pois: Vec<W>, live: PoiLiveness) -> anyhow::Result<()>
+    /// Returns the PoI that was live for the given indexer and subgraph
+    /// deployment at the given point in time, if any.
+    pub async fn live_poi_at(
+        &self,
+        indexer_address: &IndexerAddress,
+        deployment: &IpfsCid,
+        at: NaiveDateTime,
+    ) -> anyhow::Result<Option<Poi>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::live_poi_at(&mut conn, indexer_address, deployment, at).await
+    }
+
+    /// Returns the most recent PoI that the given indexer reported for the
+    /// given subgraph deployment and block number, if any, along with whether
+    /// it's live.
+    pub async fn poi(
+        &self,
+        indexer_address: &IndexerAddress,
+        deployment: &IpfsCid,
+        block_number: u64,
+    ) -> anyhow::Result<Option<(Poi, bool)>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::poi(&mut conn, indexer_address, deployment, block_number).await
+    }
+
+    /// Returns the indexers that currently serve the given subgraph deployment,
+    /// i.e. that have a live PoI for it which isn't stale, along with that PoI
+    /// and its block. Sorted by indexer address. Unknown deployments have no
+    /// indexers.
+    pub async fn indexers_for_deployment(
+        &self,
+        deployment: &IpfsCid,
+    ) -> anyhow::Result<Vec<models::IndexerDeploymentInfo>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::indexers_for_deployment(&mut conn, deployment).await
+    }
+
+    /// Flags the live PoIs of all indexers other than `reachable_indexers` as
+    /// stale. Returns how many PoIs were flagged.
+    pub async fn mark_live_pois_stale(
+        &self,
+        reachable_indexers: &[IndexerAddress],
+    ) -> anyhow::Result<usize> {
+        let mut conn = self.conn().await?;
+        diesel_queries::mark_live_pois_stale(&mut conn, reachable_indexers).await
+    }
+
+    /// Writes PoIs to the database. Returns how many of them differ from the
+    /// PoI previously stored for the same indexer, deployment and block, each
+    /// of which is recorded as a [`models::PoiChange`].
+    #[instrument(skip_all, fields(pois = pois.len()))]
+    pub async fn write_pois<W>(&self, pois: Vec<W>, live: PoiLiveness) -> anyhow::Result<usize>
     where
         W: WritablePoi + Send + Sync,
         W::IndexerId: Send + Sync,
@@ -331,129 +849,894 @@ impl Store {
         self.conn()
             .await?
             .transaction::<_, Error, _>(|conn| {
-                async move {
-                    diesel_queries::write_pois(conn, pois, live).await?;
-                    Ok(())
-                }
-                .scope_boxed()
+                async move { diesel_queries::write_pois(conn, pois, live).await }.scope_boxed()
             })
             .await
     }
 
-    pub async fn write_indexers(
-        &self,
-        indexers: &[impl AsRef<dyn IndexerClient>],
-    ) -> anyhow::Result<()> {
-        let mut conn = self.conn().await?;
-        diesel_queries::write_indexers(&mut conn, indexers).await?;
-        Ok(())
+    /// Records `pois` as being at blocks whose hashes indexers disagree on,
+    /// see [`models::BlockHashMismatch`]. PoIs that were already recorded are
+    /// skipped. Returns the number of newly recorded PoIs.
+    pub async fn write_block_hash_mismatches<W>(&self, pois: Vec<W>) -> anyhow::Result<usize>
+    where
+        W: WritablePoi + Send + Sync,
+        W::IndexerId: Send + Sync,
+    {
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move { diesel_queries::write_block_hash_mismatches(conn, pois).await }
+                    .scope_boxed()
+            })
+            .await
     }
 
-    pub async fn delete_indexer_network_subgraph_metadata(
+    /// Records the block choices of a main loop iteration. Only the latest
+    /// few block choices per subgraph deployment are kept.
+    pub async fn write_block_choice_traces(
         &self,
-        indexer_id: IntId,
+        block_choices: Vec<models::BlockChoice>,
     ) -> anyhow::Result<()> {
-        use schema::indexers;
+        use schema::block_choice_traces;
 
-        diesel::update(indexers::table.filter(indexers::id.eq(indexer_id)))
-            .set(indexers::network_subgraph_metadata.eq::<Option<IntId>>(None))
-            .execute(&mut self.conn().await?)
-            .await?;
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    let mut new_traces = vec![];
+                    for block_choice in block_choices {
+                        let sg_deployment_id = diesel_queries::get_or_insert_deployment(
+                            conn,
+                            &block_choice.deployment,
+                        )
+                        .await?;
 
-        Ok(())
+                        new_traces.push(models::NewBlockChoiceTrace {
+                            sg_deployment_id,
+                            policy: block_choice.policy,
+                            candidate_blocks: block_choice
+                                .candidate_blocks
+                                .into_iter()
+                                .map(|block| block as i64)
+                                .collect(),
+                            chosen_block: block_choice.chosen_block.map(|block| block as i64),
+                        });
+                    }
+
+                    if !new_traces.is_empty() {
+                        diesel::insert_into(block_choice_traces::table)
+                            .values(&new_traces)
+                            .execute(conn)
+                            .await?;
+                    }
+
+                    diesel::sql_query(
+                        "DELETE FROM block_choice_traces WHERE id IN (
+                            SELECT id FROM (
+                                SELECT id, row_number() OVER (
+                                    PARTITION BY sg_deployment_id ORDER BY id DESC
+                                ) AS n
+                                FROM block_choice_traces
+                            ) ranked
+                            WHERE n > $1
+                        )",
+                    )
+                    .bind::<diesel::sql_types::BigInt, _>(BLOCK_CHOICE_TRACES_RETAINED)
+                    .execute(conn)
+                    .await?;
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
     }
 
-    pub async fn create_or_update_indexer_network_subgraph_metadata(
+    /// Writes the summary of a main loop iteration, and removes the oldest ones
+    /// beyond the last [`LOOP_ITERATIONS_RETAINED`].
+    pub async fn write_loop_iteration(
         &self,
-        indexer_id: IntId,
-        metadata: NewIndexerNetworkSubgraphMetadata,
-    ) -> anyhow::Result<IntId> {
-        use schema::{indexer_network_subgraph_metadata, indexers};
+        iteration: models::NewLoopIteration,
+    ) -> anyhow::Result<()> {
+        use schema::loop_iterations;
 
         self.conn()
             .await?
             .transaction::<_, Error, _>(|conn| {
-                // Fetch the metadata id from indexer_id, and update it if it exists
-                // create a new one and set the foreign key to the indexer_id if it doesn't exist
                 async move {
-                    let metadata_id = indexers::table
-                        .select(indexers::network_subgraph_metadata)
-                        .filter(indexers::id.eq(indexer_id))
-                        .get_result::<Option<IntId>>(conn)
+                    diesel::insert_into(loop_iterations::table)
+                        .values(&iteration)
+                        .execute(conn)
                         .await?;
 
-                    let metadata_id = match metadata_id {
-                        Some(id) => {
-                            diesel::update(
-                                indexer_network_subgraph_metadata::table
-                                    .filter(indexer_network_subgraph_metadata::id.eq(id)),
-                            )
-                            .set(metadata)
-                            .execute(conn)
-                            .await?;
-                            id
-                        }
-                        None => {
-                            let metadata_id =
-                                diesel::insert_into(indexer_network_subgraph_metadata::table)
-                                    .values(&metadata)
-                                    .returning(indexer_network_subgraph_metadata::id)
-                                    .get_result(conn)
-                                    .await?;
-
-                            diesel::update(indexers::table)
-                                .filter(indexers::id.eq(indexer_id))
-                                .set(indexers::network_subgraph_metadata.eq(metadata_id))
-                                .execute(conn)
-                                .await?;
-
-                            metadata_id
-                        }
-                    };
+                    diesel::sql_query(
+                        "DELETE FROM loop_iterations WHERE id NOT IN (
+                            SELECT id FROM loop_iterations ORDER BY id DESC LIMIT $1
+                        )",
+                    )
+                    .bind::<diesel::sql_types::BigInt, _>(LOOP_ITERATIONS_RETAINED)
+                    .execute(conn)
+                    .await?;
 
-                    Ok(metadata_id)
+                    Ok(())
                 }
                 .scope_boxed()
             })
-            .await?;
+            .await
+    }
 
-        Ok(indexer_id)
+    /// Returns the summaries of the most recent main loop iterations, newest
+    /// first.
+    pub async fn recent_loop_iterations(
+        &self,
+        limit: u16,
+    ) -> anyhow::Result<Vec<models::LoopIteration>> {
+        use schema::loop_iterations;
+
+        Ok(loop_iterations::table
+            .select(models::LoopIteration::as_select())
+            .order_by(loop_iterations::id.desc())
+            .limit(limit.into())
+            .load(&mut self.conn().await?)
+            .await?)
     }
 
-    pub async fn write_graph_node_versions(
+    /// Writes how many indexers each source listed in a main loop iteration,
+    /// and removes the oldest counts beyond the last
+    /// [`INDEXER_DISCOVERY_COUNTS_RETAINED`].
+    pub async fn write_indexer_discovery_counts(
         &self,
-        versions: HashMap<
-            Arc<dyn IndexerClient>,
-            anyhow::Result<graphix_common_types::GraphNodeCollectedVersion>,
-        >,
+        counts: Vec<models::NewIndexerDiscoveryCount>,
     ) -> anyhow::Result<()> {
-        use schema::graph_node_collected_versions;
-        for version in versions.values() {
-            let conn = &mut self.conn().await?;
+        use schema::indexer_discovery_counts;
 
-            let new_version = match version {
-                Ok(v) => models::NewGraphNodeCollectedVersion {
-                    version_string: v.version.clone(),
-                    version_commit: v.commit.clone(),
-                    error_response: None,
-                },
-                Err(err) => models::NewGraphNodeCollectedVersion {
-                    version_string: None,
-                    version_commit: None,
-                    error_response: Some(err.to_string()),
-                },
-            };
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    diesel::insert_into(indexer_discovery_counts::table)
+                        .values(&counts)
+                        .execute(conn)
+                        .await?;
 
-            diesel::insert_into(graph_node_collected_versions::table)
-                .values(&new_version)
-                .execute(conn)
-                .await?;
-        }
+                    diesel::sql_query(
+                        "DELETE FROM indexer_discovery_counts WHERE id NOT IN (
+                            SELECT id FROM indexer_discovery_counts ORDER BY id DESC LIMIT $1
+                        )",
+                    )
+                    .bind::<diesel::sql_types::BigInt, _>(INDEXER_DISCOVERY_COUNTS_RETAINED)
+                    .execute(conn)
+                    .await?;
 
-        Ok(())
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
     }
 
-    pub async fn get_first_pending_divergence_investigation_request(
+    /// Returns the most recent indexer discovery counts, newest first.
+    pub async fn recent_indexer_discovery_counts(
+        &self,
+        limit: u16,
+    ) -> anyhow::Result<Vec<models::IndexerDiscoveryCount>> {
+        use schema::indexer_discovery_counts;
+
+        Ok(indexer_discovery_counts::table
+            .select(models::IndexerDiscoveryCount::as_select())
+            .order_by(indexer_discovery_counts::id.desc())
+            .limit(limit.into())
+            .load(&mut self.conn().await?)
+            .await?)
+    }
+
+    /// Returns the most recent block choices for a subgraph deployment,
+    /// newest first.
+    pub async fn block_choice_traces(
+        &self,
+        deployment: &IpfsCid,
+        limit: u16,
+    ) -> anyhow::Result<Vec<models::BlockChoiceTrace>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::block_choice_traces(&mut conn, deployment, limit).await
+    }
+
+    /// Replaces the tracked subgraph deployments with `deployments`, of which
+    /// there must be at most one per deployment and network subgraph.
+    /// Deployments that remain tracked keep their `tracked_since`.
+    pub async fn replace_tracked_deployments(
+        &self,
+        deployments: Vec<models::NewTrackedDeployment>,
+    ) -> anyhow::Result<models::TrackedDeploymentChanges> {
+        use diesel::upsert::excluded;
+        use schema::tracked_deployments;
+
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    let old_cids: BTreeSet<String> = tracked_deployments::table
+                        .select(tracked_deployments::ipfs_cid)
+                        .distinct()
+                        .load::<String>(conn)
+                        .await?
+                        .into_iter()
+                        .collect();
+                    let new_cids: BTreeSet<String> = deployments
+                        .iter()
+                        .map(|deployment| deployment.ipfs_cid.clone())
+                        .collect();
+
+                    let mut cids_by_network_subgraph = BTreeMap::<String, Vec<String>>::new();
+                    for deployment in &deployments {
+                        cids_by_network_subgraph
+                            .entry(deployment.network_subgraph.clone())
+                            .or_default()
+                            .push(deployment.ipfs_cid.clone());
+                    }
+                    diesel::delete(
+                        tracked_deployments::table.filter(
+                            tracked_deployments::network_subgraph.ne_all(
+                                cids_by_network_subgraph.keys().cloned().collect::<Vec<_>>(),
+                            ),
+                        ),
+                    )
+                    .execute(conn)
+                    .await?;
+                    for (network_subgraph, cids) in cids_by_network_subgraph {
+                        diesel::delete(
+                            tracked_deployments::table
+                                .filter(tracked_deployments::network_subgraph.eq(network_subgraph))
+                                .filter(tracked_deployments::ipfs_cid.ne_all(cids)),
+                        )
+                        .execute(conn)
+                        .await?;
+                    }
+
+                    if !deployments.is_empty() {
+                        diesel::insert_into(tracked_deployments::table)
+                            .values(&deployments)
+                            .on_conflict((
+                                tracked_deployments::ipfs_cid,
+                                tracked_deployments::network_subgraph,
+                            ))
+                            .do_update()
+                            .set((
+                                tracked_deployments::network
+                                    .eq(excluded(tracked_deployments::network)),
+                                tracked_deployments::active_allocations
+                                    .eq(excluded(tracked_deployments::active_allocations)),
+                                tracked_deployments::allocated_tokens
+                                    .eq(excluded(tracked_deployments::allocated_tokens)),
+                            ))
+                            .execute(conn)
+                            .await?;
+                    }
+
+                    Ok(models::TrackedDeploymentChanges {
+                        added: new_cids.difference(&old_cids).cloned().collect(),
+                        removed: old_cids.difference(&new_cids).cloned().collect(),
+                    })
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    /// Returns the tracked subgraph deployments, sorted by IPFS CID.
+    pub async fn tracked_deployments(&self) -> anyhow::Result<Vec<models::TrackedDeployment>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::tracked_deployments(&mut conn).await
+    }
+
+    /// Tags a subgraph deployment as watched. Deployments that are already
+    /// watched keep who tagged them and when.
+    pub async fn watch_deployment(
+        &self,
+        deployment: &IpfsCid,
+        watched_by: Option<&str>,
+    ) -> anyhow::Result<models::WatchedDeployment> {
+        use schema::watched_deployments;
+
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    diesel::insert_into(watched_deployments::table)
+                        .values((
+                            watched_deployments::ipfs_cid.eq(deployment.to_string()),
+                            watched_deployments::watched_by.eq(watched_by),
+                        ))
+                        .on_conflict_do_nothing()
+                        .execute(conn)
+                        .await?;
+
+                    Ok(watched_deployments::table
+                        .select(models::WatchedDeployment::as_select())
+                        .filter(watched_deployments::ipfs_cid.eq(deployment.to_string()))
+                        .get_result(conn)
+                        .await?)
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    /// Removes the watched tag of a subgraph deployment. Returns whether it
+    /// was watched.
+    pub async fn unwatch_deployment(&self, deployment: &IpfsCid) -> anyhow::Result<bool> {
+        use schema::watched_deployments;
+
+        let deleted = diesel::delete(watched_deployments::table)
+            .filter(watched_deployments::ipfs_cid.eq(deployment.to_string()))
+            .execute(&mut self.conn().await?)
+            .await?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Returns the watched subgraph deployments, sorted by IPFS CID.
+    pub async fn watched_deployments(&self) -> anyhow::Result<Vec<models::WatchedDeployment>> {
+        use schema::watched_deployments;
+
+        Ok(watched_deployments::table
+            .select(models::WatchedDeployment::as_select())
+            .order_by(watched_deployments::ipfs_cid)
+            .load(&mut self.conn().await?)
+            .await?)
+    }
+
+    /// Writes the active allocations that a network subgraph lists, and marks
+    /// the ones that it listed before but no longer does as closed. Returns
+    /// how many allocations were closed.
+    pub async fn write_active_allocations(
+        &self,
+        network_subgraph: &str,
+        allocations: Vec<models::NewAllocation>,
+    ) -> anyhow::Result<usize> {
+        use diesel::upsert::excluded;
+        use schema::allocations;
+
+        // Keeps inserts below the limit of bind parameters per statement.
+        const CHUNK_SIZE: usize = 1000;
+
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    for chunk in allocations.chunks(CHUNK_SIZE) {
+                        diesel::insert_into(allocations::table)
+                            .values(chunk)
+                            .on_conflict(allocations::id)
+                            .do_update()
+                            .set((
+                                allocations::allocated_tokens
+                                    .eq(excluded(allocations::allocated_tokens)),
+                                allocations::closed_at.eq(None::<NaiveDateTime>),
+                            ))
+                            .execute(conn)
+                            .await?;
+                    }
+
+                    let ids: Vec<&str> = allocations.iter().map(|a| a.id.as_str()).collect();
+                    let closed = diesel::update(
+                        allocations::table
+                            .filter(allocations::network_subgraph.eq(network_subgraph))
+                            .filter(allocations::closed_at.is_null())
+                            .filter(allocations::id.ne_all(ids)),
+                    )
+                    .set(allocations::closed_at.eq(diesel::dsl::now))
+                    .execute(conn)
+                    .await?;
+
+                    Ok(closed)
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    /// Returns the allocations of an indexer and/or on a subgraph deployment,
+    /// the largest first. Closed allocations are only included if
+    /// `include_closed` is set.
+    pub async fn allocations(
+        &self,
+        indexer_address: Option<&IndexerAddress>,
+        deployment: Option<&IpfsCid>,
+        include_closed: bool,
+    ) -> anyhow::Result<Vec<models::Allocation>> {
+        use schema::allocations;
+
+        let mut query = allocations::table
+            .select(models::Allocation::as_select())
+            .order_by((allocations::allocated_tokens.desc(), allocations::id))
+            .into_boxed();
+        if let Some(indexer_address) = indexer_address {
+            query = query.filter(allocations::indexer_address.eq(*indexer_address));
+        }
+        if let Some(deployment) = deployment {
+            query = query.filter(allocations::ipfs_cid.eq(deployment.to_string()));
+        }
+        if !include_closed {
+            query = query.filter(allocations::closed_at.is_null());
+        }
+
+        Ok(query.load(&mut self.conn().await?).await?)
+    }
+
+    /// Returns the most recent PoI changes, optionally restricted to a single
+    /// indexer and/or subgraph deployment.
+    pub async fn poi_changes(
+        &self,
+        indexer_address: Option<&IndexerAddress>,
+        deployment: Option<&IpfsCid>,
+        limit: u16,
+    ) -> anyhow::Result<Vec<models::PoiChange>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::poi_changes(&mut conn, indexer_address, deployment, limit).await
+    }
+
+    /// Returns the subgraph deployments and block numbers that indexers
+    /// reported different block hashes for, most recently detected first,
+    /// optionally restricted to a single subgraph deployment.
+    pub async fn block_hash_mismatches(
+        &self,
+        deployment: Option<&IpfsCid>,
+        limit: u16,
+    ) -> anyhow::Result<Vec<models::BlockHashMismatch>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::block_hash_mismatches(&mut conn, deployment, limit).await
+    }
+
+    /// Returns the subgraph deployments and blocks that indexers still
+    /// disagree on the PoI for, longest-standing first.
+    pub async fn stale_divergences(
+        &self,
+        pagination: Pagination,
+    ) -> anyhow::Result<Vec<models::StaleDivergence>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::stale_divergences(&mut conn, pagination).await
+    }
+
+    /// Aggregates divergences between the PoIs collected in each `bucket`
+    /// from `from` (rounded down to the start of its bucket) until `to`,
+    /// optionally only for subgraph deployments on `network`. Oldest first.
+    pub async fn divergence_stats(
+        &self,
+        network: Option<&str>,
+        bucket: models::TimeBucket,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> anyhow::Result<Vec<models::DivergenceStats>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::divergence_stats(&mut conn, network, bucket, from, to).await
+    }
+
+    /// Records the latest block of each indexer and subgraph deployment in
+    /// `indexing_statuses`, counting the main loop iterations in a row in
+    /// which it didn't advance, along with the rest of the status. Indexers
+    /// that didn't report a status are left alone, so connectivity failures
+    /// don't count as stalled indexing.
+    pub async fn update_indexing_progress(
+        &self,
+        indexing_statuses: &[IndexingStatus],
+    ) -> anyhow::Result<()> {
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    for status in indexing_statuses {
+                        let indexer_id = diesel_queries::get_indexer_id(
+                            conn,
+                            status.indexer.name(),
+                            &status.indexer.address(),
+                        )
+                        .await?;
+                        let sg_deployment_id =
+                            diesel_queries::get_or_insert_deployment(conn, &status.deployment)
+                                .await?;
+                        diesel_queries::update_indexing_progress(
+                            conn,
+                            indexer_id,
+                            sg_deployment_id,
+                            status,
+                        )
+                        .await?;
+                    }
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    /// Returns the most recent indexing status that each indexer reported for
+    /// each subgraph deployment, optionally only those of `indexer` and
+    /// `deployment`. Ordered by deployment, then by indexer.
+    pub async fn indexing_statuses(
+        &self,
+        indexer: Option<&IndexerAddress>,
+        deployment: Option<&str>,
+    ) -> anyhow::Result<Vec<models::StoredIndexingStatus>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::indexing_statuses(&mut conn, indexer, deployment).await
+    }
+
+    /// Returns the indexers whose latest block for a subgraph deployment
+    /// hasn't advanced in at least `min_iterations` main loop iterations,
+    /// longest-stalled first.
+    pub async fn stalled_indexers(
+        &self,
+        min_iterations: u32,
+        limit: u16,
+    ) -> anyhow::Result<Vec<models::StalledIndexer>> {
+        let mut conn = self.conn().await?;
+        diesel_queries::stalled_indexers(&mut conn, min_iterations, limit).await
+    }
+
+    pub async fn write_indexers(
+        &self,
+        indexers: &[impl AsRef<dyn IndexerClient>],
+    ) -> anyhow::Result<()> {
+        let mut conn = self.conn().await?;
+        diesel_queries::write_indexers(&mut conn, indexers).await?;
+        Ok(())
+    }
+
+    /// Writes operator-provided metadata of already known indexers. Unknown
+    /// indexers are skipped, as are fields that aren't set, so that they keep
+    /// their previously written values.
+    pub async fn write_indexer_metadata(
+        &self,
+        metadata: Vec<(IndexerAddress, models::IndexerMetadataChangeset)>,
+    ) -> anyhow::Result<()> {
+        use schema::indexers;
+
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    for (address, changeset) in metadata {
+                        // Diesel refuses to run empty updates.
+                        if changeset.region.is_none() && changeset.labels.is_none() {
+                            continue;
+                        }
+                        diesel::update(indexers::table.filter(indexers::address.eq(address)))
+                            .set(changeset)
+                            .execute(conn)
+                            .await?;
+                    }
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    /// Writes the amount of GRT staked by already known indexers. Unknown
+    /// indexers are skipped.
+    pub async fn write_indexer_stakes(
+        &self,
+        stakes: Vec<(IndexerAddress, f64)>,
+    ) -> anyhow::Result<()> {
+        use schema::indexers;
+
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    for (address, staked_tokens) in stakes {
+                        diesel::update(indexers::table.filter(indexers::address.eq(address)))
+                            .set(indexers::staked_tokens.eq(staked_tokens))
+                            .execute(conn)
+                            .await?;
+                    }
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    /// Flags the active indexers that no configured source listed in the last
+    /// `inactive_after_days` days as inactive, and returns how many there
+    /// were. [`Store::write_indexers`] reactivates them if they reappear.
+    pub async fn deactivate_indexers(&self, inactive_after_days: u32) -> anyhow::Result<usize> {
+        use diesel::dsl::{now, IntervalDsl};
+        use schema::indexers;
+
+        let inactive_after_days = i32::try_from(inactive_after_days)?;
+        let deactivated = diesel::update(
+            indexers::table
+                .filter(indexers::is_active)
+                .filter(indexers::last_seen_at.lt(now - inactive_after_days.days())),
+        )
+        .set(indexers::is_active.eq(false))
+        .execute(&mut self.conn().await?)
+        .await?;
+
+        Ok(deactivated)
+    }
+
+    pub async fn delete_indexer_network_subgraph_metadata(
+        &self,
+        indexer_id: IntId,
+    ) -> anyhow::Result<()> {
+        use schema::indexers;
+
+        diesel::update(indexers::table.filter(indexers::id.eq(indexer_id)))
+            .set(indexers::network_subgraph_metadata.eq::<Option<IntId>>(None))
+            .execute(&mut self.conn().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn create_or_update_indexer_network_subgraph_metadata(
+        &self,
+        indexer_id: IntId,
+        metadata: NewIndexerNetworkSubgraphMetadata,
+    ) -> anyhow::Result<IntId> {
+        use schema::{indexer_network_subgraph_metadata, indexers};
+
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                // Fetch the metadata id from indexer_id, and update it if it exists
+                // create a new one and set the foreign key to the indexer_id if it doesn't exist
+                async move {
+                    let metadata_id = indexers::table
+                        .select(indexers::network_subgraph_metadata)
+                        .filter(indexers::id.eq(indexer_id))
+                        .get_result::<Option<IntId>>(conn)
+                        .await?;
+
+                    let metadata_id = match metadata_id {
+                        Some(id) => {
+                            diesel::update(
+                                indexer_network_subgraph_metadata::table
+                                    .filter(indexer_network_subgraph_metadata::id.eq(id)),
+                            )
+                            .set(metadata)
+                            .execute(conn)
+                            .await?;
+                            id
+                        }
+                        None => {
+                            let metadata_id =
+                                diesel::insert_into(indexer_network_subgraph_metadata::table)
+                                    .values(&metadata)
+                                    .returning(indexer_network_subgraph_metadata::id)
+                                    .get_result(conn)
+                                    .await?;
+
+                            diesel::update(indexers::table)
+                                .filter(indexers::id.eq(indexer_id))
+                                .set(indexers::network_subgraph_metadata.eq(metadata_id))
+                                .execute(conn)
+                                .await?;
+
+                            metadata_id
+                        }
+                    };
+
+                    Ok(metadata_id)
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        Ok(indexer_id)
+    }
+
+    pub async fn write_graph_node_versions(
+        &self,
+        versions: HashMap<
+            Arc<dyn IndexerClient>,
+            anyhow::Result<graphix_common_types::GraphNodeCollectedVersion>,
+        >,
+    ) -> anyhow::Result<()> {
+        use schema::graph_node_collected_versions;
+        for version in versions.values() {
+            let conn = &mut self.conn().await?;
+
+            let new_version = match version {
+                Ok(v) => models::NewGraphNodeCollectedVersion {
+                    version_string: v.version.clone(),
+                    version_commit: v.commit.clone(),
+                    error_response: None,
+                    supported_features: v.supported_features.clone(),
+                },
+                Err(err) => models::NewGraphNodeCollectedVersion {
+                    version_string: None,
+                    version_commit: None,
+                    error_response: Some(err.to_string()),
+                    supported_features: None,
+                },
+            };
+
+            diesel::insert_into(graph_node_collected_versions::table)
+                .values(&new_version)
+                .execute(conn)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Folds the given per-indexer reliability samples (each between 0.0 and
+    /// 1.0) into the stored reliability scores, which are exponential moving
+    /// averages. `decay_factor` is the weight given to the new sample; indexers
+    /// without a previous score simply start at their sample value.
+    pub async fn update_indexer_reliability(
+        &self,
+        samples: HashMap<Arc<dyn IndexerClient>, f64>,
+        decay_factor: f64,
+    ) -> anyhow::Result<()> {
+        use schema::indexer_reliability;
+
+        anyhow::ensure!(
+            decay_factor > 0.0 && decay_factor <= 1.0,
+            "the reliability decay factor must be in (0, 1], got {}",
+            decay_factor
+        );
+
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    for (indexer, sample) in samples {
+                        let indexer_id = diesel_queries::get_indexer_id(
+                            conn,
+                            indexer.name(),
+                            &indexer.address(),
+                        )
+                        .await?;
+
+                        let previous_score = indexer_reliability::table
+                            .select(indexer_reliability::score)
+                            .filter(indexer_reliability::indexer_id.eq(indexer_id))
+                            .get_result::<f64>(conn)
+                            .await
+                            .optional()?;
+                        let score = match previous_score {
+                            Some(previous) => {
+                                decay_factor * sample + (1.0 - decay_factor) * previous
+                            }
+                            None => sample,
+                        }
+                        .clamp(0.0, 1.0);
+
+                        diesel::insert_into(indexer_reliability::table)
+                            .values((
+                                indexer_reliability::indexer_id.eq(indexer_id),
+                                indexer_reliability::score.eq(score),
+                            ))
+                            .on_conflict(indexer_reliability::indexer_id)
+                            .do_update()
+                            .set((
+                                indexer_reliability::score.eq(score),
+                                indexer_reliability::updated_at.eq(diesel::dsl::now),
+                            ))
+                            .execute(conn)
+                            .await?;
+                    }
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    /// Returns the reliability score of the indexer with the given address, if
+    /// one was ever computed.
+    pub async fn indexer_reliability(
+        &self,
+        address: &IndexerAddress,
+    ) -> anyhow::Result<Option<f64>> {
+        use schema::{indexer_reliability, indexers};
+
+        Ok(indexer_reliability::table
+            .inner_join(indexers::table)
+            .select(indexer_reliability::score)
+            .filter(indexers::address.eq(address))
+            .get_result(&mut self.conn().await?)
+            .await
+            .optional()?)
+    }
+
+    /// Records the outcome of the `query_name` queries sent to the given
+    /// indexers, dropping all but the latest outcomes of each indexer.
+    pub async fn write_indexer_query_outcomes(
+        &self,
+        query_name: &str,
+        outcomes: HashMap<Arc<dyn IndexerClient>, QueryOutcome>,
+    ) -> anyhow::Result<()> {
+        use schema::indexer_query_errors;
+
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    let mut new_outcomes = vec![];
+                    for (indexer, outcome) in outcomes {
+                        let indexer_id = diesel_queries::get_indexer_id(
+                            conn,
+                            indexer.name(),
+                            &indexer.address(),
+                        )
+                        .await?;
+
+                        let (results, error, error_kind) = match outcome {
+                            QueryOutcome::Success { results } => {
+                                (Some(i32::try_from(results).unwrap_or(i32::MAX)), None, None)
+                            }
+                            QueryOutcome::Failure { error, kind } => {
+                                (None, Some(error), kind.map(str::to_string))
+                            }
+                        };
+                        new_outcomes.push(models::NewIndexerQueryOutcome {
+                            indexer_id,
+                            query_name: query_name.to_string(),
+                            results,
+                            error,
+                            error_kind,
+                        });
+                    }
+
+                    if !new_outcomes.is_empty() {
+                        diesel::insert_into(indexer_query_errors::table)
+                            .values(&new_outcomes)
+                            .execute(conn)
+                            .await?;
+                    }
+
+                    diesel::sql_query(
+                        "DELETE FROM indexer_query_errors WHERE id IN (
+                            SELECT id FROM (
+                                SELECT id, row_number() OVER (
+                                    PARTITION BY indexer_id ORDER BY id DESC
+                                ) AS n
+                                FROM indexer_query_errors
+                                WHERE query_name = $1
+                            ) ranked
+                            WHERE n > $2
+                        )",
+                    )
+                    .bind::<diesel::sql_types::Text, _>(query_name)
+                    .bind::<diesel::sql_types::BigInt, _>(QUERY_OUTCOMES_RETAINED)
+                    .execute(conn)
+                    .await?;
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    /// Returns the latest outcome of each kind of query sent to the indexer
+    /// with the given ID, ordered by query name.
+    pub async fn latest_indexer_query_outcomes(
+        &self,
+        indexer_id: IntId,
+    ) -> anyhow::Result<Vec<models::IndexerQueryOutcome>> {
+        use schema::indexer_query_errors;
+
+        Ok(indexer_query_errors::table
+            .filter(indexer_query_errors::indexer_id.eq(indexer_id))
+            .distinct_on(indexer_query_errors::query_name)
+            .order_by((
+                indexer_query_errors::query_name,
+                indexer_query_errors::id.desc(),
+            ))
+            .load(&mut self.conn().await?)
+            .await?)
+    }
+
+    pub async fn get_first_pending_divergence_investigation_request(
         &self,
     ) -> anyhow::Result<Option<(Uuid, serde_json::Value)>> {
         use schema::pending_divergence_investigation_requests as requests;
@@ -465,19 +1748,163 @@ impl Store {
             .optional()?)
     }
 
+    /// Enqueues a divergence investigation request, unless it's a duplicate
+    /// of another one according to `deduplication`, in which case the UUID of
+    /// that one is returned instead. Requests are deduplicated against
+    /// requests with the same idempotency key, no matter when they were made,
+    /// and against pending or running requests with the same deduplication
+    /// key; completed ones only count if they started within the cooldown.
+    /// New requests are recorded as being about the subgraph deployments with
+    /// the IDs in `deployments`.
     pub async fn create_divergence_investigation_request(
         &self,
         request: serde_json::Value,
+        deduplication: &InvestigationDeduplication,
+        deployments: &[IntId],
     ) -> anyhow::Result<Uuid> {
-        use schema::pending_divergence_investigation_requests as requests;
+        use schema::{
+            divergence_investigation_deployments as investigation_deployments,
+            divergence_investigation_reports as reports,
+            pending_divergence_investigation_requests as requests,
+        };
+
+        let deduplication = deduplication.clone();
+        let deployments = deployments.to_vec();
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    // Concurrent identical requests must see each other.
+                    diesel::sql_query(
+                        "LOCK TABLE pending_divergence_investigation_requests IN EXCLUSIVE MODE",
+                    )
+                    .execute(conn)
+                    .await?;
+
+                    if let Some(idempotency_key) = &deduplication.idempotency_key {
+                        let pending_uuid = requests::table
+                            .select(requests::uuid)
+                            .filter(requests::idempotency_key.eq(idempotency_key))
+                            .first::<Uuid>(conn)
+                            .await
+                            .optional()?;
+                        let reported_uuid = reports::table
+                            .select(reports::uuid)
+                            .filter(reports::idempotency_key.eq(idempotency_key))
+                            .first::<Uuid>(conn)
+                            .await
+                            .optional()?;
+                        if let Some(uuid) = pending_uuid.or(reported_uuid) {
+                            return Ok(uuid);
+                        }
+                    }
+
+                    if let Some(deduplication_key) = &deduplication.key {
+                        let pending_uuid = requests::table
+                            .select(requests::uuid)
+                            .filter(requests::deduplication_key.eq(deduplication_key))
+                            .order_by(requests::created_at)
+                            .first::<Uuid>(conn)
+                            .await
+                            .optional()?;
+                        if let Some(uuid) = pending_uuid {
+                            return Ok(uuid);
+                        }
 
-        let uuid = uuid::Uuid::new_v4();
-        diesel::insert_into(requests::table)
-            .values((requests::uuid.eq(&uuid), requests::request.eq(&request)))
-            .execute(&mut self.conn().await?)
-            .await?;
+                        if let Some(cooldown) = deduplication.cooldown {
+                            let completed_uuid = reports::table
+                                .select(reports::uuid)
+                                .filter(reports::deduplication_key.eq(deduplication_key))
+                                .filter(reports::created_at.ge(Utc::now().naive_utc() - cooldown))
+                                .order_by(reports::created_at.desc())
+                                .first::<Uuid>(conn)
+                                .await
+                                .optional()?;
+                            if let Some(uuid) = completed_uuid {
+                                return Ok(uuid);
+                            }
+                        }
+                    }
+
+                    let uuid = Uuid::new_v4();
+                    diesel::insert_into(requests::table)
+                        .values((
+                            requests::uuid.eq(&uuid),
+                            requests::request.eq(&request),
+                            requests::idempotency_key.eq(&deduplication.idempotency_key),
+                            requests::deduplication_key.eq(&deduplication.key),
+                        ))
+                        .execute(conn)
+                        .await?;
+                    if !deployments.is_empty() {
+                        let deployment_rows: Vec<_> = deployments
+                            .iter()
+                            .map(|id| {
+                                (
+                                    investigation_deployments::investigation_uuid.eq(uuid),
+                                    investigation_deployments::sg_deployment_id.eq(id),
+                                )
+                            })
+                            .collect();
+                        diesel::insert_into(investigation_deployments::table)
+                            .values(deployment_rows)
+                            .on_conflict_do_nothing()
+                            .execute(conn)
+                            .await?;
+                    }
+
+                    Ok(uuid)
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    /// Identifies what a divergence investigation of `pois` is about, i.e.
+    /// the indexers and subgraph deployments of the PoIs, so that identical
+    /// investigations can be deduplicated. `None` if any of the PoIs is
+    /// unknown.
+    pub async fn divergence_investigation_deduplication_key(
+        &self,
+        pois: &[PoiBytes],
+    ) -> anyhow::Result<Option<String>> {
+        let mut indexer_ids = BTreeSet::new();
+        let mut deployment_ids = BTreeSet::new();
+        for poi in pois {
+            let Some(poi) = self.poi_by_hash(poi).await? else {
+                return Ok(None);
+            };
+            indexer_ids.insert(poi.indexer_id);
+            deployment_ids.insert(poi.sg_deployment_id);
+        }
+
+        let join = |ids: BTreeSet<IntId>| {
+            ids.into_iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        Ok(Some(format!(
+            "indexers:{};deployments:{}",
+            join(indexer_ids),
+            join(deployment_ids)
+        )))
+    }
 
-        Ok(uuid)
+    /// The IDs of the subgraph deployments of `pois`, i.e. what a divergence
+    /// investigation of them is about. Unknown PoIs are ignored.
+    pub async fn divergence_investigation_deployments(
+        &self,
+        pois: &[PoiBytes],
+    ) -> anyhow::Result<Vec<IntId>> {
+        use schema::pois;
+
+        Ok(pois::table
+            .select(pois::sg_deployment_id)
+            .filter(pois::poi.eq_any(pois.to_vec()))
+            .distinct()
+            .load(&mut self.conn().await?)
+            .await?)
     }
 
     /// Fetches the divergence investigation report with the given UUID, if it
@@ -485,30 +1912,158 @@ impl Store {
     pub async fn divergence_investigation_report(
         &self,
         uuid: &Uuid,
-    ) -> anyhow::Result<Option<serde_json::Value>> {
+    ) -> anyhow::Result<Option<models::DivergenceInvestigationReport>> {
         use schema::divergence_investigation_reports as reports;
 
         Ok(reports::table
-            .select(reports::report)
+            .select(models::DivergenceInvestigationReport::as_select())
             .filter(reports::uuid.eq(uuid))
             .first(&mut self.conn().await?)
             .await
             .optional()?)
     }
 
+    /// Returns the most recent divergence investigation reports, newest
+    /// first, optionally only those with one of `triage_statuses`.
+    pub async fn divergence_investigation_reports(
+        &self,
+        triage_statuses: Option<&[DivergenceTriageStatus]>,
+        limit: u16,
+    ) -> anyhow::Result<Vec<models::DivergenceInvestigationReport>> {
+        use schema::divergence_investigation_reports as reports;
+
+        let mut query = reports::table
+            .select(models::DivergenceInvestigationReport::as_select())
+            .order_by(reports::created_at.desc())
+            .limit(limit.into())
+            .into_boxed();
+        if let Some(triage_statuses) = triage_statuses {
+            let triage_statuses: Vec<&str> = triage_statuses.iter().map(|s| s.as_str()).collect();
+            query = query.filter(reports::triage_status.eq_any(triage_statuses));
+        }
+
+        Ok(query.load(&mut self.conn().await?).await?)
+    }
+
+    /// Returns the most recent divergence investigation reports about
+    /// `deployment`, newest first.
+    pub async fn deployment_divergence_investigation_reports(
+        &self,
+        deployment: &SgDeployment,
+        limit: u16,
+    ) -> anyhow::Result<Vec<models::DivergenceInvestigationReport>> {
+        use schema::{
+            divergence_investigation_deployments as investigation_deployments,
+            divergence_investigation_reports as reports,
+        };
+
+        Ok(reports::table
+            .inner_join(
+                investigation_deployments::table
+                    .on(investigation_deployments::investigation_uuid.eq(reports::uuid)),
+            )
+            .select(models::DivergenceInvestigationReport::as_select())
+            .filter(investigation_deployments::sg_deployment_id.eq(deployment.id))
+            .order_by(reports::created_at.desc())
+            .limit(limit.into())
+            .load(&mut self.conn().await?)
+            .await?)
+    }
+
+    /// Changes the triage status and note of a divergence investigation
+    /// report, and records the change on behalf of `actor`. Returns `false`
+    /// if there's no such report.
+    pub async fn update_divergence_triage(
+        &self,
+        uuid: &Uuid,
+        status: DivergenceTriageStatus,
+        note: Option<&str>,
+        actor: &str,
+    ) -> anyhow::Result<bool> {
+        use schema::{divergence_investigation_reports as reports, divergence_triage_events};
+
+        let uuid = *uuid;
+        let note = note.map(str::to_string);
+        let actor = actor.to_string();
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    let updated = diesel::update(reports::table.filter(reports::uuid.eq(uuid)))
+                        .set((
+                            reports::triage_status.eq(status.as_str()),
+                            reports::triage_note.eq(&note),
+                        ))
+                        .execute(conn)
+                        .await?;
+                    if updated == 0 {
+                        return Ok(false);
+                    }
+
+                    diesel::insert_into(divergence_triage_events::table)
+                        .values((
+                            divergence_triage_events::report_uuid.eq(uuid),
+                            divergence_triage_events::status.eq(status.as_str()),
+                            divergence_triage_events::note.eq(&note),
+                            divergence_triage_events::actor.eq(&actor),
+                        ))
+                        .execute(conn)
+                        .await?;
+
+                    Ok(true)
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    /// Returns the triage status changes of a divergence investigation
+    /// report, oldest first.
+    pub async fn divergence_triage_events(
+        &self,
+        uuid: &Uuid,
+    ) -> anyhow::Result<Vec<models::DivergenceTriageEvent>> {
+        use schema::divergence_triage_events;
+
+        Ok(divergence_triage_events::table
+            .select(models::DivergenceTriageEvent::as_select())
+            .filter(divergence_triage_events::report_uuid.eq(uuid))
+            .order_by(divergence_triage_events::id)
+            .load(&mut self.conn().await?)
+            .await?)
+    }
+
     pub async fn create_or_update_divergence_investigation_report(
         &self,
         uuid: &Uuid,
         report: serde_json::Value,
     ) -> anyhow::Result<()> {
-        use schema::divergence_investigation_reports as reports;
+        use schema::{
+            divergence_investigation_reports as reports,
+            pending_divergence_investigation_requests as requests,
+        };
 
+        let conn = &mut self.conn().await?;
+        // Reports inherit the deduplication keys of their requests, so that
+        // they can be found once the requests are gone.
+        let (idempotency_key, deduplication_key) = requests::table
+            .select((requests::idempotency_key, requests::deduplication_key))
+            .filter(requests::uuid.eq(uuid))
+            .first::<(Option<String>, Option<String>)>(conn)
+            .await
+            .optional()?
+            .unwrap_or_default();
         diesel::insert_into(reports::table)
-            .values((reports::uuid.eq(&uuid), reports::report.eq(&report)))
+            .values((
+                reports::uuid.eq(&uuid),
+                reports::report.eq(&report),
+                reports::idempotency_key.eq(&idempotency_key),
+                reports::deduplication_key.eq(&deduplication_key),
+            ))
             .on_conflict(reports::uuid)
             .do_update()
             .set(reports::report.eq(&report))
-            .execute(&mut self.conn().await?)
+            .execute(conn)
             .await?;
 
         Ok(())
@@ -538,10 +2093,390 @@ impl Store {
 
         Ok(())
     }
+
+    /// Enqueues a PoI backfill job, unless it can be coalesced with an
+    /// unfinished job for the same subgraph deployment: pending jobs with an
+    /// overlapping block range are widened to cover both ranges (at the
+    /// smaller interval), and in-progress jobs that already cover the range at
+    /// the same or a smaller interval are reused as they are. Jobs are only
+    /// coalesced if their blocks line up, and if the result doesn't cover more
+    /// than `max_blocks` blocks, which requests can't either. Returns the job
+    /// that will handle the request.
+    pub async fn request_poi_backfill(
+        &self,
+        deployment: &IpfsCid,
+        from_block: u64,
+        to_block: u64,
+        block_interval: u64,
+        max_blocks: u64,
+    ) -> anyhow::Result<models::PoiBackfillJob> {
+        use models::PoiBackfillStatus;
+        use schema::poi_backfill_jobs as jobs;
+
+        let from_block = i64::try_from(from_block)?;
+        let to_block = i64::try_from(to_block)?;
+        let block_interval = i64::try_from(block_interval)?;
+        anyhow::ensure!(
+            from_block <= to_block,
+            "the backfill's start block must not be after its end block"
+        );
+        anyhow::ensure!(block_interval > 0, "the block interval must be positive");
+        let max_blocks = i64::try_from(max_blocks).unwrap_or(i64::MAX);
+        let blocks = |from_block: i64, to_block: i64, block_interval: i64| {
+            (to_block - from_block) / block_interval + 1
+        };
+        let requested_blocks = blocks(from_block, to_block, block_interval);
+        anyhow::ensure!(
+            requested_blocks <= max_blocks,
+            "the backfill covers {} blocks, more than the limit of {}",
+            requested_blocks,
+            max_blocks
+        );
+
+        let deployment = deployment.to_string();
+        self.conn()
+            .await?
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    let overlapping_jobs: Vec<models::PoiBackfillJob> = jobs::table
+                        .filter(jobs::deployment.eq(&deployment))
+                        .filter(jobs::status.eq_any([
+                            PoiBackfillStatus::Pending.as_str(),
+                            PoiBackfillStatus::InProgress.as_str(),
+                        ]))
+                        .filter(jobs::from_block.le(to_block))
+                        .filter(jobs::to_block.ge(from_block))
+                        .order_by(jobs::created_at)
+                        .for_update()
+                        .load(conn)
+                        .await?;
+
+                    for job in overlapping_jobs {
+                        // Coalescing must not skip any of the requested blocks.
+                        let interval = job.block_interval.min(block_interval);
+                        let aligned = job.block_interval % interval == 0
+                            && block_interval % interval == 0
+                            && (job.from_block - from_block) % interval == 0;
+                        if !aligned {
+                            continue;
+                        }
+
+                        let widened_from_block = job.from_block.min(from_block);
+                        let widened_to_block = job.to_block.max(to_block);
+                        match job.status() {
+                            PoiBackfillStatus::Pending
+                                if blocks(widened_from_block, widened_to_block, interval)
+                                    <= max_blocks =>
+                            {
+                                return Ok(diesel::update(jobs::table.find(job.uuid))
+                                    .set((
+                                        jobs::from_block.eq(widened_from_block),
+                                        jobs::to_block.eq(widened_to_block),
+                                        jobs::block_interval.eq(interval),
+                                        jobs::updated_at.eq(diesel::dsl::now),
+                                    ))
+                                    .get_result(conn)
+                                    .await?);
+                            }
+                            _ if job.from_block <= from_block
+                                && job.to_block >= to_block
+                                && job.block_interval <= block_interval =>
+                            {
+                                return Ok(job);
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    Ok(diesel::insert_into(jobs::table)
+                        .values((
+                            jobs::uuid.eq(Uuid::new_v4()),
+                            jobs::deployment.eq(&deployment),
+                            jobs::from_block.eq(from_block),
+                            jobs::to_block.eq(to_block),
+                            jobs::block_interval.eq(block_interval),
+                        ))
+                        .get_result(conn)
+                        .await?)
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    /// Fetches the PoI backfill job with the given UUID, if it exists.
+    pub async fn poi_backfill_job(
+        &self,
+        uuid: &Uuid,
+    ) -> anyhow::Result<Option<models::PoiBackfillJob>> {
+        use schema::poi_backfill_jobs as jobs;
+
+        Ok(jobs::table
+            .find(*uuid)
+            .get_result(&mut self.conn().await?)
+            .await
+            .optional()?)
+    }
+
+    /// Marks the oldest pending PoI backfill job as in progress and returns
+    /// it, if there is one.
+    pub async fn claim_next_poi_backfill_job(
+        &self,
+    ) -> anyhow::Result<Option<models::PoiBackfillJob>> {
+        use models::PoiBackfillStatus;
+        use schema::poi_backfill_jobs as jobs;
+
+        let oldest_pending_job = jobs::table
+            .select(jobs::uuid)
+            .filter(jobs::status.eq(PoiBackfillStatus::Pending.as_str()))
+            .order_by(jobs::created_at)
+            .limit(1);
+
+        Ok(diesel::update(jobs::table)
+            .filter(jobs::uuid.eq_any(oldest_pending_job))
+            // In case the job was claimed in the meantime.
+            .filter(jobs::status.eq(PoiBackfillStatus::Pending.as_str()))
+            .set((
+                jobs::status.eq(PoiBackfillStatus::InProgress.as_str()),
+                jobs::updated_at.eq(diesel::dsl::now),
+            ))
+            .get_result(&mut self.conn().await?)
+            .await
+            .optional()?)
+    }
+
+    /// Puts PoI backfill jobs that were interrupted, e.g. by a restart, back
+    /// into the queue. Returns how many there were.
+    pub async fn requeue_in_progress_poi_backfill_jobs(&self) -> anyhow::Result<usize> {
+        use models::PoiBackfillStatus;
+        use schema::poi_backfill_jobs as jobs;
+
+        Ok(diesel::update(jobs::table)
+            .filter(jobs::status.eq(PoiBackfillStatus::InProgress.as_str()))
+            .set((
+                jobs::status.eq(PoiBackfillStatus::Pending.as_str()),
+                jobs::requests_total.eq(None::<i64>),
+                jobs::requests_sent.eq(0),
+                jobs::pois_written.eq(0),
+                jobs::updated_at.eq(diesel::dsl::now),
+            ))
+            .execute(&mut self.conn().await?)
+            .await?)
+    }
+
+    pub async fn update_poi_backfill_progress(
+        &self,
+        uuid: &Uuid,
+        requests_total: usize,
+        requests_sent: usize,
+        pois_written: usize,
+    ) -> anyhow::Result<()> {
+        use schema::poi_backfill_jobs as jobs;
+
+        diesel::update(jobs::table.find(*uuid))
+            .set((
+                jobs::requests_total.eq(i64::try_from(requests_total)?),
+                jobs::requests_sent.eq(i64::try_from(requests_sent)?),
+                jobs::pois_written.eq(i64::try_from(pois_written)?),
+                jobs::updated_at.eq(diesel::dsl::now),
+            ))
+            .execute(&mut self.conn().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Marks a PoI backfill job as completed, or as failed if there's an
+    /// error.
+    pub async fn finish_poi_backfill_job(
+        &self,
+        uuid: &Uuid,
+        error: Option<String>,
+    ) -> anyhow::Result<()> {
+        use models::PoiBackfillStatus;
+        use schema::poi_backfill_jobs as jobs;
+
+        let status = match error {
+            Some(_) => PoiBackfillStatus::Failed,
+            None => PoiBackfillStatus::Completed,
+        };
+        diesel::update(jobs::table.find(*uuid))
+            .set((
+                jobs::status.eq(status.as_str()),
+                jobs::error.eq(error),
+                jobs::updated_at.eq(diesel::dsl::now),
+            ))
+            .execute(&mut self.conn().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns up to `limit` subgraph deployments whose manifests should be
+    /// fetched: those that were never attempted, and those whose last failed
+    /// attempt is due for a retry.
+    pub async fn sg_deployments_without_manifest(
+        &self,
+        limit: u16,
+    ) -> anyhow::Result<Vec<(IntId, IpfsCid)>> {
+        use schema::{sg_deployment_manifests as manifests, sg_deployments as sgd};
+
+        // This is a single SQL statement, a transaction is not necessary.
+        Ok(sgd::table
+            .left_join(manifests::table)
+            .filter(
+                manifests::sg_deployment_id
+                    .is_null()
+                    .or(manifests::fetched_at
+                        .is_null()
+                        .and(manifests::next_attempt_at.le(diesel::dsl::now))),
+            )
+            .select((sgd::id, sgd::ipfs_cid))
+            .order_by(sgd::id)
+            .limit(limit.into())
+            .load(&mut self.conn().await?)
+            .await?)
+    }
+
+    /// Stores the manifest metadata of a subgraph deployment, replacing any
+    /// record of previously failed attempts.
+    pub async fn write_sg_deployment_manifest(
+        &self,
+        manifest: &models::NewSgDeploymentManifest,
+    ) -> anyhow::Result<()> {
+        use schema::sg_deployment_manifests as manifests;
+
+        // This is a single SQL statement, a transaction is not necessary.
+        diesel::insert_into(manifests::table)
+            .values((
+                manifest,
+                manifests::fetched_at.eq(diesel::dsl::now.nullable()),
+            ))
+            .on_conflict(manifests::sg_deployment_id)
+            .do_update()
+            .set((
+                manifest,
+                manifests::fetched_at.eq(diesel::dsl::now.nullable()),
+                manifests::last_error.eq(None::<String>),
+            ))
+            .execute(&mut self.conn().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Records a failed attempt to fetch the manifest of a subgraph
+    /// deployment. The next attempt is delayed exponentially, starting at one
+    /// minute and up to a day.
+    pub async fn write_sg_deployment_manifest_failure(
+        &self,
+        sg_deployment_id: IntId,
+        error: &str,
+    ) -> anyhow::Result<()> {
+        // This is a single SQL statement, a transaction is not necessary.
+        diesel::sql_query(
+            "INSERT INTO sg_deployment_manifests
+                (sg_deployment_id, fetch_attempts, last_error, next_attempt_at)
+            VALUES ($1, 1, $2, NOW() + INTERVAL '1 minute')
+            ON CONFLICT (sg_deployment_id) DO UPDATE SET
+                fetch_attempts = sg_deployment_manifests.fetch_attempts + 1,
+                last_error = EXCLUDED.last_error,
+                next_attempt_at = NOW() + LEAST(
+                    INTERVAL '1 minute' * POWER(2, sg_deployment_manifests.fetch_attempts),
+                    INTERVAL '1 day'
+                )",
+        )
+        .bind::<diesel::sql_types::Integer, _>(sg_deployment_id)
+        .bind::<diesel::sql_types::Text, _>(error)
+        .execute(&mut self.conn().await?)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetches the manifest metadata of a subgraph deployment, if it was
+    /// fetched successfully.
+    pub async fn sg_deployment_manifest(
+        &self,
+        sg_deployment_id: IntId,
+    ) -> anyhow::Result<Option<models::SgDeploymentManifest>> {
+        use schema::sg_deployment_manifests as manifests;
+
+        // This is a single SQL statement, a transaction is not necessary.
+        Ok(manifests::table
+            .find(sg_deployment_id)
+            .filter(manifests::fetched_at.is_not_null())
+            .select(models::SgDeploymentManifest::as_select())
+            .first(&mut self.conn().await?)
+            .await
+            .optional()?)
+    }
+}
+
+/// Utilization of the [`Store`]'s database connection pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolStatus {
+    /// Connections currently checked out of the pool.
+    pub in_use: usize,
+    /// Open connections that are waiting in the pool to be used.
+    pub idle: usize,
+    /// The maximum number of connections the pool will open.
+    pub max_size: usize,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PoiLiveness {
     Live,
     NotLive,
+    /// PoIs at blocks that are pinned in the configuration. They never become
+    /// live, and are only returned by searches for pinned PoIs.
+    Pinned,
+}
+
+/// Criteria for [`Store::search_pois`]. PoIs must match all of them; unset
+/// fields match everything.
+#[derive(Default)]
+pub struct PoiFilter {
+    /// The name of the network of the subgraph deployment.
+    pub network: Option<String>,
+    /// The subgraph deployments to return PoIs of. All deployments if empty.
+    pub deployments: Vec<IpfsCid>,
+    pub indexer_address: Option<IndexerAddress>,
+    pub block_range: Option<inputs::BlockRange>,
+    /// Whether to only return live PoIs, only PoIs that aren't live anymore,
+    /// or only PoIs at pinned blocks.
+    pub liveness: Option<PoiLiveness>,
+    /// The PoI itself, e.g. to find out which indexers reported it.
+    pub poi: Option<PoiBytes>,
+}
+
+/// How [`Store::create_divergence_investigation_request`] recognizes
+/// duplicate requests.
+#[derive(Clone, Debug, Default)]
+pub struct InvestigationDeduplication {
+    /// A client-provided key. Requests with the same key are the same
+    /// request.
+    pub idempotency_key: Option<String>,
+    /// Identifies what's being investigated, see
+    /// [`Store::divergence_investigation_deduplication_key`].
+    pub key: Option<String>,
+    /// How long completed investigations are reused for requests with the
+    /// same `key`. Only pending and running ones are if `None`.
+    pub cooldown: Option<chrono::Duration>,
+}
+
+impl InvestigationDeduplication {
+    /// The `key` of investigations of all indexers of a subgraph deployment.
+    pub fn deployment_key(deployment: &IpfsCid) -> String {
+        format!("deployment:{deployment}")
+    }
+}
+
+/// Which page of results to return.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pagination {
+    /// The number of results to skip.
+    pub offset: u32,
+    /// The maximum number of results to return.
+    pub limit: u16,
 }
@@ -170,3 +170,26 @@ impl async_graphql::dataloader::Loader<IntId>
             .collect())
     }
 }
+
+#[async_trait]
+impl async_graphql::dataloader::Loader<IntId> for StoreLoader<models::SgDeploymentManifest> {
+    type Value = models::SgDeploymentManifest;
+    type Error = String;
+
+    async fn load(&self, keys: &[IntId]) -> Result<HashMap<IntId, Self::Value>, Self::Error> {
+        use schema::sg_deployment_manifests as manifests;
+
+        // Keys are subgraph deployment IDs. Manifests that couldn't be fetched
+        // (yet) are left out.
+        Ok(manifests::table
+            .filter(manifests::sg_deployment_id.eq_any(keys))
+            .filter(manifests::fetched_at.is_not_null())
+            .select(models::SgDeploymentManifest::as_select())
+            .load::<models::SgDeploymentManifest>(&mut self.store.conn_err_string().await?)
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|manifest| (manifest.sg_deployment_id, manifest))
+            .collect())
+    }
+}
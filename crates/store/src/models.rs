@@ -7,11 +7,13 @@ use diesel::backend::Backend;
 use diesel::deserialize::FromSql;
 use diesel::pg::Pg;
 use diesel::sql_types::Jsonb;
-use diesel::{AsChangeset, AsExpression, FromSqlRow, Insertable, Queryable, Selectable};
+use diesel::{
+    AsChangeset, AsExpression, FromSqlRow, Insertable, Queryable, QueryableByName, Selectable,
+};
 use graphix_common_types as types;
 use graphix_indexer_client::IndexerId;
 use serde::{Deserialize, Serialize};
-use types::{BlockHash, IndexerAddress, IpfsCid, PoiBytes};
+use types::{BlockHash, Caip2ChainId, IndexerAddress, IpfsCid, PoiBytes};
 
 use super::schema::*;
 
@@ -36,6 +38,570 @@ pub struct Poi {
     pub indexer_id: IntId,
     pub block_id: BigIntId,
     pub created_at: NaiveDateTime,
+    /// When a newer PoI of the same indexer and subgraph deployment arrived,
+    /// if one did.
+    pub superseded_at: Option<NaiveDateTime>,
+    /// Whether the PoI is at a block that's pinned in the configuration.
+    pub pinned: bool,
+    /// The status endpoint of the indexer that the PoI was queried from, if
+    /// known.
+    pub source_url: Option<String>,
+}
+
+/// Whether a PoI is the one reported by the majority of indexers for its
+/// subgraph deployment and block, as of each indexer's most recent PoI.
+#[derive(QueryableByName, Debug, Clone)]
+pub struct PoiMajority {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub sg_deployment_id: IntId,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub block_id: BigIntId,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    pub is_majority: bool,
+}
+
+/// The indexers that reported a PoI for a subgraph deployment at a block, as
+/// of each indexer's most recent PoI for the block.
+#[derive(QueryableByName, Debug, Clone)]
+pub struct PoiSupport {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub block_number: i64,
+    #[diesel(sql_type = diesel::sql_types::Binary)]
+    pub poi: PoiBytes,
+    /// Sorted by address.
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Binary>)]
+    pub indexers: Vec<IndexerAddress>,
+}
+
+/// A PoI joined with its indexer, subgraph deployment and block, as exported
+/// by `graphix export` and the `/export/pois.ndjson` route. Fields are
+/// serialized in column order.
+#[derive(Queryable, Serialize, Debug, Clone)]
+pub struct PoiExportRow {
+    #[serde(skip)]
+    pub id: IntId,
+    pub timestamp: NaiveDateTime,
+    pub indexer_address: IndexerAddress,
+    pub deployment_cid: SgDeploymentCid,
+    pub block_number: i64,
+    pub block_hash: BlockHash,
+    pub poi: PoiBytes,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = poi_changes)]
+pub struct NewPoiChange {
+    pub indexer_id: IntId,
+    pub sg_deployment_id: IntId,
+    pub block_id: BigIntId,
+    pub old_poi: PoiBytes,
+    pub new_poi: PoiBytes,
+    pub detected_at: NaiveDateTime,
+}
+
+/// An indexer reporting a different PoI than before for the same subgraph
+/// deployment and block, typically after rewinding and resyncing.
+#[derive(Queryable, Debug, Clone, SimpleObject)]
+pub struct PoiChange {
+    #[graphql(skip)]
+    pub id: IntId,
+    pub indexer_address: IndexerAddress,
+    /// IPFS CID of the subgraph deployment.
+    pub deployment: SgDeploymentCid,
+    pub block_number: i64,
+    pub block_hash: BlockHash,
+    /// The PoI that the indexer reported previously.
+    pub old_poi: PoiBytes,
+    /// The PoI that the indexer reports now.
+    pub new_poi: PoiBytes,
+    pub detected_at: NaiveDateTime,
+}
+
+/// Indexers disagreeing on the PoI for a subgraph deployment and block, as of
+/// the most recent PoI of each indexer for that block.
+#[derive(QueryableByName, Serialize, Debug, Clone, SimpleObject)]
+pub struct StaleDivergence {
+    /// IPFS CID of the subgraph deployment.
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub deployment: SgDeploymentCid,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub block_number: i64,
+    #[diesel(sql_type = diesel::sql_types::Binary)]
+    pub block_hash: BlockHash,
+    /// When a second, different PoI was first collected for the block.
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub first_detected_at: NaiveDateTime,
+    /// How long the divergence has persisted since it was first detected.
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub persisted_for_seconds: i64,
+    /// The indexers that have a PoI for the block, whether they're in the
+    /// majority or not.
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Binary>)]
+    pub indexers: Vec<IndexerAddress>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = block_hash_mismatches)]
+pub struct NewBlockHashMismatch {
+    pub indexer_id: IntId,
+    pub sg_deployment_id: IntId,
+    pub block_id: BigIntId,
+    pub poi: PoiBytes,
+}
+
+/// Indexers reporting PoIs for the same block number of a subgraph deployment
+/// but with different block hashes, i.e. at least one of them is on a reorged
+/// fork. Their PoIs aren't compared, so these aren't divergences.
+#[derive(QueryableByName, Serialize, Debug, Clone, SimpleObject)]
+pub struct BlockHashMismatch {
+    /// IPFS CID of the subgraph deployment.
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub deployment: SgDeploymentCid,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub block_number: i64,
+    /// When the mismatch was first seen.
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub first_detected_at: NaiveDateTime,
+    /// The indexers that reported a PoI for the block.
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Binary>)]
+    pub indexers: Vec<IndexerAddress>,
+    /// The block hash that each of `indexers` reported, in the same order.
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Binary>)]
+    pub block_hashes: Vec<BlockHash>,
+}
+
+/// Divergence statistics over the PoIs collected in a time bucket. A
+/// subgraph deployment and block is compared if at least two indexers
+/// reported a PoI for it in the bucket, and diverges if the most recent of
+/// these PoIs differ.
+#[derive(QueryableByName, Debug, Clone, SimpleObject)]
+pub struct DivergenceStats {
+    /// The start of the bucket, in UTC.
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub bucket_start: NaiveDateTime,
+    /// Number of subgraph deployments with at least one diverging block.
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub n_diverging_deployments: i64,
+    /// Number of subgraph deployments with at least one compared block.
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub n_compared_deployments: i64,
+    /// Number of diverging subgraph deployment and block pairs.
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub n_diverging_blocks: i64,
+    /// Number of distinct indexers that reported a PoI for a diverging block,
+    /// whether they're in the majority or not.
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub n_diverging_indexers: i64,
+}
+
+/// The width of the time buckets of [`DivergenceStats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, async_graphql::Enum)]
+pub enum TimeBucket {
+    Hour,
+    Day,
+    /// Weeks start on Monday.
+    Week,
+}
+
+impl TimeBucket {
+    /// The name of the bucket as understood by Postgres' `date_trunc`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+            Self::Week => "week",
+        }
+    }
+
+    pub fn duration(&self) -> chrono::Duration {
+        match self {
+            Self::Hour => chrono::Duration::hours(1),
+            Self::Day => chrono::Duration::days(1),
+            Self::Week => chrono::Duration::weeks(1),
+        }
+    }
+}
+
+/// The most recent indexing status that an indexer reported for a subgraph
+/// deployment.
+#[derive(QueryableByName, Debug, Clone)]
+pub struct StoredIndexingStatus {
+    #[diesel(sql_type = diesel::sql_types::Binary)]
+    pub indexer: IndexerAddress,
+    /// IPFS CID of the subgraph deployment.
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub deployment: SgDeploymentCid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub network: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub latest_block_number: i64,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Binary>)]
+    pub latest_block_hash: Option<BlockHash>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub earliest_block_number: i64,
+    /// When the indexer last reported the status.
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub reported_at: NaiveDateTime,
+}
+
+/// An indexer whose latest block for a subgraph deployment hasn't advanced in
+/// a number of main loop iterations, even though it keeps reporting it.
+#[derive(QueryableByName, Debug, Clone, SimpleObject)]
+pub struct StalledIndexer {
+    #[diesel(sql_type = diesel::sql_types::Binary)]
+    pub indexer: IndexerAddress,
+    /// IPFS CID of the subgraph deployment.
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub deployment: SgDeploymentCid,
+    /// The latest block that the indexer reports for the deployment.
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub latest_block_number: i64,
+    /// Main loop iterations in a row in which the latest block didn't
+    /// advance.
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub iterations_without_progress: i32,
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub last_advanced_at: NaiveDateTime,
+    /// How long the latest block has been stuck for.
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub stalled_for_seconds: i64,
+}
+
+/// A request to query PoIs for past blocks of a subgraph deployment, every
+/// `block_interval` blocks from `from_block` to `to_block`.
+#[derive(Queryable, Debug, Clone)]
+pub struct PoiBackfillJob {
+    pub uuid: uuid::Uuid,
+    pub deployment: SgDeploymentCid,
+    pub from_block: i64,
+    pub to_block: i64,
+    pub block_interval: i64,
+    pub status: String,
+    pub requests_total: Option<i64>,
+    pub requests_sent: i64,
+    pub pois_written: i64,
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl PoiBackfillJob {
+    pub fn status(&self) -> PoiBackfillStatus {
+        match self.status.as_str() {
+            "pending" => PoiBackfillStatus::Pending,
+            "in_progress" => PoiBackfillStatus::InProgress,
+            "completed" => PoiBackfillStatus::Completed,
+            _ => PoiBackfillStatus::Failed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, async_graphql::Enum)]
+pub enum PoiBackfillStatus {
+    /// The job is waiting for the worker to pick it up.
+    Pending,
+    InProgress,
+    Completed,
+    Failed,
+}
+
+impl PoiBackfillStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::InProgress => "in_progress",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// A stored divergence investigation report, along with where it stands in
+/// triage.
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = divergence_investigation_reports)]
+pub struct DivergenceInvestigationReport {
+    pub uuid: uuid::Uuid,
+    /// A serialized [`types::DivergenceInvestigationReport`].
+    pub report: serde_json::Value,
+    pub created_at: NaiveDateTime,
+    pub triage_status: String,
+    pub triage_note: Option<String>,
+}
+
+impl DivergenceInvestigationReport {
+    pub fn triage_status(&self) -> types::DivergenceTriageStatus {
+        types::DivergenceTriageStatus::from_stored(&self.triage_status)
+    }
+}
+
+/// A triage status change of a divergence investigation report.
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = divergence_triage_events)]
+pub struct DivergenceTriageEvent {
+    pub id: IntId,
+    pub report_uuid: uuid::Uuid,
+    pub status: String,
+    pub note: Option<String>,
+    /// The label of the admin API key that changed the status.
+    pub actor: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl DivergenceTriageEvent {
+    pub fn status(&self) -> types::DivergenceTriageStatus {
+        types::DivergenceTriageStatus::from_stored(&self.status)
+    }
+}
+
+/// Size information about a database table, as reported by `graphix db
+/// status`.
+#[derive(QueryableByName, Debug, Clone)]
+pub struct TableStats {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub name: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub row_count: i64,
+    /// Disk space used by the table, including indexes and TOAST data.
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub total_bytes: i64,
+}
+
+/// The PoIs of two indexers for the same subgraph deployment and block.
+#[derive(Queryable, Debug, Clone)]
+pub struct PoiPair {
+    pub deployment_cid: SgDeploymentCid,
+    pub block_number: i64,
+    pub block_hash: BlockHash,
+    pub poi1: PoiBytes,
+    pub poi2: PoiBytes,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = indexer_query_errors)]
+pub struct NewIndexerQueryOutcome {
+    pub indexer_id: IntId,
+    pub query_name: String,
+    pub results: Option<i32>,
+    pub error: Option<String>,
+    pub error_kind: Option<String>,
+}
+
+/// The outcome of a query that Graphix sent to an indexer.
+#[derive(Queryable, Debug, Clone, SimpleObject)]
+#[graphql(name = "IndexerQueryStatus")]
+pub struct IndexerQueryOutcome {
+    #[graphql(skip)]
+    pub id: IntId,
+    #[graphql(skip)]
+    pub indexer_id: IntId,
+    /// The kind of query, e.g. `indexingStatuses`.
+    pub query_name: String,
+    /// How many results the query returned, if it succeeded.
+    pub results: Option<i32>,
+    /// Why the query failed, if it did.
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+    /// The kind of error that the query failed with, if known, e.g.
+    /// `Timeout`, `Dns`, `Tls`, `Connection`, `Http`, `GraphQl`,
+    /// `Deserialization`, `ResponseTooLarge` or `TooManyResults`.
+    pub error_kind: Option<String>,
+}
+
+/// The block that Graphix chose to query PoIs at for a subgraph deployment in
+/// a main loop iteration, before it's written to the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockChoice {
+    pub deployment: SgDeploymentCid,
+    /// The name of the block choice policy, e.g. `maxSyncedBlocks`.
+    pub policy: String,
+    /// The latest blocks of the indexers that serve the deployment.
+    pub candidate_blocks: Vec<u64>,
+    pub chosen_block: Option<u64>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = block_choice_traces)]
+pub struct NewBlockChoiceTrace {
+    pub sg_deployment_id: IntId,
+    pub policy: String,
+    pub candidate_blocks: Vec<i64>,
+    pub chosen_block: Option<i64>,
+}
+
+/// Which block Graphix chose to query PoIs at for a subgraph deployment in a
+/// main loop iteration, and what it chose from.
+#[derive(Queryable, Debug, Clone, SimpleObject)]
+pub struct BlockChoiceTrace {
+    #[graphql(skip)]
+    pub id: IntId,
+    /// IPFS CID of the subgraph deployment.
+    pub deployment: SgDeploymentCid,
+    /// The block choice policy in effect, e.g. `maxSyncedBlocks`.
+    pub policy: String,
+    /// The latest blocks of the indexers that served the deployment, in
+    /// ascending order.
+    pub candidate_blocks: Vec<i64>,
+    /// The block that PoIs were queried at, if the policy chose any.
+    pub chosen_block: Option<i64>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = indexer_discovery_counts)]
+pub struct NewIndexerDiscoveryCount {
+    pub source: String,
+    pub n_indexers: i32,
+}
+
+/// How many indexers a source listed in a main loop iteration, before
+/// deduplication.
+#[derive(Queryable, Selectable, Debug, Clone, SimpleObject)]
+#[diesel(table_name = indexer_discovery_counts)]
+pub struct IndexerDiscoveryCount {
+    #[graphql(skip)]
+    pub id: IntId,
+    /// `config`, the endpoint of a network subgraph, or `interceptor`.
+    pub source: String,
+    pub n_indexers: i32,
+    pub discovered_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = loop_iterations)]
+pub struct NewLoopIteration {
+    pub started_at: NaiveDateTime,
+    pub finished_at: NaiveDateTime,
+    pub indexing_statuses_seconds: f64,
+    pub pois_seconds: f64,
+    pub writes_seconds: f64,
+    pub n_indexers: i32,
+    pub n_queried_indexers: i32,
+    pub n_responding_indexers: i32,
+    pub n_pois: i32,
+    pub n_pinned_pois: i32,
+    pub write_errors: Vec<String>,
+    pub succeeded: bool,
+}
+
+/// A summary of a main loop iteration.
+#[derive(Queryable, Selectable, Debug, Clone, SimpleObject)]
+#[diesel(table_name = loop_iterations)]
+pub struct LoopIteration {
+    #[graphql(skip)]
+    pub id: IntId,
+    pub started_at: NaiveDateTime,
+    pub finished_at: NaiveDateTime,
+    /// How long querying the indexing statuses of all indexers took.
+    pub indexing_statuses_seconds: f64,
+    /// How long querying PoIs took.
+    pub pois_seconds: f64,
+    /// How long writing PoIs and everything derived from them took.
+    pub writes_seconds: f64,
+    /// Number of known indexers.
+    pub n_indexers: i32,
+    /// Number of indexers that were queried, i.e. all known ones except those
+    /// with open circuit breakers.
+    pub n_queried_indexers: i32,
+    /// Number of indexers that answered the `indexingStatuses` query.
+    pub n_responding_indexers: i32,
+    /// Number of PoIs collected, not counting those at pinned blocks.
+    pub n_pois: i32,
+    /// Number of PoIs collected at pinned blocks.
+    pub n_pinned_pois: i32,
+    /// The errors of the writes that failed during the iteration.
+    pub write_errors: Vec<String>,
+    /// Whether the PoIs were written. Other writes are best-effort.
+    pub succeeded: bool,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = tracked_deployments)]
+pub struct NewTrackedDeployment {
+    pub ipfs_cid: SgDeploymentCid,
+    pub network_subgraph: String,
+    pub network: Option<String>,
+    pub active_allocations: i32,
+    pub allocated_tokens: f64,
+}
+
+/// A subgraph deployment that Graphix tracks because indexers have active
+/// allocations on it, according to a network subgraph with deployment
+/// discovery enabled.
+#[derive(Queryable, Debug, Clone, SimpleObject)]
+pub struct TrackedDeployment {
+    /// IPFS CID of the subgraph deployment.
+    pub deployment: SgDeploymentCid,
+    /// The endpoint of the network subgraph that the deployment was
+    /// discovered through.
+    pub network_subgraph: String,
+    /// The network that the deployment indexes, if known.
+    pub network: Option<String>,
+    /// The number of active allocations on the deployment.
+    pub active_allocations: i32,
+    /// The sum of all active allocations on the deployment, in GRT.
+    pub allocated_tokens: f64,
+    /// When the deployment was first discovered through this network
+    /// subgraph.
+    pub tracked_since: NaiveDateTime,
+}
+
+/// A subgraph deployment that an operator tagged as watched through the API.
+#[derive(Queryable, Selectable, Debug, Clone, SimpleObject)]
+#[diesel(table_name = watched_deployments)]
+pub struct WatchedDeployment {
+    /// IPFS CID of the subgraph deployment.
+    #[diesel(column_name = ipfs_cid)]
+    pub deployment: SgDeploymentCid,
+    /// Who tagged the deployment, if they said so.
+    pub watched_by: Option<String>,
+    /// When the deployment was tagged.
+    pub watched_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = allocations)]
+pub struct NewAllocation {
+    pub id: String,
+    pub network_subgraph: String,
+    pub indexer_address: IndexerAddress,
+    pub ipfs_cid: SgDeploymentCid,
+    pub allocated_tokens: f64,
+    pub created_at_epoch: i32,
+}
+
+/// An indexer's allocation on a subgraph deployment, according to a network
+/// subgraph.
+#[derive(Queryable, Selectable, Debug, Clone, SimpleObject)]
+#[diesel(table_name = allocations)]
+pub struct Allocation {
+    /// The allocation ID.
+    pub id: String,
+    /// The endpoint of the network subgraph that listed the allocation.
+    pub network_subgraph: String,
+    /// The address of the allocating indexer.
+    #[diesel(column_name = indexer_address)]
+    pub indexer: IndexerAddress,
+    /// IPFS CID of the subgraph deployment.
+    #[diesel(column_name = ipfs_cid)]
+    pub deployment: SgDeploymentCid,
+    /// In GRT.
+    pub allocated_tokens: f64,
+    /// The epoch in which the allocation was created.
+    pub created_at_epoch: i32,
+    /// When Graphix first saw the allocation.
+    pub first_seen_at: NaiveDateTime,
+    /// When Graphix noticed that the network subgraph no longer lists the
+    /// allocation as active. Null while it's active.
+    pub closed_at: Option<NaiveDateTime>,
+}
+
+/// The subgraph deployments that started or stopped being tracked when the
+/// tracked deployments were replaced.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TrackedDeploymentChanges {
+    pub added: Vec<SgDeploymentCid>,
+    pub removed: Vec<SgDeploymentCid>,
 }
 
 #[derive(Selectable, Insertable, Debug)]
@@ -44,6 +610,7 @@ pub struct NewGraphNodeCollectedVersion {
     pub version_string: Option<String>,
     pub version_commit: Option<String>,
     pub error_response: Option<String>,
+    pub supported_features: Option<Vec<String>>,
 }
 
 #[derive(Queryable, Clone, Selectable, Debug, SimpleObject)]
@@ -55,6 +622,10 @@ pub struct GraphNodeCollectedVersion {
     pub version_commit: Option<String>,
     pub error_response: Option<String>,
     pub collected_at: NaiveDateTime,
+    /// The features that graph-node supports (e.g. `grafting`), which can
+    /// explain why indexers compute different PoIs for the same subgraph
+    /// deployment. Null for graph-node versions that don't list them.
+    pub supported_features: Option<Vec<String>>,
 }
 
 impl GraphNodeCollectedVersion {
@@ -64,6 +635,7 @@ impl GraphNodeCollectedVersion {
             commit: self.version_commit,
             error_response: self.error_response,
             collected_at: self.collected_at,
+            supported_features: self.supported_features,
         }
     }
 }
@@ -76,6 +648,17 @@ pub struct NewPoi {
     pub sg_deployment_id: IntId,
     pub indexer_id: IntId,
     pub block_id: BigIntId,
+    pub pinned: bool,
+    pub source_url: Option<String>,
+}
+
+/// An indexer that serves a subgraph deployment, with its most recent PoI for
+/// the deployment and the block of that PoI.
+#[derive(Clone, Debug)]
+pub struct IndexerDeploymentInfo {
+    pub indexer: Indexer,
+    pub latest_poi: Poi,
+    pub latest_block: Block,
 }
 
 #[derive(Queryable, Clone, Debug, Serialize)]
@@ -96,7 +679,12 @@ pub struct NewBlock {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DivergenceInvestigationRequest {
+    #[serde(default)]
     pub pois: Vec<PoiBytes>,
+    /// Set instead of `pois` to investigate all indexers of a subgraph
+    /// deployment, based on their latest live PoIs.
+    #[serde(default)]
+    pub deployment: Option<IpfsCid>,
     pub query_block_caches: bool,
     pub query_eth_call_caches: bool,
     pub query_entity_changes: bool,
@@ -112,6 +700,30 @@ pub struct Indexer {
     pub network_subgraph_metadata: Option<IntId>,
     #[serde(skip)]
     pub created_at: NaiveDateTime,
+    /// See [`graphix_indexer_client::IndexerNameSource`].
+    pub name_source: Option<String>,
+    /// See [`IndexerMetadataChangeset`].
+    pub region: Option<String>,
+    pub labels: Vec<String>,
+    /// See [`graphix_indexer_client::IndexerSource`].
+    pub source: Option<String>,
+    /// In GRT, if known. Only written when consensus is weighted by stake.
+    pub staked_tokens: Option<f64>,
+    /// When the indexer was last listed by any of the configured sources.
+    pub last_seen_at: NaiveDateTime,
+    /// Cleared once the indexer hasn't been seen for a while, and set again
+    /// when it reappears. See [`crate::Store::deactivate_indexers`].
+    pub is_active: bool,
+}
+
+/// Operator-provided metadata of an indexer, e.g. for grouping indexers by
+/// region in dashboards. Graphix doesn't interpret it. `None` fields keep
+/// their stored values.
+#[derive(AsChangeset, Debug, Clone, Default)]
+#[diesel(table_name = indexers)]
+pub struct IndexerMetadataChangeset {
+    pub region: Option<String>,
+    pub labels: Option<Vec<String>>,
 }
 
 impl IndexerId for Indexer {
@@ -132,7 +744,7 @@ impl IndexerId for Indexer {
 pub struct Network {
     pub id: IntId,
     pub name: String,
-    pub caip2: Option<String>,
+    pub caip2: Option<Caip2ChainId>,
 }
 
 #[derive(Debug, Insertable, AsChangeset, Serialize)]
@@ -172,7 +784,7 @@ pub struct IndexerNetworkSubgraphMetadata {
 #[diesel(table_name = networks)]
 pub struct NewNetwork {
     pub name: String,
-    pub caip2: Option<String>,
+    pub caip2: Option<Caip2ChainId>,
 }
 
 #[derive(Debug, Insertable)]
@@ -180,6 +792,8 @@ pub struct NewNetwork {
 pub struct NewIndexer {
     pub address: IndexerAddress,
     pub name: Option<String>,
+    pub name_source: Option<String>,
+    pub source: Option<String>,
 }
 
 #[derive(Debug, Clone, Queryable, Serialize)]
@@ -192,6 +806,22 @@ pub struct SgDeployment {
     pub created_at: NaiveDateTime,
 }
 
+/// A subgraph deployment that has PoIs, along with how many indexers
+/// reported them.
+#[derive(Debug, Clone)]
+pub struct DeploymentWithIndexerCount {
+    pub deployment: SgDeployment,
+    pub n_indexers: i64,
+}
+
+/// A page of [`Store::distinct_deployments`](crate::Store::distinct_deployments).
+#[derive(Debug, Clone)]
+pub struct DeploymentPage {
+    pub deployments: Vec<DeploymentWithIndexerCount>,
+    /// The number of deployments with PoIs across all pages.
+    pub total_count: i64,
+}
+
 #[derive(Debug, Insertable)]
 #[diesel(table_name = sg_deployments)]
 pub struct NewSgDeployment {
@@ -200,6 +830,48 @@ pub struct NewSgDeployment {
     pub created_at: NaiveDateTime,
 }
 
+/// Metadata from the manifest of a subgraph deployment.
+#[derive(Debug, Clone, Queryable, Selectable, SimpleObject)]
+#[diesel(table_name = sg_deployment_manifests)]
+#[graphql(name = "DeploymentManifest")]
+pub struct SgDeploymentManifest {
+    #[graphql(skip)]
+    pub sg_deployment_id: IntId,
+    pub description: Option<String>,
+    /// URL of the subgraph's source code repository.
+    pub repository: Option<String>,
+    pub spec_version: Option<String>,
+    /// The network that the manifest's first data source indexes.
+    pub network: Option<String>,
+    /// The earliest start block of all data sources.
+    pub start_block: Option<i64>,
+    /// Features that the subgraph declares to use, e.g. `grafting`.
+    pub features: Option<Vec<String>>,
+    /// The number of entity types in the subgraph's GraphQL schema.
+    pub entity_count: Option<i32>,
+    #[graphql(skip)]
+    pub fetched_at: Option<NaiveDateTime>,
+    #[graphql(skip)]
+    pub fetch_attempts: i32,
+    #[graphql(skip)]
+    pub last_error: Option<String>,
+    #[graphql(skip)]
+    pub next_attempt_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Insertable, AsChangeset)]
+#[diesel(table_name = sg_deployment_manifests)]
+pub struct NewSgDeploymentManifest {
+    pub sg_deployment_id: IntId,
+    pub description: Option<String>,
+    pub repository: Option<String>,
+    pub spec_version: Option<String>,
+    pub network: Option<String>,
+    pub start_block: Option<i64>,
+    pub features: Option<Vec<String>>,
+    pub entity_count: Option<i32>,
+}
+
 #[derive(Debug, Insertable, AsChangeset)]
 #[diesel(table_name = live_pois)]
 pub struct NewLivePoi {
@@ -0,0 +1,340 @@
+//! A backend-agnostic interface to PoI persistence, so that the logic that
+//! collects and compares PoIs doesn't need a PostgreSQL database to run.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::Utc;
+use graphix_common_types::{inputs, IndexerAddress, IpfsCid};
+use graphix_indexer_client::ProofOfIndexing;
+
+use crate::models::{PoiChange, PoiExportRow, PoiPair, StaleDivergence};
+use crate::{Pagination, PoiLiveness, Store};
+
+/// PoI reads and writes, as well as the queries that compare PoIs across
+/// indexers. [`Store`] is the PostgreSQL implementation and
+/// [`InMemoryPoiStore`] a non-persistent one.
+#[async_trait]
+pub trait PoiStore: Send + Sync {
+    /// Writes PoIs. Returns how many of them differ from the PoI previously
+    /// written for the same indexer, deployment and block.
+    async fn write_pois(
+        &self,
+        pois: Vec<ProofOfIndexing>,
+        live: PoiLiveness,
+    ) -> anyhow::Result<usize>;
+
+    /// Returns up to `limit` PoIs of the given subgraph deployments (or of all
+    /// deployments, if none are given) in the given block range, oldest
+    /// first.
+    async fn read_pois(
+        &self,
+        deployments: &[IpfsCid],
+        block_range: Option<inputs::BlockRange>,
+        limit: Option<u16>,
+    ) -> anyhow::Result<Vec<PoiExportRow>>;
+
+    /// For every subgraph deployment that both indexers have PoIs for, returns
+    /// their PoIs at the most recent block they have in common, ordered by
+    /// deployment.
+    async fn latest_common_poi_pairs(
+        &self,
+        indexer1: &IndexerAddress,
+        indexer2: &IndexerAddress,
+    ) -> anyhow::Result<Vec<PoiPair>>;
+
+    /// Returns the most recent PoI changes, newest first, optionally
+    /// restricted to a single indexer and/or subgraph deployment.
+    async fn poi_changes(
+        &self,
+        indexer_address: Option<&IndexerAddress>,
+        deployment: Option<&IpfsCid>,
+        limit: u16,
+    ) -> anyhow::Result<Vec<PoiChange>>;
+
+    /// Returns the subgraph deployments and blocks that indexers still
+    /// disagree on the PoI for, longest-standing first.
+    async fn stale_divergences(
+        &self,
+        pagination: Pagination,
+    ) -> anyhow::Result<Vec<StaleDivergence>>;
+}
+
+#[async_trait]
+impl PoiStore for Store {
+    async fn write_pois(
+        &self,
+        pois: Vec<ProofOfIndexing>,
+        live: PoiLiveness,
+    ) -> anyhow::Result<usize> {
+        Store::write_pois(self, pois, live).await
+    }
+
+    async fn read_pois(
+        &self,
+        deployments: &[IpfsCid],
+        block_range: Option<inputs::BlockRange>,
+        limit: Option<u16>,
+    ) -> anyhow::Result<Vec<PoiExportRow>> {
+        let filter = inputs::PoisQuery {
+            network: None,
+            deployments: deployments.to_vec(),
+            block_range,
+            limit: None,
+        };
+
+        self.poi_export_batch(&filter, None, None, limit.unwrap_or(u16::MAX))
+            .await
+    }
+
+    async fn latest_common_poi_pairs(
+        &self,
+        indexer1: &IndexerAddress,
+        indexer2: &IndexerAddress,
+    ) -> anyhow::Result<Vec<PoiPair>> {
+        Store::latest_common_poi_pairs(self, indexer1, indexer2).await
+    }
+
+    async fn poi_changes(
+        &self,
+        indexer_address: Option<&IndexerAddress>,
+        deployment: Option<&IpfsCid>,
+        limit: u16,
+    ) -> anyhow::Result<Vec<PoiChange>> {
+        Store::poi_changes(self, indexer_address, deployment, limit).await
+    }
+
+    async fn stale_divergences(
+        &self,
+        pagination: Pagination,
+    ) -> anyhow::Result<Vec<StaleDivergence>> {
+        Store::stale_divergences(self, pagination).await
+    }
+}
+
+/// A [`PoiStore`] that keeps everything in memory, for tests and throwaway
+/// deployments. It doesn't keep track of liveness, which none of the
+/// [`PoiStore`] queries depend on.
+#[derive(Debug, Default)]
+pub struct InMemoryPoiStore {
+    pois: Mutex<Vec<PoiExportRow>>,
+    poi_changes: Mutex<Vec<PoiChange>>,
+}
+
+impl InMemoryPoiStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl PoiStore for InMemoryPoiStore {
+    async fn write_pois(
+        &self,
+        pois: Vec<ProofOfIndexing>,
+        _live: PoiLiveness,
+    ) -> anyhow::Result<usize> {
+        let mut rows = self.pois.lock().unwrap();
+        let mut poi_changes = self.poi_changes.lock().unwrap();
+        let changes_before = poi_changes.len();
+
+        for poi in pois {
+            let row = PoiExportRow {
+                id: i32::try_from(rows.len())? + 1,
+                timestamp: Utc::now().naive_utc(),
+                indexer_address: poi.indexer.address(),
+                deployment_cid: poi.deployment.0,
+                block_number: poi.block.number.try_into()?,
+                block_hash: poi.block.hash.context("PoI without a block hash")?,
+                poi: poi.proof_of_indexing,
+            };
+
+            let previous_poi = rows.iter().rev().find(|previous| {
+                previous.indexer_address == row.indexer_address
+                    && previous.deployment_cid == row.deployment_cid
+                    && previous.block_hash == row.block_hash
+            });
+            if let Some(previous) = previous_poi.filter(|previous| previous.poi != row.poi) {
+                poi_changes.push(PoiChange {
+                    id: i32::try_from(poi_changes.len())? + 1,
+                    indexer_address: row.indexer_address,
+                    deployment: row.deployment_cid.clone(),
+                    block_number: row.block_number,
+                    block_hash: row.block_hash.clone(),
+                    old_poi: previous.poi,
+                    new_poi: row.poi,
+                    detected_at: row.timestamp,
+                });
+            }
+
+            rows.push(row);
+        }
+
+        Ok(poi_changes.len() - changes_before)
+    }
+
+    async fn read_pois(
+        &self,
+        deployments: &[IpfsCid],
+        block_range: Option<inputs::BlockRange>,
+        limit: Option<u16>,
+    ) -> anyhow::Result<Vec<PoiExportRow>> {
+        let deployments: Vec<String> = deployments.iter().map(ToString::to_string).collect();
+        let (start, end) = block_range.map_or((None, None), |range| (range.start, range.end));
+        let in_block_range = |block_number: i64| {
+            let block_number = block_number as u64;
+            start.map_or(true, |start| block_number >= start)
+                && end.map_or(true, |end| block_number <= end)
+        };
+
+        Ok(self
+            .pois
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|row| deployments.is_empty() || deployments.contains(&row.deployment_cid))
+            .filter(|row| in_block_range(row.block_number))
+            .take(limit.map_or(usize::MAX, usize::from))
+            .cloned()
+            .collect())
+    }
+
+    async fn latest_common_poi_pairs(
+        &self,
+        indexer1: &IndexerAddress,
+        indexer2: &IndexerAddress,
+    ) -> anyhow::Result<Vec<PoiPair>> {
+        let rows = self.pois.lock().unwrap();
+
+        // The most recent PoI of each indexer, by deployment and block.
+        let mut pois1 = BTreeMap::new();
+        let mut pois2 = BTreeMap::new();
+        for row in rows.iter() {
+            let key = (
+                row.deployment_cid.clone(),
+                row.block_number,
+                row.block_hash.clone(),
+            );
+            if row.indexer_address == *indexer1 {
+                pois1.insert(key.clone(), row.poi);
+            }
+            if row.indexer_address == *indexer2 {
+                pois2.insert(key, row.poi);
+            }
+        }
+
+        let mut pairs: BTreeMap<String, PoiPair> = BTreeMap::new();
+        for ((deployment_cid, block_number, block_hash), poi1) in pois1 {
+            let Some(poi2) = pois2.get(&(deployment_cid.clone(), block_number, block_hash.clone()))
+            else {
+                continue;
+            };
+
+            // Keys are sorted, so later blocks of the same deployment
+            // overwrite earlier ones.
+            pairs.insert(
+                deployment_cid.clone(),
+                PoiPair {
+                    deployment_cid,
+                    block_number,
+                    block_hash,
+                    poi1,
+                    poi2: *poi2,
+                },
+            );
+        }
+
+        Ok(pairs.into_values().collect())
+    }
+
+    async fn poi_changes(
+        &self,
+        indexer_address: Option<&IndexerAddress>,
+        deployment: Option<&IpfsCid>,
+        limit: u16,
+    ) -> anyhow::Result<Vec<PoiChange>> {
+        let deployment = deployment.map(ToString::to_string);
+
+        Ok(self
+            .poi_changes
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|change| indexer_address.map_or(true, |a| change.indexer_address == *a))
+            .filter(|change| {
+                deployment
+                    .as_ref()
+                    .map_or(true, |d| change.deployment == *d)
+            })
+            .take(limit.into())
+            .cloned()
+            .collect())
+    }
+
+    async fn stale_divergences(
+        &self,
+        pagination: Pagination,
+    ) -> anyhow::Result<Vec<StaleDivergence>> {
+        let rows = self.pois.lock().unwrap();
+
+        // The most recent PoI of each indexer, and when each PoI was first
+        // collected, by deployment and block.
+        let mut latest_pois = BTreeMap::<_, BTreeMap<IndexerAddress, _>>::new();
+        let mut first_seen_at = BTreeMap::<_, BTreeMap<_, _>>::new();
+        for row in rows.iter() {
+            let key = (
+                row.deployment_cid.clone(),
+                row.block_number,
+                row.block_hash.clone(),
+            );
+            latest_pois
+                .entry(key.clone())
+                .or_default()
+                .insert(row.indexer_address, row.poi);
+            first_seen_at
+                .entry(key)
+                .or_default()
+                .entry(row.poi)
+                .or_insert(row.timestamp);
+        }
+
+        let now = Utc::now().naive_utc();
+        let mut divergences = vec![];
+        for (key, pois) in latest_pois {
+            if pois.values().collect::<BTreeSet<_>>().len() < 2 {
+                continue;
+            }
+
+            // Detected when a second, different PoI was first collected.
+            let mut first_seen: Vec<_> = first_seen_at[&key].values().copied().collect();
+            first_seen.sort();
+            let first_detected_at = first_seen[1];
+
+            let (deployment, block_number, block_hash) = key;
+            divergences.push(StaleDivergence {
+                deployment,
+                block_number,
+                block_hash,
+                first_detected_at,
+                persisted_for_seconds: (now - first_detected_at).num_seconds(),
+                indexers: pois.into_keys().collect(),
+            });
+        }
+        divergences.sort_by_key(|divergence| {
+            (
+                divergence.first_detected_at,
+                divergence.deployment.clone(),
+                divergence.block_number,
+            )
+        });
+
+        Ok(divergences
+            .into_iter()
+            .skip(pagination.offset as usize)
+            .take(pagination.limit.into())
+            .collect())
+    }
+}
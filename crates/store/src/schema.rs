@@ -1,5 +1,40 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    allocations (id) {
+        id -> Text,
+        network_subgraph -> Text,
+        indexer_address -> Bytea,
+        ipfs_cid -> Text,
+        allocated_tokens -> Float8,
+        created_at_epoch -> Int4,
+        first_seen_at -> Timestamp,
+        closed_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    block_choice_traces (id) {
+        id -> Int4,
+        sg_deployment_id -> Int4,
+        policy -> Text,
+        candidate_blocks -> Array<Int8>,
+        chosen_block -> Nullable<Int8>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    block_hash_mismatches (id) {
+        id -> Int4,
+        indexer_id -> Int4,
+        sg_deployment_id -> Int4,
+        block_id -> Int8,
+        poi -> Bytea,
+        detected_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     blocks (id) {
         id -> Int8,
@@ -9,11 +44,33 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    divergence_investigation_deployments (investigation_uuid, sg_deployment_id) {
+        investigation_uuid -> Uuid,
+        sg_deployment_id -> Int4,
+    }
+}
+
 diesel::table! {
     divergence_investigation_reports (uuid) {
         uuid -> Uuid,
         report -> Jsonb,
         created_at -> Timestamp,
+        idempotency_key -> Nullable<Text>,
+        deduplication_key -> Nullable<Text>,
+        triage_status -> Text,
+        triage_note -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    divergence_triage_events (id) {
+        id -> Int4,
+        report_uuid -> Uuid,
+        status -> Text,
+        note -> Nullable<Text>,
+        actor -> Text,
+        created_at -> Timestamp,
     }
 }
 
@@ -35,6 +92,16 @@ diesel::table! {
         version_commit -> Nullable<Text>,
         error_response -> Nullable<Text>,
         collected_at -> Timestamp,
+        supported_features -> Nullable<Array<Text>>,
+    }
+}
+
+diesel::table! {
+    indexer_discovery_counts (id) {
+        id -> Int4,
+        source -> Text,
+        n_indexers -> Int4,
+        discovered_at -> Timestamp,
     }
 }
 
@@ -55,6 +122,26 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    indexer_query_errors (id) {
+        id -> Int4,
+        indexer_id -> Int4,
+        query_name -> Text,
+        results -> Nullable<Int4>,
+        error -> Nullable<Text>,
+        created_at -> Timestamp,
+        error_kind -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    indexer_reliability (indexer_id) {
+        indexer_id -> Int4,
+        score -> Float8,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     indexers (id) {
         id -> Int4,
@@ -63,6 +150,27 @@ diesel::table! {
         graph_node_version -> Nullable<Int4>,
         network_subgraph_metadata -> Nullable<Int4>,
         created_at -> Timestamp,
+        name_source -> Nullable<Text>,
+        region -> Nullable<Text>,
+        labels -> Array<Text>,
+        source -> Nullable<Text>,
+        staked_tokens -> Nullable<Float8>,
+        last_seen_at -> Timestamp,
+        is_active -> Bool,
+    }
+}
+
+diesel::table! {
+    indexing_progress (indexer_id, sg_deployment_id) {
+        indexer_id -> Int4,
+        sg_deployment_id -> Int4,
+        latest_block_number -> Int8,
+        iterations_without_progress -> Int4,
+        last_advanced_at -> Timestamp,
+        network -> Nullable<Text>,
+        latest_block_hash -> Nullable<Bytea>,
+        earliest_block_number -> Int8,
+        reported_at -> Timestamp,
     }
 }
 
@@ -72,6 +180,25 @@ diesel::table! {
         sg_deployment_id -> Int4,
         indexer_id -> Int4,
         poi_id -> Int4,
+        stale -> Bool,
+    }
+}
+
+diesel::table! {
+    loop_iterations (id) {
+        id -> Int4,
+        started_at -> Timestamp,
+        finished_at -> Timestamp,
+        indexing_statuses_seconds -> Float8,
+        pois_seconds -> Float8,
+        writes_seconds -> Float8,
+        n_indexers -> Int4,
+        n_queried_indexers -> Int4,
+        n_responding_indexers -> Int4,
+        n_pois -> Int4,
+        n_pinned_pois -> Int4,
+        write_errors -> Array<Text>,
+        succeeded -> Bool,
     }
 }
 
@@ -89,6 +216,37 @@ diesel::table! {
         uuid -> Uuid,
         request -> Jsonb,
         created_at -> Timestamp,
+        idempotency_key -> Nullable<Text>,
+        deduplication_key -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    poi_backfill_jobs (uuid) {
+        uuid -> Uuid,
+        deployment -> Text,
+        from_block -> Int8,
+        to_block -> Int8,
+        block_interval -> Int8,
+        status -> Text,
+        requests_total -> Nullable<Int8>,
+        requests_sent -> Int8,
+        pois_written -> Int8,
+        error -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    poi_changes (id) {
+        id -> Int4,
+        indexer_id -> Int4,
+        sg_deployment_id -> Int4,
+        block_id -> Int8,
+        old_poi -> Bytea,
+        new_poi -> Bytea,
+        detected_at -> Timestamp,
     }
 }
 
@@ -100,6 +258,9 @@ diesel::table! {
         indexer_id -> Int4,
         block_id -> Int8,
         created_at -> Timestamp,
+        superseded_at -> Nullable<Timestamp>,
+        pinned -> Bool,
+        source_url -> Nullable<Text>,
     }
 }
 
@@ -113,6 +274,23 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    sg_deployment_manifests (sg_deployment_id) {
+        sg_deployment_id -> Int4,
+        description -> Nullable<Text>,
+        repository -> Nullable<Text>,
+        spec_version -> Nullable<Text>,
+        network -> Nullable<Text>,
+        start_block -> Nullable<Int8>,
+        features -> Nullable<Array<Text>>,
+        entity_count -> Nullable<Int4>,
+        fetched_at -> Nullable<Timestamp>,
+        fetch_attempts -> Int4,
+        last_error -> Nullable<Text>,
+        next_attempt_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     sg_deployments (id) {
         id -> Int4,
@@ -131,32 +309,80 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    tracked_deployments (ipfs_cid, network_subgraph) {
+        ipfs_cid -> Text,
+        network_subgraph -> Text,
+        network -> Nullable<Text>,
+        active_allocations -> Int4,
+        allocated_tokens -> Float8,
+        tracked_since -> Timestamp,
+    }
+}
+
+diesel::table! {
+    watched_deployments (ipfs_cid) {
+        ipfs_cid -> Text,
+        watched_by -> Nullable<Text>,
+        watched_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(block_choice_traces -> sg_deployments (sg_deployment_id));
+diesel::joinable!(block_hash_mismatches -> blocks (block_id));
+diesel::joinable!(block_hash_mismatches -> indexers (indexer_id));
+diesel::joinable!(block_hash_mismatches -> sg_deployments (sg_deployment_id));
 diesel::joinable!(blocks -> networks (network_id));
+diesel::joinable!(divergence_investigation_deployments -> sg_deployments (sg_deployment_id));
+diesel::joinable!(divergence_triage_events -> divergence_investigation_reports (report_uuid));
 diesel::joinable!(failed_queries -> indexers (indexer_id));
+diesel::joinable!(indexer_query_errors -> indexers (indexer_id));
+diesel::joinable!(indexer_reliability -> indexers (indexer_id));
+diesel::joinable!(indexing_progress -> indexers (indexer_id));
+diesel::joinable!(indexing_progress -> sg_deployments (sg_deployment_id));
 diesel::joinable!(indexers -> graph_node_collected_versions (graph_node_version));
 diesel::joinable!(indexers -> indexer_network_subgraph_metadata (network_subgraph_metadata));
 diesel::joinable!(live_pois -> indexers (indexer_id));
 diesel::joinable!(live_pois -> pois (poi_id));
 diesel::joinable!(live_pois -> sg_deployments (sg_deployment_id));
+diesel::joinable!(poi_changes -> blocks (block_id));
+diesel::joinable!(poi_changes -> indexers (indexer_id));
+diesel::joinable!(poi_changes -> sg_deployments (sg_deployment_id));
 diesel::joinable!(pois -> blocks (block_id));
 diesel::joinable!(pois -> indexers (indexer_id));
 diesel::joinable!(pois -> sg_deployments (sg_deployment_id));
 diesel::joinable!(sg_deployment_api_versions -> sg_deployments (sg_deployment_id));
+diesel::joinable!(sg_deployment_manifests -> sg_deployments (sg_deployment_id));
 diesel::joinable!(sg_deployments -> networks (network));
 diesel::joinable!(sg_names -> sg_deployments (sg_deployment_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    allocations,
+    block_choice_traces,
+    block_hash_mismatches,
     blocks,
+    divergence_investigation_deployments,
     divergence_investigation_reports,
+    divergence_triage_events,
     failed_queries,
     graph_node_collected_versions,
+    indexer_discovery_counts,
     indexer_network_subgraph_metadata,
+    indexer_query_errors,
+    indexer_reliability,
     indexers,
+    indexing_progress,
     live_pois,
+    loop_iterations,
     networks,
     pending_divergence_investigation_requests,
+    poi_backfill_jobs,
+    poi_changes,
     pois,
     sg_deployment_api_versions,
+    sg_deployment_manifests,
     sg_deployments,
     sg_names,
+    tracked_deployments,
+    watched_deployments,
 );